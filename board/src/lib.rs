@@ -0,0 +1,961 @@
+//! Board descriptions that produce the pinmux and GPIO tables of the bootloader.
+//!
+//! A board is described in TOML and compiled into the bootloader by its build script,
+//! after being checked by [`validate`]:
+//!
+//! ```toml
+//! # Peripherals whose pins all have to be configured.
+//! peripherals = ["Uarta"]
+//!
+//! [[pin]]
+//! description = "UART-A TX"
+//! pin = "Uart1TxPu0"
+//! function = "Uarta"
+//! io = "Output"
+//!
+//! [[gpio]]
+//! description = "Volume Up"
+//! gpio = "X6"
+//! config = "Input"
+//! ```
+//!
+//! Values name the variants of the corresponding libtegra types. Pin settings other
+//! than `pin` and `function` may be omitted and default to no pull, passthrough,
+//! input, default lock, disabled open drain and default HV mode. The `Default`
+//! function leaves the pin muxed as it comes out of reset.
+//!
+//! [`validate`]: fn.validate.html
+
+use std::fmt::{self, Write};
+
+/// The pins that support the high voltage IO mode.
+const HV_PINS: &[&str] = &[
+    "Gen1I2CSdaPj0",
+    "Gen1I2CSclPj1",
+    "Gen2I2CSclPj2",
+    "Gen2I2CSdaPj3",
+    "Gen3I2CSclPf0",
+    "Gen3I2CSdaPf1",
+    "CamI2CSclPs2",
+    "CamI2CSdaPs3",
+    "PwrI2CSclPy3",
+    "PwrI2CSdaPy4",
+    "PexL0RstNPa0",
+    "PexL0ClkreqNPa1",
+    "PexWakeNPa2",
+    "PexL1RstNPa3",
+    "PexL1ClkreqNPa4",
+    "HdmiCecPcc0",
+    "UsbVbusEn0Pcc4",
+    "UsbVbusEn1Pcc5",
+];
+
+/// The functions of the pins the validator knows, in the order of their PM selects.
+///
+/// Only these pins may be muxed to anything but the `Default` function.
+const PIN_FUNCTIONS: &[(&str, [&str; 4])] = &[
+    ("Uart1TxPu0", ["Uarta", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Uart1RxPu1", ["Uarta", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Uart1RtsPu2", ["Uarta", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Uart1CtsPu3", ["Uarta", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Uart2TxPg0", ["Uartb", "I2s4a", "Spdif", "Uart"]),
+    ("Uart2RxPg1", ["Uartb", "I2s4a", "Spdif", "Uart"]),
+    ("Uart2RtsPg2", ["Uartb", "I2s4a", "Rsvd2", "Uart"]),
+    ("Uart2CtsPg3", ["Uartb", "I2s4a", "Rsvd2", "Uart"]),
+    ("Uart3TxPd1", ["Uartc", "Spi4", "Rsvd2", "Rsvd3"]),
+    ("Uart3RxPd2", ["Uartc", "Spi4", "Rsvd2", "Rsvd3"]),
+    ("Uart3RtsPd3", ["Uartc", "Spi4", "Rsvd2", "Rsvd3"]),
+    ("Uart3CtsPd4", ["Uartc", "Spi4", "Rsvd2", "Rsvd3"]),
+    ("Uart4TxPi4", ["Uartd", "Uart", "Rsvd2", "Rsvd3"]),
+    ("Uart4RxPi5", ["Uartd", "Uart", "Rsvd2", "Rsvd3"]),
+    ("Uart4RtsPi6", ["Uartd", "Uart", "Rsvd2", "Rsvd3"]),
+    ("Uart4CtsPi7", ["Uartd", "Uart", "Rsvd2", "Rsvd3"]),
+    ("Gen1I2CSclPj1", ["I2C1", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Gen1I2CSdaPj0", ["I2C1", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Gen2I2CSclPj2", ["I2C2", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Gen2I2CSdaPj3", ["I2C2", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Gen3I2CSclPf0", ["I2C3", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Gen3I2CSdaPf1", ["I2C3", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("PwrI2CSclPy3", ["I2Cpmu", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("PwrI2CSdaPy4", ["I2Cpmu", "Rsvd1", "Rsvd2", "Rsvd3"]),
+    ("Pe6", ["Rsvd0", "I2s5a", "Pwm2", "Rsvd3"]),
+    ("Ph6", ["Rsvd0", "Rsvd1", "Rsvd2", "Rsvd3"]),
+];
+
+/// The function that leaves the PM select of a pin alone.
+const DEFAULT_FUNCTION: &str = "Default";
+
+/// The pins every supported peripheral needs to function.
+const PERIPHERAL_PINS: &[(&str, &[&str])] = &[
+    ("Uarta", &["Uart1TxPu0", "Uart1RxPu1"]),
+    ("Uartb", &["Uart2TxPg0", "Uart2RxPg1"]),
+    ("Uartc", &["Uart3TxPd1", "Uart3RxPd2"]),
+    ("Uartd", &["Uart4TxPi4", "Uart4RxPi5"]),
+    ("I2C1", &["Gen1I2CSclPj1", "Gen1I2CSdaPj0"]),
+    ("I2C2", &["Gen2I2CSclPj2", "Gen2I2CSdaPj3"]),
+    ("I2C3", &["Gen3I2CSclPf0", "Gen3I2CSdaPf1"]),
+    ("I2Cpmu", &["PwrI2CSclPy3", "PwrI2CSdaPy4"]),
+];
+
+/// The supported GPIO configurations, with whether they drive the pin and at which
+/// level.
+const GPIO_CONFIGS: &[(&str, bool, Option<bool>)] = &[
+    ("Input", false, None),
+    ("OutputLow", true, Some(false)),
+    ("OutputHigh", true, Some(true)),
+];
+
+/// The default HV mode, the only one pins without HV support accept.
+const DEFAULT_HV: &str = "Default";
+
+/// The configuration of a single pin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pin {
+    pub description: String,
+    pub pin: String,
+    pub function: String,
+    pub pull: String,
+    pub tristate: String,
+    pub io: String,
+    pub lock: String,
+    pub od: String,
+    pub hv: String,
+}
+
+/// The configuration of a single GPIO.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gpio {
+    pub description: String,
+    /// The port, e.g. `X` or `BB`.
+    pub port: String,
+    /// The pin within the port.
+    pub pin: u8,
+    pub config: String,
+}
+
+/// A parsed board description.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Board {
+    pub peripherals: Vec<String>,
+    pub pins: Vec<Pin>,
+    pub gpios: Vec<Gpio>,
+}
+
+/// Errors in a board description.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The description is not valid TOML.
+    Syntax(String),
+    /// An entry lacks a required field.
+    MissingField { entry: String, field: &'static str },
+    /// A field holds a value of the wrong type or format.
+    InvalidValue { entry: String, field: &'static str },
+    /// A pin is configured more than once, by the entries described as `first` and
+    /// `second`.
+    DuplicatePin {
+        pin: String,
+        first: String,
+        second: String,
+    },
+    /// A GPIO is configured more than once.
+    DuplicateGpio(String),
+    /// A pin without HV support is put in an HV mode.
+    NotHvCapable { pin: String, hv: String },
+    /// A pin is muxed to a function it does not support, or that is not known.
+    UnsupportedFunction { pin: String, function: String },
+    /// A peripheral is enabled that the validator does not know the pins of.
+    UnknownPeripheral(String),
+    /// An enabled peripheral lacks one of its pins.
+    MissingPin { peripheral: String, pin: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax(message) => write!(f, "malformed board description: {}", message),
+            Error::MissingField { entry, field } => write!(f, "{} lacks `{}`", entry, field),
+            Error::InvalidValue { entry, field } => {
+                write!(f, "{} has an invalid `{}`", entry, field)
+            }
+            Error::DuplicatePin { pin, first, second } => {
+                write!(f, "pin {} is claimed by both {} and {}", pin, first, second)
+            }
+            Error::DuplicateGpio(gpio) => write!(f, "GPIO {} is configured twice", gpio),
+            Error::NotHvCapable { pin, hv } => {
+                write!(f, "pin {} does not support HV mode {}", pin, hv)
+            }
+            Error::UnsupportedFunction { pin, function } => {
+                write!(f, "pin {} does not support function {}", pin, function)
+            }
+            Error::UnknownPeripheral(peripheral) => {
+                write!(f, "the pins of peripheral {} are unknown", peripheral)
+            }
+            Error::MissingPin { peripheral, pin } => {
+                write!(f, "peripheral {} needs pin {}", peripheral, pin)
+            }
+        }
+    }
+}
+
+fn is_identifier(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Reads a variant name from `field` of `table`, falling back to `default` if given.
+fn variant(
+    table: &toml::value::Table,
+    entry: &str,
+    field: &'static str,
+    default: Option<&str>,
+) -> Result<String, Error> {
+    let value = match (table.get(field), default) {
+        (Some(value), _) => value.as_str(),
+        (None, Some(default)) => Some(default),
+        (None, None) => {
+            return Err(Error::MissingField {
+                entry: entry.to_string(),
+                field,
+            })
+        }
+    };
+
+    match value {
+        Some(value) if is_identifier(value) => Ok(value.to_string()),
+        _ => Err(Error::InvalidValue {
+            entry: entry.to_string(),
+            field,
+        }),
+    }
+}
+
+fn description(table: &toml::value::Table, kind: &str, index: usize) -> String {
+    table
+        .get("description")
+        .and_then(|description| description.as_str())
+        .map_or_else(
+            || format!("{} #{}", kind, index + 1),
+            |d| d.replace('\n', " "),
+        )
+}
+
+/// Gets the entries of the array of tables `key`.
+fn tables<'a>(
+    root: &'a toml::value::Table,
+    key: &'static str,
+) -> Result<Vec<&'a toml::value::Table>, Error> {
+    let invalid = || Error::InvalidValue {
+        entry: "the board".to_string(),
+        field: key,
+    };
+
+    match root.get(key) {
+        None => Ok(Vec::new()),
+        Some(value) => value
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|entry| entry.as_table().ok_or_else(invalid))
+            .collect(),
+    }
+}
+
+fn parse_pin(table: &toml::value::Table, index: usize) -> Result<Pin, Error> {
+    let entry = description(table, "pin", index);
+
+    Ok(Pin {
+        pin: variant(table, &entry, "pin", None)?,
+        function: variant(table, &entry, "function", None)?,
+        pull: variant(table, &entry, "pull", Some("None"))?,
+        tristate: variant(table, &entry, "tristate", Some("Passthrough"))?,
+        io: variant(table, &entry, "io", Some("Input"))?,
+        lock: variant(table, &entry, "lock", Some("Default"))?,
+        od: variant(table, &entry, "od", Some("Disable"))?,
+        hv: variant(table, &entry, "hv", Some(DEFAULT_HV))?,
+        description: entry,
+    })
+}
+
+fn parse_gpio(table: &toml::value::Table, index: usize) -> Result<Gpio, Error> {
+    let entry = description(table, "GPIO", index);
+    let gpio = variant(table, &entry, "gpio", None)?;
+
+    // GPIOs are named by their port, one or two letters, followed by the pin.
+    let split = gpio.len() - 1;
+    let (port, pin) = gpio.split_at(split);
+    let pin = pin.parse::<u8>().ok().filter(|&pin| pin < 8);
+    let pin = match pin {
+        Some(pin) if port_index(port).is_some() => pin,
+        _ => {
+            return Err(Error::InvalidValue {
+                entry,
+                field: "gpio",
+            })
+        }
+    };
+
+    let config = variant(table, &entry, "config", None)?;
+    if !GPIO_CONFIGS.iter().any(|&(name, _, _)| name == config) {
+        return Err(Error::InvalidValue {
+            entry,
+            field: "config",
+        });
+    }
+
+    Ok(Gpio {
+        port: port.to_string(),
+        pin,
+        config,
+        description: entry,
+    })
+}
+
+/// Gets the index of the GPIO port named `port`, from A to Z and then AA to FF.
+fn port_index(port: &str) -> Option<u32> {
+    let bytes = port.as_bytes();
+    let letter = |byte: u8| {
+        Some(byte)
+            .filter(u8::is_ascii_uppercase)
+            .map(|b| (b - b'A') as u32)
+    };
+
+    match *bytes {
+        [first] => letter(first),
+        [first, second] if first == second && first <= b'F' => letter(first).map(|i| 26 + i),
+        _ => None,
+    }
+}
+
+/// Gets the PM select of `function` on `pin`, or `None` for the default function and
+/// functions the pin does not support.
+fn function_select(pin: &str, function: &str) -> Option<u32> {
+    let (_, functions) = PIN_FUNCTIONS.iter().find(|(name, _)| *name == pin)?;
+    functions
+        .iter()
+        .position(|&name| name == function)
+        .map(|select| select as u32)
+}
+
+/// Gets the pull setting as encoded in the pinmux registers, if the pull is touched.
+fn pull_bits(pull: &str) -> Option<u32> {
+    match pull {
+        "None" => Some(0),
+        "Down" => Some(1),
+        "Up" => Some(2),
+        _ => None,
+    }
+}
+
+/// Maps the variant `value` to whether the setting is enabled, if it is touched.
+fn flag(value: &str, enabled: &str, disabled: &str) -> Option<bool> {
+    if value == enabled {
+        Some(true)
+    } else if value == disabled {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Parses the TOML board description `source`.
+pub fn parse(source: &str) -> Result<Board, Error> {
+    let root = source
+        .parse::<toml::Value>()
+        .map_err(|e| Error::Syntax(e.to_string()))?;
+    let root = root
+        .as_table()
+        .ok_or_else(|| Error::Syntax("expected a table".to_string()))?;
+
+    let invalid_peripherals = || Error::InvalidValue {
+        entry: "the board".to_string(),
+        field: "peripherals",
+    };
+    let peripherals = match root.get("peripherals") {
+        None => Vec::new(),
+        Some(value) => value
+            .as_array()
+            .ok_or_else(invalid_peripherals)?
+            .iter()
+            .map(|peripheral| {
+                peripheral
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(invalid_peripherals)
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    Ok(Board {
+        peripherals,
+        pins: tables(root, "pin")?
+            .into_iter()
+            .enumerate()
+            .map(|(index, table)| parse_pin(table, index))
+            .collect::<Result<_, _>>()?,
+        gpios: tables(root, "gpio")?
+            .into_iter()
+            .enumerate()
+            .map(|(index, table)| parse_gpio(table, index))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Checks `board` for conflicting and incomplete configurations.
+///
+/// Returns all errors found.
+pub fn validate(board: &Board) -> Result<(), Vec<Error>> {
+    let mut errors = Vec::new();
+
+    // Every pin may only be configured once.
+    for (index, pin) in board.pins.iter().enumerate() {
+        if let Some(first) = board.pins[..index]
+            .iter()
+            .find(|other| other.pin == pin.pin)
+        {
+            errors.push(Error::DuplicatePin {
+                pin: pin.pin.clone(),
+                first: first.description.clone(),
+                second: pin.description.clone(),
+            });
+        }
+
+        if pin.hv != DEFAULT_HV && !HV_PINS.contains(&pin.pin.as_str()) {
+            errors.push(Error::NotHvCapable {
+                pin: pin.pin.clone(),
+                hv: pin.hv.clone(),
+            });
+        }
+
+        if pin.function != DEFAULT_FUNCTION && function_select(&pin.pin, &pin.function).is_none() {
+            errors.push(Error::UnsupportedFunction {
+                pin: pin.pin.clone(),
+                function: pin.function.clone(),
+            });
+        }
+    }
+
+    // The same goes for GPIOs.
+    for (index, gpio) in board.gpios.iter().enumerate() {
+        let duplicate = board.gpios[..index]
+            .iter()
+            .any(|other| other.port == gpio.port && other.pin == gpio.pin);
+        if duplicate {
+            errors.push(Error::DuplicateGpio(format!("{}{}", gpio.port, gpio.pin)));
+        }
+    }
+
+    // Every enabled peripheral needs all of its pins.
+    for peripheral in &board.peripherals {
+        let required = match PERIPHERAL_PINS.iter().find(|(name, _)| name == peripheral) {
+            Some((_, pins)) => pins,
+            None => {
+                errors.push(Error::UnknownPeripheral(peripheral.clone()));
+                continue;
+            }
+        };
+
+        for &required in required.iter() {
+            let present = board
+                .pins
+                .iter()
+                .any(|pin| pin.pin == required && pin.function == *peripheral);
+            if !present {
+                errors.push(Error::MissingPin {
+                    peripheral: peripheral.clone(),
+                    pin: required.to_string(),
+                });
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Generates the `GPIO_CONFIG` and `PIN_CONFIG` tables of `board` as Rust source,
+/// along with the `GPIO_EXPECTED` and `PIN_EXPECTED` tables of the settings they
+/// result in.
+///
+/// The tables expect `gpio`, `PinConfig`, `PinFields`, `GpioFields` and the pinmux
+/// types of libtegra to be in scope, with `PinIoHv` as `PinEIoHv`.
+pub fn generate(board: &Board) -> String {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "pub const GPIO_CONFIG: [(gpio::Gpio, gpio::Config); {}] = [",
+        board.gpios.len()
+    )
+    .unwrap();
+    for gpio in &board.gpios {
+        writeln!(out, "    // {}", gpio.description).unwrap();
+        writeln!(
+            out,
+            "    (tegra_gpio!({}, {}), gpio::Config::{}),",
+            gpio.port, gpio.pin, gpio.config
+        )
+        .unwrap();
+    }
+    out.push_str("];\n\n");
+
+    writeln!(
+        out,
+        "pub const PIN_CONFIG: [PinConfig; {}] = [",
+        board.pins.len()
+    )
+    .unwrap();
+    for pin in &board.pins {
+        writeln!(out, "    // {}", pin.description).unwrap();
+        writeln!(out, "    PinConfig {{").unwrap();
+        writeln!(out, "        pin: PinGrP::{},", pin.pin).unwrap();
+        writeln!(out, "        function: PinFunction::{},", pin.function).unwrap();
+        writeln!(out, "        pull: PinPull::{},", pin.pull).unwrap();
+        writeln!(out, "        tristate: PinTristate::{},", pin.tristate).unwrap();
+        writeln!(out, "        io: PinIo::{},", pin.io).unwrap();
+        writeln!(out, "        lock: PinLock::{},", pin.lock).unwrap();
+        writeln!(out, "        od: PinOd::{},", pin.od).unwrap();
+        writeln!(out, "        hv: PinEIoHv::{},", pin.hv).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    out.push_str("];\n\n");
+
+    // The settings to check the live state against.
+    writeln!(
+        out,
+        "pub const PIN_EXPECTED: [PinFields; {}] = [",
+        board.pins.len()
+    )
+    .unwrap();
+    for pin in &board.pins {
+        writeln!(out, "    PinFields {{").unwrap();
+        writeln!(out, "        name: {:?},", pin.description).unwrap();
+        writeln!(
+            out,
+            "        function: {:?},",
+            function_select(&pin.pin, &pin.function)
+        )
+        .unwrap();
+        writeln!(out, "        pull: {:?},", pull_bits(&pin.pull)).unwrap();
+        writeln!(
+            out,
+            "        tristate: {:?},",
+            flag(&pin.tristate, "Tristate", "Passthrough")
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "        input: {:?},",
+            flag(&pin.io, "Input", "Output")
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "        lock: {:?},",
+            flag(&pin.lock, "Enable", "Disable")
+        )
+        .unwrap();
+        writeln!(out, "        od: {:?},", flag(&pin.od, "Enable", "Disable")).unwrap();
+        writeln!(out, "        hv: {:?},", flag(&pin.hv, "High", "Normal")).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    out.push_str("];\n\n");
+
+    writeln!(
+        out,
+        "pub const GPIO_EXPECTED: [GpioFields; {}] = [",
+        board.gpios.len()
+    )
+    .unwrap();
+    for gpio in &board.gpios {
+        let (_, output, level) = GPIO_CONFIGS
+            .iter()
+            .find(|&&(name, _, _)| name == gpio.config)
+            .expect("unsupported GPIO configuration");
+
+        writeln!(out, "    GpioFields {{").unwrap();
+        writeln!(out, "        name: {:?},", gpio.description).unwrap();
+        writeln!(out, "        port: {},", port_index(&gpio.port).unwrap()).unwrap();
+        writeln!(out, "        pin: {},", gpio.pin).unwrap();
+        writeln!(out, "        output: {},", output).unwrap();
+        writeln!(out, "        level: {:?},", level).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    out.push_str("];\n");
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The UART-A pins, which satisfy the `Uarta` peripheral.
+    const UARTA: &str = r#"
+        peripherals = ["Uarta"]
+
+        [[pin]]
+        description = "UART-A TX"
+        pin = "Uart1TxPu0"
+        function = "Uarta"
+        io = "Output"
+
+        [[pin]]
+        description = "UART-A RX"
+        pin = "Uart1RxPu1"
+        function = "Uarta"
+        pull = "Up"
+    "#;
+
+    fn check(source: &str) -> Result<(), Vec<Error>> {
+        validate(&parse(source).expect("board does not parse"))
+    }
+
+    fn invalid(entry: &str, field: &'static str) -> Error {
+        Error::InvalidValue {
+            entry: entry.to_string(),
+            field,
+        }
+    }
+
+    #[test]
+    fn accepts_shipped_boards() {
+        let board = parse(include_str!("../../boards/icosa.toml")).unwrap();
+        assert_eq!(validate(&board), Ok(()));
+    }
+
+    #[test]
+    fn applies_defaults() {
+        let board = parse(UARTA).unwrap();
+        assert_eq!(board.peripherals, ["Uarta"]);
+        assert_eq!(
+            board.pins[1],
+            Pin {
+                description: "UART-A RX".to_string(),
+                pin: "Uart1RxPu1".to_string(),
+                function: "Uarta".to_string(),
+                pull: "Up".to_string(),
+                tristate: "Passthrough".to_string(),
+                io: "Input".to_string(),
+                lock: "Default".to_string(),
+                od: "Disable".to_string(),
+                hv: "Default".to_string(),
+            }
+        );
+        assert_eq!(check(UARTA), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_pin_names() {
+        for &pin in [
+            "\"Uart1 TxPu0\"",
+            "\"1Uart\"",
+            "\"\"",
+            "\"PinGrP::Uart1TxPu0\"",
+            "5",
+        ]
+        .iter()
+        {
+            let source = format!("[[pin]]\npin = {}\nfunction = \"Uarta\"", pin);
+            assert_eq!(parse(&source), Err(invalid("pin #1", "pin")), "{}", pin);
+        }
+
+        let source = "[[pin]]\ndescription = \"TX\"\npin = \"Uart1TxPu0\"\nfunction = \"Uart-A\"";
+        assert_eq!(parse(source), Err(invalid("TX", "function")));
+    }
+
+    #[test]
+    fn rejects_missing_fields() {
+        let source = "[[pin]]\ndescription = \"UART-A\\nTX\"\npin = \"Uart1TxPu0\"";
+        assert_eq!(
+            parse(source),
+            Err(Error::MissingField {
+                entry: "UART-A TX".to_string(),
+                field: "function",
+            })
+        );
+
+        let source = "[[pin]]\npin = \"Uart1TxPu0\"\nfunction = \"Uarta\"\n[[gpio]]\n[[gpio]]";
+        assert_eq!(
+            parse(source),
+            Err(Error::MissingField {
+                entry: "GPIO #1".to_string(),
+                field: "gpio",
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_gpio_names() {
+        let good = [
+            ("X6", "X", 6),
+            ("A0", "A", 0),
+            ("BB3", "BB", 3),
+            ("FF7", "FF", 7),
+        ];
+        for &(name, port, pin) in good.iter() {
+            let source = format!("[[gpio]]\ngpio = \"{}\"\nconfig = \"Input\"", name);
+            let gpio = &parse(&source).unwrap().gpios[0];
+            assert_eq!((gpio.port.as_str(), gpio.pin), (port, pin));
+        }
+
+        for &name in ["X8", "X", "x6", "AB1", "GG1", "AAA1", "X-1", "6"].iter() {
+            let source = format!("[[gpio]]\ngpio = \"{}\"\nconfig = \"Input\"", name);
+            assert_eq!(parse(&source), Err(invalid("GPIO #1", "gpio")), "{}", name);
+        }
+
+        let source = "[[gpio]]\ngpio = \"X6\"\nconfig = \"Floating\"";
+        assert_eq!(parse(source), Err(invalid("GPIO #1", "config")));
+    }
+
+    #[test]
+    fn rejects_malformed_descriptions() {
+        assert!(matches!(parse("pin = ["), Err(Error::Syntax(_))));
+        assert_eq!(parse("pin = 1"), Err(invalid("the board", "pin")));
+        assert_eq!(parse("pin = [1]"), Err(invalid("the board", "pin")));
+        assert_eq!(
+            parse("peripherals = \"Uarta\""),
+            Err(invalid("the board", "peripherals"))
+        );
+        assert_eq!(parse(""), Ok(Board::default()));
+    }
+
+    #[test]
+    fn rejects_pins_claimed_twice() {
+        // The second UART-A TX pin is claimed by UART-B.
+        let source = format!(
+            "{}\n[[pin]]\ndescription = \"UART-B TX\"\npin = \"Uart1TxPu0\"\nfunction = \"Uartb\"",
+            UARTA
+        );
+        assert_eq!(
+            check(&source),
+            Err(vec![
+                Error::DuplicatePin {
+                    pin: "Uart1TxPu0".to_string(),
+                    first: "UART-A TX".to_string(),
+                    second: "UART-B TX".to_string(),
+                },
+                Error::UnsupportedFunction {
+                    pin: "Uart1TxPu0".to_string(),
+                    function: "Uartb".to_string(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_pins_with_other_functions() {
+        // The RX pin of UART-A is left at its default function instead.
+        let source = UARTA.replacen(
+            "function = \"Uarta\"\n        pull",
+            "function = \"Default\"\n        pull",
+            1,
+        );
+        assert_eq!(
+            check(&source),
+            Err(vec![Error::MissingPin {
+                peripheral: "Uarta".to_string(),
+                pin: "Uart1RxPu1".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn rejects_gpios_configured_twice() {
+        let source = r#"
+            [[gpio]]
+            gpio = "X6"
+            config = "Input"
+
+            [[gpio]]
+            gpio = "X7"
+            config = "Input"
+
+            [[gpio]]
+            gpio = "X6"
+            config = "OutputHigh"
+        "#;
+        assert_eq!(
+            check(source),
+            Err(vec![Error::DuplicateGpio("X6".to_string())])
+        );
+    }
+
+    #[test]
+    fn rejects_hv_mode_on_other_pins() {
+        let source = r#"
+            [[pin]]
+            pin = "Gen1I2CSdaPj0"
+            function = "I2C1"
+            hv = "High"
+
+            [[pin]]
+            pin = "Uart1TxPu0"
+            function = "Uarta"
+            hv = "Normal"
+        "#;
+        assert_eq!(
+            check(source),
+            Err(vec![Error::NotHvCapable {
+                pin: "Uart1TxPu0".to_string(),
+                hv: "Normal".to_string(),
+            }])
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_functions() {
+        let source = r#"
+            [[pin]]
+            pin = "Uart3TxPd1"
+            function = "Spi4"
+
+            [[pin]]
+            pin = "Uart2TxPg0"
+            function = "Uartc"
+
+            [[pin]]
+            pin = "Pe6"
+            function = "Default"
+
+            [[pin]]
+            pin = "Pk0"
+            function = "Default"
+
+            [[pin]]
+            pin = "Pk1"
+            function = "Rsvd0"
+        "#;
+        assert_eq!(
+            check(source),
+            Err(vec![
+                Error::UnsupportedFunction {
+                    pin: "Uart2TxPg0".to_string(),
+                    function: "Uartc".to_string(),
+                },
+                Error::UnsupportedFunction {
+                    pin: "Pk1".to_string(),
+                    function: "Rsvd0".to_string(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn selects_functions_by_pin() {
+        assert_eq!(function_select("Uart1TxPu0", "Uarta"), Some(0));
+        assert_eq!(function_select("Uart2TxPg0", "Spdif"), Some(2));
+        assert_eq!(function_select("Uart4RxPi5", "Uart"), Some(1));
+        assert_eq!(function_select("Pe6", "Pwm2"), Some(2));
+        assert_eq!(function_select("Pe6", "Default"), None);
+        assert_eq!(function_select("Pk1", "Rsvd0"), None);
+
+        // Every peripheral pin supports its peripheral.
+        for (peripheral, pins) in PERIPHERAL_PINS.iter() {
+            for pin in pins.iter() {
+                assert!(function_select(pin, peripheral).is_some(), "{}", pin);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_incomplete_peripherals() {
+        let source = "peripherals = [\"I2C1\", \"Spi1\"]";
+        assert_eq!(
+            check(source),
+            Err(vec![
+                Error::MissingPin {
+                    peripheral: "I2C1".to_string(),
+                    pin: "Gen1I2CSclPj1".to_string(),
+                },
+                Error::MissingPin {
+                    peripheral: "I2C1".to_string(),
+                    pin: "Gen1I2CSdaPj0".to_string(),
+                },
+                Error::UnknownPeripheral("Spi1".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        let error = Error::DuplicatePin {
+            pin: "Uart1TxPu0".to_string(),
+            first: "UART-A TX".to_string(),
+            second: "UART-B TX".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "pin Uart1TxPu0 is claimed by both UART-A TX and UART-B TX"
+        );
+        assert_eq!(
+            invalid("GPIO #1", "gpio").to_string(),
+            "GPIO #1 has an invalid `gpio`"
+        );
+    }
+
+    #[test]
+    fn generates_tables() {
+        let source = r#"
+            [[pin]]
+            description = "UART-A TX"
+            pin = "Uart1TxPu0"
+            function = "Uarta"
+            io = "Output"
+            pull = "Up"
+
+            [[gpio]]
+            description = "Volume Up"
+            gpio = "BB6"
+            config = "OutputLow"
+        "#;
+
+        let expected = r#"pub const GPIO_CONFIG: [(gpio::Gpio, gpio::Config); 1] = [
+    // Volume Up
+    (tegra_gpio!(BB, 6), gpio::Config::OutputLow),
+];
+
+pub const PIN_CONFIG: [PinConfig; 1] = [
+    // UART-A TX
+    PinConfig {
+        pin: PinGrP::Uart1TxPu0,
+        function: PinFunction::Uarta,
+        pull: PinPull::Up,
+        tristate: PinTristate::Passthrough,
+        io: PinIo::Output,
+        lock: PinLock::Default,
+        od: PinOd::Disable,
+        hv: PinEIoHv::Default,
+    },
+];
+
+pub const PIN_EXPECTED: [PinFields; 1] = [
+    PinFields {
+        name: "UART-A TX",
+        function: Some(0),
+        pull: Some(2),
+        tristate: Some(false),
+        input: Some(false),
+        lock: None,
+        od: Some(false),
+        hv: None,
+    },
+];
+
+pub const GPIO_EXPECTED: [GpioFields; 1] = [
+    GpioFields {
+        name: "Volume Up",
+        port: 27,
+        pin: 6,
+        output: true,
+        level: Some(false),
+    },
+];
+"#;
+
+        assert_eq!(generate(&parse(source).unwrap()), expected);
+    }
+}
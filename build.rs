@@ -0,0 +1,159 @@
+//! Build script that bakes the chain-of-trust public keys and the boot logo into the
+//! bootloader.
+//!
+//! The RSA modulus is read from the file pointed to by the `MIRAGE_RSA_MODULUS`
+//! environment variable, falling back to the development key in `keys/`. The file is
+//! expected in the format produced by `openssl rsa -pubin -in key.pem -noout -modulus`.
+//!
+//! With the `ed25519` feature, the public key is instead read from the file pointed to
+//! by `MIRAGE_ED25519_PUBLIC_KEY`, which holds the 32 raw key bytes in hexadecimal.
+//!
+//! The boot logo is read from the file pointed to by `MIRAGE_LOGO`, falling back to
+//! the one in `assets/`. It is produced by the `mklogo` tool and validated here, so
+//! that a malformed or oversized logo fails the build rather than the boot.
+//!
+//! The pinmux and GPIO tables are generated from the board description pointed to by
+//! `MIRAGE_BOARD`, falling back to the one in `boards/`. Descriptions that configure a
+//! pin twice, mux a pin to a function it does not support, put a pin without HV
+//! support into an HV mode or leave out pins of an enabled peripheral fail the build.
+//!
+//! The temperature above which the first stage keeps its clocks low and refuses to
+//! flash is read from `MIRAGE_THERMAL_LIMIT` in degrees Celsius.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+const DEFAULT_RSA_MODULUS: &str = "keys/dev_rsa.modulus";
+const DEFAULT_ED25519_PUBLIC_KEY: &str = "keys/dev_ed25519.pub";
+const DEFAULT_LOGO: &str = "assets/logo.mlg";
+const DEFAULT_BOARD: &str = "boards/icosa.toml";
+const DEFAULT_THERMAL_LIMIT: i32 = 80;
+
+/// The share of the tight IRAM budget of the first stage granted to the logo.
+///
+/// All of the first stage has to fit between its load address and the load address
+/// of the second stage, see `link.ld` and `BOOTLOADER_START`.
+const MAX_LOGO_SIZE: usize = 0x800;
+
+fn parse_hex(hex: &str, what: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect(what))
+        .collect()
+}
+
+fn parse_modulus(contents: &str) -> Vec<u8> {
+    let hex = contents.trim();
+    let hex = hex.strip_prefix("Modulus=").unwrap_or(hex);
+
+    assert!(
+        hex.len() == 512,
+        "the RSA modulus must be exactly 2048 bits long"
+    );
+
+    parse_hex(hex, "malformed RSA modulus")
+}
+
+fn parse_ed25519_key(contents: &str) -> Vec<u8> {
+    let hex = contents.trim();
+
+    assert!(
+        hex.len() == 64,
+        "the Ed25519 public key must be exactly 32 bytes long"
+    );
+
+    parse_hex(hex, "malformed Ed25519 public key")
+}
+
+fn read_key_file(var: &str, default: &str) -> String {
+    println!("cargo:rerun-if-env-changed={}", var);
+
+    let path = env::var(var).unwrap_or_else(|_| default.to_string());
+    println!("cargo:rerun-if-changed={}", path);
+
+    fs::read_to_string(&path).unwrap_or_else(|_| panic!("failed to read {}", path))
+}
+
+fn read_logo() -> Vec<u8> {
+    println!("cargo:rerun-if-env-changed=MIRAGE_LOGO");
+
+    let path = env::var("MIRAGE_LOGO").unwrap_or_else(|_| DEFAULT_LOGO.to_string());
+    println!("cargo:rerun-if-changed={}", path);
+
+    let bytes = fs::read(&path).unwrap_or_else(|_| panic!("failed to read {}", path));
+    assert!(
+        bytes.len() <= MAX_LOGO_SIZE,
+        "the logo takes {} bytes, but at most {} are available",
+        bytes.len(),
+        MAX_LOGO_SIZE
+    );
+    if let Err(e) = logo::Logo::decode(&bytes) {
+        panic!("malformed logo {}: {:?}", path, e);
+    }
+
+    bytes
+}
+
+fn read_board() -> String {
+    println!("cargo:rerun-if-env-changed=MIRAGE_BOARD");
+
+    let path = env::var("MIRAGE_BOARD").unwrap_or_else(|_| DEFAULT_BOARD.to_string());
+    println!("cargo:rerun-if-changed={}", path);
+
+    let source = fs::read_to_string(&path).unwrap_or_else(|_| panic!("failed to read {}", path));
+    let board = board::parse(&source).unwrap_or_else(|e| panic!("invalid board {}: {}", path, e));
+    if let Err(errors) = board::validate(&board) {
+        let errors = errors
+            .iter()
+            .map(|e| format!("\n  {}", e))
+            .collect::<String>();
+        panic!("invalid board {}:{}", path, errors);
+    }
+
+    board::generate(&board)
+}
+
+fn read_thermal_limit() -> i32 {
+    println!("cargo:rerun-if-env-changed=MIRAGE_THERMAL_LIMIT");
+
+    let limit = env::var("MIRAGE_THERMAL_LIMIT")
+        .map(|limit| limit.parse().expect("malformed thermal limit"))
+        .unwrap_or(DEFAULT_THERMAL_LIMIT);
+    assert!(
+        (40..=95).contains(&limit),
+        "the thermal limit must be between 40 and 95 degrees Celsius"
+    );
+
+    limit
+}
+
+fn format_bytes(name: &str, bytes: &[u8]) -> String {
+    let mut out = format!("pub const {}: [u8; {}] = [", name, bytes.len());
+    for byte in bytes {
+        write!(out, "0x{:02X}, ", byte).unwrap();
+    }
+    out.push_str("];\n");
+    out
+}
+
+fn main() {
+    let keys = if env::var_os("CARGO_FEATURE_ED25519").is_some() {
+        let key = read_key_file("MIRAGE_ED25519_PUBLIC_KEY", DEFAULT_ED25519_PUBLIC_KEY);
+        format_bytes("ED25519_PUBLIC_KEY", &parse_ed25519_key(&key))
+    } else {
+        let modulus = read_key_file("MIRAGE_RSA_MODULUS", DEFAULT_RSA_MODULUS);
+        format_bytes("RSA_MODULUS", &parse_modulus(&modulus))
+    };
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("keys.rs"), keys).expect("failed to write keys.rs");
+    fs::write(out_dir.join("logo.mlg"), read_logo()).expect("failed to write logo.mlg");
+    fs::write(
+        out_dir.join("thermal.rs"),
+        format!("pub const LIMIT_CELSIUS: i32 = {};\n", read_thermal_limit()),
+    )
+    .expect("failed to write thermal.rs");
+    fs::write(out_dir.join("board.rs"), read_board()).expect("failed to write board.rs");
+}
@@ -0,0 +1,538 @@
+//! The run-length encoded boot logo format shared between the bootloader and the
+//! host-side tooling.
+//!
+//! Logos are paletted with the 16 colors of [`PALETTE`], which the framebuffer uses
+//! as well, so that they can be decoded straight into it. An encoded logo is laid out
+//! as follows, with all integers encoded in little-endian byte order:
+//!
+//! | Offset | Size        | Description                            |
+//! |--------|-------------|----------------------------------------|
+//! | 0x00   | 0x04        | Magic value `MLGO`                     |
+//! | 0x04   | 0x02        | Format version                         |
+//! | 0x06   | 0x02        | Width in pixels                        |
+//! | 0x08   | 0x02        | Height in pixels                       |
+//! | 0x0A   | 0x02        | Size of the run data in bytes          |
+//! | 0x0C   | `data_size` | Runs of pixels in row-major order      |
+//!
+//! Each run starts with a byte holding the palette index in its upper nibble and the
+//! run length minus one in its lower nibble. A lower nibble of `0xF` instead signals
+//! a long run, the length of which is 16 plus the value of the following byte.
+//!
+//! [`PALETTE`]: constant.PALETTE.html
+
+#![no_std]
+
+/// The magic value that identifies a logo.
+pub const MAGIC: [u8; 4] = *b"MLGO";
+
+/// The revision of the logo format described by this crate.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// The size of an encoded [`Header`] in bytes.
+///
+/// [`Header`]: struct.Header.html
+pub const HEADER_SIZE: usize = 0xC;
+
+/// The 16-color palette of logos as `0xRRGGBB` values.
+pub const PALETTE: [u32; 16] = [
+    0x000000, 0x0000AA, 0x00AA00, 0x00AAAA, 0xAA0000, 0xAA00AA, 0xAA5500, 0xAAAAAA, 0x555555,
+    0x5555FF, 0x55FF55, 0x55FFFF, 0xFF5555, 0xFF55FF, 0xFFFF55, 0xFFFFFF,
+];
+
+/// The longest run that fits into a single byte.
+const MAX_SHORT_RUN: usize = 15;
+
+/// The longest run that can be encoded.
+const MAX_RUN: usize = 16 + 0xFF;
+
+/// Errors that may occur while encoding or decoding a logo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The buffer is too small to hold the encoded data.
+    Truncated,
+    /// The magic value does not identify a logo.
+    BadMagic,
+    /// The logo was produced for an unsupported format revision.
+    UnsupportedVersion,
+    /// The runs describe more pixels than the logo holds.
+    Overrun,
+    /// The runs describe fewer pixels than the logo holds.
+    Underrun,
+    /// A pixel is not a valid palette index.
+    InvalidColor,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn write_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+/// The fixed-size header of a logo.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Header {
+    /// The width of the logo in pixels.
+    pub width: u16,
+    /// The height of the logo in pixels.
+    pub height: u16,
+    /// The size of the run data in bytes.
+    pub data_size: u16,
+}
+
+impl Header {
+    /// Decodes a header from the start of `bytes`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(Error::Truncated);
+        }
+        if bytes[..4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        if read_u16(bytes, 0x4) != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        Ok(Header {
+            width: read_u16(bytes, 0x6),
+            height: read_u16(bytes, 0x8),
+            data_size: read_u16(bytes, 0xA),
+        })
+    }
+
+    /// Encodes the header into the start of `bytes`.
+    pub fn encode(&self, bytes: &mut [u8]) -> Result<(), Error> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(Error::Truncated);
+        }
+
+        bytes[..4].copy_from_slice(&MAGIC);
+        write_u16(bytes, 0x4, FORMAT_VERSION);
+        write_u16(bytes, 0x6, self.width);
+        write_u16(bytes, 0x8, self.height);
+        write_u16(bytes, 0xA, self.data_size);
+
+        Ok(())
+    }
+
+    /// Gets the number of pixels in the logo.
+    pub fn pixel_count(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+}
+
+/// A horizontal run of pixels of the same color within a single row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Run {
+    /// The column of the first pixel.
+    pub x: u16,
+    /// The row of the run.
+    pub y: u16,
+    /// The number of pixels.
+    pub length: u16,
+    /// The palette index of the pixels.
+    pub color: u8,
+}
+
+/// A logo whose run data was checked to exactly cover its pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct Logo<'a> {
+    /// The header of the logo.
+    pub header: Header,
+    data: &'a [u8],
+}
+
+impl<'a> Logo<'a> {
+    /// Parses and validates an encoded logo.
+    pub fn decode(bytes: &'a [u8]) -> Result<Self, Error> {
+        let header = Header::decode(bytes)?;
+        let data = bytes
+            .get(HEADER_SIZE..HEADER_SIZE + header.data_size as usize)
+            .ok_or(Error::Truncated)?;
+
+        // Walk all runs once so that drawing never has to deal with malformed data.
+        let mut pixels = 0usize;
+        let runs = RawRuns { data };
+        for run in runs {
+            let (_, length) = run?;
+            pixels += length;
+            if pixels > header.pixel_count() {
+                return Err(Error::Overrun);
+            }
+        }
+        if pixels != header.pixel_count() {
+            return Err(Error::Underrun);
+        }
+
+        Ok(Logo { header, data })
+    }
+
+    /// Gets the runs of the logo, split at row boundaries.
+    pub fn runs(&self) -> Runs<'a> {
+        Runs {
+            raw: RawRuns { data: self.data },
+            width: self.header.width,
+            x: 0,
+            y: 0,
+            color: 0,
+            remaining: 0,
+        }
+    }
+}
+
+/// An iterator over the runs as they are encoded, which may span multiple rows.
+struct RawRuns<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for RawRuns<'a> {
+    type Item = Result<(u8, usize), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&byte, rest) = self.data.split_first()?;
+        let (color, nibble) = (byte >> 4, (byte & 0xF) as usize);
+
+        let (length, rest) = if nibble == MAX_SHORT_RUN {
+            match rest.split_first() {
+                Some((&extra, rest)) => (16 + extra as usize, rest),
+                None => {
+                    self.data = &[];
+                    return Some(Err(Error::Truncated));
+                }
+            }
+        } else {
+            (nibble + 1, rest)
+        };
+
+        self.data = rest;
+        Some(Ok((color, length)))
+    }
+}
+
+/// An iterator over the [`Run`]s of a validated [`Logo`].
+///
+/// [`Run`]: struct.Run.html
+/// [`Logo`]: struct.Logo.html
+pub struct Runs<'a> {
+    raw: RawRuns<'a>,
+    width: u16,
+    x: u16,
+    y: u16,
+    color: u8,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Runs<'a> {
+    type Item = Run;
+
+    fn next(&mut self) -> Option<Run> {
+        if self.remaining == 0 {
+            // The logo was validated, so there are no errors to report here.
+            let (color, length) = self.raw.next()?.ok()?;
+            self.color = color;
+            self.remaining = length;
+        }
+
+        let length = self.remaining.min((self.width - self.x) as usize) as u16;
+        let run = Run {
+            x: self.x,
+            y: self.y,
+            length,
+            color: self.color,
+        };
+
+        self.remaining -= length as usize;
+        self.x += length;
+        if self.x == self.width {
+            self.x = 0;
+            self.y += 1;
+        }
+
+        Some(run)
+    }
+}
+
+/// Encodes a logo of `width`x`height` palette indices into `out`.
+///
+/// Returns the size of the encoded logo on success.
+pub fn encode(width: u16, height: u16, pixels: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    if out.len() < HEADER_SIZE {
+        return Err(Error::Truncated);
+    }
+    if pixels.len() != width as usize * height as usize {
+        return Err(Error::Underrun);
+    }
+    if pixels.iter().any(|&pixel| pixel as usize >= PALETTE.len()) {
+        return Err(Error::InvalidColor);
+    }
+
+    let mut size = HEADER_SIZE;
+    let mut rest = pixels;
+    while let Some(&color) = rest.first() {
+        let length = rest
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&pixel| pixel == color)
+            .count();
+        rest = &rest[length..];
+
+        // Emit the run as a short or a long run.
+        let (encoded, count) = if length <= MAX_SHORT_RUN {
+            ([color << 4 | (length - 1) as u8, 0], 1)
+        } else {
+            ([color << 4 | MAX_SHORT_RUN as u8, (length - 16) as u8], 2)
+        };
+        out.get_mut(size..size + count)
+            .ok_or(Error::Truncated)?
+            .copy_from_slice(&encoded[..count]);
+        size += count;
+    }
+
+    let data_size = size - HEADER_SIZE;
+    if data_size > u16::MAX as usize {
+        return Err(Error::Truncated);
+    }
+
+    Header {
+        width,
+        height,
+        data_size: data_size as u16,
+    }
+    .encode(out)?;
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A xorshift generator, so that the fuzz tests are reproducible.
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            self.next() as usize % bound
+        }
+    }
+
+    /// Draws `logo` into a `width`x`height` image, checking that every pixel is
+    /// covered exactly once.
+    fn draw(logo: &Logo<'_>, image: &mut [u8]) {
+        let mut drawn = [false; 1024];
+        let width = logo.header.width as usize;
+
+        for run in logo.runs() {
+            assert!(run.length > 0);
+            assert!(run.x as usize + run.length as usize <= width);
+            assert!((run.y as usize) < logo.header.height as usize);
+
+            let start = run.y as usize * width + run.x as usize;
+            for offset in start..start + run.length as usize {
+                assert!(!drawn[offset], "pixel {} drawn twice", offset);
+                drawn[offset] = true;
+                image[offset] = run.color;
+            }
+        }
+
+        assert!(drawn[..logo.header.pixel_count()]
+            .iter()
+            .all(|&drawn| drawn));
+    }
+
+    fn round_trip(width: u16, height: u16, pixels: &[u8]) {
+        let mut encoded = [0; 2048];
+        let size = encode(width, height, pixels, &mut encoded).unwrap();
+        let logo = Logo::decode(&encoded[..size]).unwrap();
+        assert_eq!(logo.header.width, width);
+        assert_eq!(logo.header.height, height);
+        assert_eq!(logo.header.data_size as usize, size - HEADER_SIZE);
+
+        let mut image = [0xFF; 1024];
+        draw(&logo, &mut image);
+        assert_eq!(&image[..pixels.len()], pixels);
+    }
+
+    #[test]
+    fn encodes_short_and_long_runs() {
+        let mut pixels = [0; 20];
+        pixels[16..].copy_from_slice(&[3, 3, 7, 7]);
+
+        let mut encoded = [0; 32];
+        let size = encode(5, 4, &pixels, &mut encoded).unwrap();
+        assert_eq!(
+            encoded[..size],
+            [b'M', b'L', b'G', b'O', 1, 0, 5, 0, 4, 0, 4, 0, 0x0F, 0x00, 0x31, 0x71]
+        );
+    }
+
+    #[test]
+    fn splits_runs_at_maximum_length() {
+        let pixels = [9; MAX_RUN + 1];
+        let mut encoded = [0; 32];
+        let size = encode(MAX_RUN as u16 + 1, 1, &pixels, &mut encoded).unwrap();
+        assert_eq!(encoded[HEADER_SIZE..size], [0x9F, 0xFF, 0x90]);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(0, 0, &[]);
+        round_trip(1, 1, &[15]);
+        round_trip(3, 2, &[1, 1, 1, 1, 2, 2]);
+        round_trip(32, 32, &[4; 1024]);
+
+        let mut rng = Rng(0x1234_5678);
+        for _ in 0..200 {
+            let width = 1 + rng.below(32) as u16;
+            let height = 1 + rng.below(1024 / width as usize) as u16;
+
+            // Mostly long runs, with some noise.
+            let mut pixels = [0; 1024];
+            let mut color = 0;
+            for pixel in pixels.iter_mut() {
+                if rng.below(20) == 0 {
+                    color = rng.below(PALETTE.len()) as u8;
+                }
+                *pixel = color;
+            }
+
+            round_trip(width, height, &pixels[..width as usize * height as usize]);
+        }
+    }
+
+    #[test]
+    fn encode_rejects_bad_input() {
+        let mut out = [0; 64];
+        assert_eq!(encode(2, 2, &[0; 3], &mut out), Err(Error::Underrun));
+        assert_eq!(
+            encode(2, 2, &[0, 1, 2, 16], &mut out),
+            Err(Error::InvalidColor)
+        );
+        assert_eq!(
+            encode(0, 0, &[], &mut out[..HEADER_SIZE - 1]),
+            Err(Error::Truncated)
+        );
+        assert_eq!(
+            encode(4, 1, &[0, 1, 2, 3], &mut out[..HEADER_SIZE + 3]),
+            Err(Error::Truncated)
+        );
+    }
+
+    fn logo(width: u16, height: u16, data: &[u8], out: &mut [u8]) -> usize {
+        Header {
+            width,
+            height,
+            data_size: data.len() as u16,
+        }
+        .encode(out)
+        .unwrap();
+        out[HEADER_SIZE..HEADER_SIZE + data.len()].copy_from_slice(data);
+        HEADER_SIZE + data.len()
+    }
+
+    #[test]
+    fn decode_rejects_malformed_logos() {
+        let mut bytes = [0; 32];
+
+        let size = logo(4, 1, &[0x03], &mut bytes);
+        assert!(Logo::decode(&bytes[..size]).is_ok());
+
+        let mut bad = bytes;
+        bad[0] = b'X';
+        assert_eq!(Logo::decode(&bad[..size]).err(), Some(Error::BadMagic));
+
+        let mut bad = bytes;
+        bad[4] = 2;
+        assert_eq!(
+            Logo::decode(&bad[..size]).err(),
+            Some(Error::UnsupportedVersion)
+        );
+
+        let size = logo(4, 1, &[0x04], &mut bytes);
+        assert_eq!(Logo::decode(&bytes[..size]).err(), Some(Error::Overrun));
+
+        let size = logo(4, 1, &[0x02], &mut bytes);
+        assert_eq!(Logo::decode(&bytes[..size]).err(), Some(Error::Underrun));
+
+        // A long run missing its length byte.
+        let size = logo(16, 1, &[0x0F], &mut bytes);
+        assert_eq!(Logo::decode(&bytes[..size]).err(), Some(Error::Truncated));
+    }
+
+    #[test]
+    fn decode_rejects_every_truncation() {
+        let mut encoded = [0; 64];
+        let size = encode(
+            8,
+            2,
+            &[1, 1, 2, 2, 2, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4],
+            &mut encoded,
+        )
+        .unwrap();
+
+        for len in 0..size {
+            assert_eq!(
+                Logo::decode(&encoded[..len]).err(),
+                Some(Error::Truncated),
+                "{} bytes",
+                len
+            );
+        }
+        assert!(Logo::decode(&encoded[..size]).is_ok());
+    }
+
+    #[test]
+    fn decode_survives_random_data() {
+        let mut rng = Rng(0xDEAD_BEEF);
+        for _ in 0..10_000 {
+            let mut bytes = [0; 64];
+            let width = rng.below(16) as u16;
+            let height = rng.below(64 / (width as usize).max(1)) as u16;
+            let data_size = rng.below(bytes.len() - HEADER_SIZE + 8);
+            Header {
+                width,
+                height,
+                data_size: data_size as u16,
+            }
+            .encode(&mut bytes)
+            .unwrap();
+            for byte in bytes[HEADER_SIZE..].iter_mut() {
+                *byte = rng.next() as u8;
+            }
+
+            if let Ok(logo) = Logo::decode(&bytes) {
+                draw(&logo, &mut [0; 1024]);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_survives_mutated_logos() {
+        let mut pixels = [0; 96];
+        for (index, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = (index / 7 % 16) as u8;
+        }
+        let mut encoded = [0; 128];
+        let size = encode(12, 8, &pixels, &mut encoded).unwrap();
+
+        let mut rng = Rng(0x0BAD_CAFE);
+        for _ in 0..10_000 {
+            let mut bytes = encoded;
+            for _ in 0..1 + rng.below(4) {
+                bytes[rng.below(size)] ^= 1 << rng.below(8);
+            }
+
+            if let Ok(logo) = Logo::decode(&bytes[..size]) {
+                draw(&logo, &mut [0; 1024]);
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Serialization of the signed boot manifest shared between the bootloader and the
+//! host-side tooling.
+//!
+//! A manifest describes the payloads that the first-stage bootloader loads and is laid
+//! out as follows, with all integers encoded in little-endian byte order:
+//!
+//! | Offset | Size                  | Description                          |
+//! |--------|-----------------------|--------------------------------------|
+//! | 0x00   | 0x10                  | [`Header`]                           |
+//! | 0x10   | 0x30 * `entry_count`  | [`Entry`] table                      |
+//! | ...    | `signature_size`      | Signature over all preceding bytes   |
+//!
+//! [`Header`]: struct.Header.html
+//! [`Entry`]: struct.Entry.html
+
+#![no_std]
+
+/// The magic value that identifies a manifest.
+pub const MAGIC: [u8; 4] = *b"MMFT";
+
+/// The revision of the manifest format described by this crate.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// The maximum number of payload entries a manifest can hold.
+pub const MAX_ENTRIES: usize = 4;
+
+/// The size of an encoded [`Header`] in bytes.
+///
+/// [`Header`]: struct.Header.html
+pub const HEADER_SIZE: usize = 0x10;
+
+/// The size of an encoded [`Entry`] in bytes.
+///
+/// [`Entry`]: struct.Entry.html
+pub const ENTRY_SIZE: usize = 0x30;
+
+/// The size of a SHA-256 payload hash in bytes.
+pub const HASH_SIZE: usize = 32;
+
+/// The largest signature a manifest may carry, sized for RSA-2048.
+pub const MAX_SIGNATURE_SIZE: usize = 0x100;
+
+/// The largest possible size of an encoded manifest in bytes.
+pub const MAX_MANIFEST_SIZE: usize = HEADER_SIZE + MAX_ENTRIES * ENTRY_SIZE + MAX_SIGNATURE_SIZE;
+
+/// Marks the payload that execution is passed to after loading.
+pub const FLAG_BOOT: u32 = 1 << 0;
+
+/// Marks a payload that is handed over to the TSEC for decryption and verification.
+pub const FLAG_TSEC: u32 = 1 << 1;
+
+/// Errors that may occur while encoding or decoding a manifest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The buffer is too small to hold the encoded data.
+    Truncated,
+    /// The magic value does not identify a manifest.
+    BadMagic,
+    /// The manifest was produced for an unsupported format revision.
+    UnsupportedVersion,
+    /// The manifest declares more entries than supported.
+    TooManyEntries,
+    /// The manifest declares a signature larger than supported.
+    SignatureTooLarge,
+}
+
+/// Packs a semantic version into the 32-bit representation used by manifests.
+pub const fn version(major: u8, minor: u8, patch: u8) -> u32 {
+    (major as u32) << 16 | (minor as u32) << 8 | patch as u32
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn write_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// The fixed-size header at the start of every manifest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Header {
+    /// The number of valid entries in the entry table.
+    pub entry_count: u16,
+    /// The security version of the manifest, used for rollback protection.
+    pub security_version: u32,
+    /// The size of the signature following the entry table in bytes.
+    pub signature_size: u16,
+}
+
+impl Header {
+    /// Decodes a header from the start of `bytes`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(Error::Truncated);
+        }
+        if bytes[0x0..0x4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        if read_u16(bytes, 0x4) != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        let header = Header {
+            entry_count: read_u16(bytes, 0x6),
+            security_version: read_u32(bytes, 0x8),
+            signature_size: read_u16(bytes, 0xC),
+        };
+
+        if header.entry_count as usize > MAX_ENTRIES {
+            return Err(Error::TooManyEntries);
+        }
+        if header.signature_size as usize > MAX_SIGNATURE_SIZE {
+            return Err(Error::SignatureTooLarge);
+        }
+
+        Ok(header)
+    }
+
+    /// Encodes the header into the start of `bytes`.
+    pub fn encode(&self, bytes: &mut [u8]) -> Result<(), Error> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(Error::Truncated);
+        }
+
+        bytes[0x0..0x4].copy_from_slice(&MAGIC);
+        write_u16(bytes, 0x4, FORMAT_VERSION);
+        write_u16(bytes, 0x6, self.entry_count);
+        write_u32(bytes, 0x8, self.security_version);
+        write_u16(bytes, 0xC, self.signature_size);
+        write_u16(bytes, 0xE, 0);
+
+        Ok(())
+    }
+}
+
+/// Describes a single payload to be loaded by the bootloader.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// The address the payload is loaded to.
+    pub load_address: u32,
+    /// The size of the payload in bytes.
+    pub size: u32,
+    /// A combination of the `FLAG_*` constants.
+    pub flags: u32,
+    /// The minimum bootloader version required to load the payload.
+    pub min_version: u32,
+    /// The SHA-256 hash of the payload.
+    pub hash: [u8; HASH_SIZE],
+}
+
+impl Entry {
+    /// Decodes an entry from the start of `bytes`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < ENTRY_SIZE {
+            return Err(Error::Truncated);
+        }
+
+        let mut hash = [0; HASH_SIZE];
+        hash.copy_from_slice(&bytes[0x10..0x30]);
+
+        Ok(Entry {
+            load_address: read_u32(bytes, 0x0),
+            size: read_u32(bytes, 0x4),
+            flags: read_u32(bytes, 0x8),
+            min_version: read_u32(bytes, 0xC),
+            hash,
+        })
+    }
+
+    /// Encodes the entry into the start of `bytes`.
+    pub fn encode(&self, bytes: &mut [u8]) -> Result<(), Error> {
+        if bytes.len() < ENTRY_SIZE {
+            return Err(Error::Truncated);
+        }
+
+        write_u32(bytes, 0x0, self.load_address);
+        write_u32(bytes, 0x4, self.size);
+        write_u32(bytes, 0x8, self.flags);
+        write_u32(bytes, 0xC, self.min_version);
+        bytes[0x10..0x30].copy_from_slice(&self.hash);
+
+        Ok(())
+    }
+}
+
+/// A complete manifest along with its entry table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The manifest header.
+    pub header: Header,
+    /// The entry table, of which the first `header.entry_count` entries are valid.
+    pub entries: [Entry; MAX_ENTRIES],
+}
+
+impl Manifest {
+    /// Gets the valid entries of the manifest.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries[..self.header.entry_count as usize]
+    }
+
+    /// Gets the size of the signed portion of the manifest in bytes.
+    pub fn signed_size(&self) -> usize {
+        HEADER_SIZE + self.header.entry_count as usize * ENTRY_SIZE
+    }
+
+    /// Decodes a manifest from `bytes`.
+    ///
+    /// On success, the signed portion of the manifest and its signature are returned
+    /// alongside the decoded manifest. The signature is *not* checked here.
+    pub fn decode(bytes: &[u8]) -> Result<(Self, &[u8], &[u8]), Error> {
+        let header = Header::decode(bytes)?;
+
+        let mut manifest = Manifest {
+            header,
+            entries: [Entry::default(); MAX_ENTRIES],
+        };
+
+        let signed_size = manifest.signed_size();
+        let total_size = signed_size + header.signature_size as usize;
+        if bytes.len() < total_size {
+            return Err(Error::Truncated);
+        }
+
+        for (index, entry) in manifest.entries.iter_mut().enumerate() {
+            if index == header.entry_count as usize {
+                break;
+            }
+            *entry = Entry::decode(&bytes[HEADER_SIZE + index * ENTRY_SIZE..])?;
+        }
+
+        let (signed, rest) = bytes[..total_size].split_at(signed_size);
+        Ok((manifest, signed, rest))
+    }
+
+    /// Encodes the signed portion of the manifest into `bytes`.
+    ///
+    /// Returns the number of bytes written, after which the signature is expected to
+    /// be appended.
+    pub fn encode(&self, bytes: &mut [u8]) -> Result<usize, Error> {
+        if self.header.entry_count as usize > MAX_ENTRIES {
+            return Err(Error::TooManyEntries);
+        }
+        if bytes.len() < self.signed_size() {
+            return Err(Error::Truncated);
+        }
+
+        self.header.encode(bytes)?;
+        for (index, entry) in self.entries().iter().enumerate() {
+            entry.encode(&mut bytes[HEADER_SIZE + index * ENTRY_SIZE..])?;
+        }
+
+        Ok(self.signed_size())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNATURE_SIZE: u16 = 0x40;
+
+    fn manifest() -> Manifest {
+        let mut manifest = Manifest {
+            header: Header {
+                entry_count: 2,
+                security_version: 3,
+                signature_size: SIGNATURE_SIZE,
+            },
+            ..Default::default()
+        };
+        manifest.entries[0] = Entry {
+            load_address: 0x4001_6FE0,
+            size: 0x2_8810,
+            flags: FLAG_BOOT | FLAG_TSEC,
+            min_version: version(0, 1, 0),
+            hash: [0xA5; HASH_SIZE],
+        };
+        manifest.entries[1] = Entry {
+            load_address: 0x8000_0000,
+            size: 0x1000,
+            flags: 0,
+            min_version: version(1, 2, 3),
+            hash: [0x5A; HASH_SIZE],
+        };
+        manifest
+    }
+
+    /// Encodes `manifest` followed by a signature of `0xEE` bytes.
+    fn encode(manifest: &Manifest, bytes: &mut [u8; MAX_MANIFEST_SIZE]) -> usize {
+        let signed_size = manifest.encode(bytes).unwrap();
+        let total_size = signed_size + manifest.header.signature_size as usize;
+        for byte in bytes[signed_size..total_size].iter_mut() {
+            *byte = 0xEE;
+        }
+        total_size
+    }
+
+    #[test]
+    fn version_packing() {
+        assert_eq!(version(1, 2, 3), 0x0001_0203);
+        assert_eq!(version(255, 0, 255), 0x00FF_00FF);
+    }
+
+    #[test]
+    fn round_trip() {
+        let manifest = manifest();
+        let mut bytes = [0; MAX_MANIFEST_SIZE];
+        let total_size = encode(&manifest, &mut bytes);
+
+        let (decoded, signed, signature) = Manifest::decode(&bytes[..total_size]).unwrap();
+        assert_eq!(decoded, manifest);
+        assert_eq!(decoded.entries(), &manifest.entries[..2]);
+        assert_eq!(signed, &bytes[..HEADER_SIZE + 2 * ENTRY_SIZE]);
+        assert_eq!(signature, &[0xEE; SIGNATURE_SIZE as usize][..]);
+    }
+
+    #[test]
+    fn layout() {
+        let mut bytes = [0; MAX_MANIFEST_SIZE];
+        encode(&manifest(), &mut bytes);
+
+        assert_eq!(
+            bytes[..HEADER_SIZE],
+            [
+                b'M', b'M', b'F', b'T', 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x00, 0x00, 0x40, 0x00,
+                0x00, 0x00,
+            ]
+        );
+        assert_eq!(
+            bytes[HEADER_SIZE..HEADER_SIZE + 0x10],
+            [
+                0xE0, 0x6F, 0x01, 0x40, 0x10, 0x88, 0x02, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01,
+                0x00, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let manifest = manifest();
+        let mut bytes = [0; MAX_MANIFEST_SIZE];
+        let total_size = encode(&manifest, &mut bytes);
+
+        // Anything after the signature, such as padding, is ignored.
+        let (decoded, _, signature) = Manifest::decode(&bytes).unwrap();
+        assert_eq!(decoded, manifest);
+        assert_eq!(signature.len(), total_size - manifest.signed_size());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut bytes = [0; MAX_MANIFEST_SIZE];
+        let total_size = encode(&manifest(), &mut bytes);
+
+        for length in 0..total_size {
+            assert_eq!(
+                Manifest::decode(&bytes[..length]),
+                Err(Error::Truncated),
+                "length {}",
+                length
+            );
+        }
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = [0; MAX_MANIFEST_SIZE];
+        encode(&manifest(), &mut bytes);
+
+        for index in 0..MAGIC.len() {
+            let mut corrupted = bytes;
+            corrupted[index] ^= 0x20;
+            assert_eq!(Manifest::decode(&corrupted), Err(Error::BadMagic));
+        }
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut bytes = [0; MAX_MANIFEST_SIZE];
+        encode(&manifest(), &mut bytes);
+        bytes[0x4] = 2;
+
+        assert_eq!(Manifest::decode(&bytes), Err(Error::UnsupportedVersion));
+    }
+
+    #[test]
+    fn limits() {
+        let mut bytes = [0; MAX_MANIFEST_SIZE];
+        encode(&manifest(), &mut bytes);
+
+        let mut entries = bytes;
+        entries[0x6] = MAX_ENTRIES as u8 + 1;
+        assert_eq!(Manifest::decode(&entries), Err(Error::TooManyEntries));
+
+        let mut signature = bytes;
+        signature[0xC..0xE].copy_from_slice(&(MAX_SIGNATURE_SIZE as u16 + 1).to_le_bytes());
+        assert_eq!(Manifest::decode(&signature), Err(Error::SignatureTooLarge));
+    }
+
+    #[test]
+    fn encode_errors() {
+        let mut manifest = manifest();
+        let mut bytes = [0; MAX_MANIFEST_SIZE];
+
+        assert_eq!(
+            manifest.encode(&mut bytes[..manifest.signed_size() - 1]),
+            Err(Error::Truncated)
+        );
+
+        manifest.header.entry_count = MAX_ENTRIES as u16 + 1;
+        assert_eq!(manifest.encode(&mut bytes), Err(Error::TooManyEntries));
+    }
+}
@@ -0,0 +1,502 @@
+//! A/B slot selection for the second-stage bootloader, shared between the first and
+//! the second stage.
+//!
+//! Two copies of the second stage are kept on storage, each with a priority, a number
+//! of remaining boot attempts and a flag whether it has booted successfully before.
+//! This crate implements the selection state machine and the metadata encoding; it
+//! does not access any hardware. The metadata is laid out as follows:
+//!
+//! | Offset | Size | Description                                        |
+//! |--------|------|----------------------------------------------------|
+//! | 0x0    | 0x4  | Magic, `MSLT`                                      |
+//! | 0x4    | 0x1  | Format version                                     |
+//! | 0x5    | 0x3  | Slot A: priority, tries remaining, successful      |
+//! | 0x8    | 0x3  | Slot B: priority, tries remaining, successful      |
+//! | 0xB    | 0x1  | Slot of the current attempt: 0 none, 1 A, 2 B      |
+//! | 0xC    | 0x4  | CRC-32 over all preceding bytes, little-endian     |
+//!
+//! # Contract with the second stage
+//!
+//! The first stage consumes one boot attempt of a slot that has not booted
+//! successfully before and records it as the current attempt. Once the second stage
+//! is up and considers itself healthy, it is expected to read the metadata, call
+//! [`Metadata::mark_successful`] for the slot of the [`Metadata::current`] attempt and
+//! write the metadata back. Until it does so, every boot consumes another attempt, and
+//! a slot that never gets there eventually falls back to the other slot. Only a slot
+//! that was marked successful advances the rollback counter in the fuses.
+//!
+//! [`Metadata::mark_successful`]: struct.Metadata.html#method.mark_successful
+//! [`Metadata::current`]: struct.Metadata.html#method.current
+
+#![no_std]
+
+/// The size of the encoded slot metadata in bytes.
+pub const METADATA_SIZE: usize = 16;
+
+/// The magic value that identifies slot metadata.
+const MAGIC: [u8; 4] = *b"MSLT";
+
+/// The revision of the metadata format.
+const VERSION: u8 = 1;
+
+/// The highest priority a slot can have.
+pub const MAX_PRIORITY: u8 = 15;
+
+/// The number of boot attempts a slot gets before it is considered broken.
+pub const MAX_TRIES: u8 = 7;
+
+/// One of the two boot slots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    /// Gets the index of the slot in the metadata.
+    pub fn index(self) -> usize {
+        match self {
+            Slot::A => 0,
+            Slot::B => 1,
+        }
+    }
+
+    /// Decodes the slot of the current attempt.
+    fn decode_current(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(Slot::A),
+            2 => Some(Slot::B),
+            _ => None,
+        }
+    }
+
+    /// Encodes the slot of the current attempt.
+    fn encode_current(slot: Option<Self>) -> u8 {
+        match slot {
+            None => 0,
+            Some(Slot::A) => 1,
+            Some(Slot::B) => 2,
+        }
+    }
+}
+
+/// The boot state of a single slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotInfo {
+    /// The priority of the slot, with 0 meaning the slot is unbootable.
+    pub priority: u8,
+    /// The number of boot attempts left until the slot is considered broken.
+    pub tries_remaining: u8,
+    /// Whether the slot has booted successfully before.
+    pub successful: bool,
+}
+
+impl SlotInfo {
+    /// Checks whether the slot may be booted.
+    pub fn is_bootable(&self) -> bool {
+        self.priority > 0 && (self.successful || self.tries_remaining > 0)
+    }
+}
+
+/// The persistent boot state of both slots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    slots: [SlotInfo; 2],
+    current: Option<Slot>,
+}
+
+impl Default for Metadata {
+    /// Creates metadata for a freshly provisioned device, which prefers slot A.
+    fn default() -> Self {
+        let slot = |priority| SlotInfo {
+            priority,
+            tries_remaining: MAX_TRIES,
+            successful: false,
+        };
+
+        Metadata {
+            slots: [slot(MAX_PRIORITY), slot(MAX_PRIORITY - 1)],
+            current: None,
+        }
+    }
+}
+
+/// Computes the CRC-32 (IEEE 802.3) checksum of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+impl Metadata {
+    /// Decodes slot metadata from `bytes`.
+    ///
+    /// Returns `None` if the metadata is missing or corrupted.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < METADATA_SIZE || bytes[..4] != MAGIC || bytes[4] != VERSION {
+            return None;
+        }
+
+        let crc = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+        if crc32(&bytes[..12]) != crc {
+            return None;
+        }
+
+        let slot = |offset: usize| SlotInfo {
+            priority: bytes[offset].min(MAX_PRIORITY),
+            tries_remaining: bytes[offset + 1].min(MAX_TRIES),
+            successful: bytes[offset + 2] != 0,
+        };
+
+        Some(Metadata {
+            slots: [slot(5), slot(8)],
+            current: Slot::decode_current(bytes[11]),
+        })
+    }
+
+    /// Encodes the slot metadata.
+    pub fn encode(&self) -> [u8; METADATA_SIZE] {
+        let mut bytes = [0; METADATA_SIZE];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4] = VERSION;
+
+        for (info, offset) in self.slots.iter().zip([5, 8].iter()) {
+            bytes[*offset] = info.priority;
+            bytes[*offset + 1] = info.tries_remaining;
+            bytes[*offset + 2] = info.successful as u8;
+        }
+        bytes[11] = Slot::encode_current(self.current);
+
+        let crc = crc32(&bytes[..12]);
+        bytes[12..].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Gets the boot state of `slot`.
+    pub fn slot(&self, slot: Slot) -> &SlotInfo {
+        &self.slots[slot.index()]
+    }
+
+    /// Gets the slot of the current boot attempt, if any.
+    ///
+    /// This is the slot the second stage was loaded from, which it marks successful.
+    pub fn current(&self) -> Option<Slot> {
+        self.current
+    }
+
+    /// Selects the bootable slot with the highest priority, preferring slot A on ties.
+    pub fn select(&self) -> Option<Slot> {
+        let a = self.slot(Slot::A);
+        let b = self.slot(Slot::B);
+
+        match (a.is_bootable(), b.is_bootable()) {
+            (true, true) if b.priority > a.priority => Some(Slot::B),
+            (true, _) => Some(Slot::A),
+            (false, true) => Some(Slot::B),
+            (false, false) => None,
+        }
+    }
+
+    /// Selects the slot to boot, consumes one of its boot attempts and records it as
+    /// the current attempt.
+    ///
+    /// The metadata must be persisted before attempting to boot the returned slot, so
+    /// that a slot that hangs or crashes eventually runs out of attempts.
+    pub fn begin_attempt(&mut self) -> Option<Slot> {
+        self.current = self.select();
+        let slot = self.current?;
+
+        let info = &mut self.slots[slot.index()];
+        if !info.successful {
+            info.tries_remaining -= 1;
+        }
+
+        Some(slot)
+    }
+
+    /// Marks `slot` as having booted successfully, which stops it from consuming boot
+    /// attempts and allows it to advance the rollback counter.
+    ///
+    /// This is called by the second stage once it is healthy. Slots that were marked
+    /// unbootable in the meantime are left alone.
+    pub fn mark_successful(&mut self, slot: Slot) {
+        let info = &mut self.slots[slot.index()];
+        if info.priority > 0 {
+            info.tries_remaining = MAX_TRIES;
+            info.successful = true;
+        }
+    }
+
+    /// Makes `slot` the preferred slot, e.g. after a new second stage was written to it.
+    ///
+    /// The slot gets the full number of boot attempts. The other slot keeps its state,
+    /// but drops below it in priority.
+    pub fn activate(&mut self, slot: Slot) {
+        let other = &mut self.slots[1 - slot.index()];
+        other.priority = other.priority.min(MAX_PRIORITY - 1);
+
+        self.slots[slot.index()] = SlotInfo {
+            priority: MAX_PRIORITY,
+            tries_remaining: MAX_TRIES,
+            successful: false,
+        };
+    }
+
+    /// Marks `slot` as unbootable, e.g. after it failed verification.
+    pub fn mark_unbootable(&mut self, slot: Slot) {
+        self.slots[slot.index()] = SlotInfo {
+            priority: 0,
+            tries_remaining: 0,
+            successful: false,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets every possible state of a single slot.
+    fn all_infos() -> impl Iterator<Item = SlotInfo> + Clone {
+        (0..=MAX_PRIORITY).flat_map(|priority| {
+            (0..=MAX_TRIES).flat_map(move |tries_remaining| {
+                [false, true].iter().map(move |&successful| SlotInfo {
+                    priority,
+                    tries_remaining,
+                    successful,
+                })
+            })
+        })
+    }
+
+    /// Gets every possible metadata state.
+    fn all_metadata() -> impl Iterator<Item = Metadata> {
+        all_infos().flat_map(|a| {
+            all_infos().flat_map(move |b| {
+                [None, Some(Slot::A), Some(Slot::B)]
+                    .iter()
+                    .map(move |&current| Metadata {
+                        slots: [a, b],
+                        current,
+                    })
+            })
+        })
+    }
+
+    fn other(slot: Slot) -> Slot {
+        match slot {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+
+    #[test]
+    fn select_prefers_bootable_slot_with_highest_priority() {
+        for metadata in all_metadata() {
+            match metadata.select() {
+                Some(slot) => {
+                    let selected = metadata.slot(slot);
+                    let other = metadata.slot(other(slot));
+                    assert!(selected.is_bootable(), "{:?}", metadata);
+                    if other.is_bootable() {
+                        assert!(other.priority <= selected.priority, "{:?}", metadata);
+                        assert!(
+                            other.priority < selected.priority || slot == Slot::A,
+                            "{:?}",
+                            metadata
+                        );
+                    }
+                }
+                None => {
+                    assert!(!metadata.slot(Slot::A).is_bootable(), "{:?}", metadata);
+                    assert!(!metadata.slot(Slot::B).is_bootable(), "{:?}", metadata);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn begin_attempt_consumes_one_try_of_unproven_slots() {
+        for before in all_metadata() {
+            let mut after = before;
+            let slot = after.begin_attempt();
+
+            assert_eq!(slot, before.select(), "{:?}", before);
+            assert_eq!(after.current(), slot, "{:?}", before);
+
+            let slot = match slot {
+                Some(slot) => slot,
+                None => {
+                    assert_eq!(after.slots, before.slots);
+                    continue;
+                }
+            };
+
+            let (old, new) = (before.slot(slot), after.slot(slot));
+            assert_eq!(new.priority, old.priority);
+            assert_eq!(new.successful, old.successful);
+            if old.successful {
+                assert_eq!(new.tries_remaining, old.tries_remaining, "{:?}", before);
+            } else {
+                assert_eq!(new.tries_remaining, old.tries_remaining - 1, "{:?}", before);
+            }
+            assert_eq!(after.slot(other(slot)), before.slot(other(slot)));
+        }
+    }
+
+    #[test]
+    fn unproven_slot_falls_back_after_running_out_of_tries() {
+        let mut metadata = Metadata::default();
+
+        for _ in 0..MAX_TRIES {
+            assert_eq!(metadata.begin_attempt(), Some(Slot::A));
+        }
+        assert_eq!(metadata.begin_attempt(), Some(Slot::B));
+        assert_eq!(metadata.current(), Some(Slot::B));
+    }
+
+    #[test]
+    fn successful_slot_keeps_booting() {
+        let mut metadata = Metadata::default();
+        assert_eq!(metadata.begin_attempt(), Some(Slot::A));
+        metadata.mark_successful(metadata.current().unwrap());
+
+        for _ in 0..2 * MAX_TRIES {
+            assert_eq!(metadata.begin_attempt(), Some(Slot::A));
+        }
+        assert_eq!(
+            *metadata.slot(Slot::A),
+            SlotInfo {
+                priority: MAX_PRIORITY,
+                tries_remaining: MAX_TRIES,
+                successful: true,
+            }
+        );
+    }
+
+    #[test]
+    fn mark_successful_leaves_unbootable_slots_alone() {
+        let mut metadata = Metadata::default();
+        metadata.mark_unbootable(Slot::A);
+        metadata.mark_successful(Slot::A);
+
+        assert!(!metadata.slot(Slot::A).is_bootable());
+        assert_eq!(metadata.select(), Some(Slot::B));
+    }
+
+    #[test]
+    fn activate_prefers_new_slot_until_it_fails() {
+        let mut metadata = Metadata::default();
+        metadata.mark_successful(Slot::A);
+        metadata.activate(Slot::B);
+
+        assert_eq!(metadata.begin_attempt(), Some(Slot::B));
+        assert!(!metadata.slot(Slot::B).successful);
+        assert!(metadata.slot(Slot::A).successful);
+
+        metadata.mark_unbootable(Slot::B);
+        assert_eq!(metadata.begin_attempt(), Some(Slot::A));
+    }
+
+    #[test]
+    fn no_bootable_slot() {
+        let mut metadata = Metadata::default();
+        metadata.mark_unbootable(Slot::A);
+        metadata.mark_unbootable(Slot::B);
+
+        assert_eq!(metadata.begin_attempt(), None);
+        assert_eq!(metadata.current(), None);
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        for metadata in all_metadata() {
+            assert_eq!(Metadata::decode(&metadata.encode()), Some(metadata));
+        }
+    }
+
+    #[test]
+    fn encoding_layout() {
+        let mut metadata = Metadata::default();
+        metadata.begin_attempt();
+
+        // The checksum was computed with Python's `zlib.crc32`.
+        let bytes = metadata.encode();
+        assert_eq!(bytes[..5], *b"MSLT\x01");
+        assert_eq!(bytes[5..12], [15, 6, 0, 14, 7, 0, 1]);
+        assert_eq!(bytes[12..], 0xE151_FE4Au32.to_le_bytes());
+    }
+
+    #[test]
+    fn decode_ignores_trailing_bytes() {
+        let metadata = Metadata::default();
+        let mut block = [0xFF; 512];
+        block[..METADATA_SIZE].copy_from_slice(&metadata.encode());
+
+        assert_eq!(Metadata::decode(&block), Some(metadata));
+    }
+
+    #[test]
+    fn decode_rejects_truncated_metadata() {
+        let bytes = Metadata::default().encode();
+        for len in 0..METADATA_SIZE {
+            assert_eq!(Metadata::decode(&bytes[..len]), None, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn decode_rejects_every_single_bit_flip() {
+        let bytes = Metadata::default().encode();
+        for bit in 0..METADATA_SIZE * 8 {
+            let mut corrupted = bytes;
+            corrupted[bit / 8] ^= 1 << (bit % 8);
+            assert_eq!(Metadata::decode(&corrupted), None, "bit {}", bit);
+        }
+    }
+
+    #[test]
+    fn decode_rejects_unknown_version() {
+        let mut bytes = Metadata::default().encode();
+        bytes[4] = VERSION + 1;
+        let crc = crc32(&bytes[..12]);
+        bytes[12..].copy_from_slice(&crc.to_le_bytes());
+
+        assert_eq!(Metadata::decode(&bytes), None);
+    }
+
+    #[test]
+    fn decode_clamps_out_of_range_values() {
+        let mut bytes = [0; METADATA_SIZE];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4] = VERSION;
+        bytes[5..12].copy_from_slice(&[0xFF, 0xFF, 0x02, 0x10, 0x08, 0x00, 0x03]);
+        let crc = crc32(&bytes[..12]);
+        bytes[12..].copy_from_slice(&crc.to_le_bytes());
+
+        let metadata = Metadata::decode(&bytes).unwrap();
+        let clamped = SlotInfo {
+            priority: MAX_PRIORITY,
+            tries_remaining: MAX_TRIES,
+            successful: true,
+        };
+        assert_eq!(*metadata.slot(Slot::A), clamped);
+        assert_eq!(
+            *metadata.slot(Slot::B),
+            SlotInfo {
+                successful: false,
+                ..clamped
+            }
+        );
+        assert_eq!(metadata.current(), None);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,164 @@
+//! Duty cycle computation and brightness ramps for the backlight.
+//!
+//! Perceived brightness is not linear in the PWM duty cycle, so brightness levels
+//! in percent are mapped through a gamma curve of 2.2. The curve is approximated by
+//! linear interpolation between knots at every 10%.
+
+/// The duty cycle value of a fully lit backlight.
+pub const MAX_DUTY: u16 = 256;
+
+/// The interval between two steps of a brightness ramp in milliseconds.
+pub const RAMP_STEP_MS: u32 = 10;
+
+/// `MAX_DUTY * (x / 100)^2.2` for `x` in steps of 10%.
+const GAMMA_KNOTS: [u16; 11] = [0, 2, 7, 18, 34, 56, 83, 117, 157, 203, MAX_DUTY];
+
+/// Computes the duty cycle for a brightness level of `percent`.
+///
+/// Levels above 100% are clamped. Any non-zero level yields a non-zero duty cycle
+/// so that the backlight never turns off by accident.
+pub fn duty_cycle(percent: u8) -> u16 {
+    let percent = percent.min(100) as u16;
+    if percent == 0 {
+        return 0;
+    }
+
+    // Interpolate between the two nearest knots, rounding to the nearest value.
+    let knot = (percent / 10) as usize;
+    let offset = percent % 10;
+    let duty = if offset == 0 {
+        GAMMA_KNOTS[knot]
+    } else {
+        let (low, high) = (GAMMA_KNOTS[knot], GAMMA_KNOTS[knot + 1]);
+        low + ((high - low) * offset + 5) / 10
+    };
+
+    duty.max(1)
+}
+
+/// An iterator over the duty cycles of a smooth brightness transition.
+///
+/// The brightness changes linearly in perceived brightness, with one step every
+/// [`RAMP_STEP_MS`] milliseconds. The last step always reaches the target level.
+///
+/// [`RAMP_STEP_MS`]: constant.RAMP_STEP_MS.html
+pub struct Ramp {
+    from: i32,
+    to: i32,
+    steps: u32,
+    step: u32,
+}
+
+impl Ramp {
+    /// Creates a ramp from `from` to `to` percent, taking `duration_ms` milliseconds.
+    pub fn new(from: u8, to: u8, duration_ms: u32) -> Self {
+        Ramp {
+            from: from.min(100) as i32,
+            to: to.min(100) as i32,
+            steps: (duration_ms / RAMP_STEP_MS).max(1),
+            step: 0,
+        }
+    }
+}
+
+impl Iterator for Ramp {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if self.step == self.steps {
+            return None;
+        }
+
+        self.step += 1;
+        let percent = self.from + (self.to - self.from) * self.step as i32 / self.steps as i32;
+        Some(duty_cycle(percent as u8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn knots_follow_gamma_curve() {
+        for (index, &knot) in GAMMA_KNOTS.iter().enumerate() {
+            let exact = MAX_DUTY as f64 * (index as f64 / 10.0).powf(2.2);
+            assert!((knot as f64 - exact).abs() <= 0.5, "knot {}", index);
+            assert_eq!(duty_cycle(index as u8 * 10), knot);
+        }
+    }
+
+    #[test]
+    fn duty_cycle_endpoints() {
+        assert_eq!(duty_cycle(0), 0);
+        assert_eq!(duty_cycle(1), 1);
+        assert_eq!(duty_cycle(100), MAX_DUTY);
+        assert_eq!(duty_cycle(101), MAX_DUTY);
+        assert_eq!(duty_cycle(u8::MAX), MAX_DUTY);
+    }
+
+    #[test]
+    fn duty_cycle_is_monotonic_and_never_off() {
+        for percent in 1..=100 {
+            assert!(duty_cycle(percent) >= 1);
+            assert!(
+                duty_cycle(percent) >= duty_cycle(percent - 1),
+                "{}%",
+                percent
+            );
+        }
+    }
+
+    #[test]
+    fn duty_cycle_interpolates_between_knots() {
+        // Halfway between 18 and 34.
+        assert_eq!(duty_cycle(35), 26);
+        // 203 + 53 * 0.7 = 240.1
+        assert_eq!(duty_cycle(97), 240);
+    }
+
+    #[test]
+    fn ramp_reaches_target() {
+        let ramp = Ramp::new(0, 100, 500);
+        assert_eq!(ramp.count(), 50);
+
+        let mut ramp = Ramp::new(0, 100, 500);
+        assert_eq!(ramp.next(), Some(duty_cycle(2)));
+        assert_eq!(ramp.last(), Some(MAX_DUTY));
+    }
+
+    #[test]
+    fn ramp_is_monotonic() {
+        let up = Ramp::new(10, 90, 300);
+        assert!(
+            up.fold((true, 0), |(ok, last), duty| (ok && duty >= last, duty))
+                .0
+        );
+
+        let down = Ramp::new(90, 10, 300);
+        let (ok, last) = down.fold((true, MAX_DUTY), |(ok, last), duty| {
+            (ok && duty <= last, duty)
+        });
+        assert!(ok);
+        assert_eq!(last, duty_cycle(10));
+    }
+
+    #[test]
+    fn ramp_without_duration_jumps_to_target() {
+        let mut ramp = Ramp::new(100, 0, 0);
+        assert_eq!(ramp.next(), Some(0));
+        assert_eq!(ramp.next(), None);
+
+        let mut ramp = Ramp::new(20, 60, RAMP_STEP_MS - 1);
+        assert_eq!(ramp.next(), Some(duty_cycle(60)));
+        assert_eq!(ramp.next(), None);
+    }
+
+    #[test]
+    fn ramp_clamps_levels() {
+        assert_eq!(Ramp::new(150, 200, 100).last(), Some(MAX_DUTY));
+        assert!(Ramp::new(200, 100, 100).all(|duty| duty == MAX_DUTY));
+    }
+}
@@ -0,0 +1,115 @@
+//! Brightness control for the LCD backlight.
+//!
+//! The backlight is enabled through GPIO V1 and its brightness is driven by channel 0
+//! of the PWM controller on pin V0.
+
+pub mod curve;
+
+use core::ptr;
+
+use libtegra::gpio;
+use libtegra::pinmux::{PinFunction, PinGrP, PinTristate};
+use libtegra::timer::usleep;
+
+use self::curve::{Ramp, RAMP_STEP_MS};
+
+/// The base address of the PWM controller.
+const PWM_BASE: usize = 0x7000_A000;
+
+/// The base address of the Clock and Reset Controller.
+const CAR_BASE: usize = 0x6000_6000;
+
+const CLK_RST_CONTROLLER_RST_DEVICES_L: usize = 0x4;
+const CLK_RST_CONTROLLER_CLK_OUT_ENB_L: usize = 0x10;
+const CLK_RST_CONTROLLER_CLK_SOURCE_PWM: usize = 0x110;
+const PWM_DEVICE_BIT: u32 = 1 << 17;
+
+/// CLK_M (19.2MHz) divided by 3 as the PWM clock source.
+const PWM_CLOCK_SOURCE: u32 = 6 << 29 | 4;
+
+const PWM_CONTROLLER_PWM_CSR_0: usize = 0x0;
+const PWM_CSR_ENABLE: u32 = 1 << 31;
+const PWM_CSR_WIDTH_SHIFT: u32 = 16;
+
+fn write_csr(value: u32) {
+    unsafe { ptr::write_volatile((PWM_BASE + PWM_CONTROLLER_PWM_CSR_0) as *mut u32, value) }
+}
+
+fn modify_car_reg(offset: usize, clear: u32, set: u32) {
+    let reg = (CAR_BASE + offset) as *mut u32;
+    unsafe { ptr::write_volatile(reg, (ptr::read_volatile(reg) & !clear) | set) }
+}
+
+fn set_duty(duty: u16) {
+    write_csr(PWM_CSR_ENABLE | (duty as u32) << PWM_CSR_WIDTH_SHIFT);
+}
+
+/// Takes direct control of the backlight through its GPIOs, bypassing the PWM
+/// controller, whether the backlight was brought up or not.
+///
+/// The backlight is left fully on.
+pub fn take_over() {
+    unsafe {
+        PinGrP::LcdBlPwmPv0.set_tristate(PinTristate::Passthrough);
+        PinGrP::LcdBlEnPv1.set_tristate(PinTristate::Passthrough);
+    }
+    tegra_gpio!(V, 0).config(gpio::Config::OutputHigh);
+    tegra_gpio!(V, 1).config(gpio::Config::OutputHigh);
+}
+
+/// Turns the backlight fully on or off, after [`take_over`].
+///
+/// [`take_over`]: fn.take_over.html
+pub fn set_on(on: bool) {
+    let level = if on {
+        gpio::Level::High
+    } else {
+        gpio::Level::Low
+    };
+    tegra_gpio!(V, 0).write(level);
+}
+
+/// The LCD backlight.
+pub struct Backlight {
+    brightness: u8,
+}
+
+impl Backlight {
+    /// Brings up the PWM controller and enables the backlight, initially turned off.
+    pub fn init() -> Self {
+        // Assert reset, enable the clock and release the PWM controller from reset.
+        modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_L, 0, PWM_DEVICE_BIT);
+        modify_car_reg(CLK_RST_CONTROLLER_CLK_OUT_ENB_L, 0, PWM_DEVICE_BIT);
+        unsafe {
+            ptr::write_volatile(
+                (CAR_BASE + CLK_RST_CONTROLLER_CLK_SOURCE_PWM) as *mut u32,
+                PWM_CLOCK_SOURCE,
+            );
+        }
+        usleep(2);
+        modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_L, PWM_DEVICE_BIT, 0);
+
+        // Start with a duty cycle of 0.
+        write_csr(PWM_CSR_ENABLE);
+
+        // Hand pin V0 over to the PWM controller and enable the backlight.
+        unsafe {
+            PinGrP::LcdBlPwmPv0.set_function(PinFunction::Pwm0);
+            PinGrP::LcdBlPwmPv0.set_tristate(PinTristate::Passthrough);
+            PinGrP::LcdBlEnPv1.set_tristate(PinTristate::Passthrough);
+        }
+        tegra_gpio!(V, 1).config(gpio::Config::OutputHigh);
+
+        Backlight { brightness: 0 }
+    }
+
+    /// Smoothly changes the brightness to `percent` over `duration_ms` milliseconds.
+    pub fn ramp_to(&mut self, percent: u8, duration_ms: u32) {
+        for duty in Ramp::new(self.brightness, percent, duration_ms) {
+            set_duty(duty);
+            usleep(RAMP_STEP_MS * 1000);
+        }
+
+        self.brightness = percent.min(100);
+    }
+}
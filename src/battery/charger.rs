@@ -0,0 +1,256 @@
+//! Status readout of the BQ24193 battery charger.
+//!
+//! The charger reports where its input power comes from, whether it is charging and
+//! any faults in two status registers. Faults are latched until the fault register is
+//! read, so the first read may report a fault that has since cleared. Call
+//! [`clear_faults`] before deciding anything on the status.
+//!
+//! [`clear_faults`]: fn.clear_faults.html
+
+use crate::sequencer::I2cBus;
+
+/// The I2C address of the BQ24193.
+pub const BQ24193: u32 = 0x6B;
+
+const REG_SYSTEM_STATUS: u8 = 0x08;
+const REG_FAULT: u8 = 0x09;
+
+const STATUS_VBUS_SHIFT: u8 = 6;
+const STATUS_CHARGE_SHIFT: u8 = 4;
+const STATUS_POWER_GOOD: u8 = 1 << 2;
+
+const FAULT_CHARGE_SHIFT: u8 = 4;
+const FAULT_BATTERY: u8 = 1 << 3;
+const FAULT_NTC_MASK: u8 = 0x7;
+
+/// The source of the input power of the charger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerSource {
+    /// No input power, or an unknown source.
+    None,
+    /// A USB host port.
+    UsbHost,
+    /// A power adapter.
+    Adapter,
+    /// The charger itself, powering USB in OTG mode.
+    Otg,
+}
+
+/// The charging phase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChargeState {
+    NotCharging,
+    PreCharge,
+    FastCharge,
+    Done,
+}
+
+/// A fault reported by the charger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The input voltage is out of range.
+    Input,
+    /// The charger shut down due to overheating.
+    Thermal,
+    /// Charging took longer than the safety timer allows.
+    SafetyTimer,
+    /// The battery voltage is too high.
+    BatteryOvervoltage,
+    /// The battery temperature is out of range, as measured by the thermistor.
+    BatteryTemperature,
+}
+
+/// The decoded status of the charger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChargerStatus {
+    /// Where the input power comes from.
+    pub source: PowerSource,
+    /// Whether the input power is good enough to charge from.
+    pub power_good: bool,
+    /// The charging phase.
+    pub state: ChargeState,
+    /// The most severe fault, if any.
+    pub fault: Option<Fault>,
+}
+
+impl ChargerStatus {
+    /// Decodes the values of the system status and fault registers.
+    pub fn decode(system_status: u8, fault: u8) -> Self {
+        let source = match system_status >> STATUS_VBUS_SHIFT & 0x3 {
+            1 => PowerSource::UsbHost,
+            2 => PowerSource::Adapter,
+            3 => PowerSource::Otg,
+            _ => PowerSource::None,
+        };
+        let state = match system_status >> STATUS_CHARGE_SHIFT & 0x3 {
+            1 => ChargeState::PreCharge,
+            2 => ChargeState::FastCharge,
+            3 => ChargeState::Done,
+            _ => ChargeState::NotCharging,
+        };
+
+        // Report battery faults first, as they are the most severe.
+        let fault = if fault & FAULT_BATTERY != 0 {
+            Some(Fault::BatteryOvervoltage)
+        } else if fault & FAULT_NTC_MASK != 0 {
+            Some(Fault::BatteryTemperature)
+        } else {
+            match fault >> FAULT_CHARGE_SHIFT & 0x3 {
+                1 => Some(Fault::Input),
+                2 => Some(Fault::Thermal),
+                3 => Some(Fault::SafetyTimer),
+                _ => None,
+            }
+        };
+
+        ChargerStatus {
+            source,
+            power_good: system_status & STATUS_POWER_GOOD != 0,
+            state,
+            fault,
+        }
+    }
+
+    /// Whether the battery is being charged.
+    pub fn is_charging(&self) -> bool {
+        self.power_good
+            && self.fault.is_none()
+            && (self.state == ChargeState::PreCharge || self.state == ChargeState::FastCharge)
+    }
+}
+
+/// Clears the faults that were latched since the fault register was last read, e.g.
+/// by an input glitch while plugging in the charger.
+pub fn clear_faults<B: I2cBus>(bus: &mut B) -> Result<(), B::Error> {
+    bus.read_byte(BQ24193, REG_FAULT).map(|_| ())
+}
+
+/// Reads the status of the charger.
+pub fn read_status<B: I2cBus>(bus: &mut B) -> Result<ChargerStatus, B::Error> {
+    let system_status = bus.read_byte(BQ24193, REG_SYSTEM_STATUS)?;
+    let fault = bus.read_byte(BQ24193, REG_FAULT)?;
+
+    Ok(ChargerStatus::decode(system_status, fault))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::simulated::SimulatedBattery;
+
+    #[test]
+    fn decodes_source_and_state() {
+        let sources = [
+            PowerSource::None,
+            PowerSource::UsbHost,
+            PowerSource::Adapter,
+            PowerSource::Otg,
+        ];
+        let states = [
+            ChargeState::NotCharging,
+            ChargeState::PreCharge,
+            ChargeState::FastCharge,
+            ChargeState::Done,
+        ];
+
+        for (vbus, &source) in sources.iter().enumerate() {
+            for (charge, &state) in states.iter().enumerate() {
+                let system_status = (vbus << 6 | charge << 4) as u8;
+                let status = ChargerStatus::decode(system_status, 0);
+                assert_eq!(status.source, source);
+                assert_eq!(status.state, state);
+                assert!(!status.power_good);
+                assert_eq!(status.fault, None);
+
+                let status = ChargerStatus::decode(system_status | 0x04, 0);
+                assert!(status.power_good);
+            }
+        }
+    }
+
+    #[test]
+    fn reports_most_severe_fault() {
+        let faults = [
+            (0x00, None),
+            (0x10, Some(Fault::Input)),
+            (0x20, Some(Fault::Thermal)),
+            (0x30, Some(Fault::SafetyTimer)),
+            (0x05, Some(Fault::BatteryTemperature)),
+            (0x35, Some(Fault::BatteryTemperature)),
+            (0x08, Some(Fault::BatteryOvervoltage)),
+            (0x3F, Some(Fault::BatteryOvervoltage)),
+            // Watchdog and boost faults do not affect charging from the input.
+            (0xC0, None),
+        ];
+
+        for &(fault, expected) in faults.iter() {
+            assert_eq!(
+                ChargerStatus::decode(0, fault).fault,
+                expected,
+                "{:02X}",
+                fault
+            );
+        }
+    }
+
+    #[test]
+    fn charges_only_with_good_power_and_no_fault() {
+        // A USB host port in fast charge with good power.
+        assert!(ChargerStatus::decode(0x64, 0x00).is_charging());
+        assert!(ChargerStatus::decode(0x54, 0x00).is_charging());
+        assert!(!ChargerStatus::decode(0x60, 0x00).is_charging());
+        assert!(!ChargerStatus::decode(0x64, 0x10).is_charging());
+        assert!(!ChargerStatus::decode(0x44, 0x00).is_charging());
+        assert!(!ChargerStatus::decode(0x74, 0x00).is_charging());
+    }
+
+    #[test]
+    fn reads_status_from_device() {
+        let mut bus = SimulatedBattery::default();
+        bus.charger[0x08] = 0xA4;
+        bus.charger[0x09] = 0x00;
+
+        assert_eq!(
+            read_status(&mut bus),
+            Ok(ChargerStatus {
+                source: PowerSource::Adapter,
+                power_good: true,
+                state: ChargeState::FastCharge,
+                fault: None,
+            })
+        );
+        assert_eq!(bus.fault_reads, 1);
+    }
+
+    #[test]
+    fn reports_latched_fault_once() {
+        let mut bus = SimulatedBattery::default();
+        bus.charger[0x08] = 0x64;
+        bus.latched_faults = 0x20;
+
+        let status = read_status(&mut bus).unwrap();
+        assert_eq!(status.fault, Some(Fault::Thermal));
+        assert!(!status.is_charging());
+
+        let status = read_status(&mut bus).unwrap();
+        assert_eq!(status.fault, None);
+        assert!(status.is_charging());
+    }
+
+    #[test]
+    fn clears_latched_faults() {
+        let mut bus = SimulatedBattery::default();
+        bus.charger[0x08] = 0x64;
+        bus.latched_faults = 0x20;
+
+        assert_eq!(clear_faults(&mut bus), Ok(()));
+        let status = read_status(&mut bus).unwrap();
+        assert_eq!(status.fault, None);
+        assert!(status.is_charging());
+
+        // Faults that persist are still reported.
+        bus.charger[0x09] = 0x20;
+        assert_eq!(clear_faults(&mut bus), Ok(()));
+        assert_eq!(read_status(&mut bus).unwrap().fault, Some(Fault::Thermal));
+    }
+}
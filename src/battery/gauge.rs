@@ -0,0 +1,98 @@
+//! Readout of the MAX17050 fuel gauge.
+//!
+//! The fuel gauge tracks the state of charge of the battery and measures its voltage.
+//! All of its registers are 16 bits wide.
+
+use crate::sequencer::I2cBus;
+
+/// The I2C address of the MAX17050.
+pub const MAX17050: u32 = 0x36;
+
+/// The reported state of charge, in 1/256%.
+const REG_REP_SOC: u8 = 0x06;
+/// The cell voltage, in 78.125µV.
+const REG_VCELL: u8 = 0x09;
+
+/// A reading of the fuel gauge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reading {
+    /// The state of charge in percent, rounded down.
+    pub charge_percent: u8,
+    /// The cell voltage in millivolts, rounded down.
+    pub voltage_mv: u16,
+}
+
+/// Converts a value of the state of charge register to percent.
+///
+/// Values above 100% occur during calibration and are clamped.
+pub fn charge_percent(rep_soc: u16) -> u8 {
+    ((rep_soc >> 8) as u8).min(100)
+}
+
+/// Converts a value of the cell voltage register to millivolts.
+pub fn voltage_mv(vcell: u16) -> u16 {
+    // 78.125µV are 5/64mV.
+    (vcell as u32 * 5 / 64) as u16
+}
+
+/// Reads the state of charge and the voltage of the battery.
+pub fn read<B: I2cBus>(bus: &mut B) -> Result<Reading, B::Error> {
+    let rep_soc = bus.read_word(MAX17050, REG_REP_SOC)?;
+    let vcell = bus.read_word(MAX17050, REG_VCELL)?;
+
+    Ok(Reading {
+        charge_percent: charge_percent(rep_soc),
+        voltage_mv: voltage_mv(vcell),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::simulated::{Nak, SimulatedBattery};
+
+    #[test]
+    fn converts_charge() {
+        assert_eq!(charge_percent(0x0000), 0);
+        assert_eq!(charge_percent(0x00FF), 0);
+        assert_eq!(charge_percent(0x3280), 50);
+        assert_eq!(charge_percent(0x6400), 100);
+        assert_eq!(charge_percent(0x6500), 100);
+        assert_eq!(charge_percent(0xFFFF), 100);
+    }
+
+    #[test]
+    fn converts_voltage() {
+        assert_eq!(voltage_mv(0), 0);
+        assert_eq!(voltage_mv(12), 0);
+        assert_eq!(voltage_mv(13), 1);
+        assert_eq!(voltage_mv(43520), 3400);
+        assert_eq!(voltage_mv(53760), 4200);
+        assert_eq!(voltage_mv(0xFFFF), 5119);
+    }
+
+    #[test]
+    fn reads_from_device() {
+        let mut bus = SimulatedBattery::default();
+        bus.gauge[0x06] = 0x4B80;
+        bus.gauge[0x09] = 0xC000;
+
+        assert_eq!(
+            read(&mut bus),
+            Ok(Reading {
+                charge_percent: 75,
+                voltage_mv: 3840,
+            })
+        );
+    }
+
+    #[test]
+    fn fails_without_battery() {
+        let mut bus = SimulatedBattery {
+            gauge_present: false,
+            ..SimulatedBattery::default()
+        };
+
+        assert_eq!(read(&mut bus), Err(Nak));
+    }
+}
@@ -0,0 +1,153 @@
+//! Battery checks before booting.
+//!
+//! Booting on a nearly empty battery risks a brownout in the middle of an eMMC write.
+//! Before touching storage, the first stage reads the [`gauge`] and the [`charger`]
+//! and [`decide`]s whether the battery holds enough charge to boot, has to charge
+//! first or cannot be charged and the system has to be powered off.
+//!
+//! The drivers only depend on the [`I2cBus`] abstraction, so they can be exercised
+//! against simulated devices on the build host.
+//!
+//! [`gauge`]: gauge/index.html
+//! [`charger`]: charger/index.html
+//! [`decide`]: fn.decide.html
+//! [`I2cBus`]: ../sequencer/trait.I2cBus.html
+
+pub mod charger;
+pub mod gauge;
+#[cfg(test)]
+pub mod simulated;
+
+use self::charger::{ChargeState, ChargerStatus};
+use self::gauge::Reading;
+
+/// The minimum state of charge to boot with, in percent.
+pub const MIN_CHARGE_PERCENT: u8 = 3;
+
+/// The minimum cell voltage to boot with, in millivolts.
+pub const MIN_VOLTAGE_MV: u16 = 3400;
+
+/// What to do about the battery before booting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// The battery holds enough charge to boot.
+    Boot,
+    /// The battery is too low, but charging. Check again later.
+    Charge,
+    /// The battery is too low and not charging.
+    PowerOff,
+}
+
+/// Decides whether to boot given the `reading` of the fuel gauge and the status of
+/// the `charger`.
+pub fn decide(reading: &Reading, charger: &ChargerStatus) -> Decision {
+    let sufficient =
+        reading.charge_percent >= MIN_CHARGE_PERCENT && reading.voltage_mv >= MIN_VOLTAGE_MV;
+
+    // A charger that is done knows better than a fuel gauge that lost its calibration.
+    if sufficient || (charger.state == ChargeState::Done && charger.fault.is_none()) {
+        Decision::Boot
+    } else if charger.is_charging() {
+        Decision::Charge
+    } else {
+        Decision::PowerOff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::simulated::SimulatedBattery;
+
+    /// Charger statuses: idle on battery, fast charging from a USB host port, done and
+    /// done with a battery temperature fault.
+    const IDLE: (u8, u8) = (0x00, 0x00);
+    const CHARGING: (u8, u8) = (0x64, 0x00);
+    const DONE: (u8, u8) = (0x74, 0x00);
+    const DONE_HOT: (u8, u8) = (0x74, 0x02);
+
+    fn decide_for(charge_percent: u8, voltage_mv: u16, (status, fault): (u8, u8)) -> Decision {
+        let reading = Reading {
+            charge_percent,
+            voltage_mv,
+        };
+        decide(&reading, &ChargerStatus::decode(status, fault))
+    }
+
+    #[test]
+    fn boots_with_sufficient_charge() {
+        for &charger in [IDLE, CHARGING, DONE, DONE_HOT].iter() {
+            assert_eq!(
+                decide_for(MIN_CHARGE_PERCENT, MIN_VOLTAGE_MV, charger),
+                Decision::Boot
+            );
+            assert_eq!(decide_for(100, 4200, charger), Decision::Boot);
+        }
+    }
+
+    #[test]
+    fn charges_when_low() {
+        assert_eq!(
+            decide_for(MIN_CHARGE_PERCENT - 1, 4200, CHARGING),
+            Decision::Charge
+        );
+        assert_eq!(
+            decide_for(100, MIN_VOLTAGE_MV - 1, CHARGING),
+            Decision::Charge
+        );
+        assert_eq!(decide_for(0, 0, CHARGING), Decision::Charge);
+    }
+
+    #[test]
+    fn powers_off_when_low_and_not_charging() {
+        assert_eq!(
+            decide_for(MIN_CHARGE_PERCENT - 1, 4200, IDLE),
+            Decision::PowerOff
+        );
+        assert_eq!(
+            decide_for(100, MIN_VOLTAGE_MV - 1, IDLE),
+            Decision::PowerOff
+        );
+        assert_eq!(decide_for(0, 0, DONE_HOT), Decision::PowerOff);
+    }
+
+    #[test]
+    fn trusts_charger_that_is_done() {
+        assert_eq!(decide_for(0, 0, DONE), Decision::Boot);
+    }
+
+    #[test]
+    fn decides_from_devices() {
+        let mut bus = SimulatedBattery::default();
+        bus.gauge[0x06] = 0x0180;
+        bus.gauge[0x09] = 0xA000;
+        bus.charger[0x08] = CHARGING.0;
+
+        let reading = gauge::read(&mut bus).unwrap();
+        let status = charger::read_status(&mut bus).unwrap();
+        assert_eq!(decide(&reading, &status), Decision::Charge);
+
+        // The charger unplugged in between.
+        bus.charger[0x08] = IDLE.0;
+        let status = charger::read_status(&mut bus).unwrap();
+        assert_eq!(decide(&reading, &status), Decision::PowerOff);
+    }
+
+    #[test]
+    fn ignores_faults_latched_before_the_check() {
+        // A low battery on a working charger that latched an input fault while being
+        // plugged in.
+        let mut bus = SimulatedBattery {
+            latched_faults: 0x10,
+            ..SimulatedBattery::default()
+        };
+        bus.gauge[0x06] = 0x0100;
+        bus.gauge[0x09] = 0x9C00;
+        bus.charger[0x08] = CHARGING.0;
+
+        charger::clear_faults(&mut bus).unwrap();
+        let reading = gauge::read(&mut bus).unwrap();
+        let status = charger::read_status(&mut bus).unwrap();
+        assert_eq!(decide(&reading, &status), Decision::Charge);
+    }
+}
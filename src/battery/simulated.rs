@@ -0,0 +1,77 @@
+//! A charger and a fuel gauge on a simulated I2C bus, for testing the battery checks
+//! on the host.
+
+use super::charger::BQ24193;
+use super::gauge::MAX17050;
+use crate::sequencer::I2cBus;
+
+/// The fault register of the BQ24193.
+const REG_FAULT: u8 = 0x09;
+
+/// The error of a transfer that no device acknowledged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nak;
+
+/// A BQ24193 charger and a MAX17050 fuel gauge on the same bus.
+#[derive(Clone, Debug)]
+pub struct SimulatedBattery {
+    /// The byte registers of the charger.
+    pub charger: [u8; 0x0B],
+    /// The faults that were latched since the fault register was last read.
+    pub latched_faults: u8,
+    /// The word registers of the fuel gauge.
+    pub gauge: [u16; 0x100],
+    /// Whether the fuel gauge responds, which it does not without a battery.
+    pub gauge_present: bool,
+    /// The number of reads of the fault register.
+    pub fault_reads: usize,
+}
+
+impl Default for SimulatedBattery {
+    fn default() -> Self {
+        SimulatedBattery {
+            charger: [0; 0x0B],
+            latched_faults: 0,
+            gauge: [0; 0x100],
+            gauge_present: true,
+            fault_reads: 0,
+        }
+    }
+}
+
+impl I2cBus for SimulatedBattery {
+    type Error = Nak;
+
+    fn read_byte(&mut self, device: u32, register: u8) -> Result<u8, Nak> {
+        if device != BQ24193 {
+            return Err(Nak);
+        }
+        let value = *self.charger.get(register as usize).ok_or(Nak)?;
+
+        // Reading the fault register reports and clears the latched faults.
+        if register == REG_FAULT {
+            self.fault_reads += 1;
+            let latched = self.latched_faults;
+            self.latched_faults = 0;
+            return Ok(value | latched);
+        }
+
+        Ok(value)
+    }
+
+    fn write_byte(&mut self, device: u32, register: u8, value: u8) -> Result<(), Nak> {
+        if device != BQ24193 {
+            return Err(Nak);
+        }
+        *self.charger.get_mut(register as usize).ok_or(Nak)? = value;
+        Ok(())
+    }
+
+    fn read_word(&mut self, device: u32, register: u8) -> Result<u16, Nak> {
+        if device != MAX17050 || !self.gauge_present {
+            return Err(Nak);
+        }
+
+        Ok(self.gauge[register as usize])
+    }
+}
@@ -0,0 +1,182 @@
+//! Encoding of error codes as backlight blink patterns.
+//!
+//! Retail units lack a debug UART, so fatal errors are signalled by flashing the
+//! backlight. A [`BootError`] code is sent most significant bit first as 16 blinks,
+//! where a long blink is a 1 and a short blink is a 0. A long preamble marks the
+//! start of the code, longer pauses separate the nibbles and a final pause separates
+//! repetitions of the pattern. The error `0x1204` for example reads as:
+//!
+//! ```text
+//! ▇▇▇▇▇  ▁ ▁ ▁ ▇▇  ▁ ▁ ▇▇ ▁  ▁ ▁ ▁ ▁  ▁ ▇▇ ▁ ▁
+//! ```
+//!
+//! [`BootError`]: ../error/struct.BootError.html
+
+/// The number of bits in an error code.
+const CODE_BITS: usize = 16;
+
+/// The duration of the preamble blink in milliseconds.
+pub const PREAMBLE_MS: u32 = 1500;
+/// The duration of a blink encoding a 0 bit in milliseconds.
+pub const SHORT_MS: u32 = 200;
+/// The duration of a blink encoding a 1 bit in milliseconds.
+pub const LONG_MS: u32 = 600;
+/// The pause between two bits of a nibble in milliseconds.
+pub const BIT_GAP_MS: u32 = 300;
+/// The pause after the preamble and between two nibbles in milliseconds.
+pub const NIBBLE_GAP_MS: u32 = 1000;
+/// The pause after the last bit, before the pattern repeats, in milliseconds.
+pub const TRAILER_MS: u32 = 3000;
+
+/// The number of pulses that make up the pattern of a single code.
+pub const PULSES: usize = 2 + 2 * CODE_BITS;
+
+/// A period of time during which the backlight is either on or off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pulse {
+    /// Whether the backlight is on.
+    pub on: bool,
+    /// The duration of the pulse in milliseconds.
+    pub duration_ms: u32,
+}
+
+/// Gets the pulse at `index` in the blink pattern of `code`.
+///
+/// Returns `None` when `index` is past the end of the pattern.
+pub fn pulse(code: u16, index: usize) -> Option<Pulse> {
+    let (on, duration_ms) = match index {
+        0 => (true, PREAMBLE_MS),
+        1 => (false, NIBBLE_GAP_MS),
+        _ if index < PULSES => {
+            let bit = (index - 2) / 2;
+            if index % 2 == 0 {
+                let set = code & (1 << (CODE_BITS - 1 - bit)) != 0;
+                (true, if set { LONG_MS } else { SHORT_MS })
+            } else if bit == CODE_BITS - 1 {
+                (false, TRAILER_MS)
+            } else if bit % 4 == 3 {
+                (false, NIBBLE_GAP_MS)
+            } else {
+                (false, BIT_GAP_MS)
+            }
+        }
+        _ => return None,
+    };
+
+    Some(Pulse { on, duration_ms })
+}
+
+/// An iterator over the pulses in the blink pattern of a code.
+pub struct Schedule {
+    code: u16,
+    index: usize,
+}
+
+impl Iterator for Schedule {
+    type Item = Pulse;
+
+    fn next(&mut self) -> Option<Pulse> {
+        let pulse = pulse(self.code, self.index)?;
+        self.index += 1;
+        Some(pulse)
+    }
+}
+
+/// Gets the blink pattern of `code`.
+pub fn schedule(code: u16) -> Schedule {
+    Schedule { code, index: 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads a code back from its blink pattern, like someone watching the backlight.
+    fn decode(mut pulses: impl Iterator<Item = Pulse>) -> Option<u16> {
+        let preamble = pulses.next()?;
+        if !preamble.on || preamble.duration_ms != PREAMBLE_MS {
+            return None;
+        }
+
+        let mut code = 0;
+        for pulse in pulses.skip(1).step_by(2) {
+            let bit = match pulse.duration_ms {
+                LONG_MS => 1,
+                SHORT_MS => 0,
+                _ => return None,
+            };
+            code = code << 1 | bit;
+        }
+
+        Some(code)
+    }
+
+    #[test]
+    fn example_pattern() {
+        let ms = |on, duration_ms| Pulse { on, duration_ms };
+        let (short, long) = (ms(true, SHORT_MS), ms(true, LONG_MS));
+        let (bit, nibble) = (ms(false, BIT_GAP_MS), ms(false, NIBBLE_GAP_MS));
+
+        #[rustfmt::skip]
+        let expected = [
+            ms(true, PREAMBLE_MS),
+            nibble,
+            short, bit, short, bit, short, bit, long, nibble,
+            short, bit, short, bit, long, bit, short, nibble,
+            short, bit, short, bit, short, bit, short, nibble,
+            short, bit, long, bit, short, bit, short,
+            ms(false, TRAILER_MS),
+        ];
+
+        assert!(schedule(0x1204).eq(expected.iter().copied()));
+    }
+
+    #[test]
+    fn every_code_round_trips() {
+        for code in 0..=u16::MAX {
+            assert_eq!(decode(schedule(code)), Some(code));
+        }
+    }
+
+    #[test]
+    fn pattern_alternates_and_ends() {
+        for &code in [0x0000, 0xFFFF, 0xA5A5].iter() {
+            assert_eq!(schedule(code).count(), PULSES);
+            assert!(schedule(code)
+                .enumerate()
+                .all(|(index, pulse)| pulse.on == (index % 2 == 0)));
+            assert_eq!(pulse(code, PULSES), None);
+            assert_eq!(pulse(code, usize::MAX), None);
+        }
+    }
+
+    #[test]
+    fn pauses_separate_nibbles() {
+        let pauses = |duration_ms| {
+            schedule(0)
+                .filter(|pulse| !pulse.on && pulse.duration_ms == duration_ms)
+                .count()
+        };
+
+        // The gap after the preamble counts as a nibble gap, the last one is the trailer.
+        assert_eq!(pauses(NIBBLE_GAP_MS), CODE_BITS / 4);
+        assert_eq!(pauses(BIT_GAP_MS), CODE_BITS - CODE_BITS / 4);
+        assert_eq!(pauses(TRAILER_MS), 1);
+        assert_eq!(
+            pulse(0, PULSES - 1).map(|pulse| pulse.duration_ms),
+            Some(TRAILER_MS)
+        );
+    }
+
+    #[test]
+    fn pattern_length_depends_on_set_bits() {
+        let duration = |code| schedule(code).map(|pulse| pulse.duration_ms).sum::<u32>();
+
+        let zeros = duration(0x0000);
+        assert_eq!(
+            duration(0xFFFF),
+            zeros + CODE_BITS as u32 * (LONG_MS - SHORT_MS)
+        );
+        assert_eq!(duration(0x1204), zeros + 3 * (LONG_MS - SHORT_MS));
+    }
+}
@@ -0,0 +1,90 @@
+//! The pinmux and GPIO tables of the board and checks of the live state against them.
+//!
+//! The tables are generated at build time from a board description, see the `board`
+//! crate and `boards/`. The [`state`] module compares what the registers hold to what
+//! the tables ask for, to track down pins that were reconfigured behind our back.
+//!
+//! [`state`]: state/index.html
+
+pub mod state;
+
+#[cfg(feature = "debug_uart_port")]
+use core::fmt;
+use core::ptr;
+
+use libtegra::gpio;
+use libtegra::pinmux::{
+    PinFunction, PinGrP, PinIo, PinIoHv as PinEIoHv, PinLock, PinOd, PinPull, PinTristate,
+};
+
+use self::state::{GpioFields, GpioState, Mismatch, PinFields, PinState};
+
+/// The base address of the pinmux registers, one per pin group.
+const PINMUX_BASE: usize = 0x7000_3000;
+
+/// The base address of the GPIO controller.
+const GPIO_BASE: usize = 0x6000_D000;
+const GPIO_BANK_SIZE: usize = 0x100;
+const GPIO_PORTS_PER_BANK: usize = 4;
+const GPIO_CNF: usize = 0x00;
+const GPIO_OE: usize = 0x10;
+const GPIO_OUT: usize = 0x20;
+
+/// The configuration of a single pin.
+pub struct PinConfig {
+    pub pin: PinGrP,
+    pub function: PinFunction,
+    pub pull: PinPull,
+    pub tristate: PinTristate,
+    pub io: PinIo,
+    pub lock: PinLock,
+    pub od: PinOd,
+    pub hv: PinEIoHv,
+}
+
+include!(concat!(env!("OUT_DIR"), "/board.rs"));
+
+fn read_reg(address: usize) -> u32 {
+    unsafe { ptr::read_volatile(address as *const u32) }
+}
+
+fn read_pin(pin: PinGrP) -> PinState {
+    PinState::decode(read_reg(PINMUX_BASE + pin as usize * 4))
+}
+
+fn read_gpio(gpio: &GpioFields) -> GpioState {
+    let port = gpio.port as usize;
+    let base =
+        GPIO_BASE + port / GPIO_PORTS_PER_BANK * GPIO_BANK_SIZE + port % GPIO_PORTS_PER_BANK * 4;
+    let bit = |offset| read_reg(base + offset) & 1 << gpio.pin != 0;
+
+    GpioState {
+        gpio_mode: bit(GPIO_CNF),
+        output: bit(GPIO_OE),
+        level: bit(GPIO_OUT),
+    }
+}
+
+/// Writes the live state of all configured pins and GPIOs to `out`, one per line.
+#[cfg(feature = "debug_uart_port")]
+pub fn dump<W: fmt::Write>(out: &mut W) -> fmt::Result {
+    for (config, expected) in PIN_CONFIG.iter().zip(PIN_EXPECTED.iter()) {
+        writeln!(out, "{}: {}", expected.name, read_pin(config.pin))?;
+    }
+    for expected in GPIO_EXPECTED.iter() {
+        writeln!(out, "{}: {}", expected.name, read_gpio(expected))?;
+    }
+
+    Ok(())
+}
+
+/// Compares the live state of all configured pins and GPIOs to the board
+/// description, passing every mismatch to `report`.
+pub fn diff<F: FnMut(Mismatch)>(mut report: F) {
+    for (config, expected) in PIN_CONFIG.iter().zip(PIN_EXPECTED.iter()) {
+        state::diff_pin(expected, &read_pin(config.pin), &mut report);
+    }
+    for expected in GPIO_EXPECTED.iter() {
+        state::diff_gpio(expected, &read_gpio(expected), &mut report);
+    }
+}
@@ -0,0 +1,424 @@
+//! Decoding of the live pinmux and GPIO state and comparison against the board.
+//!
+//! The register values are read by the caller, which keeps this module free of
+//! hardware accesses.
+
+use core::fmt;
+
+const PM_MASK: u32 = 0x3;
+const PUPD_SHIFT: u32 = 2;
+const PUPD_MASK: u32 = 0x3;
+const TRISTATE: u32 = 1 << 4;
+const E_INPUT: u32 = 1 << 6;
+const LOCK: u32 = 1 << 7;
+const E_IO_HV: u32 = 1 << 10;
+const E_OD: u32 = 1 << 11;
+
+/// The settings of a pin that the board description asks for.
+///
+/// Settings that are left at their defaults are not touched when the pin is
+/// configured and hence `None`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinFields {
+    pub name: &'static str,
+    /// The PM select of the function.
+    pub function: Option<u32>,
+    pub pull: Option<u32>,
+    pub tristate: Option<bool>,
+    pub input: Option<bool>,
+    pub lock: Option<bool>,
+    pub od: Option<bool>,
+    pub hv: Option<bool>,
+}
+
+/// The settings of a GPIO that the board description asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpioFields {
+    pub name: &'static str,
+    /// The index of the port, counting from A.
+    pub port: u32,
+    pub pin: u32,
+    pub output: bool,
+    /// The output level, if the GPIO is an output.
+    pub level: Option<bool>,
+}
+
+/// The state of a pin as read back from its pinmux register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinState {
+    /// The index of the selected function among the functions of the pin.
+    pub function: u32,
+    pub pull: u32,
+    pub tristate: bool,
+    pub input: bool,
+    pub lock: bool,
+    pub od: bool,
+    pub hv: bool,
+}
+
+impl PinState {
+    /// Decodes the value of a pinmux register.
+    pub fn decode(value: u32) -> Self {
+        PinState {
+            function: value & PM_MASK,
+            pull: value >> PUPD_SHIFT & PUPD_MASK,
+            tristate: value & TRISTATE != 0,
+            input: value & E_INPUT != 0,
+            lock: value & LOCK != 0,
+            od: value & E_OD != 0,
+            hv: value & E_IO_HV != 0,
+        }
+    }
+}
+
+impl fmt::Display for PinState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "function {}, pull {}, tristate {}, input {}, lock {}, od {}, hv {}",
+            self.function,
+            self.pull,
+            self.tristate as u8,
+            self.input as u8,
+            self.lock as u8,
+            self.od as u8,
+            self.hv as u8
+        )
+    }
+}
+
+/// The state of a GPIO as read back from its registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpioState {
+    /// Whether the pin is controlled by the GPIO controller rather than its function.
+    pub gpio_mode: bool,
+    pub output: bool,
+    pub level: bool,
+}
+
+impl fmt::Display for GpioState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gpio {}, output {}, level {}",
+            self.gpio_mode as u8, self.output as u8, self.level as u8
+        )
+    }
+}
+
+/// A setting of a pin or GPIO.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Function,
+    Pull,
+    Tristate,
+    Input,
+    Lock,
+    OpenDrain,
+    Hv,
+    GpioMode,
+    Output,
+    Level,
+}
+
+/// A setting that does not hold the expected value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub name: &'static str,
+    pub field: Field,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:?} is {}, expected {}",
+            self.name, self.field, self.actual, self.expected
+        )
+    }
+}
+
+/// Compares the state of a pin against the board description, passing every
+/// mismatch to `report`.
+pub fn diff_pin<F: FnMut(Mismatch)>(expected: &PinFields, actual: &PinState, mut report: F) {
+    let checks = [
+        (Field::Function, expected.function, actual.function),
+        (Field::Pull, expected.pull, actual.pull),
+        (
+            Field::Tristate,
+            expected.tristate.map(u32::from),
+            actual.tristate as u32,
+        ),
+        (
+            Field::Input,
+            expected.input.map(u32::from),
+            actual.input as u32,
+        ),
+        (
+            Field::Lock,
+            expected.lock.map(u32::from),
+            actual.lock as u32,
+        ),
+        (
+            Field::OpenDrain,
+            expected.od.map(u32::from),
+            actual.od as u32,
+        ),
+        (Field::Hv, expected.hv.map(u32::from), actual.hv as u32),
+    ];
+
+    for &(field, wanted, actual) in checks.iter() {
+        match wanted {
+            Some(wanted) if wanted != actual => report(Mismatch {
+                name: expected.name,
+                field,
+                expected: wanted,
+                actual,
+            }),
+            _ => {}
+        }
+    }
+}
+
+/// Compares the state of a GPIO against the board description, passing every
+/// mismatch to `report`.
+pub fn diff_gpio<F: FnMut(Mismatch)>(expected: &GpioFields, actual: &GpioState, mut report: F) {
+    let checks = [
+        (Field::GpioMode, Some(true), actual.gpio_mode),
+        (Field::Output, Some(expected.output), actual.output),
+        (Field::Level, expected.level, actual.level),
+    ];
+
+    for &(field, wanted, actual) in checks.iter() {
+        match wanted {
+            Some(wanted) if wanted != actual => report(Mismatch {
+                name: expected.name,
+                field,
+                expected: wanted as u32,
+                actual: actual as u32,
+            }),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    /// UART-A RX: pulled up, driven by the pin and an input.
+    const UART_RX: PinFields = PinFields {
+        name: "UART-A RX",
+        function: Some(0),
+        pull: Some(2),
+        tristate: Some(false),
+        input: Some(true),
+        lock: None,
+        od: Some(false),
+        hv: None,
+    };
+
+    /// The state UART-A RX is configured to, with function select 0.
+    const UART_RX_STATE: PinState = PinState {
+        function: 0,
+        pull: 2,
+        tristate: false,
+        input: true,
+        lock: false,
+        od: false,
+        hv: false,
+    };
+
+    /// Volume Down: an input on GPIO X7.
+    const VOLUME_DOWN: GpioFields = GpioFields {
+        name: "Volume Down",
+        port: 23,
+        pin: 7,
+        output: false,
+        level: None,
+    };
+
+    /// The LCD reset: an output on GPIO V2, driven low.
+    const LCD_RESET: GpioFields = GpioFields {
+        name: "LCD reset",
+        port: 21,
+        pin: 2,
+        output: true,
+        level: Some(false),
+    };
+
+    fn pin_mismatches(expected: &PinFields, actual: &PinState) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        diff_pin(expected, actual, |mismatch| mismatches.push(mismatch));
+        mismatches
+    }
+
+    fn gpio_mismatches(expected: &GpioFields, actual: &GpioState) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        diff_gpio(expected, actual, |mismatch| mismatches.push(mismatch));
+        mismatches
+    }
+
+    fn mismatch(name: &'static str, field: Field, expected: u32, actual: u32) -> Mismatch {
+        Mismatch {
+            name,
+            field,
+            expected,
+            actual,
+        }
+    }
+
+    #[test]
+    fn decodes_pinmux_registers() {
+        assert_eq!(PinState::decode(0x0000_0048), UART_RX_STATE);
+        assert_eq!(
+            PinState::decode(0x0000_0CD7),
+            PinState {
+                function: 3,
+                pull: 1,
+                tristate: true,
+                input: true,
+                lock: true,
+                od: true,
+                hv: true,
+            }
+        );
+
+        // The park, schmitt and drive strength bits are not compared.
+        assert_eq!(PinState::decode(0xFFFF_F320), PinState::decode(0x0000_0000));
+    }
+
+    #[test]
+    fn shows_state() {
+        assert_eq!(
+            PinState::decode(0x0000_0CD7).to_string(),
+            "function 3, pull 1, tristate 1, input 1, lock 1, od 1, hv 1"
+        );
+        let state = GpioState {
+            gpio_mode: true,
+            output: true,
+            level: false,
+        };
+        assert_eq!(state.to_string(), "gpio 1, output 1, level 0");
+        assert_eq!(
+            mismatch("UART-A RX", Field::Pull, 2, 1).to_string(),
+            "UART-A RX: Pull is 1, expected 2"
+        );
+    }
+
+    #[test]
+    fn accepts_configured_pin() {
+        assert_eq!(pin_mismatches(&UART_RX, &UART_RX_STATE), []);
+
+        // Settings the board leaves alone may hold anything.
+        let state = PinState {
+            lock: true,
+            hv: true,
+            ..UART_RX_STATE
+        };
+        assert_eq!(pin_mismatches(&UART_RX, &state), []);
+    }
+
+    #[test]
+    fn reports_every_pin_mismatch() {
+        let state = PinState {
+            function: 1,
+            pull: 0,
+            tristate: true,
+            input: false,
+            lock: true,
+            od: true,
+            hv: true,
+        };
+        let everything = PinFields {
+            lock: Some(false),
+            hv: Some(false),
+            ..UART_RX
+        };
+
+        assert_eq!(
+            pin_mismatches(&everything, &state),
+            [
+                mismatch("UART-A RX", Field::Function, 0, 1),
+                mismatch("UART-A RX", Field::Pull, 2, 0),
+                mismatch("UART-A RX", Field::Tristate, 0, 1),
+                mismatch("UART-A RX", Field::Input, 1, 0),
+                mismatch("UART-A RX", Field::Lock, 0, 1),
+                mismatch("UART-A RX", Field::OpenDrain, 0, 1),
+                mismatch("UART-A RX", Field::Hv, 0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_function_to_expected_select() {
+        let state = PinState {
+            function: 2,
+            ..UART_RX_STATE
+        };
+        let expected = PinFields {
+            function: Some(2),
+            ..UART_RX
+        };
+        assert_eq!(pin_mismatches(&expected, &state), []);
+        assert_eq!(
+            pin_mismatches(&UART_RX, &state),
+            [mismatch("UART-A RX", Field::Function, 0, 2)]
+        );
+
+        // Pins left at their default function may select anything.
+        let expected = PinFields {
+            function: None,
+            ..UART_RX
+        };
+        assert_eq!(pin_mismatches(&expected, &state), []);
+    }
+
+    #[test]
+    fn accepts_configured_gpios() {
+        let input = GpioState {
+            gpio_mode: true,
+            output: false,
+            level: true,
+        };
+        assert_eq!(gpio_mismatches(&VOLUME_DOWN, &input), []);
+
+        let low = GpioState {
+            gpio_mode: true,
+            output: true,
+            level: false,
+        };
+        assert_eq!(gpio_mismatches(&LCD_RESET, &low), []);
+    }
+
+    #[test]
+    fn reports_every_gpio_mismatch() {
+        // Handed back to its function, driving high.
+        let state = GpioState {
+            gpio_mode: false,
+            output: true,
+            level: true,
+        };
+
+        assert_eq!(
+            gpio_mismatches(&VOLUME_DOWN, &state),
+            [
+                mismatch("Volume Down", Field::GpioMode, 1, 0),
+                mismatch("Volume Down", Field::Output, 0, 1),
+            ]
+        );
+        assert_eq!(
+            gpio_mismatches(&LCD_RESET, &state),
+            [
+                mismatch("LCD reset", Field::GpioMode, 1, 0),
+                mismatch("LCD reset", Field::Level, 0, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Loading of the second-stage bootloader from the A/B slots on the eMMC.
+//!
+//! Both slots and their shared metadata block live in the second eMMC boot partition.
+//! Each slot holds the second-stage bootloader blob directly followed by its signed
+//! boot manifest, mirroring the layout expected in memory.
+
+use core::slice;
+
+use manifest::{Manifest, MAX_MANIFEST_SIZE};
+use slot::{Metadata, Slot};
+
+use crate::error::BootError;
+use crate::fuses::FuseArray;
+use crate::memory;
+use crate::rollback;
+use crate::storage::{self, BlockDevice, BLOCK_SIZE};
+use crate::verify;
+use crate::{BOOTLOADER_SIZE, BOOTLOADER_START};
+
+/// The block holding the slot metadata.
+pub const METADATA_LBA: u32 = 0x1000;
+
+/// The first blocks of slot A and slot B, respectively.
+pub const SLOT_LBAS: [u32; 2] = [0x1100, 0x1300];
+
+/// The number of blocks occupied by the blob and manifest in a slot.
+const SLOT_BLOCKS: usize = (BOOTLOADER_SIZE + MAX_MANIFEST_SIZE + BLOCK_SIZE - 1) / BLOCK_SIZE;
+
+/// Errors that may occur while loading the second-stage bootloader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Accessing the eMMC failed.
+    Storage(storage::Error),
+    /// Neither slot contains a bootable second stage.
+    NoBootableSlot,
+}
+
+impl From<storage::Error> for Error {
+    fn from(error: storage::Error) -> Self {
+        Error::Storage(error)
+    }
+}
+
+/// Reads the slot metadata, falling back to defaults if it is missing or corrupted.
+pub fn read_metadata<D: BlockDevice>(device: &mut D) -> Result<Metadata, Error> {
+    let mut block = [0; BLOCK_SIZE];
+    device.read_blocks(METADATA_LBA, &mut block)?;
+
+    Ok(Metadata::decode(&block).unwrap_or_default())
+}
+
+/// Persists the slot metadata.
+pub fn write_metadata<D: BlockDevice>(device: &mut D, metadata: &Metadata) -> Result<(), Error> {
+    let mut block = [0; BLOCK_SIZE];
+    block[..metadata.encode().len()].copy_from_slice(&metadata.encode());
+
+    device.write_blocks(METADATA_LBA, &block)?;
+    Ok(())
+}
+
+/// Checks whether the second stage in memory may be booted, by verifying it against its
+/// manifest and the manifest against the rollback fuses.
+pub fn verify_loaded<F: FuseArray>(fuses: &F) -> Result<Manifest, BootError> {
+    let manifest = verify::verify_second_stage()?;
+    rollback::check(fuses, manifest.header.security_version)?;
+
+    Ok(manifest)
+}
+
+/// Loads `slot` into memory and checks whether it may be booted.
+///
+/// Returns `Ok(Err(_))` with the reason if the slot was loaded, but failed
+/// verification.
+fn load_slot<D: BlockDevice, F: FuseArray>(
+    device: &mut D,
+    fuses: &F,
+    slot: Slot,
+) -> Result<Result<Manifest, BootError>, Error> {
+    let buffer =
+        unsafe { slice::from_raw_parts_mut(BOOTLOADER_START as *mut u8, SLOT_BLOCKS * BLOCK_SIZE) };
+    device.read_blocks(SLOT_LBAS[slot.index()], buffer)?;
+
+    Ok(verify_loaded(fuses))
+}
+
+/// Clears the area a slot is loaded to, so that a rejected image does not linger in
+/// memory.
+pub fn clear_loaded() {
+    unsafe {
+        memory::clear_mem(BOOTLOADER_START..BOOTLOADER_START.add(SLOT_BLOCKS * BLOCK_SIZE / 4));
+    }
+}
+
+/// Writes the second stage in memory to `slot` and makes it the preferred slot.
+///
+/// The second stage is expected to have passed [`verify_loaded`] before.
+///
+/// [`verify_loaded`]: fn.verify_loaded.html
+pub fn install_slot<D: BlockDevice>(device: &mut D, slot: Slot) -> Result<(), Error> {
+    let buffer =
+        unsafe { slice::from_raw_parts(BOOTLOADER_START as *const u8, SLOT_BLOCKS * BLOCK_SIZE) };
+    device.write_blocks(SLOT_LBAS[slot.index()], buffer)?;
+
+    let mut metadata = read_metadata(device)?;
+    metadata.activate(slot);
+    write_metadata(device, &metadata)
+}
+
+/// Loads and verifies the second-stage bootloader from the best bootable slot.
+///
+/// Every attempt is recorded in the slot metadata before the slot is loaded. A slot
+/// that fails verification is passed to `rejected` along with the reason, cleared from
+/// memory and marked unbootable, in which case the other slot is tried instead.
+///
+/// The second stage is expected to mark the slot successful once it is healthy, see
+/// the [`slot`] crate.
+///
+/// [`slot`]: ../../slot/index.html
+pub fn load_second_stage<D: BlockDevice, F: FuseArray>(
+    device: &mut D,
+    fuses: &F,
+    mut rejected: impl FnMut(Slot, BootError),
+) -> Result<(Slot, Manifest), Error> {
+    let mut metadata = read_metadata(device)?;
+
+    loop {
+        let slot = metadata.begin_attempt().ok_or(Error::NoBootableSlot)?;
+        write_metadata(device, &metadata)?;
+
+        match load_slot(device, fuses, slot)? {
+            Ok(manifest) => return Ok((slot, manifest)),
+            Err(error) => {
+                rejected(slot, error);
+                clear_loaded();
+            }
+        }
+
+        metadata.mark_unbootable(slot);
+        write_metadata(device, &metadata)?;
+    }
+}
@@ -0,0 +1,44 @@
+//! Hardware implementations of the bus abstractions used by the driver modules.
+//!
+//! Keeping these out of the drivers themselves allows the drivers to be exercised
+//! against simulated devices on the build host.
+
+use libtegra::i2c::{Error, I2c};
+use libtegra::timer::{self, usleep};
+
+use crate::deadline::Clock;
+use crate::sequencer::{Delay, I2cBus};
+
+impl I2cBus for I2c {
+    type Error = Error;
+
+    fn read_byte(&mut self, device: u32, register: u8) -> Result<u8, Error> {
+        I2c::read_byte(self, device, register)
+    }
+
+    fn write_byte(&mut self, device: u32, register: u8, value: u8) -> Result<(), Error> {
+        I2c::write_byte(self, device, register, value)
+    }
+
+    fn read_word(&mut self, device: u32, register: u8) -> Result<u16, Error> {
+        let mut buffer = [0; 2];
+        I2c::read(self, device, register, &mut buffer)?;
+        Ok(u16::from_le_bytes(buffer))
+    }
+}
+
+/// Busy-wait delays and timestamps based on the microsecond timer.
+pub struct TimerDelay;
+
+impl Delay for TimerDelay {
+    fn delay_us(&self, us: u32) {
+        usleep(us);
+    }
+}
+
+impl Clock for TimerDelay {
+    fn now_us(&self) -> u32 {
+        let timer = unsafe { &*timer::timerus::REGISTERS };
+        timer.TIMERUS_CNTR_1US_0.get()
+    }
+}
@@ -0,0 +1,221 @@
+//! Configuration of the system clocks and the PLLs.
+//!
+//! The register values are derived from the rates in the [`tree`] module. SCLK starts
+//! out at [`BOOT_RATES`] and is raised to [`FULL_RATES`] once the PMIC is configured.
+//! The dividers of the PLLs are selected by the [`pll`] module.
+//!
+//! [`tree`]: tree/index.html
+//! [`pll`]: pll/index.html
+//! [`BOOT_RATES`]: constant.BOOT_RATES.html
+//! [`FULL_RATES`]: constant.FULL_RATES.html
+
+pub mod pll;
+pub mod tree;
+
+use core::ptr;
+
+use libtegra::car;
+use libtegra::timer::usleep;
+
+use self::pll::{Dividers, Pll};
+use self::tree::{BurstPolicy, Rates, SuperDivider, SystemClocks, SystemRate, SystemSource};
+use crate::bus::TimerDelay;
+use crate::deadline;
+use crate::error::{BootError, ErrorKind, Stage};
+
+/// The base address of the Clock and Reset Controller.
+const CAR_BASE: usize = 0x6000_6000;
+
+const PLL_BYPASS: u32 = 1 << 31;
+const PLL_ENABLE: u32 = 1 << 30;
+const PLL_LOCK: u32 = 1 << 27;
+
+/// The time a PLL takes to power up after leaving IDDQ, in microseconds.
+const PLL_IDDQ_DELAY_US: u32 = 5;
+/// The time a PLL may take to lock, in microseconds.
+const PLL_LOCK_TIMEOUT_US: u32 = 1000;
+
+/// The rates of the system clocks when running from CLK_M.
+pub const CLK_M_RATES: Rates = Rates {
+    sclk_hz: tree::CLK_M_HZ,
+    hclk_hz: tree::CLK_M_HZ,
+    pclk_hz: tree::CLK_M_HZ,
+};
+
+/// The rates of the system clocks during early boot, from PLLP_OUT2.
+pub const BOOT_RATES: Rates = Rates {
+    sclk_hz: 204_000_000,
+    hclk_hz: 204_000_000,
+    pclk_hz: 68_000_000,
+};
+
+/// The rates of the system clocks at full speed, from PLLP_OUT0.
+pub const FULL_RATES: Rates = Rates {
+    sclk_hz: 408_000_000,
+    hclk_hz: 408_000_000,
+    pclk_hz: 136_000_000,
+};
+
+/// The dividers to apply while switching sources, which keep HCLK and PCLK in range
+/// for any source.
+const SAFE_SYSTEM_RATE: SystemRate = SystemRate {
+    hclk_divisor: 2,
+    pclk_divisor: 1,
+};
+
+/// The registers of a PLL, as offsets into the CAR and bits within them.
+struct PllRegisters {
+    base: usize,
+    /// Powers the PLL down.
+    iddq: (usize, u32),
+    /// Enables lock detection.
+    lock_enable: (usize, u32),
+    /// Holds the PLL in reset, if it has a reset of its own.
+    reset: Option<(usize, u32)>,
+}
+
+fn registers(pll: Pll) -> PllRegisters {
+    match pll {
+        Pll::P => PllRegisters {
+            base: 0xA0,
+            iddq: (0xAC, 1 << 3),
+            lock_enable: (0xAC, 1 << 18),
+            reset: None,
+        },
+        Pll::C => PllRegisters {
+            base: 0x80,
+            iddq: (0x8C, 1 << 27),
+            lock_enable: (0x88, 1 << 24),
+            reset: Some((0x88, 1 << 30)),
+        },
+        Pll::M => PllRegisters {
+            base: 0x90,
+            iddq: (0x9C, 1 << 5),
+            lock_enable: (0x9C, 1 << 4),
+            reset: None,
+        },
+        Pll::MB => PllRegisters {
+            base: 0x5E8,
+            iddq: (0x5EC, 1 << 17),
+            lock_enable: (0x5EC, 1 << 16),
+            reset: None,
+        },
+    }
+}
+
+fn read_car_reg(offset: usize) -> u32 {
+    unsafe { ptr::read_volatile((CAR_BASE + offset) as *const u32) }
+}
+
+fn write_car_reg(offset: usize, value: u32) {
+    unsafe { ptr::write_volatile((CAR_BASE + offset) as *mut u32, value) }
+}
+
+fn modify_car_reg(offset: usize, clear: u32, set: u32) {
+    write_car_reg(offset, (read_car_reg(offset) & !clear) | set);
+}
+
+/// Derives the system clocks from `source` at the `target` rates.
+pub fn set_system_clocks(source: SystemSource, target: Rates) -> Result<(), BootError> {
+    let car = unsafe { &*car::REGISTERS };
+    let clocks = SystemClocks::configure(source, target)
+        .ok_or_else(|| BootError::new(ErrorKind::Config, Stage::Clock))?;
+
+    // Slow down HCLK before switching the source.
+    car.CLK_RST_CONTROLLER_CLK_SYSTEM_RATE_0
+        .set(SAFE_SYSTEM_RATE.value());
+
+    // Switch the source, then apply the dividers.
+    car.CLK_RST_CONTROLLER_SCLK_BURST_POLICY_0
+        .set(clocks.policy.value());
+    car.CLK_RST_CONTROLLER_SUPER_SCLK_DIVIDER_0
+        .set(clocks.divider.value());
+    car.CLK_RST_CONTROLLER_CLK_SYSTEM_RATE_0
+        .set(clocks.rate.value());
+
+    Ok(())
+}
+
+/// Reads back the effective rates of the system clocks.
+///
+/// Returns `None` if SCLK is fed by a source that is not modelled.
+pub fn system_rates() -> Option<Rates> {
+    let car = unsafe { &*car::REGISTERS };
+    let clocks = SystemClocks {
+        policy: BurstPolicy::decode(car.CLK_RST_CONTROLLER_SCLK_BURST_POLICY_0.get())?,
+        divider: SuperDivider::decode(car.CLK_RST_CONTROLLER_SUPER_SCLK_DIVIDER_0.get()),
+        rate: SystemRate::decode(car.CLK_RST_CONTROLLER_CLK_SYSTEM_RATE_0.get()),
+    };
+
+    Some(clocks.rates())
+}
+
+/// Brings up `pll` as close to `rate_hz` as it gets without exceeding it and waits
+/// for it to lock.
+///
+/// Returns the rate the PLL runs at. Nothing may be fed by the PLL while it is
+/// reprogrammed.
+pub fn enable_pll(pll: Pll, rate_hz: u32) -> Result<u32, BootError> {
+    let dividers = pll::dividers(pll, rate_hz)
+        .ok_or_else(|| BootError::new(ErrorKind::Config, Stage::Clock))?;
+    let fields = dividers
+        .encode(pll)
+        .ok_or_else(|| BootError::new(ErrorKind::Config, Stage::Clock))?;
+    let registers = registers(pll);
+
+    // Stop the PLL and hold it in reset while it is reprogrammed.
+    modify_car_reg(registers.base, PLL_ENABLE | PLL_BYPASS, 0);
+    if let Some((offset, bit)) = registers.reset {
+        modify_car_reg(offset, 0, bit);
+    }
+
+    // Power up the PLL and give it time to settle.
+    let (offset, bit) = registers.iddq;
+    modify_car_reg(offset, bit, 0);
+    usleep(PLL_IDDQ_DELAY_US);
+
+    // Program the dividers and enable lock detection.
+    write_car_reg(registers.base, fields);
+    let (offset, bit) = registers.lock_enable;
+    modify_car_reg(offset, 0, bit);
+
+    // Release the PLL and wait for it to lock.
+    if let Some((offset, bit)) = registers.reset {
+        modify_car_reg(offset, bit, 0);
+    }
+    modify_car_reg(registers.base, 0, PLL_ENABLE);
+    let locked = deadline::poll_until(
+        &TimerDelay,
+        || read_car_reg(registers.base) & PLL_LOCK != 0,
+        PLL_LOCK_TIMEOUT_US,
+    );
+
+    if locked.is_err() {
+        disable_pll(pll);
+        return Err(BootError::new(ErrorKind::Timeout, Stage::Clock));
+    }
+
+    Ok(dividers.rate_hz())
+}
+
+/// Stops `pll` and powers it down.
+pub fn disable_pll(pll: Pll) {
+    let registers = registers(pll);
+
+    modify_car_reg(registers.base, PLL_ENABLE, 0);
+    let (offset, bit) = registers.iddq;
+    modify_car_reg(offset, 0, bit);
+}
+
+/// Reads back the rate `pll` runs at, if it is enabled.
+pub fn pll_rate_hz(pll: Pll) -> Option<u32> {
+    let base = read_car_reg(registers(pll).base);
+
+    if base & PLL_ENABLE == 0 {
+        None
+    } else if base & PLL_BYPASS != 0 {
+        Some(tree::OSC_HZ)
+    } else {
+        Dividers::decode(pll, base).map(|dividers| dividers.rate_hz())
+    }
+}
@@ -0,0 +1,309 @@
+//! Divider selection for the PLLs of the Tegra X1.
+//!
+//! Every PLL multiplies its reference, the crystal oscillator, as
+//! `OSC / M * N / P`. The comparison frequency `OSC / M` and the VCO frequency
+//! `OSC / M * N` have to stay within the limits of the PLL, and each PLL only
+//! supports a fixed set of post dividers `P`.
+//!
+//! The limits follow the ones of the Linux clock driver for the Tegra X1. This module
+//! does not access any hardware.
+
+use super::tree::OSC_HZ;
+
+/// The post dividers of PLLs with a quasi-linear P field.
+const QLIN_P_VALUES: [u32; 17] = [1, 2, 3, 4, 5, 6, 8, 9, 10, 12, 15, 16, 18, 20, 24, 30, 32];
+
+const M_WIDTH: u32 = 8;
+const N_WIDTH: u32 = 8;
+const P_SHIFT: u32 = 20;
+const P_WIDTH: u32 = 5;
+
+/// The PLLs that can be configured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pll {
+    /// The peripheral PLL, which feeds SCLK and most peripherals.
+    P,
+    /// The general purpose PLL.
+    C,
+    /// The memory PLL.
+    M,
+    /// The secondary memory PLL, used while switching memory frequencies.
+    MB,
+}
+
+/// The limits of a PLL, in Hz.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub cf_min_hz: u32,
+    pub cf_max_hz: u32,
+    pub vco_min_hz: u32,
+    pub vco_max_hz: u32,
+    /// The supported post dividers, in the order of their encoding.
+    pub p_values: &'static [u32],
+}
+
+impl Pll {
+    /// Gets the limits of the PLL.
+    pub fn limits(self) -> Limits {
+        match self {
+            Pll::P => Limits {
+                cf_min_hz: 1_000_000,
+                cf_max_hz: 6_000_000,
+                vco_min_hz: 350_000_000,
+                vco_max_hz: 700_000_000,
+                p_values: &[1],
+            },
+            Pll::C => Limits {
+                cf_min_hz: 12_000_000,
+                cf_max_hz: 50_000_000,
+                vco_min_hz: 600_000_000,
+                vco_max_hz: 1_200_000_000,
+                p_values: &QLIN_P_VALUES,
+            },
+            Pll::M | Pll::MB => Limits {
+                cf_min_hz: 9_600_000,
+                cf_max_hz: 19_200_000,
+                vco_min_hz: 800_000_000,
+                vco_max_hz: 1_866_000_000,
+                p_values: &[1, 2],
+            },
+        }
+    }
+
+    /// Gets the position of the N field in the base register.
+    fn n_shift(self) -> u32 {
+        match self {
+            Pll::P | Pll::C => 10,
+            Pll::M | Pll::MB => 8,
+        }
+    }
+}
+
+/// The dividers of a PLL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dividers {
+    pub m: u32,
+    pub n: u32,
+    pub p: u32,
+}
+
+impl Dividers {
+    /// Computes the output rate of the PLL in Hz.
+    pub fn rate_hz(&self) -> u32 {
+        (OSC_HZ as u64 * self.n as u64 / (self.m as u64 * self.p as u64)) as u32
+    }
+
+    /// Encodes the dividers into the fields of the base register of `pll`.
+    ///
+    /// Returns `None` if the post divider is not supported by `pll`.
+    pub fn encode(&self, pll: Pll) -> Option<u32> {
+        let p = pll.limits().p_values.iter().position(|&p| p == self.p)? as u32;
+
+        Some(p << P_SHIFT | self.n << pll.n_shift() | self.m)
+    }
+
+    /// Decodes the dividers from the value of the base register of `pll`.
+    ///
+    /// Returns `None` if the fields do not hold a valid configuration.
+    pub fn decode(pll: Pll, base: u32) -> Option<Self> {
+        let m = base & mask(M_WIDTH);
+        let n = base >> pll.n_shift() & mask(N_WIDTH);
+        let p = base >> P_SHIFT & mask(P_WIDTH);
+
+        match pll.limits().p_values.get(p as usize) {
+            Some(&p) if m != 0 && n != 0 => Some(Dividers { m, n, p }),
+            _ => None,
+        }
+    }
+}
+
+fn mask(width: u32) -> u32 {
+    (1 << width) - 1
+}
+
+/// Selects the dividers that bring `pll` closest to `target_hz` without exceeding
+/// it.
+///
+/// Among equally close configurations, the one with the highest comparison frequency
+/// is preferred, as it has the least jitter. Returns `None` if no configuration stays
+/// within the limits of the PLL.
+pub fn dividers(pll: Pll, target_hz: u32) -> Option<Dividers> {
+    let limits = pll.limits();
+    let mut best: Option<Dividers> = None;
+
+    for &p in limits.p_values {
+        let vco_hz = target_hz as u64 * p as u64;
+        if vco_hz < limits.vco_min_hz as u64 || vco_hz > limits.vco_max_hz as u64 {
+            continue;
+        }
+
+        for m in 1..=mask(M_WIDTH) {
+            let cf_hz = OSC_HZ / m;
+            if cf_hz > limits.cf_max_hz {
+                continue;
+            }
+            if cf_hz < limits.cf_min_hz {
+                break;
+            }
+
+            // Round N down, so the output never exceeds the target.
+            let n = (vco_hz * m as u64 / OSC_HZ as u64) as u32;
+            if n == 0 || n > mask(N_WIDTH) {
+                continue;
+            }
+
+            if (OSC_HZ as u64 * n as u64 / m as u64) < limits.vco_min_hz as u64 {
+                continue;
+            }
+
+            let candidate = Dividers { m, n, p };
+            let better = best.map_or(true, |best| {
+                let (rate, best_rate) = (candidate.rate_hz(), best.rate_hz());
+                rate > best_rate || (rate == best_rate && candidate.m < best.m)
+            });
+            if better {
+                best = Some(candidate);
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLLS: [Pll; 4] = [Pll::P, Pll::C, Pll::M, Pll::MB];
+
+    /// Checks that `dividers` stay within the limits of `pll` and do not exceed
+    /// `target_hz`.
+    fn assert_valid(pll: Pll, dividers: Dividers, target_hz: u32) {
+        let limits = pll.limits();
+        let cf_hz = OSC_HZ / dividers.m;
+        let vco_hz = OSC_HZ as u64 * dividers.n as u64 / dividers.m as u64;
+
+        assert!(dividers.rate_hz() <= target_hz, "{:?} {:?}", pll, dividers);
+        assert!((limits.cf_min_hz..=limits.cf_max_hz).contains(&cf_hz));
+        assert!((limits.vco_min_hz as u64..=limits.vco_max_hz as u64).contains(&vco_hz));
+        assert!(limits.p_values.contains(&dividers.p));
+    }
+
+    #[test]
+    fn selects_exact_dividers() {
+        // 38.4MHz * 85 / 8 = 408MHz, at the highest comparison frequency.
+        assert_eq!(
+            dividers(Pll::P, 408_000_000),
+            Some(Dividers { m: 8, n: 85, p: 1 })
+        );
+        assert_eq!(
+            dividers(Pll::C, 480_000_000),
+            Some(Dividers { m: 1, n: 25, p: 2 })
+        );
+        // The closest to 300MHz within the VCO range of 600MHz to 1.2GHz is 298.67MHz.
+        assert_eq!(
+            dividers(Pll::C, 300_000_000),
+            Some(Dividers { m: 3, n: 70, p: 3 })
+        );
+        assert_eq!(
+            dividers(Pll::M, 1_600_000_000),
+            Some(Dividers { m: 3, n: 125, p: 1 })
+        );
+        assert_eq!(
+            dividers(Pll::MB, 800_000_000),
+            Some(Dividers { m: 3, n: 125, p: 2 })
+        );
+    }
+
+    #[test]
+    fn rounds_down_to_closest_rate() {
+        for &pll in PLLS.iter() {
+            for target_hz in (100_000_000..2_000_000_000).step_by(37_654_321) {
+                let selected = match dividers(pll, target_hz) {
+                    Some(selected) => selected,
+                    None => continue,
+                };
+                assert_valid(pll, selected, target_hz);
+
+                // No configuration within the limits comes closer.
+                let limits = pll.limits();
+                for &p in limits.p_values {
+                    for m in 1..=mask(M_WIDTH) {
+                        for n in 1..=mask(N_WIDTH) {
+                            let vco_hz = OSC_HZ as u64 * n as u64 / m as u64;
+                            let rate = vco_hz / p as u64;
+                            if rate > selected.rate_hz() as u64 && rate <= target_hz as u64 {
+                                let cf_hz = OSC_HZ / m;
+                                assert!(
+                                    cf_hz < limits.cf_min_hz
+                                        || cf_hz > limits.cf_max_hz
+                                        || vco_hz < limits.vco_min_hz as u64
+                                        || vco_hz > limits.vco_max_hz as u64,
+                                    "{:?} {:?} beats {:?}",
+                                    pll,
+                                    Dividers { m, n, p },
+                                    selected
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unreachable_rates() {
+        assert_eq!(dividers(Pll::P, 800_000_000), None);
+        assert_eq!(dividers(Pll::P, 300_000_000), None);
+        assert_eq!(dividers(Pll::C, 10_000_000), None);
+        assert_eq!(dividers(Pll::M, 100_000_000), None);
+        assert_eq!(dividers(Pll::M, 2_000_000_000), None);
+        assert_eq!(dividers(Pll::M, 0), None);
+    }
+
+    #[test]
+    fn encodes_dividers() {
+        let pllc = Dividers { m: 2, n: 125, p: 8 };
+        assert_eq!(pllc.encode(Pll::C), Some(6 << 20 | 125 << 10 | 2));
+
+        let pllm = Dividers { m: 3, n: 125, p: 2 };
+        assert_eq!(pllm.encode(Pll::M), Some(1 << 20 | 125 << 8 | 3));
+
+        // PLLP has no post divider.
+        assert_eq!(pllc.encode(Pll::P), None);
+        assert_eq!(pllm.encode(Pll::P), None);
+    }
+
+    #[test]
+    fn decodes_dividers() {
+        for &pll in PLLS.iter() {
+            for &p in pll.limits().p_values {
+                let dividers = Dividers { m: 4, n: 200, p };
+                let base = dividers.encode(pll).unwrap();
+                // Bits outside of the fields, e.g. enable and lock, are ignored.
+                assert_eq!(Dividers::decode(pll, base | 1 << 30), Some(dividers));
+            }
+        }
+
+        assert_eq!(Dividers::decode(Pll::P, 0), None);
+        assert_eq!(Dividers::decode(Pll::P, 85 << 10), None);
+        assert_eq!(Dividers::decode(Pll::P, 8), None);
+        assert_eq!(Dividers::decode(Pll::P, 1 << 20 | 85 << 10 | 8), None);
+        assert_eq!(Dividers::decode(Pll::M, 2 << 20 | 125 << 8 | 3), None);
+    }
+
+    #[test]
+    fn computes_rates() {
+        assert_eq!(Dividers { m: 8, n: 85, p: 1 }.rate_hz(), 408_000_000);
+        assert_eq!(
+            Dividers {
+                m: 255,
+                n: 1,
+                p: 32,
+            }
+            .rate_hz(),
+            4705
+        );
+    }
+}
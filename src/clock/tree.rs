@@ -0,0 +1,539 @@
+//! The system clock tree of the Tegra X1 and the register values that configure it.
+//!
+//! SCLK, which clocks the BPMP and the AVP bus, is selected by a burst policy from
+//! one of several sources and then scaled by the super clock divider. HCLK is derived
+//! from SCLK and PCLK from HCLK, each through an integer divider. The microsecond
+//! timer and the timestamp counter are fed by CLK_M, which is the oscillator divided
+//! by two.
+//!
+//! All values are computed from rates in Hz, so they can be checked on the build host.
+//! This module does not access any hardware.
+
+/// The rate of the crystal oscillator.
+pub const OSC_HZ: u32 = 38_400_000;
+/// The rate of CLK_M, the oscillator divided by two.
+pub const CLK_M_HZ: u32 = OSC_HZ / 2;
+/// The rate of CLK_S, the 32kHz clock of the PMIC.
+pub const CLK_S_HZ: u32 = 32_768;
+/// The rate of PLLP, as configured by the boot ROM.
+pub const PLLP_HZ: u32 = 408_000_000;
+
+/// The rate of the microsecond timer.
+pub const TIMERUS_HZ: u32 = 1_000_000;
+
+/// The number of CLK_S cycles the timestamp counter multiplier is expressed in.
+const TSC_MULT_CLK_S_CYCLES: u32 = 16;
+
+const BURST_STATE_SHIFT: u32 = 28;
+const BURST_SOURCE_BITS: u32 = 4;
+const BURST_SOURCE_MASK: u32 = 0xF;
+
+const SUPER_DIVIDER_ENABLE: u32 = 1 << 31;
+const SUPER_DIVIDEND_SHIFT: u32 = 8;
+
+const HCLK_DIVISOR_SHIFT: u32 = 4;
+const MAX_HCLK_DIVISOR: u32 = 8;
+const MAX_PCLK_DIVISOR: u32 = 4;
+
+/// The largest dividend or divisor of a fractional divider.
+const MAX_FRACTION: u32 = 256;
+
+/// A source of SCLK.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemSource {
+    /// The oscillator divided by two.
+    ClkM,
+    /// PLLP at full rate.
+    PllpOut0,
+    /// PLLP divided by two.
+    PllpOut2,
+    /// The 32kHz clock.
+    ClkS,
+}
+
+impl SystemSource {
+    /// Gets the rate of the source in Hz.
+    pub fn rate_hz(self) -> u32 {
+        match self {
+            SystemSource::ClkM => CLK_M_HZ,
+            SystemSource::PllpOut0 => PLLP_HZ,
+            SystemSource::PllpOut2 => PLLP_HZ / 2,
+            SystemSource::ClkS => CLK_S_HZ,
+        }
+    }
+
+    fn select(self) -> u32 {
+        match self {
+            SystemSource::ClkM => 0,
+            SystemSource::PllpOut0 => 3,
+            SystemSource::PllpOut2 => 4,
+            SystemSource::ClkS => 6,
+        }
+    }
+
+    fn from_select(select: u32) -> Option<Self> {
+        match select {
+            0 => Some(SystemSource::ClkM),
+            3 => Some(SystemSource::PllpOut0),
+            4 => Some(SystemSource::PllpOut2),
+            6 => Some(SystemSource::ClkS),
+            _ => None,
+        }
+    }
+}
+
+/// The burst policy of SCLK, which keeps the system in the run state on `source`.
+///
+/// The same source is programmed for the idle, IRQ and FIQ states, so that SCLK does
+/// not change if the state does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BurstPolicy {
+    pub source: SystemSource,
+}
+
+impl BurstPolicy {
+    /// The run state of the policy.
+    const STATE_RUN: u32 = 2;
+
+    /// Gets the value of the `SCLK_BURST_POLICY` register.
+    pub fn value(&self) -> u32 {
+        let select = self.source.select();
+        let sources = (0..4).fold(0, |value, state| {
+            value | select << (state * BURST_SOURCE_BITS)
+        });
+
+        Self::STATE_RUN << BURST_STATE_SHIFT | sources
+    }
+
+    /// Decodes the source of the current state from a `SCLK_BURST_POLICY` value.
+    pub fn decode(value: u32) -> Option<Self> {
+        // Each state is a single bit and selects the source field of the same index.
+        let state = (value >> BURST_STATE_SHIFT).trailing_zeros();
+        if state >= 4 {
+            return None;
+        }
+
+        let select = value >> (state * BURST_SOURCE_BITS) & BURST_SOURCE_MASK;
+        SystemSource::from_select(select).map(|source| BurstPolicy { source })
+    }
+}
+
+/// A fractional divider that scales a rate by `dividend / divisor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fraction {
+    pub dividend: u32,
+    pub divisor: u32,
+}
+
+impl Fraction {
+    /// A divider that passes the rate through.
+    pub const ONE: Fraction = Fraction {
+        dividend: 1,
+        divisor: 1,
+    };
+
+    /// Finds the divider that scales `source_hz` to exactly `target_hz`.
+    ///
+    /// Returns `None` if the ratio cannot be expressed with a dividend and a divisor of
+    /// at most 256.
+    pub fn between(source_hz: u32, target_hz: u32) -> Option<Self> {
+        if source_hz == 0 || target_hz == 0 {
+            return None;
+        }
+
+        let gcd = gcd(source_hz, target_hz);
+        let fraction = Fraction {
+            dividend: target_hz / gcd,
+            divisor: source_hz / gcd,
+        };
+
+        if fraction.dividend <= MAX_FRACTION && fraction.divisor <= MAX_FRACTION {
+            Some(fraction)
+        } else {
+            None
+        }
+    }
+
+    /// Applies the divider to `rate_hz`.
+    pub fn apply(&self, rate_hz: u32) -> u32 {
+        (rate_hz as u64 * self.dividend as u64 / self.divisor as u64) as u32
+    }
+
+    /// Encodes the divider with the dividend in bits 15:8 and the divisor in bits 7:0,
+    /// both minus one.
+    fn encode(&self) -> u32 {
+        (self.dividend - 1) << SUPER_DIVIDEND_SHIFT | (self.divisor - 1)
+    }
+
+    fn decode(value: u32) -> Self {
+        Fraction {
+            dividend: (value >> SUPER_DIVIDEND_SHIFT & 0xFF) + 1,
+            divisor: (value & 0xFF) + 1,
+        }
+    }
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        let remainder = a % b;
+        a = b;
+        b = remainder;
+    }
+
+    a
+}
+
+/// The super clock divider, which skips SCLK cycles to slow it down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SuperDivider(pub Fraction);
+
+impl SuperDivider {
+    /// Gets the value of the `SUPER_SCLK_DIVIDER` register.
+    pub fn value(&self) -> u32 {
+        SUPER_DIVIDER_ENABLE | self.0.encode()
+    }
+
+    /// Decodes a `SUPER_SCLK_DIVIDER` value. A disabled divider passes SCLK through.
+    pub fn decode(value: u32) -> Self {
+        if value & SUPER_DIVIDER_ENABLE == 0 {
+            SuperDivider(Fraction::ONE)
+        } else {
+            SuperDivider(Fraction::decode(value))
+        }
+    }
+}
+
+/// The integer dividers of HCLK from SCLK and of PCLK from HCLK.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SystemRate {
+    pub hclk_divisor: u32,
+    pub pclk_divisor: u32,
+}
+
+impl SystemRate {
+    /// Gets the value of the `CLK_SYSTEM_RATE` register.
+    pub fn value(&self) -> u32 {
+        (self.hclk_divisor - 1) << HCLK_DIVISOR_SHIFT | (self.pclk_divisor - 1)
+    }
+
+    /// Decodes a `CLK_SYSTEM_RATE` value.
+    pub fn decode(value: u32) -> Self {
+        SystemRate {
+            hclk_divisor: (value >> HCLK_DIVISOR_SHIFT & 0x7) + 1,
+            pclk_divisor: (value & 0x3) + 1,
+        }
+    }
+}
+
+/// The rates of the system clocks in Hz.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rates {
+    pub sclk_hz: u32,
+    pub hclk_hz: u32,
+    pub pclk_hz: u32,
+}
+
+/// A complete configuration of the system clocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SystemClocks {
+    pub policy: BurstPolicy,
+    pub divider: SuperDivider,
+    pub rate: SystemRate,
+}
+
+impl SystemClocks {
+    /// Finds the configuration that derives the `target` rates from `source`.
+    ///
+    /// Returns `None` if a rate is higher than the one it is derived from or cannot
+    /// be reached exactly.
+    pub fn configure(source: SystemSource, target: Rates) -> Option<Self> {
+        let fraction = Fraction::between(source.rate_hz(), target.sclk_hz)?;
+        let hclk_divisor = exact_divisor(target.sclk_hz, target.hclk_hz, MAX_HCLK_DIVISOR)?;
+        let pclk_divisor = exact_divisor(target.hclk_hz, target.pclk_hz, MAX_PCLK_DIVISOR)?;
+
+        // The super divider can only skip cycles, not add them.
+        if fraction.dividend > fraction.divisor {
+            return None;
+        }
+
+        Some(SystemClocks {
+            policy: BurstPolicy { source },
+            divider: SuperDivider(fraction),
+            rate: SystemRate {
+                hclk_divisor,
+                pclk_divisor,
+            },
+        })
+    }
+
+    /// Computes the rates that this configuration produces.
+    pub fn rates(&self) -> Rates {
+        let sclk_hz = self.divider.0.apply(self.policy.source.rate_hz());
+        let hclk_hz = sclk_hz / self.rate.hclk_divisor;
+
+        Rates {
+            sclk_hz,
+            hclk_hz,
+            pclk_hz: hclk_hz / self.rate.pclk_divisor,
+        }
+    }
+}
+
+fn exact_divisor(source_hz: u32, target_hz: u32, max: u32) -> Option<u32> {
+    if target_hz == 0 || source_hz % target_hz != 0 {
+        return None;
+    }
+
+    let divisor = source_hz / target_hz;
+    if (1..=max).contains(&divisor) {
+        Some(divisor)
+    } else {
+        None
+    }
+}
+
+/// Gets the value of the `TIMERUS_USEC_CFG` register, which derives the microsecond
+/// timer from `clk_m_hz`.
+pub fn usec_config(clk_m_hz: u32) -> Option<u32> {
+    Fraction::between(clk_m_hz, TIMERUS_HZ).map(|fraction| fraction.encode())
+}
+
+/// Gets the value of the `TSC_MULT` field, the number of `counter_hz` cycles in 16
+/// cycles of CLK_S.
+pub fn tsc_mult(counter_hz: u32) -> Option<u32> {
+    let cycles = counter_hz as u64 * TSC_MULT_CLK_S_CYCLES as u64;
+    if cycles % CLK_S_HZ as u64 != 0 {
+        return None;
+    }
+
+    let mult = cycles / CLK_S_HZ as u64;
+    if mult <= 0xFFFF {
+        Some(mult as u32)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCES: [SystemSource; 4] = [
+        SystemSource::ClkM,
+        SystemSource::PllpOut0,
+        SystemSource::PllpOut2,
+        SystemSource::ClkS,
+    ];
+
+    #[test]
+    fn encodes_burst_policy() {
+        let policy = BurstPolicy {
+            source: SystemSource::PllpOut0,
+        };
+        assert_eq!(policy.value(), 0x2000_3333);
+
+        let policy = BurstPolicy {
+            source: SystemSource::ClkM,
+        };
+        assert_eq!(policy.value(), 0x2000_0000);
+
+        for &source in SOURCES.iter() {
+            let policy = BurstPolicy { source };
+            assert_eq!(BurstPolicy::decode(policy.value()), Some(policy));
+        }
+    }
+
+    #[test]
+    fn decodes_burst_policy_of_current_state() {
+        // Idle on CLK_S, run on PLLP_OUT2.
+        assert_eq!(
+            BurstPolicy::decode(0x1000_0046).map(|policy| policy.source),
+            Some(SystemSource::ClkS)
+        );
+        assert_eq!(
+            BurstPolicy::decode(0x2000_0046).map(|policy| policy.source),
+            Some(SystemSource::PllpOut2)
+        );
+        // No state, and a source that is not known.
+        assert_eq!(BurstPolicy::decode(0x0000_0000), None);
+        assert_eq!(BurstPolicy::decode(0x2000_0010), None);
+    }
+
+    #[test]
+    fn finds_exact_fractions() {
+        assert_eq!(Fraction::between(PLLP_HZ, PLLP_HZ), Some(Fraction::ONE));
+        assert_eq!(
+            Fraction::between(PLLP_HZ, 204_000_000),
+            Some(Fraction {
+                dividend: 1,
+                divisor: 2,
+            })
+        );
+        assert_eq!(
+            Fraction::between(CLK_M_HZ, TIMERUS_HZ),
+            Some(Fraction {
+                dividend: 5,
+                divisor: 96,
+            })
+        );
+        assert_eq!(
+            Fraction::between(OSC_HZ, TIMERUS_HZ),
+            Some(Fraction {
+                dividend: 5,
+                divisor: 192,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_unreachable_fractions() {
+        assert_eq!(Fraction::between(0, TIMERUS_HZ), None);
+        assert_eq!(Fraction::between(CLK_M_HZ, 0), None);
+        assert_eq!(Fraction::between(CLK_S_HZ, TIMERUS_HZ), None);
+        assert_eq!(Fraction::between(PLLP_HZ, 100_000_001), None);
+    }
+
+    #[test]
+    fn applies_fractions() {
+        let fraction = Fraction::between(CLK_M_HZ, TIMERUS_HZ).unwrap();
+        assert_eq!(fraction.apply(CLK_M_HZ), TIMERUS_HZ);
+        // Does not overflow for the fastest sources.
+        let fraction = Fraction {
+            dividend: 255,
+            divisor: 256,
+        };
+        assert_eq!(fraction.apply(u32::MAX), 4_278_190_079);
+    }
+
+    #[test]
+    fn encodes_super_divider() {
+        let divider = SuperDivider(Fraction {
+            dividend: 1,
+            divisor: 2,
+        });
+        assert_eq!(divider.value(), 0x8000_0001);
+        assert_eq!(SuperDivider::decode(divider.value()), divider);
+
+        // A disabled divider ignores its fraction.
+        assert_eq!(
+            SuperDivider::decode(0x0000_0001),
+            SuperDivider(Fraction::ONE)
+        );
+    }
+
+    #[test]
+    fn encodes_system_rate() {
+        let rate = SystemRate {
+            hclk_divisor: 1,
+            pclk_divisor: 3,
+        };
+        assert_eq!(rate.value(), 0x02);
+
+        for hclk_divisor in 1..=MAX_HCLK_DIVISOR {
+            for pclk_divisor in 1..=MAX_PCLK_DIVISOR {
+                let rate = SystemRate {
+                    hclk_divisor,
+                    pclk_divisor,
+                };
+                assert_eq!(SystemRate::decode(rate.value()), rate);
+            }
+        }
+    }
+
+    #[test]
+    fn configures_system_clocks() {
+        let rates = Rates {
+            sclk_hz: 204_000_000,
+            hclk_hz: 204_000_000,
+            pclk_hz: 68_000_000,
+        };
+        let clocks = SystemClocks::configure(SystemSource::PllpOut2, rates).unwrap();
+        assert_eq!(clocks.divider, SuperDivider(Fraction::ONE));
+        assert_eq!(
+            clocks.rate,
+            SystemRate {
+                hclk_divisor: 1,
+                pclk_divisor: 3,
+            }
+        );
+        assert_eq!(clocks.rates(), rates);
+
+        // The same rates from PLLP at full rate skip every other cycle.
+        let clocks = SystemClocks::configure(SystemSource::PllpOut0, rates).unwrap();
+        assert_eq!(clocks.divider.value(), 0x8000_0001);
+        assert_eq!(clocks.rates(), rates);
+
+        let rates = Rates {
+            sclk_hz: 6_400_000,
+            hclk_hz: 3_200_000,
+            pclk_hz: 800_000,
+        };
+        let clocks = SystemClocks::configure(SystemSource::ClkM, rates).unwrap();
+        assert_eq!(clocks.rates(), rates);
+    }
+
+    #[test]
+    fn rejects_unreachable_system_clocks() {
+        let configure = |source, sclk_hz, hclk_hz, pclk_hz| {
+            SystemClocks::configure(
+                source,
+                Rates {
+                    sclk_hz,
+                    hclk_hz,
+                    pclk_hz,
+                },
+            )
+        };
+
+        // Faster than the source.
+        assert_eq!(
+            configure(SystemSource::ClkM, 38_400_000, 38_400_000, 38_400_000),
+            None
+        );
+        // HCLK faster than SCLK, PCLK faster than HCLK.
+        assert_eq!(
+            configure(
+                SystemSource::PllpOut0,
+                204_000_000,
+                408_000_000,
+                204_000_000
+            ),
+            None
+        );
+        assert_eq!(
+            configure(
+                SystemSource::PllpOut0,
+                408_000_000,
+                204_000_000,
+                408_000_000
+            ),
+            None
+        );
+        // Divisors that are out of range or not integers.
+        assert_eq!(
+            configure(SystemSource::PllpOut0, 408_000_000, 45_333_333, 45_333_333),
+            None
+        );
+        assert_eq!(
+            configure(SystemSource::PllpOut0, 408_000_000, 408_000_000, 81_600_000),
+            None
+        );
+        assert_eq!(
+            configure(SystemSource::PllpOut0, 408_000_000, 136_000_000, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn configures_timers() {
+        assert_eq!(usec_config(CLK_M_HZ), Some(0x045F));
+        assert_eq!(usec_config(OSC_HZ), Some(0x04BF));
+        assert_eq!(usec_config(CLK_S_HZ), None);
+
+        assert_eq!(tsc_mult(CLK_M_HZ), Some(0x249F));
+        assert_eq!(tsc_mult(OSC_HZ), Some(0x493E));
+        assert_eq!(tsc_mult(TIMERUS_HZ), None);
+        assert_eq!(tsc_mult(134_217_728), None);
+        assert_eq!(tsc_mult(134_215_680), Some(0xFFFF));
+    }
+}
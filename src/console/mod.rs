@@ -0,0 +1,437 @@
+//! A text console that renders onto an abstract pixel buffer.
+//!
+//! The console draws text in the built-in [`font`] using palette indices as colors,
+//! scrolls once the screen is full and reserves its bottom line for a progress bar.
+//! It implements [`core::fmt::Write`], so it can be used as a log sink just like the
+//! debug UART.
+//!
+//! [`font`]: font/index.html
+//! [`core::fmt::Write`]: https://doc.rust-lang.org/core/fmt/trait.Write.html
+
+pub mod font;
+
+use core::fmt;
+
+/// A buffer of pixels holding palette indices.
+///
+/// Only [`pixel`] and [`set_pixel`] are mandatory; the remaining operations may be
+/// overridden with faster implementations.
+///
+/// [`pixel`]: #tymethod.pixel
+/// [`set_pixel`]: #tymethod.set_pixel
+pub trait Canvas {
+    /// Gets the width of the canvas in pixels.
+    fn width(&self) -> usize;
+
+    /// Gets the height of the canvas in pixels.
+    fn height(&self) -> usize;
+
+    /// Gets the palette index of the pixel at (`x`, `y`).
+    fn pixel(&self, x: usize, y: usize) -> u8;
+
+    /// Sets the pixel at (`x`, `y`) to the palette index `color`.
+    ///
+    /// Pixels outside of the canvas are ignored.
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8);
+
+    /// Fills a rectangle with the palette index `color`.
+    fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: u8) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Moves the top `height` rows of the canvas up by `lines` rows and fills the
+    /// rows that become free with `color`.
+    fn scroll_up(&mut self, height: usize, lines: usize, color: u8) {
+        let width = self.width();
+        for y in lines..height {
+            for x in 0..width {
+                let pixel = self.pixel(x, y);
+                self.set_pixel(x, y - lines, pixel);
+            }
+        }
+
+        let lines = lines.min(height);
+        self.fill_rect(0, height - lines, width, lines, color);
+    }
+}
+
+/// A text console on a [`Canvas`].
+///
+/// [`Canvas`]: trait.Canvas.html
+pub struct Console<C: Canvas> {
+    canvas: C,
+    column: usize,
+    row: usize,
+    foreground: u8,
+    background: u8,
+}
+
+impl<C: Canvas> Console<C> {
+    /// Creates a console on `canvas` and clears it.
+    pub fn new(canvas: C, foreground: u8, background: u8) -> Self {
+        let mut console = Console {
+            canvas,
+            column: 0,
+            row: 0,
+            foreground,
+            background,
+        };
+        console.clear();
+
+        console
+    }
+
+    /// Gets the number of characters that fit into a line.
+    pub fn columns(&self) -> usize {
+        self.canvas.width() / font::WIDTH
+    }
+
+    /// Gets the number of text lines, excluding the line of the progress bar.
+    pub fn rows(&self) -> usize {
+        (self.canvas.height() / font::HEIGHT).saturating_sub(1)
+    }
+
+    /// Sets the colors for subsequently written text.
+    pub fn set_colors(&mut self, foreground: u8, background: u8) {
+        self.foreground = foreground;
+        self.background = background;
+    }
+
+    /// Clears the whole canvas with the background color and moves to the top left.
+    pub fn clear(&mut self) {
+        let (width, height) = (self.canvas.width(), self.canvas.height());
+        self.canvas.fill_rect(0, 0, width, height, self.background);
+
+        self.column = 0;
+        self.row = 0;
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows() {
+            self.row += 1;
+        } else {
+            // Make room by scrolling the text area up by one line.
+            let height = self.rows() * font::HEIGHT;
+            self.canvas.scroll_up(height, font::HEIGHT, self.background);
+        }
+    }
+
+    fn draw_char(&mut self, c: char) {
+        let x = self.column * font::WIDTH;
+        let y = self.row * font::HEIGHT;
+
+        self.canvas
+            .fill_rect(x, y, font::WIDTH, font::HEIGHT, self.background);
+        for (dy, bits) in font::glyph(c).iter().enumerate() {
+            for dx in 0..font::GLYPH_WIDTH {
+                if bits & (1 << (font::GLYPH_WIDTH - 1 - dx)) != 0 {
+                    self.canvas.set_pixel(x + dx, y + dy, self.foreground);
+                }
+            }
+        }
+    }
+
+    /// Writes a single character, wrapping at the end of the line.
+    pub fn put_char(&mut self, c: char) {
+        if self.rows() == 0 || self.columns() == 0 {
+            return;
+        }
+
+        match c {
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            _ => {
+                if self.column == self.columns() {
+                    self.new_line();
+                }
+
+                self.draw_char(c);
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Draws a progress bar filled to `percent` into the bottom line.
+    pub fn progress_bar(&mut self, percent: u8) {
+        let width = self.canvas.width();
+        let y = self.rows() * font::HEIGHT;
+        let (fg, bg) = (self.foreground, self.background);
+
+        // Draw the frame, leaving a blank row above and below.
+        self.canvas.fill_rect(0, y, width, font::HEIGHT, bg);
+        self.canvas.fill_rect(0, y + 1, width, 1, fg);
+        self.canvas.fill_rect(0, y + font::HEIGHT - 2, width, 1, fg);
+        self.canvas.fill_rect(0, y + 1, 1, font::HEIGHT - 2, fg);
+        self.canvas
+            .fill_rect(width - 1, y + 1, 1, font::HEIGHT - 2, fg);
+
+        // Fill the inside proportionally.
+        let inner = width.saturating_sub(4);
+        let filled = inner * percent.min(100) as usize / 100;
+        self.canvas
+            .fill_rect(2, y + 3, filled, font::HEIGHT - 6, fg);
+    }
+}
+
+impl<C: Canvas> fmt::Write for Console<C> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.put_char(c);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use super::*;
+
+    const WIDTH: usize = 2 * font::WIDTH;
+    const HEIGHT: usize = 3 * font::HEIGHT;
+
+    const FOREGROUND: u8 = 1;
+    const BACKGROUND: u8 = 0;
+
+    /// A canvas of two columns and two lines of text, plus the progress bar.
+    struct Image {
+        pixels: [[u8; WIDTH]; HEIGHT],
+    }
+
+    impl Image {
+        fn new() -> Self {
+            // Start from garbage to make sure the console clears the canvas.
+            Image {
+                pixels: [[7; WIDTH]; HEIGHT],
+            }
+        }
+
+        /// Compares the rows starting at `y` to `golden`, where `#` is the foreground
+        /// and `.` the background.
+        fn assert_rows(&self, y: usize, golden: &[&str]) {
+            for (dy, expected) in golden.iter().enumerate() {
+                let mut row = [b'?'; WIDTH];
+                for (pixel, color) in row.iter_mut().zip(self.pixels[y + dy].iter()) {
+                    *pixel = match *color {
+                        FOREGROUND => b'#',
+                        BACKGROUND => b'.',
+                        _ => b'?',
+                    };
+                }
+
+                assert_eq!(
+                    core::str::from_utf8(&row).unwrap(),
+                    *expected,
+                    "row {}",
+                    y + dy
+                );
+            }
+        }
+    }
+
+    impl Canvas for Image {
+        fn width(&self) -> usize {
+            WIDTH
+        }
+
+        fn height(&self) -> usize {
+            HEIGHT
+        }
+
+        fn pixel(&self, x: usize, y: usize) -> u8 {
+            self.pixels[y][x]
+        }
+
+        fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+            if x < WIDTH && y < HEIGHT {
+                self.pixels[y][x] = color;
+            }
+        }
+    }
+
+    fn console() -> Console<Image> {
+        Console::new(Image::new(), FOREGROUND, BACKGROUND)
+    }
+
+    const BLANK_LINE: [&str; 8] = ["............"; 8];
+
+    #[test]
+    fn geometry() {
+        let console = console();
+        assert_eq!(console.columns(), 2);
+        assert_eq!(console.rows(), 2);
+    }
+
+    #[test]
+    fn renders_glyphs() {
+        let mut console = console();
+        write!(console, "AB").unwrap();
+
+        #[rustfmt::skip]
+        console.canvas.assert_rows(0, &[
+            ".###..####..",
+            "#...#.#...#.",
+            "#...#.#...#.",
+            "#####.####..",
+            "#...#.#...#.",
+            "#...#.#...#.",
+            "#...#.####..",
+            "............",
+        ]);
+        console.canvas.assert_rows(font::HEIGHT, &BLANK_LINE);
+        console.canvas.assert_rows(2 * font::HEIGHT, &BLANK_LINE);
+    }
+
+    #[test]
+    fn renders_replacement_for_unknown_characters() {
+        let mut console = console();
+        write!(console, "\u{E9}").unwrap();
+
+        #[rustfmt::skip]
+        console.canvas.assert_rows(0, &[
+            ".###........",
+            "#...#.......",
+            "....#.......",
+            "...#........",
+            "..#.........",
+            "............",
+            "..#.........",
+            "............",
+        ]);
+    }
+
+    #[test]
+    fn wraps_at_end_of_line() {
+        let mut console = console();
+        write!(console, "ABi").unwrap();
+
+        #[rustfmt::skip]
+        console.canvas.assert_rows(font::HEIGHT, &[
+            "..#.........",
+            "............",
+            ".##.........",
+            "..#.........",
+            "..#.........",
+            "..#.........",
+            ".###........",
+            "............",
+        ]);
+    }
+
+    #[test]
+    fn carriage_return_overwrites_line() {
+        let mut console = console();
+        write!(console, "A\ri").unwrap();
+
+        console
+            .canvas
+            .assert_rows(0, &["..#.........", "............"]);
+    }
+
+    #[test]
+    fn scrolls_when_full() {
+        let mut console = console();
+        write!(console, "AB\ni\n?").unwrap();
+
+        // The first line scrolled out, the last line holds the newest text.
+        #[rustfmt::skip]
+        console.canvas.assert_rows(0, &[
+            "..#.........",
+            "............",
+            ".##.........",
+            "..#.........",
+            "..#.........",
+            "..#.........",
+            ".###........",
+            "............",
+            ".###........",
+            "#...#.......",
+            "....#.......",
+            "...#........",
+            "..#.........",
+            "............",
+            "..#.........",
+            "............",
+        ]);
+
+        // Scrolling leaves the progress bar alone.
+        console.canvas.assert_rows(2 * font::HEIGHT, &BLANK_LINE);
+    }
+
+    #[test]
+    fn scrolling_clears_new_line() {
+        let mut console = console();
+        write!(console, "A\nB\n").unwrap();
+
+        console.canvas.assert_rows(font::HEIGHT, &BLANK_LINE);
+    }
+
+    #[test]
+    fn draws_progress_bar() {
+        let mut console = console();
+        write!(console, "\n\n\n").unwrap();
+        console.progress_bar(50);
+
+        #[rustfmt::skip]
+        console.canvas.assert_rows(2 * font::HEIGHT, &[
+            "............",
+            "############",
+            "#..........#",
+            "#.####.....#",
+            "#.####.....#",
+            "#..........#",
+            "############",
+            "............",
+        ]);
+
+        console.progress_bar(250);
+        console
+            .canvas
+            .assert_rows(2 * font::HEIGHT + 3, &["#.########.#", "#.########.#"]);
+    }
+
+    #[test]
+    fn clear_resets_position() {
+        let mut console = console();
+        write!(console, "AB\nAB").unwrap();
+        console.clear();
+        write!(console, "i").unwrap();
+
+        console
+            .canvas
+            .assert_rows(0, &["..#.........", "............"]);
+        console.canvas.assert_rows(font::HEIGHT, &BLANK_LINE);
+    }
+
+    #[test]
+    fn ignores_canvas_without_room_for_text() {
+        struct Tiny;
+
+        impl Canvas for Tiny {
+            fn width(&self) -> usize {
+                font::WIDTH
+            }
+
+            fn height(&self) -> usize {
+                font::HEIGHT
+            }
+
+            fn pixel(&self, _: usize, _: usize) -> u8 {
+                BACKGROUND
+            }
+
+            fn set_pixel(&mut self, _: usize, _: usize, _: u8) {}
+        }
+
+        let mut console = Console::new(Tiny, FOREGROUND, BACKGROUND);
+        assert_eq!(console.rows(), 0);
+        writeln!(console, "AB").unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+//! Constant-time fixed-width big integer arithmetic for RSA-2048.
+//!
+//! All numbers are stored as little-endian arrays of 32-bit limbs. The operations in
+//! here never branch on or index by secret data, so the time they take only depends
+//! on the width of the operands.
+
+/// The number of 32-bit limbs in a 2048-bit integer.
+pub const LIMBS: usize = 64;
+
+/// The size of a 2048-bit integer in bytes.
+pub const BYTES: usize = LIMBS * 4;
+
+/// A 2048-bit unsigned integer.
+pub type Uint = [u32; LIMBS];
+
+/// Parses a big-endian byte string of exactly [`BYTES`] bytes into an integer.
+///
+/// [`BYTES`]: constant.BYTES.html
+pub fn from_be_bytes(bytes: &[u8; BYTES]) -> Uint {
+    let mut out = [0; LIMBS];
+    for (limb, chunk) in out.iter_mut().zip(bytes.chunks(4).rev()) {
+        *limb = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    out
+}
+
+/// Serializes an integer into a big-endian byte string of [`BYTES`] bytes.
+///
+/// [`BYTES`]: constant.BYTES.html
+pub fn to_be_bytes(value: &Uint, bytes: &mut [u8; BYTES]) {
+    for (chunk, limb) in bytes.chunks_mut(4).rev().zip(value.iter()) {
+        chunk.copy_from_slice(&limb.to_be_bytes());
+    }
+}
+
+/// Computes `a - b` and returns the result along with the borrow out of the top limb.
+fn sub(a: &Uint, b: &Uint) -> (Uint, u32) {
+    let mut out = [0; LIMBS];
+    let mut borrow = 0u64;
+    for i in 0..LIMBS {
+        let diff = (a[i] as u64).wrapping_sub(b[i] as u64).wrapping_sub(borrow);
+        out[i] = diff as u32;
+        borrow = (diff >> 63) & 1;
+    }
+    (out, borrow as u32)
+}
+
+/// Selects `a` if `choice` is `1` and `b` if it is `0` without branching.
+fn select(choice: u32, a: &Uint, b: &Uint) -> Uint {
+    let mask = choice.wrapping_neg();
+    let mut out = [0; LIMBS];
+    for i in 0..LIMBS {
+        out[i] = (a[i] & mask) | (b[i] & !mask);
+    }
+    out
+}
+
+/// Checks whether `a < b` in constant time.
+pub fn less_than(a: &Uint, b: &Uint) -> bool {
+    sub(a, b).1 == 1
+}
+
+/// Montgomery arithmetic context for a fixed odd modulus.
+pub struct Montgomery {
+    modulus: Uint,
+    /// `-modulus^-1 mod 2^32`.
+    n0_inv: u32,
+    /// `R^2 mod modulus` where `R = 2^2048`.
+    r_squared: Uint,
+}
+
+impl Montgomery {
+    /// Prepares a Montgomery context for the given `modulus`.
+    ///
+    /// Returns `None` if the modulus is even and thus unsuitable for Montgomery
+    /// multiplication.
+    pub fn new(modulus: &Uint) -> Option<Self> {
+        if modulus[0] & 1 == 0 {
+            return None;
+        }
+
+        // Newton iteration doubles the number of correct bits on every step.
+        let mut inv = 1u32;
+        for _ in 0..5 {
+            inv = inv.wrapping_mul(2u32.wrapping_sub(modulus[0].wrapping_mul(inv)));
+        }
+
+        // Compute R^2 mod n by repeatedly doubling 1 modulo n.
+        let mut r_squared = [0; LIMBS];
+        r_squared[0] = 1;
+        for _ in 0..2 * LIMBS * 32 {
+            let mut doubled = [0; LIMBS];
+            let mut carry = 0;
+            for i in 0..LIMBS {
+                doubled[i] = (r_squared[i] << 1) | carry;
+                carry = r_squared[i] >> 31;
+            }
+
+            let (reduced, borrow) = sub(&doubled, modulus);
+            r_squared = select(carry | (borrow ^ 1), &reduced, &doubled);
+        }
+
+        Some(Montgomery {
+            modulus: *modulus,
+            n0_inv: inv.wrapping_neg(),
+            r_squared,
+        })
+    }
+
+    /// Computes `a * b * R^-1 mod n`.
+    // The variables are named after the ones in the Handbook of Applied Cryptography,
+    // algorithm 14.36.
+    #[allow(clippy::many_single_char_names)]
+    pub fn mul(&self, a: &Uint, b: &Uint) -> Uint {
+        let n = &self.modulus;
+        let mut t = [0u32; LIMBS + 2];
+
+        for &a_i in a.iter() {
+            // t += a[i] * b
+            let mut carry = 0u64;
+            for j in 0..LIMBS {
+                let sum = t[j] as u64 + a_i as u64 * b[j] as u64 + carry;
+                t[j] = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[LIMBS] as u64 + carry;
+            t[LIMBS] = sum as u32;
+            t[LIMBS + 1] = (sum >> 32) as u32;
+
+            // t = (t + m * n) / 2^32
+            let m = t[0].wrapping_mul(self.n0_inv);
+            let mut carry = (t[0] as u64 + m as u64 * n[0] as u64) >> 32;
+            for j in 1..LIMBS {
+                let sum = t[j] as u64 + m as u64 * n[j] as u64 + carry;
+                t[j - 1] = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[LIMBS] as u64 + carry;
+            t[LIMBS - 1] = sum as u32;
+            t[LIMBS] = t[LIMBS + 1] + (sum >> 32) as u32;
+        }
+
+        let mut result = [0; LIMBS];
+        result.copy_from_slice(&t[..LIMBS]);
+        let (reduced, borrow) = sub(&result, n);
+        select(t[LIMBS] | (borrow ^ 1), &reduced, &result)
+    }
+
+    /// Computes `base^65537 mod n`, the RSA public operation with the common exponent.
+    pub fn pow_f4(&self, base: &Uint) -> Uint {
+        let base = self.mul(base, &self.r_squared);
+
+        let mut acc = base;
+        for _ in 0..16 {
+            acc = self.mul(&acc, &acc);
+        }
+        acc = self.mul(&acc, &base);
+
+        // Convert back out of the Montgomery domain.
+        let mut one = [0; LIMBS];
+        one[0] = 1;
+        self.mul(&acc, &one)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2048-bit odd modulus with the top bit set.
+    fn modulus() -> Uint {
+        let mut modulus = [0x9E37_79B9; LIMBS];
+        modulus[0] |= 1;
+        modulus[LIMBS - 1] |= 1 << 31;
+        modulus
+    }
+
+    fn small(value: u32) -> Uint {
+        let mut out = [0; LIMBS];
+        out[0] = value;
+        out
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut bytes = [0; BYTES];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        let value = from_be_bytes(&bytes);
+        assert_eq!(value[0], 0xFCFD_FEFF);
+        assert_eq!(value[LIMBS - 1], 0x0001_0203);
+
+        let mut out = [0; BYTES];
+        to_be_bytes(&value, &mut out);
+        assert_eq!(out[..], bytes[..]);
+    }
+
+    #[test]
+    fn comparison() {
+        let mut high = small(0);
+        high[LIMBS - 1] = 1;
+
+        assert!(less_than(&small(1), &small(2)));
+        assert!(!less_than(&small(2), &small(2)));
+        assert!(!less_than(&small(3), &small(2)));
+        assert!(less_than(&small(u32::MAX), &high));
+        assert!(!less_than(&high, &small(u32::MAX)));
+    }
+
+    #[test]
+    fn even_modulus() {
+        let mut modulus = modulus();
+        modulus[0] &= !1;
+
+        assert!(Montgomery::new(&modulus).is_none());
+    }
+
+    #[test]
+    fn public_operation() {
+        let modulus = modulus();
+        let context = Montgomery::new(&modulus).unwrap();
+
+        assert_eq!(context.pow_f4(&small(0)), small(0));
+        assert_eq!(context.pow_f4(&small(1)), small(1));
+
+        // An odd power of -1 is -1.
+        let (minus_one, _) = sub(&modulus, &small(1));
+        assert_eq!(context.pow_f4(&minus_one), minus_one);
+
+        // 2^65537 = 2 * R^32, and every multiplication with R^2 adds a factor of R.
+        let mut expected = small(2);
+        for _ in 0..32 {
+            expected = context.mul(&expected, &context.r_squared);
+        }
+        assert_eq!(context.pow_f4(&small(2)), expected);
+    }
+}
@@ -0,0 +1,511 @@
+//! Ed25519 signature verification as specified in RFC 8032.
+//!
+//! The arithmetic follows TweetNaCl: field elements are sixteen 16-bit limbs held in
+//! `i64`s, and scalar multiplication is a ladder over all 256 bits that swaps points
+//! with masks instead of branches. This is slow next to optimized implementations, but
+//! takes only a few kilobytes of the IRAM budget of the first stage.
+
+use super::sha512::Sha512;
+use super::{ct_eq, Error, Verifier};
+
+/// The size of an Ed25519 public key in bytes.
+pub const PUBLIC_KEY_SIZE: usize = 32;
+
+/// The size of an Ed25519 signature in bytes.
+pub const SIGNATURE_SIZE: usize = 64;
+
+/// An element of the field modulo `2^255 - 19`.
+type Fe = [i64; 16];
+
+/// A point on the curve in extended coordinates `(X, Y, Z, T)`.
+type Point = [Fe; 4];
+
+const ZERO: Fe = [0; 16];
+const ONE: Fe = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/// The curve constant `d = -121665 / 121666`.
+#[rustfmt::skip]
+const D: Fe = [
+    0x78A3, 0x1359, 0x4DCA, 0x75EB, 0xD8AB, 0x4141, 0x0A4D, 0x0070,
+    0xE898, 0x7779, 0x4079, 0x8CC7, 0xFE73, 0x2B6F, 0x6CEE, 0x5203,
+];
+
+/// `2 * d`.
+#[rustfmt::skip]
+const D2: Fe = [
+    0xF159, 0x26B2, 0x9B94, 0xEBD6, 0xB156, 0x8283, 0x149A, 0x00E0,
+    0xD130, 0xEEF3, 0x80F2, 0x198E, 0xFCE7, 0x56DF, 0xD9DC, 0x2406,
+];
+
+/// A square root of `-1`.
+#[rustfmt::skip]
+const SQRT_M1: Fe = [
+    0xA0B0, 0x4A0E, 0x1B27, 0xC4EE, 0xE478, 0xAD2F, 0x1806, 0x2F43,
+    0xD7A7, 0x3DFB, 0x0099, 0x2B4D, 0xDF0B, 0x4FC1, 0x2480, 0x2B83,
+];
+
+/// The x coordinate of the base point.
+#[rustfmt::skip]
+const BASE_X: Fe = [
+    0xD51A, 0x8F25, 0x2D60, 0xC956, 0xA7B2, 0x9525, 0xC760, 0x692C,
+    0xDC5C, 0xFDD6, 0xE231, 0xC0A4, 0x53FE, 0xCD6E, 0x36D3, 0x2169,
+];
+
+/// The y coordinate of the base point, `4 / 5`.
+#[rustfmt::skip]
+const BASE_Y: Fe = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+];
+
+/// The order of the base point, `2^252 + 27742317777372353535851937790883648493`, in
+/// little-endian bytes.
+#[rustfmt::skip]
+const L: [i64; 32] = [
+    0xED, 0xD3, 0xF5, 0x5C, 0x1A, 0x63, 0x12, 0x58, 0xD6, 0x9C, 0xF7, 0xA2, 0xDE, 0xF9,
+    0xDE, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x10,
+];
+
+/// Propagates the carries of all limbs, folding the top one back in as `2^256 = 38`.
+fn carry(o: &mut Fe) {
+    for i in 0..16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            o[0] += 38 * (c - 1);
+        }
+        o[i] -= c << 16;
+    }
+}
+
+/// Swaps `p` and `q` if `swap` is `1` and leaves them alone if it is `0`.
+fn cswap(p: &mut Fe, q: &mut Fe, swap: i64) {
+    let mask = !(swap - 1);
+    for i in 0..16 {
+        let t = mask & (p[i] ^ q[i]);
+        p[i] ^= t;
+        q[i] ^= t;
+    }
+}
+
+fn add(a: &Fe, b: &Fe) -> Fe {
+    let mut o = ZERO;
+    for i in 0..16 {
+        o[i] = a[i] + b[i];
+    }
+    o
+}
+
+fn sub(a: &Fe, b: &Fe) -> Fe {
+    let mut o = ZERO;
+    for i in 0..16 {
+        o[i] = a[i] - b[i];
+    }
+    o
+}
+
+fn mul(a: &Fe, b: &Fe) -> Fe {
+    let mut t = [0i64; 31];
+    for i in 0..16 {
+        for j in 0..16 {
+            t[i + j] += a[i] * b[j];
+        }
+    }
+    for i in 0..15 {
+        t[i] += 38 * t[i + 16];
+    }
+
+    let mut o = ZERO;
+    o.copy_from_slice(&t[..16]);
+    carry(&mut o);
+    carry(&mut o);
+    o
+}
+
+fn square(a: &Fe) -> Fe {
+    mul(a, a)
+}
+
+/// Computes `a^(2^255 - 21)`, the inverse of `a`.
+fn invert(a: &Fe) -> Fe {
+    let mut c = *a;
+    for i in (0..254).rev() {
+        c = square(&c);
+        if i != 2 && i != 4 {
+            c = mul(&c, a);
+        }
+    }
+    c
+}
+
+/// Computes `a^(2^252 - 3)`, which is used for square roots.
+fn pow2523(a: &Fe) -> Fe {
+    let mut c = *a;
+    for i in (0..251).rev() {
+        c = square(&c);
+        if i != 1 {
+            c = mul(&c, a);
+        }
+    }
+    c
+}
+
+/// Encodes the fully reduced `a` in little-endian bytes.
+fn pack(a: &Fe) -> [u8; 32] {
+    let mut t = *a;
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+
+    // Subtract the modulus twice, keeping the difference whenever it does not borrow.
+    for _ in 0..2 {
+        let mut m = ZERO;
+        m[0] = t[0] - 0xFFED;
+        for i in 1..15 {
+            m[i] = t[i] - 0xFFFF - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xFFFF;
+        }
+        m[15] = t[15] - 0x7FFF - ((m[14] >> 16) & 1);
+        let borrow = (m[15] >> 16) & 1;
+        m[14] &= 0xFFFF;
+        cswap(&mut t, &mut m, 1 - borrow);
+    }
+
+    let mut out = [0; 32];
+    for i in 0..16 {
+        out[2 * i] = t[i] as u8;
+        out[2 * i + 1] = (t[i] >> 8) as u8;
+    }
+    out
+}
+
+/// Decodes a little-endian encoded field element, ignoring the top bit.
+fn unpack(bytes: &[u8]) -> Fe {
+    let mut o = ZERO;
+    for i in 0..16 {
+        o[i] = bytes[2 * i] as i64 + ((bytes[2 * i + 1] as i64) << 8);
+    }
+    o[15] &= 0x7FFF;
+    o
+}
+
+fn equal(a: &Fe, b: &Fe) -> bool {
+    ct_eq(&pack(a), &pack(b))
+}
+
+fn parity(a: &Fe) -> u8 {
+    pack(a)[0] & 1
+}
+
+/// Computes `p + q` with the unified addition formula, which also doubles.
+// The temporaries are named after the ones in RFC 8032.
+#[allow(clippy::many_single_char_names)]
+fn point_add(p: &Point, q: &Point) -> Point {
+    let a = mul(&sub(&p[1], &p[0]), &sub(&q[1], &q[0]));
+    let b = mul(&add(&p[0], &p[1]), &add(&q[0], &q[1]));
+    let c = mul(&mul(&p[3], &q[3]), &D2);
+    let d = mul(&p[2], &q[2]);
+    let d = add(&d, &d);
+    let e = sub(&b, &a);
+    let f = sub(&d, &c);
+    let g = add(&d, &c);
+    let h = add(&b, &a);
+
+    [mul(&e, &f), mul(&h, &g), mul(&g, &f), mul(&e, &h)]
+}
+
+fn point_cswap(p: &mut Point, q: &mut Point, swap: i64) {
+    for (a, b) in p.iter_mut().zip(q.iter_mut()) {
+        cswap(a, b, swap);
+    }
+}
+
+/// Computes `s * q` for the little-endian scalar `s`.
+fn scalar_mul(mut q: Point, s: &[u8]) -> Point {
+    let mut p = [ZERO, ONE, ONE, ZERO];
+    for i in (0..256).rev() {
+        let bit = ((s[i / 8] >> (i & 7)) & 1) as i64;
+        point_cswap(&mut p, &mut q, bit);
+        q = point_add(&q, &p);
+        p = point_add(&p, &p);
+        point_cswap(&mut p, &mut q, bit);
+    }
+    p
+}
+
+/// Computes `s * B` for the little-endian scalar `s` and the base point `B`.
+fn scalar_mul_base(s: &[u8]) -> Point {
+    scalar_mul([BASE_X, BASE_Y, ONE, mul(&BASE_X, &BASE_Y)], s)
+}
+
+/// Encodes a point as its y coordinate with the sign of x in the top bit.
+fn point_pack(p: &Point) -> [u8; 32] {
+    let z = invert(&p[2]);
+    let x = mul(&p[0], &z);
+    let y = mul(&p[1], &z);
+
+    let mut out = pack(&y);
+    out[31] ^= parity(&x) << 7;
+    out
+}
+
+/// Decodes a point and negates it, failing if the encoding is not on the curve.
+fn point_unpack_neg(bytes: &[u8; 32]) -> Option<Point> {
+    let y = unpack(bytes);
+
+    // Recover x from x^2 = (y^2 - 1) / (d * y^2 + 1).
+    let y2 = square(&y);
+    let num = sub(&y2, &ONE);
+    let den = add(&mul(&y2, &D), &ONE);
+    let den2 = square(&den);
+    let den6 = mul(&square(&den2), &den2);
+
+    let mut t = pow2523(&mul(&mul(&den6, &num), &den));
+    t = mul(&mul(&mul(&t, &num), &den), &den);
+    let mut x = mul(&t, &den);
+
+    if !equal(&mul(&square(&x), &den), &num) {
+        x = mul(&x, &SQRT_M1);
+    }
+    if !equal(&mul(&square(&x), &den), &num) {
+        return None;
+    }
+
+    if parity(&x) == bytes[31] >> 7 {
+        x = sub(&ZERO, &x);
+    }
+
+    Some([x, y, ONE, mul(&x, &y)])
+}
+
+/// Reduces the little-endian number in `x` modulo [`L`].
+///
+/// [`L`]: constant.L.html
+fn reduce(x: &mut [i64; 64]) -> [u8; 32] {
+    for i in (32..64).rev() {
+        let mut carry = 0;
+        for j in i - 32..i - 12 {
+            x[j] += carry - 16 * x[i] * L[j - (i - 32)];
+            carry = (x[j] + 128) >> 8;
+            x[j] -= carry << 8;
+        }
+        x[i - 12] += carry;
+        x[i] = 0;
+    }
+
+    let mut carry = 0;
+    for j in 0..32 {
+        x[j] += carry - (x[31] >> 4) * L[j];
+        carry = x[j] >> 8;
+        x[j] &= 0xFF;
+    }
+    for j in 0..32 {
+        x[j] -= carry * L[j];
+    }
+
+    let mut out = [0; 32];
+    for i in 0..32 {
+        x[i + 1] += x[i] >> 8;
+        out[i] = x[i] as u8;
+    }
+    out
+}
+
+/// Checks whether the little-endian scalar `s` is below [`L`], as RFC 8032 requires
+/// of the `S` half of signatures.
+///
+/// [`L`]: constant.L.html
+fn is_canonical(s: &[u8]) -> bool {
+    for i in (0..32).rev() {
+        if s[i] as i64 != L[i] {
+            return (s[i] as i64) < L[i];
+        }
+    }
+    false
+}
+
+/// An Ed25519 public key.
+pub struct PublicKey {
+    encoded: [u8; PUBLIC_KEY_SIZE],
+    negated: Point,
+}
+
+impl PublicKey {
+    /// Creates a public key from its 32-byte compressed encoding.
+    pub fn new(key: &[u8; PUBLIC_KEY_SIZE]) -> Result<Self, Error> {
+        let negated = point_unpack_neg(key).ok_or(Error::InvalidKey)?;
+
+        Ok(PublicKey {
+            encoded: *key,
+            negated,
+        })
+    }
+}
+
+impl Verifier for PublicKey {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+        if signature.len() != SIGNATURE_SIZE || !is_canonical(&signature[32..]) {
+            return Err(Error::InvalidSignature);
+        }
+        let (r, s) = signature.split_at(32);
+
+        // k = SHA-512(R || A || M) mod L
+        let mut hasher = Sha512::new();
+        hasher.update(r);
+        hasher.update(&self.encoded);
+        hasher.update(message);
+        let mut wide = [0; 64];
+        for (limb, byte) in wide.iter_mut().zip(hasher.finalize().iter()) {
+            *limb = *byte as i64;
+        }
+        let k = reduce(&mut wide);
+
+        // The signature is valid if s * B - k * A encodes to R.
+        let point = point_add(&scalar_mul(self.negated, &k), &scalar_mul_base(s));
+        if ct_eq(&point_pack(&point), r) {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignature)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes a hexadecimal string into `out`.
+    fn decode(hex: &str, out: &mut [u8]) {
+        assert_eq!(hex.len(), out.len() * 2);
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        }
+    }
+
+    /// A test vector from RFC 8032, section 7.1.
+    struct Vector {
+        key: &'static str,
+        message: &'static [u8],
+        signature: &'static str,
+    }
+
+    impl Vector {
+        fn key(&self) -> PublicKey {
+            let mut key = [0; PUBLIC_KEY_SIZE];
+            decode(self.key, &mut key);
+            PublicKey::new(&key).unwrap()
+        }
+
+        fn signature(&self) -> [u8; SIGNATURE_SIZE] {
+            let mut signature = [0; SIGNATURE_SIZE];
+            decode(self.signature, &mut signature);
+            signature
+        }
+    }
+
+    const VECTORS: [Vector; 3] = [
+        Vector {
+            key: "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            message: b"",
+            signature: "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+                        5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        },
+        Vector {
+            key: "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            message: b"\x72",
+            signature: "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+                        085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        },
+        Vector {
+            key: "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            message: b"\xAF\x82",
+            signature: "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+                        18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        },
+    ];
+
+    #[test]
+    fn rfc8032_vectors() {
+        for (i, vector) in VECTORS.iter().enumerate() {
+            assert_eq!(
+                vector.key().verify(vector.message, &vector.signature()),
+                Ok(()),
+                "test {}",
+                i + 1
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_message() {
+        let vector = &VECTORS[1];
+
+        assert_eq!(
+            vector.key().verify(b"\x73", &vector.signature()),
+            Err(Error::InvalidSignature)
+        );
+        assert_eq!(
+            VECTORS[0].key().verify(vector.message, &vector.signature()),
+            Err(Error::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn tampered_signature() {
+        let vector = &VECTORS[2];
+
+        for &index in [0, 31, 32, SIGNATURE_SIZE - 1].iter() {
+            let mut signature = vector.signature();
+            signature[index] ^= 0x01;
+            assert_eq!(
+                vector.key().verify(vector.message, &signature),
+                Err(Error::InvalidSignature),
+                "flipped byte {}",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn non_canonical_signature() {
+        // Adding the group order to S yields the same point, but RFC 8032 demands S < L.
+        let vector = &VECTORS[0];
+        let mut signature = vector.signature();
+        let mut carry = 0;
+        for (byte, &l) in signature[32..].iter_mut().zip(L.iter()) {
+            let sum = *byte as i64 + l + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+
+        assert_eq!(carry, 0);
+        assert_eq!(
+            vector.key().verify(vector.message, &signature),
+            Err(Error::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn point_not_on_curve() {
+        // There is no x for y = 2.
+        let mut key = [0; PUBLIC_KEY_SIZE];
+        key[0] = 2;
+
+        assert!(PublicKey::new(&key).is_err());
+    }
+
+    #[test]
+    fn truncated_signature() {
+        let vector = &VECTORS[0];
+
+        assert_eq!(
+            vector
+                .key()
+                .verify(vector.message, &vector.signature()[1..]),
+            Err(Error::InvalidSignature)
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! Cryptographic primitives for establishing the chain of trust.
+//!
+//! Everything in here is implemented purely in software and does not touch any
+//! hardware, so the code is tested on the build host against known-answer vectors.
+
+#[cfg(any(test, not(feature = "ed25519")))]
+pub mod bignum;
+#[cfg(any(test, feature = "ed25519"))]
+pub mod ed25519;
+#[cfg(any(test, not(feature = "ed25519")))]
+pub mod rsa;
+pub mod sha256;
+#[cfg(any(test, feature = "ed25519"))]
+pub mod sha512;
+
+/// Errors that may occur during cryptographic operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The supplied key material is malformed or not supported.
+    InvalidKey,
+    /// The signature does not match the message.
+    InvalidSignature,
+}
+
+/// A signature scheme that can verify messages against a fixed public key.
+pub trait Verifier {
+    /// Verifies that `signature` is a valid signature over `message`.
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), Error>;
+}
+
+/// Compares two byte slices in constant time.
+///
+/// Slices of different lengths always compare unequal.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut diff = 0;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_time_comparison() {
+        assert!(ct_eq(b"", b""));
+        assert!(ct_eq(b"mirage", b"mirage"));
+        assert!(!ct_eq(b"mirage", b"mirafe"));
+        assert!(!ct_eq(b"mirage", b"mirag"));
+    }
+}
@@ -0,0 +1,202 @@
+//! RSA-2048 signature verification using the PSS padding scheme.
+//!
+//! Signatures are expected to be produced with SHA-256 as both the message digest and
+//! the MGF1 hash function, a salt length of 32 bytes and the public exponent 65537.
+//! This matches what `openssl dgst -sha256 -sigopt rsa_padding_mode:pss
+//! -sigopt rsa_pss_saltlen:32` produces.
+
+use super::bignum::{self, Montgomery, Uint};
+use super::sha256::{self, Sha256, DIGEST_SIZE};
+use super::{ct_eq, Error, Verifier};
+
+/// The size of an RSA-2048 signature in bytes.
+pub const SIGNATURE_SIZE: usize = bignum::BYTES;
+
+/// The length of the PSS salt in bytes.
+const SALT_SIZE: usize = DIGEST_SIZE;
+
+/// The length of the PSS `DB` block inside the encoded message.
+const DB_SIZE: usize = SIGNATURE_SIZE - DIGEST_SIZE - 1;
+
+/// An RSA-2048 public key with the exponent 65537.
+pub struct PublicKey {
+    modulus: Uint,
+    context: Montgomery,
+}
+
+impl PublicKey {
+    /// Creates a public key from a big-endian encoded 2048-bit modulus.
+    pub fn new(modulus: &[u8; SIGNATURE_SIZE]) -> Result<Self, Error> {
+        // The encoding below assumes the modulus uses all 2048 bits.
+        if modulus[0] & 0x80 == 0 {
+            return Err(Error::InvalidKey);
+        }
+
+        let modulus = bignum::from_be_bytes(modulus);
+        let context = Montgomery::new(&modulus).ok_or(Error::InvalidKey)?;
+
+        Ok(PublicKey { modulus, context })
+    }
+
+    /// Performs the raw RSA public operation and recovers the encoded message.
+    fn recover(&self, signature: &[u8]) -> Result<[u8; SIGNATURE_SIZE], Error> {
+        if signature.len() != SIGNATURE_SIZE {
+            return Err(Error::InvalidSignature);
+        }
+
+        let mut raw = [0; SIGNATURE_SIZE];
+        raw.copy_from_slice(signature);
+        let s = bignum::from_be_bytes(&raw);
+        if !bignum::less_than(&s, &self.modulus) {
+            return Err(Error::InvalidSignature);
+        }
+
+        let m = self.context.pow_f4(&s);
+        bignum::to_be_bytes(&m, &mut raw);
+        Ok(raw)
+    }
+}
+
+impl Verifier for PublicKey {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let encoded = self.recover(signature)?;
+        pss_verify(&sha256::sha256(message), &encoded)
+    }
+}
+
+/// Applies the MGF1 mask generated from `seed` to `out` using SHA-256.
+fn mgf1_xor(seed: &[u8], out: &mut [u8]) {
+    for (counter, chunk) in out.chunks_mut(DIGEST_SIZE).enumerate() {
+        let mut hasher = Sha256::new();
+        hasher.update(seed);
+        hasher.update(&(counter as u32).to_be_bytes());
+
+        for (byte, mask) in chunk.iter_mut().zip(hasher.finalize().iter()) {
+            *byte ^= *mask;
+        }
+    }
+}
+
+/// Verifies an EMSA-PSS encoded message against the digest of the signed message,
+/// as described in RFC 8017, section 9.1.2.
+///
+/// The encoded message is the output of the RSA public operation with a length of
+/// `emLen = 256` bytes and `emBits = 2047`.
+pub fn pss_verify(digest: &[u8; DIGEST_SIZE], encoded: &[u8; SIGNATURE_SIZE]) -> Result<(), Error> {
+    let mut valid = (encoded[SIGNATURE_SIZE - 1] == 0xBC) as u8;
+    // The leftmost bit of the encoded message must be zero since emBits is 2047.
+    valid &= (encoded[0] & 0x80 == 0) as u8;
+
+    let (masked_db, rest) = encoded.split_at(DB_SIZE);
+    let h = &rest[..DIGEST_SIZE];
+
+    let mut db = [0; DB_SIZE];
+    db.copy_from_slice(masked_db);
+    mgf1_xor(h, &mut db);
+    db[0] &= 0x7F;
+
+    // The DB must be a zero padding string followed by 0x01 and the salt.
+    let padding_end = DB_SIZE - SALT_SIZE - 1;
+    let mut nonzero = 0;
+    for byte in db[..padding_end].iter() {
+        nonzero |= *byte;
+    }
+    valid &= (nonzero == 0) as u8;
+    valid &= (db[padding_end] == 0x01) as u8;
+
+    // H' = Hash(0x00 * 8 || mHash || salt)
+    let mut hasher = Sha256::new();
+    hasher.update(&[0; 8]);
+    hasher.update(digest);
+    hasher.update(&db[DB_SIZE - SALT_SIZE..]);
+    valid &= ct_eq(h, &hasher.finalize()) as u8;
+
+    if valid == 1 {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The vectors were generated with OpenSSL from a throwaway 2048-bit key:
+    //
+    //   openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048 -out key.pem
+    //   openssl rsa -in key.pem -noout -modulus | cut -d= -f2 | xxd -r -p > rsa_modulus.bin
+    //   openssl dgst -sha256 -sigopt rsa_padding_mode:pss -sigopt rsa_pss_saltlen:32 \
+    //       -sign key.pem -out pss_fox.sig fox.txt
+    //
+    // `pss_empty.sig` was produced the same way from an empty file.
+    const MODULUS: &[u8; SIGNATURE_SIZE] = include_bytes!("testdata/rsa_modulus.bin");
+    const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog";
+    const FOX_SIGNATURE: &[u8; SIGNATURE_SIZE] = include_bytes!("testdata/pss_fox.sig");
+    const EMPTY_SIGNATURE: &[u8; SIGNATURE_SIZE] = include_bytes!("testdata/pss_empty.sig");
+
+    fn key() -> PublicKey {
+        PublicKey::new(MODULUS).unwrap()
+    }
+
+    #[test]
+    fn valid_signatures() {
+        assert_eq!(key().verify(FOX, FOX_SIGNATURE), Ok(()));
+        assert_eq!(key().verify(b"", EMPTY_SIGNATURE), Ok(()));
+    }
+
+    #[test]
+    fn wrong_message() {
+        assert_eq!(
+            key().verify(
+                b"The quick brown fox jumps over the lazy cog",
+                FOX_SIGNATURE
+            ),
+            Err(Error::InvalidSignature)
+        );
+        assert_eq!(
+            key().verify(FOX, EMPTY_SIGNATURE),
+            Err(Error::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn tampered_signature() {
+        for &index in [0, 1, SIGNATURE_SIZE / 2, SIGNATURE_SIZE - 1].iter() {
+            let mut signature = *FOX_SIGNATURE;
+            signature[index] ^= 0x01;
+            assert_eq!(
+                key().verify(FOX, &signature),
+                Err(Error::InvalidSignature),
+                "flipped byte {}",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_signature() {
+        let key = key();
+
+        assert_eq!(
+            key.verify(FOX, &FOX_SIGNATURE[1..]),
+            Err(Error::InvalidSignature)
+        );
+        assert_eq!(key.verify(FOX, MODULUS), Err(Error::InvalidSignature));
+        assert_eq!(
+            key.verify(FOX, &[0xFF; SIGNATURE_SIZE]),
+            Err(Error::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn malformed_keys() {
+        let mut short = *MODULUS;
+        short[0] &= 0x7F;
+        assert!(PublicKey::new(&short).is_err());
+
+        let mut even = *MODULUS;
+        even[SIGNATURE_SIZE - 1] &= !1;
+        assert!(PublicKey::new(&even).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! Bounded waits for polling hardware.
+//!
+//! Every loop that waits on the hardware should give up eventually, so that a device
+//! that never becomes ready surfaces as an error instead of hanging the boot. A
+//! [`Deadline`] measures the time since it was started on a [`Clock`], and
+//! [`poll_until`] polls a condition until it holds or the deadline expires.
+//!
+//! The clock is a free-running 32-bit microsecond counter that wraps around roughly
+//! every 71 minutes. Deadlines only ever compare elapsed times, which are correct
+//! across a single wraparound, so any timeout below that period works regardless of
+//! when it is started.
+//!
+//! [`Deadline`]: struct.Deadline.html
+//! [`Clock`]: trait.Clock.html
+//! [`poll_until`]: fn.poll_until.html
+
+/// A free-running microsecond counter.
+pub trait Clock {
+    /// Gets the current value of the counter in microseconds.
+    fn now_us(&self) -> u32;
+}
+
+/// The error of a wait that did not complete in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeout;
+
+/// A point in time that lies a fixed timeout after the deadline was started.
+pub struct Deadline<'a, C: Clock> {
+    clock: &'a C,
+    start: u32,
+    timeout_us: u32,
+}
+
+impl<'a, C: Clock> Deadline<'a, C> {
+    /// Starts a deadline that expires `timeout_us` microseconds from now.
+    pub fn new(clock: &'a C, timeout_us: u32) -> Self {
+        Deadline {
+            clock,
+            start: clock.now_us(),
+            timeout_us,
+        }
+    }
+
+    /// Gets the time since the deadline was started in microseconds.
+    pub fn elapsed_us(&self) -> u32 {
+        // The difference stays correct when the counter wrapped around in between.
+        self.clock.now_us().wrapping_sub(self.start)
+    }
+
+    /// Whether the deadline has passed.
+    pub fn expired(&self) -> bool {
+        self.elapsed_us() >= self.timeout_us
+    }
+}
+
+/// Polls `condition` until it holds, for at most `timeout_us` microseconds.
+///
+/// The condition is always checked at least once, and once more after the deadline
+/// has passed, so a wait is never reported as timed out just because polling was
+/// held up.
+pub fn poll_until<C, F>(clock: &C, mut condition: F, timeout_us: u32) -> Result<(), Timeout>
+where
+    C: Clock,
+    F: FnMut() -> bool,
+{
+    let deadline = Deadline::new(clock, timeout_us);
+
+    loop {
+        let expired = deadline.expired();
+        if condition() {
+            return Ok(());
+        }
+        if expired {
+            return Err(Timeout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// A clock that advances by a fixed step every time it is read.
+    struct FakeClock {
+        now: Cell<u32>,
+        step: u32,
+    }
+
+    impl FakeClock {
+        fn new(now: u32, step: u32) -> Self {
+            FakeClock {
+                now: Cell::new(now),
+                step,
+            }
+        }
+
+        fn advance(&self, us: u32) {
+            self.now.set(self.now.get().wrapping_add(us));
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now_us(&self) -> u32 {
+            let now = self.now.get();
+            self.advance(self.step);
+            now
+        }
+    }
+
+    #[test]
+    fn expires_at_timeout() {
+        let clock = FakeClock::new(1000, 0);
+        let deadline = Deadline::new(&clock, 50);
+
+        clock.advance(49);
+        assert_eq!(deadline.elapsed_us(), 49);
+        assert!(!deadline.expired());
+        clock.advance(1);
+        assert!(deadline.expired());
+    }
+
+    #[test]
+    fn measures_across_wraparound() {
+        let clock = FakeClock::new(u32::MAX - 9, 0);
+        let deadline = Deadline::new(&clock, 30);
+
+        clock.advance(20);
+        assert_eq!(clock.now.get(), 10);
+        assert_eq!(deadline.elapsed_us(), 20);
+        assert!(!deadline.expired());
+        clock.advance(10);
+        assert!(deadline.expired());
+    }
+
+    #[test]
+    fn handles_longest_timeout() {
+        let clock = FakeClock::new(0x8000_0000, 0);
+        let deadline = Deadline::new(&clock, u32::MAX);
+
+        clock.advance(u32::MAX - 1);
+        assert!(!deadline.expired());
+        clock.advance(1);
+        assert!(deadline.expired());
+    }
+
+    #[test]
+    fn checks_condition_at_least_once() {
+        let clock = FakeClock::new(0, 1000);
+        let mut checks = 0;
+        let result = poll_until(
+            &clock,
+            || {
+                checks += 1;
+                true
+            },
+            0,
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(checks, 1);
+    }
+
+    #[test]
+    fn times_out_after_final_check() {
+        let clock = FakeClock::new(0, 10);
+        let mut checks = 0;
+        let result = poll_until(
+            &clock,
+            || {
+                checks += 1;
+                false
+            },
+            100,
+        );
+
+        // The deadline is started at 0 and read at 10, 20, ..., 100.
+        assert_eq!(result, Err(Timeout));
+        assert_eq!(checks, 10);
+    }
+
+    #[test]
+    fn succeeds_when_polling_was_held_up() {
+        // A single check takes longer than the whole timeout.
+        let clock = FakeClock::new(0, 0);
+        let result = poll_until(
+            &clock,
+            || {
+                clock.advance(500);
+                clock.now.get() >= 1000
+            },
+            100,
+        );
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn polls_across_wraparound() {
+        let clock = FakeClock::new(u32::MAX - 45, 10);
+        let mut checks = 0;
+        let result = poll_until(
+            &clock,
+            || {
+                checks += 1;
+                false
+            },
+            100,
+        );
+
+        // Neither cut short nor extended by the counter wrapping around.
+        assert_eq!(result, Err(Timeout));
+        assert_eq!(checks, 10);
+
+        let clock = FakeClock::new(u32::MAX - 45, 10);
+        let result = poll_until(&clock, || clock.now.get() < 100, 100);
+        assert_eq!(result, Ok(()));
+        assert!(clock.now.get() < 100);
+    }
+}
@@ -0,0 +1,278 @@
+//! Packing and parsing of MIPI DSI packets.
+//!
+//! The DSI host takes packets as a stream of 32-bit words through its `DSI_WR_DATA`
+//! register and computes the ECC and checksum of each packet itself. This module
+//! produces those word streams and parses the replies the panel sends back through
+//! the read FIFO after a bus turnaround.
+
+/// DCS short write without parameters.
+pub const DCS_SHORT_WRITE: u8 = 0x05;
+/// DCS short write with one parameter.
+pub const DCS_SHORT_WRITE_PARAM: u8 = 0x15;
+/// DCS long write.
+pub const DCS_LONG_WRITE: u8 = 0x39;
+/// DCS read without parameters.
+pub const DCS_READ: u8 = 0x06;
+/// Sets the maximum size of the packets the panel returns.
+pub const SET_MAX_RETURN_PACKET_SIZE: u8 = 0x37;
+
+/// Acknowledge and error report.
+const ACK_ERROR_REPORT: u8 = 0x02;
+/// DCS long read response.
+const DCS_LONG_READ_RESPONSE: u8 = 0x1C;
+/// DCS short read response with one byte.
+const DCS_SHORT_READ_RESPONSE_1: u8 = 0x21;
+/// DCS short read response with two bytes.
+const DCS_SHORT_READ_RESPONSE_2: u8 = 0x22;
+
+/// The trigger message the host reports in the read FIFO upon an acknowledge.
+const TRIGGER_ACK: u32 = 0x84;
+
+/// Errors that occur while parsing a read response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The panel reported an error instead of data.
+    Reported(u16),
+    /// The response is truncated or of an unexpected type.
+    Malformed,
+}
+
+/// Packs a packet header of `data_type` on virtual channel 0.
+pub const fn header(data_type: u8, data0: u8, data1: u8) -> u32 {
+    data_type as u32 | (data0 as u32) << 8 | (data1 as u32) << 16
+}
+
+/// A DSI packet to be sent to the panel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Packet<'a> {
+    data_type: u8,
+    payload: &'a [u8],
+}
+
+impl<'a> Packet<'a> {
+    /// Creates a DCS write of a command byte followed by its parameters.
+    ///
+    /// The shortest packet type that fits `bytes` is selected.
+    pub fn dcs_write(bytes: &'a [u8]) -> Self {
+        let data_type = match bytes.len() {
+            1 => DCS_SHORT_WRITE,
+            2 => DCS_SHORT_WRITE_PARAM,
+            _ => DCS_LONG_WRITE,
+        };
+
+        Packet {
+            data_type,
+            payload: bytes,
+        }
+    }
+
+    /// Checks whether the packet is a long packet with a separate payload.
+    pub fn is_long(&self) -> bool {
+        self.data_type == DCS_LONG_WRITE
+    }
+
+    /// Gets the words to write to `DSI_WR_DATA` for the packet.
+    pub fn words(&self) -> Words<'a> {
+        let header = if self.is_long() {
+            let length = self.payload.len() as u16;
+            header(self.data_type, length as u8, (length >> 8) as u8)
+        } else {
+            let byte = |index| self.payload.get(index).copied().unwrap_or(0);
+            header(self.data_type, byte(0), byte(1))
+        };
+
+        Words {
+            header: Some(header),
+            payload: if self.is_long() { self.payload } else { &[] },
+        }
+    }
+}
+
+/// An iterator over the words of a [`Packet`].
+///
+/// [`Packet`]: struct.Packet.html
+pub struct Words<'a> {
+    header: Option<u32>,
+    payload: &'a [u8],
+}
+
+impl<'a> Iterator for Words<'a> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if let Some(header) = self.header.take() {
+            return Some(header);
+        }
+
+        if self.payload.is_empty() {
+            return None;
+        }
+
+        // Pack up to four bytes in little-endian order, padding with zeroes.
+        let count = self.payload.len().min(4);
+        let (chunk, rest) = self.payload.split_at(count);
+        self.payload = rest;
+
+        let mut word = [0; 4];
+        word[..count].copy_from_slice(chunk);
+        Some(u32::from_le_bytes(word))
+    }
+}
+
+/// Parses the reply to a DCS read from the words of the read FIFO into `out`.
+///
+/// Returns the number of bytes received on success.
+pub fn parse_read_response(words: &[u32], out: &mut [u8]) -> Result<usize, Error> {
+    // Skip the acknowledge trigger the host may report before the packet.
+    let words = match words.first() {
+        Some(&word) if word & 0xFF == TRIGGER_ACK => &words[1..],
+        _ => words,
+    };
+
+    let (&header, payload) = words.split_first().ok_or(Error::Malformed)?;
+    let data = header.to_le_bytes();
+
+    let received: &[u8] = match data[0] & 0x3F {
+        ACK_ERROR_REPORT => return Err(Error::Reported(u16::from_le_bytes([data[1], data[2]]))),
+        DCS_SHORT_READ_RESPONSE_1 => &data[1..2],
+        DCS_SHORT_READ_RESPONSE_2 => &data[1..3],
+        DCS_LONG_READ_RESPONSE => {
+            let length = u16::from_le_bytes([data[1], data[2]]) as usize;
+            if length > payload.len() * 4 {
+                return Err(Error::Malformed);
+            }
+
+            // Copy the payload out of the words it is packed into.
+            let count = length.min(out.len());
+            for (index, byte) in out[..count].iter_mut().enumerate() {
+                *byte = payload[index / 4].to_le_bytes()[index % 4];
+            }
+            return Ok(count);
+        }
+        _ => return Err(Error::Malformed),
+    };
+
+    let count = received.len().min(out.len());
+    out[..count].copy_from_slice(&received[..count]);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects the words of `packet` into a fixed buffer.
+    fn words(packet: Packet<'_>) -> ([u32; 4], usize) {
+        let mut words = [0; 4];
+        let mut count = 0;
+        for word in packet.words() {
+            words[count] = word;
+            count += 1;
+        }
+        (words, count)
+    }
+
+    #[test]
+    fn short_writes() {
+        let packet = Packet::dcs_write(&[0x11]);
+        assert!(!packet.is_long());
+        assert_eq!(words(packet), ([0x0000_1105, 0, 0, 0], 1));
+
+        let packet = Packet::dcs_write(&[0x51, 0xFF]);
+        assert!(!packet.is_long());
+        assert_eq!(words(packet), ([0x00FF_5115, 0, 0, 0], 1));
+    }
+
+    #[test]
+    fn long_write_packs_payload_little_endian() {
+        let packet = Packet::dcs_write(&[0xB9, 0xFF, 0x83, 0x94, 0x01]);
+        assert!(packet.is_long());
+        assert_eq!(
+            words(packet),
+            ([0x0000_0539, 0x9483_FFB9, 0x0000_0001, 0], 3)
+        );
+
+        let packet = Packet::dcs_write(&[0xB9, 0xFF, 0x83]);
+        assert_eq!(words(packet), ([0x0000_0339, 0x0083_FFB9, 0, 0], 2));
+    }
+
+    #[test]
+    fn long_write_encodes_16_bit_length() {
+        let payload = [0xAA; 0x123];
+        let packet = Packet::dcs_write(&payload);
+
+        assert_eq!(packet.words().next(), Some(0x0001_2339));
+        assert_eq!(packet.words().count(), 1 + 0x124 / 4);
+    }
+
+    #[test]
+    fn header_packing() {
+        assert_eq!(header(SET_MAX_RETURN_PACKET_SIZE, 3, 0), 0x0000_0337);
+        assert_eq!(header(DCS_READ, 0x04, 0), 0x0000_0406);
+    }
+
+    #[test]
+    fn parses_short_responses() {
+        let mut out = [0; 3];
+        assert_eq!(parse_read_response(&[0x0000_1021], &mut out), Ok(1));
+        assert_eq!(out[0], 0x10);
+
+        let mut out = [0; 3];
+        assert_eq!(
+            parse_read_response(&[TRIGGER_ACK, 0x0083_1022], &mut out),
+            Ok(2)
+        );
+        assert_eq!(out, [0x10, 0x83, 0]);
+    }
+
+    #[test]
+    fn parses_long_response() {
+        let words = [TRIGGER_ACK, 0x0000_031C, 0x000F_8310];
+        let mut out = [0; 3];
+        assert_eq!(parse_read_response(&words, &mut out), Ok(3));
+        assert_eq!(out, [0x10, 0x83, 0x0F]);
+
+        // A short buffer only receives the first bytes.
+        let mut out = [0; 2];
+        assert_eq!(parse_read_response(&words, &mut out), Ok(2));
+        assert_eq!(out, [0x10, 0x83]);
+    }
+
+    #[test]
+    fn ignores_virtual_channel() {
+        let mut out = [0; 1];
+        assert_eq!(parse_read_response(&[0x0000_1061], &mut out), Ok(1));
+        assert_eq!(out[0], 0x10);
+    }
+
+    #[test]
+    fn reports_panel_errors() {
+        let mut out = [0; 3];
+        assert_eq!(
+            parse_read_response(&[TRIGGER_ACK, 0x0001_0002], &mut out),
+            Err(Error::Reported(0x0100))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_responses() {
+        let mut out = [0; 8];
+        for words in [
+            &[][..],
+            &[TRIGGER_ACK][..],
+            // Long response claiming more bytes than were received.
+            &[0x0000_051C, 0x0403_0201][..],
+            // A write packet is not a response.
+            &[0x0000_1105][..],
+        ]
+        .iter()
+        {
+            assert_eq!(
+                parse_read_response(words, &mut out),
+                Err(Error::Malformed),
+                "{:X?}",
+                words
+            );
+        }
+    }
+}
@@ -0,0 +1,559 @@
+//! Bring-up of the display controller and the DSI panel for the boot splash.
+//!
+//! The first stage does not train DRAM, so the framebuffer lives in the otherwise
+//! unused lower IRAM. To fit there, it is a 180x320 image with 4-bit palette indices
+//! that window A of the display controller scales up to the native 720x1280 of
+//! the panel.
+
+pub mod dsi;
+pub mod panel;
+
+use core::ptr;
+
+use libtegra::gpio;
+use libtegra::i2c::I2c;
+use libtegra::pinmux::{PinGrP, PinTristate};
+use libtegra::timer::usleep;
+
+use self::dsi::Packet;
+use self::panel::{Command, PanelId};
+use crate::bus::TimerDelay;
+use crate::console::Canvas;
+use crate::deadline::{self, Timeout};
+use crate::error::{BootError, ErrorKind, Stage};
+use crate::memory;
+use crate::sequencer::{self, Step};
+
+/// The 16-color palette of the framebuffer, shared with the boot logo.
+pub use logo::PALETTE;
+
+/// The width of the framebuffer in pixels.
+pub const WIDTH: usize = 180;
+/// The height of the framebuffer in pixels.
+pub const HEIGHT: usize = 320;
+
+/// The width of the panel in pixels.
+const PANEL_WIDTH: u32 = 720;
+/// The height of the panel in pixels.
+const PANEL_HEIGHT: u32 = 1280;
+
+/// The address of the framebuffer in IRAM, see [`memory::FRAMEBUFFER`].
+///
+/// [`memory::FRAMEBUFFER`]: ../memory/constant.FRAMEBUFFER.html
+const FRAMEBUFFER: usize = memory::FRAMEBUFFER.start;
+/// The distance between two lines of the framebuffer in bytes.
+const STRIDE: usize = 128;
+
+// Fails to compile if the framebuffer outgrows its region of IRAM.
+const _: [(); 1] = [(); memory::fits(STRIDE * HEIGHT, memory::FRAMEBUFFER) as usize];
+
+/// The palette index of regular text.
+pub const TEXT_COLOR: u8 = 0xF;
+/// The palette index of highlighted text.
+pub const HIGHLIGHT_COLOR: u8 = 0xB;
+/// The palette index of the background of the boot splash.
+pub const SPLASH_BACKGROUND: u8 = 0x0;
+/// The palette index of the background of the error screen.
+pub const ERROR_BACKGROUND: u8 = 0x4;
+
+const MAX77620_PWR: u32 = 0x3C;
+
+/// The MAX77620 configuration that powers the panel logic.
+const POWER_SEQUENCE: [Step; 3] = [
+    // Set LDO0 to 1.2V in normal mode.
+    Step::write(MAX77620_PWR, 0x23, 0xD0).verified(),
+    // Drive GPIO7 high to enable the panel's DSI supply.
+    Step::modify(MAX77620_PWR, 0x3D, 0x09, 0x09),
+    Step::delay(1000),
+];
+
+/// The base address of the Clock and Reset Controller.
+const CAR_BASE: usize = 0x6000_6000;
+
+const CLK_RST_CONTROLLER_RST_DEVICES_L: usize = 0x4;
+const CLK_RST_CONTROLLER_RST_DEVICES_H: usize = 0x8;
+const CLK_RST_CONTROLLER_CLK_OUT_ENB_L: usize = 0x10;
+const CLK_RST_CONTROLLER_CLK_OUT_ENB_H: usize = 0x14;
+const CLK_RST_CONTROLLER_PLLD_BASE: usize = 0xD0;
+const CLK_RST_CONTROLLER_PLLD_MISC1: usize = 0xD8;
+const CLK_RST_CONTROLLER_PLLD_MISC: usize = 0xDC;
+const CLK_RST_CONTROLLER_CLK_SOURCE_DISP1: usize = 0x138;
+const CLK_RST_CONTROLLER_CLK_SOURCE_HOST1X: usize = 0x180;
+
+const DISP1_DEVICE_BIT: u32 = 1 << 27;
+const HOST1X_DEVICE_BIT: u32 = 1 << 28;
+const DSI_DEVICE_BIT: u32 = 1 << 16;
+const MIPI_CAL_DEVICE_BIT: u32 = 1 << 24;
+
+/// PLLD configured for a 234MHz DSI clock from the 38.4MHz oscillator.
+const PLLD_BASE: u32 = 0x4810_C001;
+const PLLD_MISC1: u32 = 0x20;
+const PLLD_MISC: u32 = 0x2D_0AAA;
+const PLLD_LOCK: u32 = 1 << 27;
+
+/// The time PLLD may take to lock, in microseconds.
+const PLL_LOCK_TIMEOUT_US: u32 = 1000;
+
+/// DISP1 fed by PLLD_OUT0.
+const DISP1_CLOCK_SOURCE: u32 = 2 << 29;
+/// HOST1X fed by PLLP_OUT0 divided by 2.5.
+const HOST1X_CLOCK_SOURCE: u32 = 4 << 29 | 3;
+
+/// The base address of the display controller.
+const DC_BASE: usize = 0x5420_0000;
+
+const DC_CMD_DISPLAY_COMMAND: usize = 0x32;
+const DC_CMD_DISPLAY_POWER_CONTROL: usize = 0x36;
+const DC_CMD_STATE_CONTROL: usize = 0x41;
+const DC_CMD_DISPLAY_WINDOW_HEADER: usize = 0x42;
+const DC_DISP_DISP_WIN_OPTIONS: usize = 0x402;
+const DC_DISP_DISP_TIMING_OPTIONS: usize = 0x405;
+const DC_DISP_REF_TO_SYNC: usize = 0x406;
+const DC_DISP_SYNC_WIDTH: usize = 0x407;
+const DC_DISP_BACK_PORCH: usize = 0x408;
+const DC_DISP_ACTIVE: usize = 0x409;
+const DC_DISP_FRONT_PORCH: usize = 0x40A;
+const DC_DISP_DISP_CLOCK_CONTROL: usize = 0x42E;
+const DC_DISP_DISP_INTERFACE_CONTROL: usize = 0x42F;
+const DC_DISP_DISP_COLOR_CONTROL: usize = 0x430;
+const DC_DISP_BLEND_BACKGROUND_COLOR: usize = 0x4E4;
+const DC_WIN_COLOR_PALETTE: usize = 0x500;
+const DC_WIN_WIN_OPTIONS: usize = 0x700;
+const DC_WIN_COLOR_DEPTH: usize = 0x703;
+const DC_WIN_POSITION: usize = 0x704;
+const DC_WIN_SIZE: usize = 0x705;
+const DC_WIN_PRESCALED_SIZE: usize = 0x706;
+const DC_WIN_H_INITIAL_DDA: usize = 0x707;
+const DC_WIN_V_INITIAL_DDA: usize = 0x708;
+const DC_WIN_DDA_INC: usize = 0x709;
+const DC_WIN_LINE_STRIDE: usize = 0x70A;
+const DC_WINBUF_START_ADDR: usize = 0x800;
+const DC_WINBUF_ADDR_H_OFFSET: usize = 0x806;
+const DC_WINBUF_ADDR_V_OFFSET: usize = 0x808;
+
+const GENERAL_ACT_REQ: u32 = 1 << 0;
+const WIN_A_ACT_REQ: u32 = 1 << 1;
+const GENERAL_UPDATE: u32 = 1 << 8;
+const WIN_A_UPDATE: u32 = 1 << 9;
+const WINDOW_A_SELECT: u32 = 1 << 4;
+const DISP_CTRL_MODE_C_DISPLAY: u32 = 1 << 5;
+const DSI_ENABLE: u32 = 1 << 29;
+const WIN_ENABLE: u32 = 1 << 30;
+const COLOR_DEPTH_P4: u32 = 2;
+
+/// Scaling factor between the framebuffer and the panel in 4.12 fixed point.
+const DDA_INC: u32 = ((WIDTH as u32) << 12) / PANEL_WIDTH;
+
+/// The display controller configuration for the 720x1280 panel.
+const DC_CONFIG: [(usize, u32); 16] = [
+    // Power up the display pipeline.
+    (
+        DC_CMD_DISPLAY_POWER_CONTROL,
+        1 << 0 | 1 << 2 | 1 << 4 | 1 << 6 | 1 << 8 | 1 << 16 | 1 << 18,
+    ),
+    // Feed the DSI host with 24-bit pixels.
+    (DC_DISP_DISP_WIN_OPTIONS, DSI_ENABLE),
+    (DC_DISP_DISP_INTERFACE_CONTROL, 0),
+    (DC_DISP_DISP_COLOR_CONTROL, 8),
+    (DC_DISP_DISP_CLOCK_CONTROL, 4),
+    // Configure the panel timings.
+    (DC_DISP_DISP_TIMING_OPTIONS, 0),
+    (DC_DISP_REF_TO_SYNC, 1 << 16),
+    (DC_DISP_SYNC_WIDTH, 0x1_0048),
+    (DC_DISP_BACK_PORCH, 0x9_0048),
+    (DC_DISP_ACTIVE, PANEL_HEIGHT << 16 | PANEL_WIDTH),
+    (DC_DISP_FRONT_PORCH, 0xA_0088),
+    (DC_DISP_BLEND_BACKGROUND_COLOR, 0),
+    // Scale the 4-bit paletted framebuffer in window A up to the panel.
+    (DC_WIN_COLOR_DEPTH, COLOR_DEPTH_P4),
+    (DC_WIN_POSITION, 0),
+    (DC_WIN_SIZE, PANEL_HEIGHT << 16 | PANEL_WIDTH),
+    (
+        DC_WIN_PRESCALED_SIZE,
+        (HEIGHT as u32) << 16 | (WIDTH / 2) as u32,
+    ),
+];
+
+/// The base address of the DSI host.
+const DSI_BASE: usize = 0x5430_0000;
+
+const DSI_RD_DATA: usize = 0x9;
+const DSI_WR_DATA: usize = 0xA;
+const DSI_POWER_CONTROL: usize = 0xB;
+const DSI_HOST_CONTROL: usize = 0xF;
+const DSI_CONTROL: usize = 0x10;
+const DSI_SOL_DELAY: usize = 0x11;
+const DSI_TRIGGER: usize = 0x13;
+const DSI_STATUS: usize = 0x15;
+const DSI_PKT_SEQ_0_LO: usize = 0x23;
+const DSI_PKT_LEN_0_1: usize = 0x34;
+const DSI_PHY_TIMING_0: usize = 0x3C;
+const DSI_PHY_TIMING_1: usize = 0x3D;
+const DSI_PHY_TIMING_2: usize = 0x3E;
+const DSI_BTA_TIMING: usize = 0x3F;
+const DSI_TIMEOUT_0: usize = 0x44;
+const DSI_TIMEOUT_1: usize = 0x45;
+const DSI_TO_TALLY: usize = 0x46;
+const DSI_PAD_CONTROL_0: usize = 0x4B;
+
+const DSI_POWER_CONTROL_ENABLE: u32 = 1 << 0;
+const DSI_HOST_CONTROL_IMM_BTA: u32 = 1 << 3;
+const DSI_HOST_CONTROL_CS: u32 = 1 << 5;
+const DSI_HOST_CONTROL_ECC: u32 = 1 << 6;
+const DSI_HOST_CONTROL_TX_TRIG_HOST: u32 = 3 << 12;
+const DSI_CONTROL_HOST_ENABLE: u32 = 1 << 0;
+const DSI_CONTROL_VIDEO_ENABLE: u32 = 1 << 1;
+const DSI_CONTROL_FORMAT_24: u32 = 3 << 12;
+const DSI_CONTROL_LANES_4: u32 = 3 << 16;
+const DSI_TRIGGER_HOST: u32 = 1 << 1;
+const DSI_STATUS_RD_FIFO_COUNT: u32 = 0x1F;
+
+/// The time the DSI host may take to send a packet or to get the bus back from the
+/// panel, in microseconds.
+const DSI_TIMEOUT_US: u32 = 10_000;
+
+/// The DSI host configuration for host-driven command mode on four lanes.
+const DSI_CONFIG: [(usize, u32); 9] = [
+    (DSI_POWER_CONTROL, 0),
+    (DSI_PAD_CONTROL_0, 0),
+    (DSI_PHY_TIMING_0, 0x0607_0601),
+    (DSI_PHY_TIMING_1, 0x040A_0E05),
+    (DSI_PHY_TIMING_2, 0x0003_0109),
+    (DSI_BTA_TIMING, 0x0019_0A14),
+    (DSI_TIMEOUT_0, 0x2000_FFFF),
+    (DSI_TIMEOUT_1, 0x0765_2000),
+    (DSI_TO_TALLY, 0),
+];
+
+/// The packet sequence for non-burst video mode with sync events, followed by the
+/// lengths of the blanking and pixel packets.
+const DSI_VIDEO_SEQUENCE: [u32; 12] = [
+    0x4000_0208,
+    0,
+    0x4000_0308,
+    0,
+    0x4000_0308,
+    0,
+    0x3F3B_2B08,
+    0x2CC,
+    0x4000_0308,
+    0,
+    0x3F3B_2B08,
+    0x2CC,
+];
+const DSI_VIDEO_LENGTHS: [u32; 4] = [0xCE_0000, 0x0870_01A2, 0x190, 0x190];
+
+/// Whether the display is up and the error screen can be shown.
+static mut ACTIVE: bool = false;
+
+fn modify_car_reg(offset: usize, clear: u32, set: u32) {
+    let reg = (CAR_BASE + offset) as *mut u32;
+    unsafe { ptr::write_volatile(reg, (ptr::read_volatile(reg) & !clear) | set) }
+}
+
+fn write_car_reg(offset: usize, value: u32) {
+    unsafe { ptr::write_volatile((CAR_BASE + offset) as *mut u32, value) }
+}
+
+fn read_car_reg(offset: usize) -> u32 {
+    unsafe { ptr::read_volatile((CAR_BASE + offset) as *const u32) }
+}
+
+fn write_dc(reg: usize, value: u32) {
+    unsafe { ptr::write_volatile((DC_BASE + reg * 4) as *mut u32, value) }
+}
+
+fn read_dsi(reg: usize) -> u32 {
+    unsafe { ptr::read_volatile((DSI_BASE + reg * 4) as *const u32) }
+}
+
+fn write_dsi(reg: usize, value: u32) {
+    unsafe { ptr::write_volatile((DSI_BASE + reg * 4) as *mut u32, value) }
+}
+
+/// Reports a wait for the display hardware that timed out.
+fn timed_out(_: Timeout) -> BootError {
+    BootError::new(ErrorKind::Timeout, Stage::Display)
+}
+
+fn enable_clocks() -> Result<(), BootError> {
+    // Bring up PLLD, which clocks both the display controller and the DSI host.
+    write_car_reg(CLK_RST_CONTROLLER_PLLD_BASE, PLLD_BASE);
+    write_car_reg(CLK_RST_CONTROLLER_PLLD_MISC1, PLLD_MISC1);
+    write_car_reg(CLK_RST_CONTROLLER_PLLD_MISC, PLLD_MISC);
+    deadline::poll_until(
+        &TimerDelay,
+        || read_car_reg(CLK_RST_CONTROLLER_PLLD_BASE) & PLLD_LOCK != 0,
+        PLL_LOCK_TIMEOUT_US,
+    )
+    .map_err(timed_out)?;
+
+    // Assert reset, enable the clocks and release HOST1X, DISP1, DSI and MIPI_CAL.
+    let l_bits = DISP1_DEVICE_BIT | HOST1X_DEVICE_BIT;
+    let h_bits = DSI_DEVICE_BIT | MIPI_CAL_DEVICE_BIT;
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_L, 0, l_bits);
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_H, 0, h_bits);
+    write_car_reg(CLK_RST_CONTROLLER_CLK_SOURCE_HOST1X, HOST1X_CLOCK_SOURCE);
+    write_car_reg(CLK_RST_CONTROLLER_CLK_SOURCE_DISP1, DISP1_CLOCK_SOURCE);
+    modify_car_reg(CLK_RST_CONTROLLER_CLK_OUT_ENB_L, 0, l_bits);
+    modify_car_reg(CLK_RST_CONTROLLER_CLK_OUT_ENB_H, 0, h_bits);
+    usleep(2);
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_L, l_bits, 0);
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_H, h_bits, 0);
+
+    Ok(())
+}
+
+fn power_up_panel() -> Result<(), BootError> {
+    // Power the panel logic through the PMIC.
+    sequencer::run(&mut I2c::C5, &TimerDelay, &POWER_SEQUENCE)
+        .map_err(|failure| BootError::from_sequence(Stage::Display, &POWER_SEQUENCE, failure))?;
+
+    unsafe {
+        PinGrP::NfcEnPi0.set_tristate(PinTristate::Passthrough);
+        PinGrP::NfcIntPi1.set_tristate(PinTristate::Passthrough);
+        PinGrP::LcdRstPv2.set_tristate(PinTristate::Passthrough);
+    }
+
+    // Enable the +5V and -5V rails of the panel, in this order.
+    tegra_gpio!(I, 0).config(gpio::Config::OutputHigh);
+    usleep(10_000);
+    tegra_gpio!(I, 1).config(gpio::Config::OutputHigh);
+    usleep(10_000);
+
+    Ok(())
+}
+
+fn send_packet(packet: Packet<'_>) -> Result<(), BootError> {
+    for word in packet.words() {
+        write_dsi(DSI_WR_DATA, word);
+    }
+
+    write_dsi(DSI_TRIGGER, DSI_TRIGGER_HOST);
+    deadline::poll_until(&TimerDelay, || read_dsi(DSI_TRIGGER) == 0, DSI_TIMEOUT_US)
+        .map_err(timed_out)
+}
+
+fn read_panel_id() -> Option<PanelId> {
+    let host_control = DSI_HOST_CONTROL_TX_TRIG_HOST | DSI_HOST_CONTROL_CS | DSI_HOST_CONTROL_ECC;
+
+    // Limit the reply to the three ID bytes and request them.
+    write_dsi(
+        DSI_WR_DATA,
+        dsi::header(dsi::SET_MAX_RETURN_PACKET_SIZE, 3, 0),
+    );
+    write_dsi(
+        DSI_WR_DATA,
+        dsi::header(dsi::DCS_READ, panel::GET_DISPLAY_ID, 0),
+    );
+
+    // Hand the bus over to the panel and wait for it to return it. A panel that keeps
+    // the bus is treated like one that does not identify itself.
+    write_dsi(DSI_HOST_CONTROL, host_control | DSI_HOST_CONTROL_IMM_BTA);
+    deadline::poll_until(
+        &TimerDelay,
+        || read_dsi(DSI_HOST_CONTROL) & DSI_HOST_CONTROL_IMM_BTA == 0,
+        DSI_TIMEOUT_US,
+    )
+    .ok()?;
+
+    // Drain the read FIFO and parse the reply.
+    let mut words = [0; 8];
+    let count = (read_dsi(DSI_STATUS) & DSI_STATUS_RD_FIFO_COUNT) as usize;
+    for word in words.iter_mut().take(count) {
+        *word = read_dsi(DSI_RD_DATA);
+    }
+
+    let mut reply = [0; 3];
+    match dsi::parse_read_response(&words[..count.min(words.len())], &mut reply) {
+        Ok(3) => Some(PanelId::from_display_id(reply)),
+        _ => None,
+    }
+}
+
+fn init_dsi() -> Result<(), BootError> {
+    for &(reg, value) in DSI_CONFIG.iter() {
+        write_dsi(reg, value);
+    }
+
+    // Enable the host in command mode.
+    write_dsi(
+        DSI_HOST_CONTROL,
+        DSI_HOST_CONTROL_TX_TRIG_HOST | DSI_HOST_CONTROL_CS | DSI_HOST_CONTROL_ECC,
+    );
+    write_dsi(
+        DSI_CONTROL,
+        DSI_CONTROL_LANES_4 | DSI_CONTROL_FORMAT_24 | DSI_CONTROL_HOST_ENABLE,
+    );
+    write_dsi(DSI_POWER_CONTROL, DSI_POWER_CONTROL_ENABLE);
+    usleep(10_000);
+
+    // Release the panel from reset.
+    tegra_gpio!(V, 2).config(gpio::Config::OutputHigh);
+    usleep(60_000);
+
+    // Identify the panel and send the matching initialization sequence. Panels that
+    // fail to identify themselves get the generic one.
+    let panel = read_panel_id().map_or(&panel::GENERIC, panel::lookup);
+    for command in panel.init {
+        match *command {
+            Command::Dcs(bytes) => send_packet(Packet::dcs_write(bytes))?,
+            Command::Delay(ms) => usleep(ms * 1000),
+        }
+    }
+
+    // Switch over to video mode, with pixels coming from the display controller.
+    for (index, &value) in DSI_VIDEO_SEQUENCE.iter().enumerate() {
+        write_dsi(DSI_PKT_SEQ_0_LO + index, value);
+    }
+    for (index, &value) in DSI_VIDEO_LENGTHS.iter().enumerate() {
+        write_dsi(DSI_PKT_LEN_0_1 + index, value);
+    }
+    write_dsi(DSI_SOL_DELAY, 0x18);
+    write_dsi(DSI_HOST_CONTROL, DSI_HOST_CONTROL_CS | DSI_HOST_CONTROL_ECC);
+    write_dsi(
+        DSI_CONTROL,
+        DSI_CONTROL_LANES_4 | DSI_CONTROL_FORMAT_24 | DSI_CONTROL_VIDEO_ENABLE,
+    );
+
+    Ok(())
+}
+
+fn init_dc() {
+    // Select window A for the window registers.
+    write_dc(DC_CMD_DISPLAY_WINDOW_HEADER, WINDOW_A_SELECT);
+
+    for &(reg, value) in DC_CONFIG.iter() {
+        write_dc(reg, value);
+    }
+
+    for (index, &color) in PALETTE.iter().enumerate() {
+        // The palette takes colors as 0xBBGGRR.
+        let color = (color & 0xFF) << 16 | (color & 0xFF00) | color >> 16;
+        write_dc(DC_WIN_COLOR_PALETTE + index, color);
+    }
+
+    write_dc(DC_WIN_H_INITIAL_DDA, 0);
+    write_dc(DC_WIN_V_INITIAL_DDA, 0);
+    write_dc(DC_WIN_DDA_INC, DDA_INC << 16 | DDA_INC);
+    write_dc(DC_WIN_LINE_STRIDE, STRIDE as u32);
+    write_dc(DC_WINBUF_START_ADDR, FRAMEBUFFER as u32);
+    write_dc(DC_WINBUF_ADDR_H_OFFSET, 0);
+    write_dc(DC_WINBUF_ADDR_V_OFFSET, 0);
+    write_dc(DC_WIN_WIN_OPTIONS, WIN_ENABLE);
+
+    // Latch the configuration and start scanning out.
+    write_dc(DC_CMD_DISPLAY_COMMAND, DISP_CTRL_MODE_C_DISPLAY);
+    write_dc(DC_CMD_STATE_CONTROL, GENERAL_UPDATE | WIN_A_UPDATE);
+    write_dc(DC_CMD_STATE_CONTROL, GENERAL_ACT_REQ | WIN_A_ACT_REQ);
+}
+
+/// The framebuffer of 4-bit palette indices, two pixels per byte.
+pub struct Framebuffer {
+    _private: (),
+}
+
+fn pixel_address(x: usize, y: usize) -> *mut u8 {
+    (FRAMEBUFFER + y * STRIDE + x / 2) as *mut u8
+}
+
+impl Framebuffer {
+    /// Fills the whole framebuffer with the palette index `color`.
+    pub fn fill(&mut self, color: u8) {
+        let color = color & 0xF;
+        let word = u32::from_ne_bytes([color << 4 | color; 4]);
+        let start = FRAMEBUFFER as *mut u32;
+
+        unsafe {
+            memory::memset(start..start.add(STRIDE * HEIGHT / 4), word);
+        }
+    }
+}
+
+impl Canvas for Framebuffer {
+    fn width(&self) -> usize {
+        WIDTH
+    }
+
+    fn height(&self) -> usize {
+        HEIGHT
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        if x >= WIDTH || y >= HEIGHT {
+            return 0;
+        }
+
+        // The left pixel of a pair lives in the low nibble.
+        unsafe { ptr::read_volatile(pixel_address(x, y)) >> ((x % 2) * 4) & 0xF }
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        if x >= WIDTH || y >= HEIGHT {
+            return;
+        }
+
+        let shift = (x % 2) * 4;
+        unsafe {
+            let byte = ptr::read_volatile(pixel_address(x, y)) & !(0xF << shift);
+            ptr::write_volatile(pixel_address(x, y), byte | (color & 0xF) << shift);
+        }
+    }
+
+    fn scroll_up(&mut self, height: usize, lines: usize, color: u8) {
+        let height = height.min(HEIGHT);
+        let lines = lines.min(height);
+
+        // Whole lines are contiguous in memory, so move them in one go.
+        unsafe {
+            ptr::copy(
+                pixel_address(0, lines),
+                pixel_address(0, 0),
+                (height - lines) * STRIDE,
+            );
+        }
+
+        self.fill_rect(0, height - lines, WIDTH, lines, color);
+    }
+}
+
+/// The display, driven by the display controller through the DSI host.
+pub struct Display {
+    _private: (),
+}
+
+impl Display {
+    /// Powers up the panel, configures the display controller and the DSI host and
+    /// starts scanning out the framebuffer, initially filled with the splash background.
+    pub fn init() -> Result<Self, BootError> {
+        power_up_panel()?;
+        enable_clocks()?;
+
+        let mut framebuffer = Framebuffer { _private: () };
+        framebuffer.fill(SPLASH_BACKGROUND);
+
+        init_dsi()?;
+        init_dc();
+
+        unsafe {
+            ACTIVE = true;
+        }
+
+        Ok(Display { _private: () })
+    }
+
+    /// Gets the framebuffer that is being scanned out.
+    pub fn framebuffer(&mut self) -> Framebuffer {
+        Framebuffer { _private: () }
+    }
+}
+
+/// Switches the display to the error screen, if it was brought up.
+///
+/// This is meant to be called from the panic handler, after all other state was torn
+/// down.
+pub unsafe fn show_error_screen() {
+    if ACTIVE {
+        Framebuffer { _private: () }.fill(ERROR_BACKGROUND);
+    }
+}
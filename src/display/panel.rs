@@ -0,0 +1,170 @@
+//! Identification and initialization of the LCD panels found in the field.
+//!
+//! Different production runs ship panels from different vendors, which all speak
+//! DCS but need slightly different initialization. The panel is identified by the
+//! reply to the DCS `get_display_id` command, of which the first byte holds the
+//! vendor and the third byte the model.
+
+/// The DCS command that reads the three-byte display ID.
+pub const GET_DISPLAY_ID: u8 = 0x04;
+
+const EXIT_SLEEP_MODE: u8 = 0x11;
+const SET_DISPLAY_ON: u8 = 0x29;
+
+/// A step of a panel initialization sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Sends a DCS command byte followed by its parameters.
+    Dcs(&'static [u8]),
+    /// Waits for the given number of milliseconds.
+    Delay(u32),
+}
+
+/// The ID of a panel as reported by `get_display_id`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PanelId {
+    /// The vendor of the panel.
+    pub vendor: u8,
+    /// The vendor-specific model of the panel.
+    pub model: u8,
+}
+
+impl PanelId {
+    /// Extracts the panel ID from the reply to `get_display_id`.
+    pub fn from_display_id(reply: [u8; 3]) -> Self {
+        PanelId {
+            vendor: reply[0],
+            model: reply[2],
+        }
+    }
+}
+
+/// A known panel along with its initialization sequence.
+#[derive(Debug)]
+pub struct Panel {
+    /// The vendor of the panel.
+    pub vendor: u8,
+    /// The model of the panel, or `None` to match all models of the vendor.
+    pub model: Option<u8>,
+    /// The commands to send to bring the panel up.
+    pub init: &'static [Command],
+}
+
+/// The sequence that works for all panels without vendor-specific needs.
+const GENERIC_INIT: [Command; 4] = [
+    Command::Dcs(&[EXIT_SLEEP_MODE]),
+    Command::Delay(180),
+    Command::Dcs(&[SET_DISPLAY_ON]),
+    Command::Delay(20),
+];
+
+/// The sequence for Himax-based panels, which need their extended command set unlocked.
+const HIMAX_INIT: [Command; 6] = [
+    // Unlock the extended command set and select register bank 0.
+    Command::Dcs(&[0xB9, 0xFF, 0x83, 0x94]),
+    Command::Dcs(&[0xBD, 0x00]),
+    Command::Dcs(&[EXIT_SLEEP_MODE]),
+    Command::Delay(180),
+    Command::Dcs(&[SET_DISPLAY_ON]),
+    Command::Delay(20),
+];
+
+/// The panel assumed when the reported ID matches none of the known panels.
+pub static GENERIC: Panel = Panel {
+    vendor: 0,
+    model: None,
+    init: &GENERIC_INIT,
+};
+
+/// The known panels, with more specific entries first.
+pub static PANELS: [Panel; 4] = [
+    // JDI LPM062M326A
+    Panel {
+        vendor: 0x10,
+        model: None,
+        init: &GENERIC_INIT,
+    },
+    // InnoLux P062CCA-AZ1
+    Panel {
+        vendor: 0x20,
+        model: None,
+        init: &HIMAX_INIT,
+    },
+    // AUO A062TAN01
+    Panel {
+        vendor: 0x30,
+        model: None,
+        init: &HIMAX_INIT,
+    },
+    // Sharp LQ055T1SW10
+    Panel {
+        vendor: 0x40,
+        model: None,
+        init: &GENERIC_INIT,
+    },
+];
+
+/// Looks up the panel with the given `id`, falling back to [`GENERIC`].
+///
+/// [`GENERIC`]: static.GENERIC.html
+pub fn lookup(id: PanelId) -> &'static Panel {
+    PANELS
+        .iter()
+        .find(|panel| {
+            panel.vendor == id.vendor && panel.model.map_or(true, |model| model == id.model)
+        })
+        .unwrap_or(&GENERIC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(vendor: u8, model: u8) -> PanelId {
+        PanelId { vendor, model }
+    }
+
+    #[test]
+    fn display_id_holds_vendor_and_model() {
+        assert_eq!(PanelId::from_display_id([0x20, 0x99, 0x0F]), id(0x20, 0x0F));
+    }
+
+    #[test]
+    fn looks_up_known_vendors() {
+        for (vendor, init) in [
+            (0x10, &GENERIC_INIT[..]),
+            (0x20, &HIMAX_INIT[..]),
+            (0x30, &HIMAX_INIT[..]),
+            (0x40, &GENERIC_INIT[..]),
+        ]
+        .iter()
+        {
+            for &model in [0x00, 0x0F, 0xFF].iter() {
+                let panel = lookup(id(*vendor, model));
+                assert_eq!(panel.vendor, *vendor);
+                assert_eq!(panel.init, *init);
+            }
+        }
+    }
+
+    #[test]
+    fn falls_back_to_generic_panel() {
+        for &vendor in [0x00, 0x11, 0x50, 0xFF].iter() {
+            assert!(core::ptr::eq(lookup(id(vendor, 0x0F)), &GENERIC));
+        }
+    }
+
+    #[test]
+    fn init_sequences_wake_panel_before_turning_it_on() {
+        for init in PANELS.iter().chain(Some(&GENERIC)).map(|panel| panel.init) {
+            let position = |command: u8| {
+                init.iter()
+                    .position(|step| matches!(*step, Command::Dcs(bytes) if bytes == [command]))
+                    .unwrap()
+            };
+
+            assert!(position(EXIT_SLEEP_MODE) < position(SET_DISPLAY_ON));
+            assert!(matches!(init[position(EXIT_SLEEP_MODE) + 1], Command::Delay(ms) if ms >= 120));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! The layout of the compact 16-bit error codes.
+//!
+//! This module does not depend on any hardware, so that both the error type and the
+//! panic policy, which only sees the code of the last error, agree on the layout.
+
+/// The category of I2C transfer failures.
+pub const I2C: u8 = 0x1;
+/// The category of registers that did not hold the value written to them.
+pub const REGISTER_MISMATCH: u8 = 0x2;
+/// The category of hardware that did not respond in time.
+pub const TIMEOUT: u8 = 0x3;
+/// The category of block storage failures.
+pub const STORAGE: u8 = 0x4;
+/// The category of failed cryptographic operations.
+pub const CRYPTO: u8 = 0x5;
+/// The category of manifests and payloads that failed verification.
+pub const VERIFICATION: u8 = 0x6;
+/// The category of failed rollback checks.
+pub const ROLLBACK: u8 = 0x7;
+/// The category of fuse access failures.
+pub const FUSE: u8 = 0x8;
+/// The category of boots without a bootable slot.
+pub const NO_BOOTABLE_SLOT: u8 = 0x9;
+/// The category of invalid build-time configurations.
+pub const CONFIG: u8 = 0xA;
+
+/// Packs the 4-bit `stage`, the 4-bit `category` and the `detail` byte into a code.
+pub const fn pack(stage: u8, category: u8, detail: u8) -> u16 {
+    ((stage & 0xF) as u16) << 12 | ((category & 0xF) as u16) << 8 | detail as u16
+}
+
+/// Gets the category from bits 11:8 of `code`.
+pub const fn category(code: u16) -> u8 {
+    (code >> 8 & 0xF) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_fields_into_their_bits() {
+        assert_eq!(pack(0x1, I2C, 0x00), 0x1100);
+        assert_eq!(pack(0x2, STORAGE, 0x12), 0x2412);
+        assert_eq!(pack(0x8, CONFIG, 0xFF), 0x8AFF);
+        assert_eq!(pack(0xF, 0xF, 0xFF), 0xFFFF);
+    }
+
+    #[test]
+    fn truncates_oversized_fields() {
+        assert_eq!(pack(0x13, 0x24, 0x56), 0x3456);
+    }
+
+    #[test]
+    fn unpacks_the_category() {
+        for &(stage, category_, detail) in [(0x1, I2C, 0x00), (0xF, NO_BOOTABLE_SLOT, 0xFF)].iter()
+        {
+            assert_eq!(category(pack(stage, category_, detail)), category_);
+        }
+    }
+
+    #[test]
+    fn categories_are_distinct_and_fit_their_field() {
+        let categories = [
+            I2C,
+            REGISTER_MISMATCH,
+            TIMEOUT,
+            STORAGE,
+            CRYPTO,
+            VERIFICATION,
+            ROLLBACK,
+            FUSE,
+            NO_BOOTABLE_SLOT,
+            CONFIG,
+        ];
+
+        for (i, &a) in categories.iter().enumerate() {
+            assert!(a != 0 && a <= 0xF, "category {:#X}", a);
+            assert!(!categories[i + 1..].contains(&a), "category {:#X}", a);
+        }
+    }
+}
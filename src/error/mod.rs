@@ -0,0 +1,409 @@
+//! The bootloader-wide error type.
+//!
+//! Every fatal error ends up as a [`BootError`] that records what went wrong, in which
+//! stage of the boot flow and on which device and register. Each error maps to a
+//! compact 16-bit code that can be displayed even without a debug UART:
+//!
+//! | Bits  | Description                                   |
+//! |-------|-----------------------------------------------|
+//! | 15:12 | The [`Stage`] the error occurred in           |
+//! | 11:8  | The category of the [`ErrorKind`]            |
+//! | 7:0   | Kind-specific detail, e.g. the register index |
+//!
+//! The layout and the category numbers are defined in [`code`].
+//!
+//! [`BootError`]: struct.BootError.html
+//! [`Stage`]: enum.Stage.html
+//! [`ErrorKind`]: enum.ErrorKind.html
+//! [`code`]: code/index.html
+
+pub mod code;
+
+use core::fmt;
+
+use libtegra::i2c;
+
+use crate::sequencer::{Failure, Step, StepError};
+use crate::{boot, crypto, fuses, rollback, storage, verify};
+
+/// The stages of the boot flow in which errors may occur.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Stage {
+    /// PMIC configuration.
+    Pmic = 0x1,
+    /// Storage access.
+    Storage = 0x2,
+    /// Manifest and payload verification.
+    Verify = 0x3,
+    /// Rollback protection.
+    Rollback = 0x4,
+    /// Slot selection and payload loading.
+    Boot = 0x5,
+    /// Display bring-up.
+    Display = 0x6,
+    /// USB device bring-up.
+    Usb = 0x7,
+    /// Clock configuration.
+    Clock = 0x8,
+}
+
+/// The kinds of errors that may occur during boot.
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// An I2C transfer failed.
+    I2c(i2c::Error),
+    /// A register did not hold the value that was written to it.
+    RegisterMismatch { expected: u8, actual: u8 },
+    /// The hardware did not respond in time.
+    Timeout,
+    /// Accessing block storage failed.
+    Storage(storage::Error),
+    /// A cryptographic operation failed.
+    Crypto(crypto::Error),
+    /// The manifest or a payload failed verification.
+    Verification(verify::Error),
+    /// The rollback check failed.
+    Rollback(rollback::Error),
+    /// Accessing the fuses failed.
+    Fuse(fuses::Error),
+    /// Neither boot slot contains a bootable payload.
+    NoBootableSlot,
+    /// The build-time configuration is invalid.
+    Config,
+}
+
+impl ErrorKind {
+    /// Gets the 4-bit category of the error.
+    fn category(&self) -> u8 {
+        match self {
+            ErrorKind::I2c(_) => code::I2C,
+            ErrorKind::RegisterMismatch { .. } => code::REGISTER_MISMATCH,
+            ErrorKind::Timeout => code::TIMEOUT,
+            ErrorKind::Storage(_) => code::STORAGE,
+            ErrorKind::Crypto(_) => code::CRYPTO,
+            ErrorKind::Verification(_) => code::VERIFICATION,
+            ErrorKind::Rollback(_) => code::ROLLBACK,
+            ErrorKind::Fuse(_) => code::FUSE,
+            ErrorKind::NoBootableSlot => code::NO_BOOTABLE_SLOT,
+            ErrorKind::Config => code::CONFIG,
+        }
+    }
+
+    /// Gets the detail byte of the error, if the kind itself carries any detail.
+    fn detail(&self) -> Option<u8> {
+        let detail = match self {
+            ErrorKind::Storage(storage::Error::CommandFailed { command, .. }) => *command,
+            ErrorKind::Storage(storage::Error::DataError) => 0xFE,
+            ErrorKind::Storage(storage::Error::UnalignedBuffer) => 0xFF,
+            ErrorKind::Crypto(crypto::Error::InvalidKey) => 0x1,
+            ErrorKind::Crypto(crypto::Error::InvalidSignature) => 0x2,
+            ErrorKind::Verification(error) => match error {
+                verify::Error::Manifest(_) => 0x1,
+                verify::Error::Crypto(_) => 0x2,
+                verify::Error::MissingPayload => 0x3,
+                verify::Error::SizeMismatch => 0x4,
+                verify::Error::HashMismatch => 0x5,
+                verify::Error::VersionTooOld => 0x6,
+            },
+            ErrorKind::Rollback(rollback::Error::Rollback { fused, .. }) => *fused as u8,
+            _ => return None,
+        };
+
+        Some(detail)
+    }
+}
+
+/// A fatal error along with the context it occurred in.
+#[derive(Debug)]
+pub struct BootError {
+    /// What went wrong.
+    pub kind: ErrorKind,
+    /// The stage of the boot flow the error occurred in.
+    pub stage: Stage,
+    /// The address of the device involved, if any.
+    pub device: Option<u32>,
+    /// The register of the device involved, if any.
+    pub register: Option<u32>,
+}
+
+impl BootError {
+    /// Creates a new error of the given `kind` in `stage`.
+    pub const fn new(kind: ErrorKind, stage: Stage) -> Self {
+        BootError {
+            kind,
+            stage,
+            device: None,
+            register: None,
+        }
+    }
+
+    /// Attaches the device and register involved in the error.
+    pub fn at(self, device: u32, register: u32) -> Self {
+        BootError {
+            device: Some(device),
+            register: Some(register),
+            ..self
+        }
+    }
+
+    /// Creates an error from a failed register sequence.
+    pub fn from_sequence(stage: Stage, steps: &[Step], failure: Failure<i2c::Error>) -> Self {
+        let kind = match failure.error {
+            StepError::Bus(error) => ErrorKind::I2c(error),
+            StepError::Mismatch { expected, actual } => {
+                ErrorKind::RegisterMismatch { expected, actual }
+            }
+            StepError::Timeout => ErrorKind::Timeout,
+            StepError::TooManyRegisters => ErrorKind::Config,
+        };
+
+        let error = BootError::new(kind, stage);
+        match steps
+            .get(failure.step)
+            .and_then(|step| step.action.target())
+        {
+            Some((device, register)) => error.at(device, register as u32),
+            None => error,
+        }
+    }
+
+    /// Gets the compact numeric code of the error.
+    pub fn code(&self) -> u16 {
+        let detail = self
+            .kind
+            .detail()
+            .or_else(|| self.register.map(|register| register as u8))
+            .unwrap_or(0);
+
+        code::pack(self.stage as u8, self.kind.category(), detail)
+    }
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "E{:04X}: {:?} in {:?}",
+            self.code(),
+            self.kind,
+            self.stage
+        )?;
+        if let Some(device) = self.device {
+            write!(f, " (device {:#X}", device)?;
+            if let Some(register) = self.register {
+                write!(f, ", register {:#X}", register)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<storage::Error> for BootError {
+    fn from(error: storage::Error) -> Self {
+        BootError::new(ErrorKind::from(error), Stage::Storage)
+    }
+}
+
+impl From<storage::Error> for ErrorKind {
+    fn from(error: storage::Error) -> Self {
+        // Timeouts are reported as such, regardless of the device that timed out.
+        match error {
+            storage::Error::Timeout => ErrorKind::Timeout,
+            error => ErrorKind::Storage(error),
+        }
+    }
+}
+
+impl From<crypto::Error> for BootError {
+    fn from(error: crypto::Error) -> Self {
+        BootError::new(ErrorKind::Crypto(error), Stage::Verify)
+    }
+}
+
+impl From<verify::Error> for BootError {
+    fn from(error: verify::Error) -> Self {
+        BootError::new(ErrorKind::Verification(error), Stage::Verify)
+    }
+}
+
+impl From<rollback::Error> for BootError {
+    fn from(error: rollback::Error) -> Self {
+        let kind = match error {
+            #[cfg(feature = "fuse_burn")]
+            rollback::Error::Fuse(fuses::Error::Timeout) => ErrorKind::Timeout,
+            error => ErrorKind::Rollback(error),
+        };
+        BootError::new(kind, Stage::Rollback)
+    }
+}
+
+impl From<fuses::Error> for BootError {
+    fn from(error: fuses::Error) -> Self {
+        let kind = match error {
+            #[cfg(feature = "fuse_burn")]
+            fuses::Error::Timeout => ErrorKind::Timeout,
+            error => ErrorKind::Fuse(error),
+        };
+        BootError::new(kind, Stage::Rollback)
+    }
+}
+
+impl From<boot::Error> for BootError {
+    fn from(error: boot::Error) -> Self {
+        match error {
+            boot::Error::Storage(error) => BootError::new(ErrorKind::from(error), Stage::Boot),
+            boot::Error::NoBootableSlot => BootError::new(ErrorKind::NoBootableSlot, Stage::Boot),
+        }
+    }
+}
+
+/// The code of the fatal error that brought the bootloader down, if any.
+static mut LAST_ERROR: Option<u16> = None;
+
+/// Gets the code of the last error reported through [`fatal`], if any.
+///
+/// [`fatal`]: fn.fatal.html
+pub fn last_error_code() -> Option<u16> {
+    unsafe { LAST_ERROR }
+}
+
+/// Reports a fatal `error` by recording its code and entering the panic handler.
+pub fn fatal(error: BootError) -> ! {
+    unsafe {
+        LAST_ERROR = Some(error.code());
+    }
+
+    panic!("{}", error)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+
+    use super::*;
+
+    const PMIC: u32 = 0x3C;
+
+    #[test]
+    fn maps_kinds_to_their_category() {
+        let kinds = [
+            (
+                ErrorKind::RegisterMismatch {
+                    expected: 0x1,
+                    actual: 0x2,
+                },
+                code::REGISTER_MISMATCH,
+            ),
+            (ErrorKind::Timeout, code::TIMEOUT),
+            (ErrorKind::Storage(storage::Error::DataError), code::STORAGE),
+            (ErrorKind::Crypto(crypto::Error::InvalidKey), code::CRYPTO),
+            (
+                ErrorKind::Verification(verify::Error::HashMismatch),
+                code::VERIFICATION,
+            ),
+            (
+                ErrorKind::Rollback(rollback::Error::Rollback { image: 1, fused: 2 }),
+                code::ROLLBACK,
+            ),
+            (ErrorKind::Fuse(fuses::Error::InvalidWord), code::FUSE),
+            (ErrorKind::NoBootableSlot, code::NO_BOOTABLE_SLOT),
+            (ErrorKind::Config, code::CONFIG),
+        ];
+
+        for (kind, category) in kinds.iter() {
+            assert_eq!(kind.category(), *category, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn packs_stage_category_and_detail() {
+        let error = BootError::new(
+            ErrorKind::Verification(verify::Error::SizeMismatch),
+            Stage::Verify,
+        );
+        assert_eq!(error.code(), 0x3604);
+
+        let error = BootError::new(
+            ErrorKind::Storage(storage::Error::CommandFailed {
+                command: 18,
+                status: 0x10,
+            }),
+            Stage::Boot,
+        );
+        assert_eq!(error.code(), 0x5412);
+    }
+
+    #[test]
+    fn falls_back_to_the_register_as_detail() {
+        let error = BootError::new(ErrorKind::Timeout, Stage::Clock);
+        assert_eq!(error.code(), 0x8300);
+        assert_eq!(error.at(PMIC, 0x41).code(), 0x8341);
+
+        // Details of the kind take precedence over the register.
+        let error = BootError::new(
+            ErrorKind::Crypto(crypto::Error::InvalidSignature),
+            Stage::Verify,
+        );
+        assert_eq!(error.at(PMIC, 0x41).code(), 0x3502);
+    }
+
+    #[test]
+    fn sequence_failures_carry_their_device_and_register() {
+        let steps = [
+            Step::write(PMIC, 0x40, 0x01),
+            Step::delay(100),
+            Step::write(PMIC, 0x41, 0x80).verified(),
+        ];
+        let failure = Failure {
+            step: 2,
+            error: StepError::Mismatch {
+                expected: 0x80,
+                actual: 0x00,
+            },
+            rolled_back: true,
+        };
+
+        let error = BootError::from_sequence(Stage::Pmic, &steps, failure);
+        assert!(matches!(
+            error.kind,
+            ErrorKind::RegisterMismatch {
+                expected: 0x80,
+                actual: 0x00
+            }
+        ));
+        assert_eq!(error.device, Some(PMIC));
+        assert_eq!(error.register, Some(0x41));
+        assert_eq!(error.code(), 0x1241);
+    }
+
+    #[test]
+    fn sequence_failures_without_a_target_carry_no_context() {
+        let steps = [Step::delay(100)];
+        let failure = Failure {
+            step: 0,
+            error: StepError::Timeout,
+            rolled_back: true,
+        };
+
+        let error = BootError::from_sequence(Stage::Display, &steps, failure);
+        assert!(matches!(error.kind, ErrorKind::Timeout));
+        assert_eq!((error.device, error.register), (None, None));
+        assert_eq!(error.code(), 0x6300);
+    }
+
+    #[test]
+    fn displays_code_kind_and_context() {
+        let error = BootError::new(ErrorKind::Timeout, Stage::Pmic);
+        assert_eq!(error.to_string(), "E1300: Timeout in Pmic");
+
+        let error = error.at(PMIC, 0x41);
+        assert_eq!(
+            error.to_string(),
+            "E1341: Timeout in Pmic (device 0x3C, register 0x41)"
+        );
+    }
+}
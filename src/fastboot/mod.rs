@@ -0,0 +1,177 @@
+//! A fastboot device mode for staging and flashing the second stage.
+//!
+//! Fastboot mode is entered by holding Volume Down while the first stage starts. It
+//! supports just enough of the protocol for standard clients to query the slot state,
+//! boot a second stage without installing it and flash it to either slot:
+//!
+//! ```text
+//! fastboot getvar current-slot
+//! fastboot flash bootloader_b slot.img
+//! fastboot boot slot.img
+//! fastboot oem log
+//! ```
+//!
+//! Images use the layout of a slot, i.e. the second-stage blob padded to
+//! `BOOTLOADER_SIZE`, followed by its signed manifest. They are downloaded straight into
+//! the payload area at `BOOTLOADER_START` and always verified before they are booted or
+//! flashed.
+//!
+//! With the `fuse_burn` feature, `oem fuse` burns ODM reserved fuses. It always shows
+//! the bitwise diff first and only burns with the token reported by a dry run:
+//!
+//! ```text
+//! fastboot oem fuse 5 00000001
+//! fastboot oem fuse 5 00000001 1C0FFEE5
+//! ```
+
+pub mod protocol;
+
+#[cfg(feature = "fuse_burn")]
+use core::fmt::{self, Write};
+use core::slice;
+
+use libtegra::gpio;
+use libtegra::pinmux::{PinGrP, PinTristate};
+use manifest::{Manifest, MAX_MANIFEST_SIZE};
+use slot::{Metadata, Slot};
+
+use self::protocol::{Outcome, Response, Target};
+use crate::fuses::Fuses;
+use crate::storage::BlockDevice;
+use crate::usb::control::{self, Descriptors};
+use crate::usb::{self, UsbDevice, MAX_TRANSFER_SIZE};
+use crate::{boot, log, power, thermal};
+use crate::{BOOTLOADER_SIZE, BOOTLOADER_START};
+
+/// The size of the download buffer in bytes.
+const DOWNLOAD_SIZE: usize = BOOTLOADER_SIZE + MAX_MANIFEST_SIZE;
+
+static MANUFACTURER: [u8; 14] = control::string_descriptor(b"Mirage");
+static PRODUCT: [u8; 32] = control::string_descriptor(b"Mirage Fastboot");
+static STRINGS: [&[u8]; 3] = [&control::LANGUAGES, &MANUFACTURER, &PRODUCT];
+
+/// The descriptors of the fastboot device, with the interface class, subclass and
+/// protocol that fastboot clients look for.
+pub static DESCRIPTORS: Descriptors = Descriptors::bulk(0xFF, 0x42, 0x03, &STRINGS);
+
+/// Checks whether the user asks for fastboot mode by holding Volume Down.
+pub fn requested() -> bool {
+    unsafe {
+        PinGrP::ButtonVolDownPx7.set_tristate(PinTristate::Passthrough);
+    }
+    tegra_gpio!(X, 7).config(gpio::Config::Input);
+
+    // The button pulls the line low while it is pressed.
+    tegra_gpio!(X, 7).read() == gpio::Level::Low
+}
+
+/// The payload area and the boot slots as a fastboot target, connected over USB.
+///
+/// Images are downloaded straight into the payload area at `BOOTLOADER_START`.
+struct UsbTarget<'a, D: BlockDevice> {
+    usb: &'a mut UsbDevice,
+    device: &'a mut D,
+    manifest: Option<Manifest>,
+    #[cfg(feature = "fuse_burn")]
+    fuses: Fuses,
+}
+
+impl<'a, D: BlockDevice> Target for UsbTarget<'a, D> {
+    type Error = usb::Error;
+    #[cfg(feature = "fuse_burn")]
+    type Fuses = Fuses;
+
+    fn receive(&mut self, packet: &mut [u8]) -> Result<usize, usb::Error> {
+        self.usb.read(packet)
+    }
+
+    fn send(&mut self, response: &Response) -> Result<(), usb::Error> {
+        self.usb.write(response.as_bytes())
+    }
+
+    fn capacity(&self) -> usize {
+        DOWNLOAD_SIZE
+    }
+
+    fn download(&mut self, size: usize) -> Result<(), usb::Error> {
+        self.manifest = None;
+        let buffer = unsafe { slice::from_raw_parts_mut(BOOTLOADER_START as *mut u8, size) };
+
+        let mut offset = 0;
+        while offset < size {
+            let end = size.min(offset + MAX_TRANSFER_SIZE);
+            offset += self.usb.read(&mut buffer[offset..end])?;
+        }
+
+        Ok(())
+    }
+
+    fn verify(&mut self) -> Result<(), u16> {
+        let manifest = boot::verify_loaded(&Fuses).map_err(|e| e.code())?;
+
+        self.manifest = Some(manifest);
+        Ok(())
+    }
+
+    fn install(&mut self, slot: Slot) -> Result<(), &'static str> {
+        boot::install_slot(self.device, slot).map_err(|_| "failed to write the slot")
+    }
+
+    fn metadata(&mut self) -> Option<Metadata> {
+        boot::read_metadata(self.device).ok()
+    }
+
+    fn device_id(&self) -> u64 {
+        Fuses.identity().device_id
+    }
+
+    fn overheated(&mut self) -> bool {
+        thermal::check() != thermal::Status::Normal
+    }
+
+    fn read_log(&self, offset: usize, out: &mut [u8]) -> usize {
+        log::with_ring(|ring| ring.read(offset, out))
+    }
+
+    #[cfg(feature = "fuse_burn")]
+    fn log(&mut self, args: fmt::Arguments) {
+        let _ = log::with_ring(|ring| ring.write_fmt(args));
+    }
+
+    #[cfg(feature = "fuse_burn")]
+    fn fuses(&mut self) -> &mut Fuses {
+        &mut self.fuses
+    }
+}
+
+/// Runs fastboot mode until the host boots a verified image, whose manifest is
+/// returned.
+///
+/// `device` has to be the storage holding the boot slots.
+pub fn run<D: BlockDevice>(usb: &mut UsbDevice, device: &mut D) -> Manifest {
+    let mut target = UsbTarget {
+        usb,
+        device,
+        manifest: None,
+        #[cfg(feature = "fuse_burn")]
+        fuses: Fuses,
+    };
+
+    loop {
+        // Wait for the host to enumerate the device.
+        while !target.usb.is_configured() {
+            let _ = target.usb.poll();
+        }
+
+        match protocol::serve(&mut target) {
+            Ok(Outcome::Boot) => {
+                // The session only boots after a successful verification.
+                if let Some(manifest) = target.manifest {
+                    return manifest;
+                }
+            }
+            Ok(Outcome::Reboot) => power::reboot(),
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,968 @@
+//! Parsing of fastboot commands and encoding of responses.
+//!
+//! Fastboot is a text protocol on a pair of bulk endpoints. The host sends a command of
+//! at most [`MAX_COMMAND_SIZE`] ASCII bytes in a single transfer, which the device
+//! answers with responses of at most [`MAX_RESPONSE_SIZE`] bytes that start with a
+//! four-letter status:
+//!
+//! | Status | Meaning                                                     |
+//! |--------|-------------------------------------------------------------|
+//! | `INFO` | Informational text; more responses follow                   |
+//! | `DATA` | Ready to receive the given number of bytes, as 8 hex digits |
+//! | `OKAY` | The command succeeded, with an optional value               |
+//! | `FAIL` | The command failed, with a reason                           |
+//!
+//! Only the subset of commands needed to stage and flash a second stage is supported.
+//!
+//! This module does not access any hardware. The device side, including the transport
+//! to the host, is abstracted behind the [`Target`] trait, so that [`serve`] can be
+//! driven by anything that exchanges packets with it, including a replayed transcript.
+//!
+//! [`MAX_COMMAND_SIZE`]: constant.MAX_COMMAND_SIZE.html
+//! [`MAX_RESPONSE_SIZE`]: constant.MAX_RESPONSE_SIZE.html
+//! [`Target`]: trait.Target.html
+//! [`serve`]: fn.serve.html
+
+use core::fmt::{self, Write};
+use core::str;
+
+use slot::{Metadata, Slot};
+
+#[cfg(feature = "fuse_burn")]
+use crate::fuses::{self, burn, FuseProgrammer};
+
+/// The maximum size of a command in bytes.
+pub const MAX_COMMAND_SIZE: usize = 64;
+
+/// The maximum size of a response in bytes, including the status.
+pub const MAX_RESPONSE_SIZE: usize = 64;
+
+/// The size of the status at the start of every response.
+const STATUS_SIZE: usize = 4;
+
+/// The maximum size of the text of a response in bytes.
+pub const MAX_MESSAGE_SIZE: usize = MAX_RESPONSE_SIZE - STATUS_SIZE;
+
+/// The version of the fastboot protocol.
+const PROTOCOL_VERSION: &str = "0.4";
+
+/// The number of hex digits in the size of a `download` command and in the values of
+/// an `oem fuse` command.
+const HEX_DIGITS: usize = 8;
+
+/// The name of the `oem fuse` command, which is followed by its arguments.
+#[cfg(feature = "fuse_burn")]
+const OEM_FUSE: &str = "oem fuse ";
+
+/// The supported fastboot commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command<'a> {
+    /// `getvar:NAME` queries a variable.
+    GetVar(&'a str),
+    /// `download:SIZE` receives SIZE bytes into the download buffer.
+    Download(u32),
+    /// `boot` boots the downloaded image.
+    Boot,
+    /// `flash:PARTITION` writes the downloaded image to a partition.
+    Flash(&'a str),
+    /// `reboot` resets the device.
+    Reboot,
+    /// `oem log` sends the boot log as informational responses.
+    OemLog,
+    /// `oem fuse WORD VALUE [TOKEN]` shows the diff of burning VALUE into the ODM
+    /// reserved fuse word WORD, and burns it if TOKEN confirms the diff.
+    #[cfg(feature = "fuse_burn")]
+    OemFuse {
+        word: usize,
+        value: u32,
+        token: Option<u32>,
+    },
+}
+
+/// Errors that may occur while parsing a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The command exceeds [`MAX_COMMAND_SIZE`].
+    ///
+    /// [`MAX_COMMAND_SIZE`]: constant.MAX_COMMAND_SIZE.html
+    TooLong,
+    /// The command is not valid ASCII.
+    InvalidEncoding,
+    /// The command is not supported.
+    UnknownCommand,
+    /// The argument of the command is malformed.
+    InvalidArgument,
+}
+
+impl Error {
+    /// Gets the reason to report to the host.
+    pub fn message(self) -> &'static str {
+        match self {
+            Error::TooLong => "command too long",
+            Error::InvalidEncoding => "command is not ASCII",
+            Error::UnknownCommand => "unknown command",
+            Error::InvalidArgument => "invalid argument",
+        }
+    }
+}
+
+impl<'a> Command<'a> {
+    /// Parses a command as received from the host.
+    pub fn parse(packet: &'a [u8]) -> Result<Self, Error> {
+        if packet.len() > MAX_COMMAND_SIZE {
+            return Err(Error::TooLong);
+        }
+        if !packet.is_ascii() {
+            return Err(Error::InvalidEncoding);
+        }
+        let command = str::from_utf8(packet).map_err(|_| Error::InvalidEncoding)?;
+
+        // Only the command name is split off, as variable names may contain colons.
+        let (name, argument) = match command.find(':') {
+            Some(index) => (&command[..index], Some(&command[index + 1..])),
+            None => (command, None),
+        };
+
+        match (name, argument) {
+            ("getvar", Some(variable)) => Ok(Command::GetVar(variable)),
+            ("download", Some(size)) => parse_hex(size).map(Command::Download),
+            ("boot", None) => Ok(Command::Boot),
+            ("flash", Some(partition)) => Ok(Command::Flash(partition)),
+            ("reboot", None) => Ok(Command::Reboot),
+            ("oem log", None) => Ok(Command::OemLog),
+            #[cfg(feature = "fuse_burn")]
+            (name, None) if name.starts_with(OEM_FUSE) => parse_fuse(&name[OEM_FUSE.len()..]),
+            ("getvar", None) | ("download", None) | ("flash", None) => Err(Error::InvalidArgument),
+            _ => Err(Error::UnknownCommand),
+        }
+    }
+}
+
+/// Parses a value that is exactly 8 hex digits.
+fn parse_hex(value: &str) -> Result<u32, Error> {
+    if value.len() != HEX_DIGITS || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::InvalidArgument);
+    }
+
+    u32::from_str_radix(value, 16).map_err(|_| Error::InvalidArgument)
+}
+
+/// Parses the arguments of an `oem fuse` command: a decimal word index, the value in
+/// hex and optionally the confirmation token in hex.
+#[cfg(feature = "fuse_burn")]
+fn parse_fuse(arguments: &str) -> Result<Command<'static>, Error> {
+    let mut arguments = arguments.split(' ');
+    let word = arguments
+        .next()
+        .and_then(|word| word.parse().ok())
+        .ok_or(Error::InvalidArgument)?;
+    let value = parse_hex(arguments.next().ok_or(Error::InvalidArgument)?)?;
+    let token = arguments.next().map(parse_hex).transpose()?;
+
+    if arguments.next().is_some() {
+        return Err(Error::InvalidArgument);
+    }
+
+    Ok(Command::OemFuse { word, value, token })
+}
+
+/// The status of a response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Info,
+    Data,
+    Okay,
+    Fail,
+}
+
+impl Status {
+    fn as_bytes(self) -> &'static [u8; STATUS_SIZE] {
+        match self {
+            Status::Info => b"INFO",
+            Status::Data => b"DATA",
+            Status::Okay => b"OKAY",
+            Status::Fail => b"FAIL",
+        }
+    }
+}
+
+/// A response to the host.
+///
+/// Text written to the response through [`fmt::Write`] is silently truncated to
+/// [`MAX_RESPONSE_SIZE`].
+///
+/// [`fmt::Write`]: https://doc.rust-lang.org/core/fmt/trait.Write.html
+/// [`MAX_RESPONSE_SIZE`]: constant.MAX_RESPONSE_SIZE.html
+pub struct Response {
+    buffer: [u8; MAX_RESPONSE_SIZE],
+    length: usize,
+}
+
+impl Response {
+    /// Creates a response with the given `status` and no text.
+    pub fn new(status: Status) -> Self {
+        let mut buffer = [0; MAX_RESPONSE_SIZE];
+        buffer[..STATUS_SIZE].copy_from_slice(status.as_bytes());
+
+        Response {
+            buffer,
+            length: STATUS_SIZE,
+        }
+    }
+
+    /// Creates a response with the given `status` and `message`.
+    pub fn message(status: Status, message: &str) -> Self {
+        let mut response = Response::new(status);
+        response.push(message.as_bytes());
+        response
+    }
+
+    /// Creates a response announcing that the device is ready to receive `size` bytes.
+    pub fn data(size: u32) -> Self {
+        let mut response = Response::new(Status::Data);
+        let _ = write!(response, "{:08x}", size);
+        response
+    }
+
+    /// Appends `bytes` to the text of the response, as far as they fit.
+    pub fn push(&mut self, bytes: &[u8]) {
+        let length = bytes.len().min(MAX_RESPONSE_SIZE - self.length);
+        self.buffer[self.length..self.length + length].copy_from_slice(&bytes[..length]);
+        self.length += length;
+    }
+
+    /// Gets the encoded response.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.length]
+    }
+}
+
+impl fmt::Write for Response {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The device side of the protocol, including the transport to the host.
+pub trait Target {
+    /// The error type reported by the transport.
+    type Error;
+    /// The fuses that `oem fuse` burns.
+    #[cfg(feature = "fuse_burn")]
+    type Fuses: FuseProgrammer;
+
+    /// Receives a command from the host into `packet` and returns its size.
+    fn receive(&mut self, packet: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Sends `response` to the host.
+    fn send(&mut self, response: &Response) -> Result<(), Self::Error>;
+
+    /// Gets the size of the download buffer in bytes.
+    fn capacity(&self) -> usize;
+
+    /// Receives `size` bytes from the host into the download buffer. `size` never
+    /// exceeds the capacity.
+    fn download(&mut self, size: usize) -> Result<(), Self::Error>;
+
+    /// Verifies the downloaded image, returning an error code on failure.
+    fn verify(&mut self) -> Result<(), u16>;
+
+    /// Writes the verified image to `slot` and makes it the preferred slot, returning
+    /// the reason on failure.
+    fn install(&mut self, slot: Slot) -> Result<(), &'static str>;
+
+    /// Reads the slot metadata, or returns `None` if the storage is not accessible.
+    fn metadata(&mut self) -> Option<Metadata>;
+
+    /// Gets the unique ID of the unit.
+    fn device_id(&self) -> u64;
+
+    /// Checks whether the unit is too hot for long writes, e.g. to the eMMC or fuses.
+    fn overheated(&mut self) -> bool;
+
+    /// Copies the log, starting at `offset`, to `out` and returns the number of bytes
+    /// copied.
+    fn read_log(&self, offset: usize, out: &mut [u8]) -> usize;
+
+    /// Appends `args` to the log.
+    #[cfg(feature = "fuse_burn")]
+    fn log(&mut self, args: fmt::Arguments);
+
+    /// Gets the fuses that `oem fuse` burns.
+    #[cfg(feature = "fuse_burn")]
+    fn fuses(&mut self) -> &mut Self::Fuses;
+}
+
+/// How a fastboot session ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The host boots the downloaded image, which passed verification.
+    Boot,
+    /// The host asks for a reboot.
+    Reboot,
+}
+
+/// Reports that the downloaded image failed verification with the error `code`.
+fn verification_failed(code: u16) -> Response {
+    let mut response = Response::new(Status::Fail);
+    let _ = write!(response, "verification failed: E{:04X}", code);
+    response
+}
+
+/// Gets the value of the variable `name` as a response.
+fn variable<T: Target>(target: &mut T, name: &str) -> Response {
+    let mut response = Response::new(Status::Okay);
+    let _ = match name {
+        "version" => write!(response, "{}", PROTOCOL_VERSION),
+        "version-bootloader" => write!(response, "{}", env!("CARGO_PKG_VERSION")),
+        "product" => write!(response, "mirage"),
+        "serialno" => write!(response, "{:016X}", target.device_id()),
+        "max-download-size" => write!(response, "{:#010x}", target.capacity()),
+        "slot-count" => write!(response, "2"),
+        "secure" => write!(response, "yes"),
+        "is-userspace" => write!(response, "no"),
+        "current-slot" => match target.metadata().map(|metadata| metadata.select()) {
+            Some(Some(Slot::A)) => write!(response, "a"),
+            Some(Some(Slot::B)) => write!(response, "b"),
+            Some(None) => return Response::message(Status::Fail, "no bootable slot"),
+            None => return Response::message(Status::Fail, "failed to read slot metadata"),
+        },
+        // Partitions are flashed to an explicit slot.
+        _ if name.starts_with("has-slot:") => write!(response, "no"),
+        _ => return Response::message(Status::Fail, "unknown variable"),
+    };
+
+    response
+}
+
+/// Sends the boot log, one line per informational response.
+fn send_log<T: Target>(target: &mut T) -> Result<(), T::Error> {
+    let mut offset = 0;
+
+    loop {
+        let mut chunk = [0; MAX_MESSAGE_SIZE];
+        let length = target.read_log(offset, &mut chunk);
+        if length == 0 {
+            return Ok(());
+        }
+
+        // Overly long lines are split across responses.
+        let line = chunk[..length]
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap_or(length);
+        offset += line + (line < length) as usize;
+
+        let mut response = Response::new(Status::Info);
+        response.push(&chunk[..line]);
+        target.send(&response)?;
+    }
+}
+
+/// Verifies the downloaded image and writes it to the slot named by `partition`.
+fn flash<T: Target>(target: &mut T, partition: &str) -> Response {
+    let slot = match partition {
+        "bootloader_a" => Slot::A,
+        "bootloader_b" => Slot::B,
+        _ => return Response::message(Status::Fail, "unknown partition"),
+    };
+
+    if let Err(code) = target.verify() {
+        return verification_failed(code);
+    }
+
+    // Long writes heat up the eMMC and the SoC even further.
+    if target.overheated() {
+        return Response::message(Status::Fail, "too hot to flash");
+    }
+
+    match target.install(slot) {
+        Ok(()) => Response::new(Status::Okay),
+        Err(reason) => Response::message(Status::Fail, reason),
+    }
+}
+
+/// Shows the bitwise diff of burning `value` into the ODM reserved fuse word `word`
+/// and burns it if `token` confirms the diff. Without a token, this is a dry run that
+/// reports the token to confirm with.
+#[cfg(feature = "fuse_burn")]
+fn burn_fuse<T: Target>(
+    target: &mut T,
+    word: usize,
+    value: u32,
+    token: Option<u32>,
+) -> Result<Response, T::Error> {
+    let device_id = target.device_id();
+    let plan = match burn::plan(target.fuses(), word, value) {
+        Ok(plan) => plan,
+        Err(error) => return Ok(burn_failed(error)),
+    };
+
+    // Show the diff before anything is burnt.
+    let mut response = Response::new(Status::Info);
+    let _ = write!(response, "{}", plan);
+    target.send(&response)?;
+    for row in plan.rows().iter() {
+        let mut response = Response::new(Status::Info);
+        let _ = write!(response, "{}", row);
+        target.send(&response)?;
+    }
+
+    if plan.mask() == 0 {
+        return Ok(Response::message(Status::Okay, "nothing to burn"));
+    }
+
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let mut response = Response::new(Status::Okay);
+            let _ = write!(
+                response,
+                "dry run, confirm with {:08X}",
+                plan.token(device_id)
+            );
+            return Ok(response);
+        }
+    };
+
+    // Programming is unreliable at high temperatures.
+    if target.overheated() {
+        return Ok(Response::message(Status::Fail, "too hot to burn fuses"));
+    }
+
+    Ok(
+        match burn::burn(target.fuses(), word, value, device_id, token) {
+            Ok(()) => {
+                target.log(format_args!("Burnt {}.\n", plan));
+                Response::message(Status::Okay, "burnt and verified")
+            }
+            Err(error) => burn_failed(error),
+        },
+    )
+}
+
+/// Reports that burning fuses failed with `error`.
+#[cfg(feature = "fuse_burn")]
+fn burn_failed(error: burn::Error) -> Response {
+    let mut response = Response::new(Status::Fail);
+    let _ = match error {
+        burn::Error::ClearsBits { burnt } => {
+            write!(response, "would clear burnt bits {:08X}", burnt)
+        }
+        burn::Error::Unconfirmed => write!(response, "token does not match the diff"),
+        burn::Error::Fuse(fuses::Error::InvalidWord) => write!(response, "unknown fuse word"),
+        burn::Error::Fuse(fuses::Error::ProgrammingDisabled) => {
+            write!(response, "fuse programming disabled")
+        }
+        burn::Error::Fuse(fuses::Error::VerifyFailed) => write!(response, "fuses failed to verify"),
+        burn::Error::Fuse(fuses::Error::Timeout) => write!(response, "fuse controller timed out"),
+    };
+    response
+}
+
+/// Serves fastboot commands until the host boots an image or reboots, or a transfer
+/// fails.
+pub fn serve<T: Target>(target: &mut T) -> Result<Outcome, T::Error> {
+    let mut downloaded = false;
+
+    loop {
+        let mut packet = [0; MAX_COMMAND_SIZE];
+        let length = target.receive(&mut packet)?;
+
+        let command = match Command::parse(&packet[..length]) {
+            Ok(command) => command,
+            Err(error) => {
+                target.send(&Response::message(Status::Fail, error.message()))?;
+                continue;
+            }
+        };
+
+        let response = match command {
+            Command::GetVar(name) => variable(target, name),
+            Command::Download(size) if size as usize > target.capacity() => {
+                Response::message(Status::Fail, "data too large")
+            }
+            Command::Download(size) => {
+                target.send(&Response::data(size))?;
+                target.download(size as usize)?;
+
+                downloaded = true;
+                Response::new(Status::Okay)
+            }
+            Command::Boot | Command::Flash(_) if !downloaded => {
+                Response::message(Status::Fail, "no image downloaded")
+            }
+            Command::Boot => match target.verify() {
+                Ok(()) => {
+                    target.send(&Response::new(Status::Okay))?;
+                    return Ok(Outcome::Boot);
+                }
+                Err(code) => verification_failed(code),
+            },
+            Command::Flash(partition) => flash(target, partition),
+            Command::Reboot => {
+                target.send(&Response::new(Status::Okay))?;
+                return Ok(Outcome::Reboot);
+            }
+            Command::OemLog => {
+                send_log(target)?;
+                Response::new(Status::Okay)
+            }
+            #[cfg(feature = "fuse_burn")]
+            Command::OemFuse { word, value, token } => burn_fuse(target, word, value, token)?,
+        };
+
+        target.send(&response)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "fuse_burn")]
+    use crate::fuses::simulated::SimulatedFuses;
+
+    extern crate std;
+    #[cfg(feature = "fuse_burn")]
+    use std::string::String;
+
+    use self::Packet::{Data, Device, Host};
+
+    /// A packet of a fastboot transcript.
+    #[derive(Clone, Copy, Debug)]
+    enum Packet {
+        /// A command sent by the host.
+        Host(&'static str),
+        /// Download data sent by the host.
+        Data(&'static [u8]),
+        /// A response sent by the device.
+        Device(&'static str),
+    }
+
+    /// The host disconnected at the end of the transcript.
+    #[derive(Debug, PartialEq, Eq)]
+    struct Disconnected;
+
+    /// The error code reported for images that fail verification.
+    const BAD_IMAGE: u16 = 0x3605;
+
+    const LOG: &[u8] = b"Initializing the eMMC...\n\
+        A line that is too long to fit into a single response, so it is split.\n\
+        \n\
+        Loading the second stage...";
+
+    /// A target that plays the host side of a transcript and checks the responses of
+    /// the device against it.
+    struct Replay {
+        transcript: &'static [Packet],
+        position: usize,
+        buffer: [u8; 64],
+        metadata: Option<Metadata>,
+        installed: Option<Slot>,
+        hot: bool,
+        #[cfg(feature = "fuse_burn")]
+        log: String,
+        #[cfg(feature = "fuse_burn")]
+        fuses: SimulatedFuses,
+    }
+
+    impl Replay {
+        fn new(transcript: &'static [Packet]) -> Self {
+            Replay {
+                transcript,
+                position: 0,
+                buffer: [0; 64],
+                metadata: Some(Metadata::default()),
+                installed: None,
+                hot: false,
+                #[cfg(feature = "fuse_burn")]
+                log: String::new(),
+                #[cfg(feature = "fuse_burn")]
+                fuses: SimulatedFuses::default(),
+            }
+        }
+
+        /// Serves the transcript, checking that all of it was replayed.
+        fn run(&mut self) -> Result<Outcome, Disconnected> {
+            let outcome = serve(self);
+            assert_eq!(
+                self.position,
+                self.transcript.len(),
+                "transcript not finished"
+            );
+            outcome
+        }
+
+        fn next(&mut self) -> Option<Packet> {
+            let packet = self.transcript.get(self.position).copied()?;
+            self.position += 1;
+            Some(packet)
+        }
+    }
+
+    impl Target for Replay {
+        type Error = Disconnected;
+        #[cfg(feature = "fuse_burn")]
+        type Fuses = SimulatedFuses;
+
+        fn receive(&mut self, packet: &mut [u8]) -> Result<usize, Disconnected> {
+            match self.next() {
+                Some(Host(command)) => {
+                    packet[..command.len()].copy_from_slice(command.as_bytes());
+                    Ok(command.len())
+                }
+                None => Err(Disconnected),
+                Some(packet) => panic!("device waits for a command instead of {:?}", packet),
+            }
+        }
+
+        fn send(&mut self, response: &Response) -> Result<(), Disconnected> {
+            let sent = str::from_utf8(response.as_bytes()).unwrap();
+            match self.next() {
+                Some(Device(expected)) => assert_eq!(sent, expected),
+                packet => panic!("device sent {:?} instead of {:?}", sent, packet),
+            }
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.buffer.len()
+        }
+
+        fn download(&mut self, size: usize) -> Result<(), Disconnected> {
+            match self.next() {
+                Some(Data(data)) => {
+                    assert_eq!(data.len(), size);
+                    self.buffer[..size].copy_from_slice(data);
+                    Ok(())
+                }
+                packet => panic!("device downloads instead of {:?}", packet),
+            }
+        }
+
+        fn verify(&mut self) -> Result<(), u16> {
+            if self.buffer.starts_with(b"GOOD") {
+                Ok(())
+            } else {
+                Err(BAD_IMAGE)
+            }
+        }
+
+        fn install(&mut self, slot: Slot) -> Result<(), &'static str> {
+            self.installed = Some(slot);
+            Ok(())
+        }
+
+        fn metadata(&mut self) -> Option<Metadata> {
+            self.metadata
+        }
+
+        fn device_id(&self) -> u64 {
+            0x03A8_1B1D_4554_6812
+        }
+
+        fn overheated(&mut self) -> bool {
+            self.hot
+        }
+
+        fn read_log(&self, offset: usize, out: &mut [u8]) -> usize {
+            let log = LOG.get(offset..).unwrap_or(&[]);
+            let length = log.len().min(out.len());
+            out[..length].copy_from_slice(&log[..length]);
+            length
+        }
+
+        #[cfg(feature = "fuse_burn")]
+        fn log(&mut self, args: fmt::Arguments) {
+            self.log.write_fmt(args).unwrap();
+        }
+
+        #[cfg(feature = "fuse_burn")]
+        fn fuses(&mut self) -> &mut SimulatedFuses {
+            &mut self.fuses
+        }
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            Command::parse(b"getvar:has-slot:bootloader"),
+            Ok(Command::GetVar("has-slot:bootloader"))
+        );
+        assert_eq!(
+            Command::parse(b"download:0001aBcD"),
+            Ok(Command::Download(0x1ABCD))
+        );
+        assert_eq!(Command::parse(b"boot"), Ok(Command::Boot));
+        assert_eq!(
+            Command::parse(b"flash:bootloader_a"),
+            Ok(Command::Flash("bootloader_a"))
+        );
+        assert_eq!(Command::parse(b"reboot"), Ok(Command::Reboot));
+        assert_eq!(Command::parse(b"oem log"), Ok(Command::OemLog));
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        assert_eq!(
+            Command::parse(&[b'a'; MAX_COMMAND_SIZE + 1]),
+            Err(Error::TooLong)
+        );
+        assert_eq!(
+            Command::parse(b"getvar:\xC3\xA9"),
+            Err(Error::InvalidEncoding)
+        );
+        assert_eq!(Command::parse(b"erase:misc"), Err(Error::UnknownCommand));
+        assert_eq!(Command::parse(b"boot:now"), Err(Error::UnknownCommand));
+        assert_eq!(Command::parse(b""), Err(Error::UnknownCommand));
+        assert_eq!(Command::parse(b"getvar"), Err(Error::InvalidArgument));
+        assert_eq!(Command::parse(b"flash"), Err(Error::InvalidArgument));
+
+        for size in [
+            "download",
+            "download:",
+            "download:1000",
+            "download:+0001000",
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Command::parse(size.as_bytes()),
+                Err(Error::InvalidArgument),
+                "{}",
+                size
+            );
+        }
+        assert_eq!(
+            Command::parse(b"download:0000100g"),
+            Err(Error::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn truncates_responses() {
+        let mut response = Response::message(Status::Info, "");
+        response.push(&[b'x'; MAX_RESPONSE_SIZE]);
+        assert_eq!(response.as_bytes().len(), MAX_RESPONSE_SIZE);
+        assert_eq!(Response::data(0x1ABCD).as_bytes(), b"DATA0001abcd");
+    }
+
+    #[test]
+    fn answers_variables() {
+        const TRANSCRIPT: &[Packet] = &[
+            Host("getvar:version"),
+            Device("OKAY0.4"),
+            Host("getvar:product"),
+            Device("OKAYmirage"),
+            Host("getvar:serialno"),
+            Device("OKAY03A81B1D45546812"),
+            Host("getvar:max-download-size"),
+            Device("OKAY0x00000040"),
+            Host("getvar:slot-count"),
+            Device("OKAY2"),
+            Host("getvar:current-slot"),
+            Device("OKAYa"),
+            Host("getvar:has-slot:bootloader"),
+            Device("OKAYno"),
+            Host("getvar:partition-type:bootloader_a"),
+            Device("FAILunknown variable"),
+        ];
+
+        assert_eq!(Replay::new(TRANSCRIPT).run(), Err(Disconnected));
+    }
+
+    #[test]
+    fn reports_slot_problems() {
+        const TRANSCRIPT: &[Packet] =
+            &[Host("getvar:current-slot"), Device("FAILno bootable slot")];
+        let mut replay = Replay::new(TRANSCRIPT);
+        let mut metadata = Metadata::default();
+        metadata.mark_unbootable(Slot::A);
+        metadata.mark_unbootable(Slot::B);
+        replay.metadata = Some(metadata);
+        assert_eq!(replay.run(), Err(Disconnected));
+
+        const UNREADABLE: &[Packet] = &[
+            Host("getvar:current-slot"),
+            Device("FAILfailed to read slot metadata"),
+        ];
+        let mut replay = Replay::new(UNREADABLE);
+        replay.metadata = None;
+        assert_eq!(replay.run(), Err(Disconnected));
+    }
+
+    /// `fastboot flash bootloader_b slot.img`
+    const FLASH: &[Packet] = &[
+        Host("getvar:has-slot:bootloader_b"),
+        Device("OKAYno"),
+        Host("getvar:max-download-size"),
+        Device("OKAY0x00000040"),
+        Host("getvar:is-userspace"),
+        Device("OKAYno"),
+        Host("download:00000014"),
+        Device("DATA00000014"),
+        Data(b"GOOD second stage..."),
+        Device("OKAY"),
+        Host("flash:bootloader_b"),
+        Device("OKAY"),
+    ];
+
+    #[test]
+    fn flashes_verified_image() {
+        let mut replay = Replay::new(FLASH);
+        assert_eq!(replay.run(), Err(Disconnected));
+        assert_eq!(replay.installed, Some(Slot::B));
+    }
+
+    #[test]
+    fn refuses_to_flash_when_hot() {
+        const TRANSCRIPT: &[Packet] = &[
+            Host("download:00000004"),
+            Device("DATA00000004"),
+            Data(b"GOOD"),
+            Device("OKAY"),
+            Host("flash:bootloader_a"),
+            Device("FAILtoo hot to flash"),
+        ];
+
+        let mut replay = Replay::new(TRANSCRIPT);
+        replay.hot = true;
+        assert_eq!(replay.run(), Err(Disconnected));
+        assert_eq!(replay.installed, None);
+    }
+
+    #[test]
+    fn boots_verified_image() {
+        // `fastboot boot slot.img`
+        const TRANSCRIPT: &[Packet] = &[
+            Host("download:00000008"),
+            Device("DATA00000008"),
+            Data(b"GOODboot"),
+            Device("OKAY"),
+            Host("boot"),
+            Device("OKAY"),
+        ];
+
+        assert_eq!(Replay::new(TRANSCRIPT).run(), Ok(Outcome::Boot));
+    }
+
+    #[test]
+    fn rejects_unverified_images() {
+        const TRANSCRIPT: &[Packet] = &[
+            Host("boot"),
+            Device("FAILno image downloaded"),
+            Host("flash:bootloader_a"),
+            Device("FAILno image downloaded"),
+            Host("download:00000041"),
+            Device("FAILdata too large"),
+            Host("download:00000004"),
+            Device("DATA00000004"),
+            Data(b"EVIL"),
+            Device("OKAY"),
+            Host("boot"),
+            Device("FAILverification failed: E3605"),
+            Host("flash:bootloader_a"),
+            Device("FAILverification failed: E3605"),
+            Host("flash:bootloader_c"),
+            Device("FAILunknown partition"),
+        ];
+
+        let mut replay = Replay::new(TRANSCRIPT);
+        assert_eq!(replay.run(), Err(Disconnected));
+        assert_eq!(replay.installed, None);
+    }
+
+    #[test]
+    fn reports_parse_errors_and_continues() {
+        const TRANSCRIPT: &[Packet] = &[
+            Host("erase:misc"),
+            Device("FAILunknown command"),
+            Host("download:1000"),
+            Device("FAILinvalid argument"),
+            Host("getvar:\u{E9}"),
+            Device("FAILcommand is not ASCII"),
+            Host("reboot"),
+            Device("OKAY"),
+        ];
+
+        assert_eq!(Replay::new(TRANSCRIPT).run(), Ok(Outcome::Reboot));
+    }
+
+    #[test]
+    fn sends_log_line_by_line() {
+        // `fastboot oem log`
+        const TRANSCRIPT: &[Packet] = &[
+            Host("oem log"),
+            Device("INFOInitializing the eMMC..."),
+            Device("INFOA line that is too long to fit into a single response, so it"),
+            Device("INFO is split."),
+            Device("INFO"),
+            Device("INFOLoading the second stage..."),
+            Device("OKAY"),
+        ];
+
+        assert_eq!(Replay::new(TRANSCRIPT).run(), Err(Disconnected));
+    }
+
+    #[cfg(feature = "fuse_burn")]
+    #[test]
+    fn burns_fuses_after_dry_run() {
+        const TRANSCRIPT: &[Packet] = &[
+            Host("oem fuse 5 00000101"),
+            Device("INFOODM word 5: 00000001 -> 00000101 (1 new bits)"),
+            Device("INFOcurrent 00000000 00000000 00000000 00000001"),
+            Device("INFOtarget  00000000 00000000 00000001 00000001"),
+            Device("INFOburn    ........ ........ .......^ ........"),
+            Device("OKAYdry run, confirm with 2654DB5F"),
+            Host("oem fuse 5 00000101 2654DB5F"),
+            Device("INFOODM word 5: 00000001 -> 00000101 (1 new bits)"),
+            Device("INFOcurrent 00000000 00000000 00000000 00000001"),
+            Device("INFOtarget  00000000 00000000 00000001 00000001"),
+            Device("INFOburn    ........ ........ .......^ ........"),
+            Device("OKAYburnt and verified"),
+            // The token of the dry run is stale once the word changed.
+            Host("oem fuse 5 00000101 2654DB5F"),
+            Device("INFOODM word 5: 00000101 -> 00000101 (0 new bits)"),
+            Device("INFOcurrent 00000000 00000000 00000001 00000001"),
+            Device("INFOtarget  00000000 00000000 00000001 00000001"),
+            Device("INFOburn    ........ ........ ........ ........"),
+            Device("OKAYnothing to burn"),
+        ];
+
+        let mut replay = Replay::new(TRANSCRIPT);
+        replay.fuses.odm[5] = 0x1;
+        assert_eq!(replay.run(), Err(Disconnected));
+        assert_eq!(replay.fuses.odm[5], 0x101);
+        assert_eq!(replay.fuses.burns, 1);
+        assert_eq!(
+            replay.log,
+            "Burnt ODM word 5: 00000001 -> 00000101 (1 new bits).\n"
+        );
+    }
+
+    #[cfg(feature = "fuse_burn")]
+    #[test]
+    fn refuses_unsafe_fuse_burns() {
+        const TRANSCRIPT: &[Packet] = &[
+            Host("oem fuse 5 00000000"),
+            Device("FAILwould clear burnt bits 00000001"),
+            Host("oem fuse 8 00000001"),
+            Device("FAILunknown fuse word"),
+            Host("oem fuse 5 00000003 00000000"),
+            Device("INFOODM word 5: 00000001 -> 00000003 (1 new bits)"),
+            Device("INFOcurrent 00000000 00000000 00000000 00000001"),
+            Device("INFOtarget  00000000 00000000 00000000 00000011"),
+            Device("INFOburn    ........ ........ ........ ......^."),
+            Device("FAILtoken does not match the diff"),
+            Host("oem fuse 5 3"),
+            Device("FAILinvalid argument"),
+        ];
+
+        let mut replay = Replay::new(TRANSCRIPT);
+        replay.fuses.odm[5] = 0x1;
+        assert_eq!(replay.run(), Err(Disconnected));
+        assert_eq!(replay.fuses.burns, 0);
+        assert_eq!(replay.log, "");
+    }
+}
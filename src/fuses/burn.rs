@@ -0,0 +1,356 @@
+//! Guarded burning of the ODM reserved fuses, e.g. for provisioning.
+//!
+//! A burn is described by the value an ODM word should hold afterwards. It is first
+//! planned against the current value of the word, which fails if reaching the value
+//! would require clearing a burnt bit. A [`Plan`] can be shown as a bitwise diff
+//! without touching the fuses, which doubles as a dry run, and yields the token the
+//! burn has to be confirmed with. The token covers the unit, the word and both of its
+//! values, so it is rejected for any other change or once the word has changed.
+//!
+//! This module only accesses the fuses through the [`FuseArray`] and
+//! [`FuseProgrammer`] traits.
+//!
+//! [`Plan`]: struct.Plan.html
+//! [`FuseArray`]: ../trait.FuseArray.html
+//! [`FuseProgrammer`]: ../trait.FuseProgrammer.html
+
+use core::fmt;
+
+use super::{FuseArray, FuseProgrammer};
+
+/// The offset basis of the 32-bit FNV-1a hash.
+const FNV_OFFSET_BASIS: u32 = 0x811C_9DC5;
+/// The prime of the 32-bit FNV-1a hash.
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Errors that may occur while burning fuses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The target value clears bits that are already burnt.
+    ClearsBits {
+        /// The burnt bits that are not set in the target value.
+        burnt: u32,
+    },
+    /// The burn was not confirmed with the token of its plan.
+    Unconfirmed,
+    /// Accessing the fuses failed.
+    Fuse(super::Error),
+}
+
+impl From<super::Error> for Error {
+    fn from(error: super::Error) -> Self {
+        Error::Fuse(error)
+    }
+}
+
+/// A validated change of a single ODM reserved fuse word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Plan {
+    /// The index of the ODM word.
+    pub index: usize,
+    /// The value the word currently holds.
+    pub current: u32,
+    /// The value the word holds after burning.
+    pub target: u32,
+}
+
+impl Plan {
+    /// Gets the bits that are burnt by this plan.
+    pub fn mask(&self) -> u32 {
+        self.target & !self.current
+    }
+
+    /// Computes the token that confirms this plan on the unit with `device_id`.
+    pub fn token(&self, device_id: u64) -> u32 {
+        let words = [
+            device_id as u32,
+            (device_id >> 32) as u32,
+            self.index as u32,
+            self.current,
+            self.target,
+        ];
+
+        let mut hash = FNV_OFFSET_BASIS;
+        for word in words.iter() {
+            for &byte in word.to_le_bytes().iter() {
+                hash = (hash ^ byte as u32).wrapping_mul(FNV_PRIME);
+            }
+        }
+
+        hash
+    }
+
+    /// Gets the rows of the bitwise diff of this plan.
+    pub fn rows(&self) -> [Row; 3] {
+        [
+            Row::Current(self.current),
+            Row::Target(self.target),
+            Row::Burn(self.mask()),
+        ]
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ODM word {}: {:08X} -> {:08X} ({} new bits)",
+            self.index,
+            self.current,
+            self.target,
+            self.mask().count_ones()
+        )
+    }
+}
+
+/// A row of the bitwise diff of a [`Plan`], most significant bit first.
+///
+/// [`Plan`]: struct.Plan.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Row {
+    /// The current value of the word.
+    Current(u32),
+    /// The value of the word after burning.
+    Target(u32),
+    /// The bits that are burnt, marked with `^`.
+    Burn(u32),
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (label, bits, set, clear) = match *self {
+            Row::Current(bits) => ("current", bits, '1', '0'),
+            Row::Target(bits) => ("target", bits, '1', '0'),
+            Row::Burn(bits) => ("burn", bits, '^', '.'),
+        };
+
+        write!(f, "{:<8}", label)?;
+        for bit in (0..32).rev() {
+            let c = if bits & 1 << bit != 0 { set } else { clear };
+            write!(f, "{}", c)?;
+            if bit % 8 == 0 && bit != 0 {
+                f.write_str(" ")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Plans burning the ODM word at `index` so that it holds `target`.
+///
+/// Fails if `target` lacks any bit that is already burnt.
+pub fn plan<F: FuseArray>(fuses: &F, index: usize, target: u32) -> Result<Plan, Error> {
+    let current = fuses.read_odm(index)?;
+    let burnt = current & !target;
+    if burnt != 0 {
+        return Err(Error::ClearsBits { burnt });
+    }
+
+    Ok(Plan {
+        index,
+        current,
+        target,
+    })
+}
+
+/// Burns the ODM word at `index` so that it holds `target`, if `token` confirms the
+/// plan for the current value of the word on the unit with `device_id`.
+///
+/// The word is read back afterwards to make sure the change took effect. Targets that
+/// are already burnt leave the fuses untouched.
+pub fn burn<F: FuseProgrammer>(
+    fuses: &mut F,
+    index: usize,
+    target: u32,
+    device_id: u64,
+    token: u32,
+) -> Result<(), Error> {
+    // Plan again, so that a token from a dry run before the word changed is rejected.
+    let plan = plan(fuses, index, target)?;
+    if plan.mask() == 0 {
+        return Ok(());
+    }
+    if plan.token(device_id) != token {
+        return Err(Error::Unconfirmed);
+    }
+
+    fuses.burn_odm(index, plan.mask())?;
+
+    if fuses.read_odm(index)? != target {
+        return Err(Error::Fuse(super::Error::VerifyFailed));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuses::simulated::SimulatedFuses;
+    use crate::fuses::{Error as FuseError, ODM_WORDS};
+
+    extern crate std;
+    use std::string::ToString;
+
+    const DEVICE_ID: u64 = 0x03A8_1B1D_4554_6812;
+
+    /// Fuses whose ODM word 5 already has bit 0 burnt.
+    fn fuses() -> SimulatedFuses {
+        let mut fuses = SimulatedFuses::default();
+        fuses.odm[5] = 0x0000_0001;
+        fuses
+    }
+
+    /// Gets the token a dry run reports for burning `target` into word `index`.
+    fn token(fuses: &SimulatedFuses, index: usize, target: u32) -> u32 {
+        plan(fuses, index, target).unwrap().token(DEVICE_ID)
+    }
+
+    #[test]
+    fn plans_bitwise_diff() {
+        let plan = plan(&fuses(), 5, 0x8000_0101).unwrap();
+        assert_eq!(
+            plan,
+            Plan {
+                index: 5,
+                current: 0x0000_0001,
+                target: 0x8000_0101,
+            }
+        );
+        assert_eq!(plan.mask(), 0x8000_0100);
+        assert_eq!(
+            plan.to_string(),
+            "ODM word 5: 00000001 -> 80000101 (2 new bits)"
+        );
+
+        let rows = plan.rows();
+        assert_eq!(
+            rows[0].to_string(),
+            "current 00000000 00000000 00000000 00000001"
+        );
+        assert_eq!(
+            rows[1].to_string(),
+            "target  10000000 00000000 00000001 00000001"
+        );
+        assert_eq!(
+            rows[2].to_string(),
+            "burn    ^....... ........ .......^ ........"
+        );
+    }
+
+    #[test]
+    fn rejects_clearing_bits() {
+        let mut fuses = fuses();
+        fuses.odm[3] = 0xF0F0_0000;
+
+        assert_eq!(
+            plan(&fuses, 5, 0x0000_0100),
+            Err(Error::ClearsBits { burnt: 0x0000_0001 })
+        );
+        assert_eq!(
+            plan(&fuses, 3, 0x0FF0_0000),
+            Err(Error::ClearsBits { burnt: 0xF000_0000 })
+        );
+
+        // Not even with a token, e.g. from a dry run of another change.
+        let token = token(&fuses, 5, 0x0000_0101);
+        assert_eq!(
+            burn(&mut fuses, 5, 0x0000_0100, DEVICE_ID, token),
+            Err(Error::ClearsBits { burnt: 0x0000_0001 })
+        );
+        assert_eq!(fuses.burns, 0);
+    }
+
+    #[test]
+    fn burns_confirmed_plan() {
+        let mut fuses = fuses();
+        let token = token(&fuses, 5, 0x8000_0101);
+
+        assert_eq!(burn(&mut fuses, 5, 0x8000_0101, DEVICE_ID, token), Ok(()));
+        assert_eq!(fuses.odm[5], 0x8000_0101);
+        assert_eq!(fuses.burns, 1);
+        assert_eq!(fuses.odm[4], 0);
+    }
+
+    #[test]
+    fn rejects_unconfirmed_burns() {
+        let mut fuses = fuses();
+        let token = token(&fuses, 5, 0x0000_0101);
+
+        // The token only confirms the planned change on the planned unit.
+        assert_eq!(
+            burn(&mut fuses, 5, 0x0000_0103, DEVICE_ID, token),
+            Err(Error::Unconfirmed)
+        );
+        assert_eq!(
+            burn(&mut fuses, 5, 0x0000_0101, DEVICE_ID + 1, token),
+            Err(Error::Unconfirmed)
+        );
+        assert_eq!(
+            burn(&mut fuses, 6, 0x0000_0101, DEVICE_ID, token),
+            Err(Error::Unconfirmed)
+        );
+        assert_eq!(
+            burn(&mut fuses, 5, 0x0000_0101, DEVICE_ID, !token),
+            Err(Error::Unconfirmed)
+        );
+        assert_eq!(fuses.burns, 0);
+    }
+
+    #[test]
+    fn rejects_stale_tokens() {
+        let mut fuses = fuses();
+        let token = token(&fuses, 5, 0x0000_0111);
+
+        // The word changed after the dry run, so the diff that was shown is outdated.
+        fuses.odm[5] |= 0x0000_0010;
+        assert_eq!(
+            burn(&mut fuses, 5, 0x0000_0111, DEVICE_ID, token),
+            Err(Error::Unconfirmed)
+        );
+        assert_eq!(fuses.burns, 0);
+        assert_eq!(fuses.odm[5], 0x0000_0011);
+    }
+
+    #[test]
+    fn skips_burnt_targets() {
+        let mut fuses = fuses();
+
+        // Nothing to burn needs no confirmation.
+        assert_eq!(plan(&fuses, 5, 0x0000_0001).unwrap().mask(), 0);
+        assert_eq!(burn(&mut fuses, 5, 0x0000_0001, DEVICE_ID, 0), Ok(()));
+        assert_eq!(fuses.burns, 0);
+    }
+
+    #[test]
+    fn detects_readback_mismatch() {
+        let mut fuses = fuses();
+        fuses.stuck = 0x8000_0000;
+        let token = token(&fuses, 5, 0x8000_0101);
+
+        assert_eq!(
+            burn(&mut fuses, 5, 0x8000_0101, DEVICE_ID, token),
+            Err(Error::Fuse(FuseError::VerifyFailed))
+        );
+        assert_eq!(fuses.burns, 1);
+        assert_eq!(fuses.odm[5], 0x0000_0101);
+    }
+
+    #[test]
+    fn reports_fuse_errors() {
+        let mut fuses = fuses();
+        assert_eq!(
+            plan(&fuses, ODM_WORDS, 1),
+            Err(Error::Fuse(FuseError::InvalidWord))
+        );
+
+        fuses.disabled = true;
+        let token = token(&fuses, 5, 0x0000_0101);
+        assert_eq!(
+            burn(&mut fuses, 5, 0x0000_0101, DEVICE_ID, token),
+            Err(Error::Fuse(FuseError::ProgrammingDisabled))
+        );
+        assert_eq!(fuses.odm[5], 0x0000_0001);
+    }
+}
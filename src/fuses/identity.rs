@@ -0,0 +1,281 @@
+//! Decoding of the fuses that identify a unit.
+//!
+//! All values are extracted from fuse cache words handed in by the caller, so that
+//! known fuse dumps can be decoded on the build host. This module does not access any
+//! hardware.
+
+use core::fmt;
+
+use super::{FUSE_RESERVED_ODM0, ODM_WORDS};
+
+// Offsets of the fuse cache words from the base of the fuse controller.
+const FUSE_SKU_INFO: usize = 0x110;
+const FUSE_CPU_SPEEDO_0: usize = 0x114;
+const FUSE_CPU_IDDQ: usize = 0x118;
+const FUSE_CPU_SPEEDO_1: usize = 0x12C;
+const FUSE_CPU_SPEEDO_2: usize = 0x130;
+const FUSE_SOC_SPEEDO_0: usize = 0x134;
+const FUSE_SOC_SPEEDO_1: usize = 0x138;
+const FUSE_SOC_SPEEDO_2: usize = 0x13C;
+const FUSE_SOC_IDDQ: usize = 0x140;
+const FUSE_PUBLIC_KEY0: usize = 0x164;
+const FUSE_SECURITY_MODE: usize = 0x1A0;
+const FUSE_OPT_VENDOR_CODE: usize = 0x200;
+const FUSE_OPT_FAB_CODE: usize = 0x204;
+const FUSE_OPT_LOT_CODE_0: usize = 0x208;
+const FUSE_OPT_WAFER_ID: usize = 0x210;
+const FUSE_OPT_X_COORDINATE: usize = 0x214;
+const FUSE_OPT_Y_COORDINATE: usize = 0x218;
+const FUSE_GPU_IDDQ: usize = 0x228;
+
+/// The IDDQ fuses count in units of 4mA.
+const IDDQ_SCALE_MA: u32 = 4;
+
+/// The size of the hash of the secure boot public key in bytes.
+pub const KEY_HASH_SIZE: usize = 32;
+
+/// The fused identity and characterization of a unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Identity {
+    /// The SKU of the SoC.
+    pub sku: u8,
+    /// The unique ID of the chip, derived from its lot, wafer and position on the
+    /// wafer like the one reported by the stock firmware.
+    pub device_id: u64,
+    /// The CPU speedo values, which rate the speed of the CPU cluster.
+    pub cpu_speedo: [u32; 3],
+    /// The SoC speedo values, which rate the speed of the core logic.
+    pub soc_speedo: [u32; 3],
+    /// The quiescent currents of the CPU, the SoC and the GPU, in milliamperes.
+    pub cpu_iddq_ma: u32,
+    pub soc_iddq_ma: u32,
+    pub gpu_iddq_ma: u32,
+    /// The SHA-256 hash of the public key the boot ROM verifies the first stage with.
+    pub key_hash: [u8; KEY_HASH_SIZE],
+    /// Whether the ODM production mode is burnt, which enforces secure boot.
+    pub production_mode: bool,
+    /// The ODM reserved words.
+    pub odm: [u32; ODM_WORDS],
+}
+
+/// Derives the unique ID of a chip from its lot code, wafer and position.
+fn device_id<R: Fn(usize) -> u32>(read: &R) -> u64 {
+    // The lot code holds five base-36 digits, six bits each.
+    let lot_code = read(FUSE_OPT_LOT_CODE_0);
+    let derived_lot_code = (0..5).fold(0, |code, digit| {
+        code * 36 + (lot_code >> (24 - 6 * digit) & 0x3F)
+    }) & 0x03FF_FFFF;
+
+    (read(FUSE_OPT_Y_COORDINATE) & 0x1FF) as u64
+        | ((read(FUSE_OPT_X_COORDINATE) & 0x1FF) as u64) << 9
+        | ((read(FUSE_OPT_WAFER_ID) & 0x3F) as u64) << 18
+        | (derived_lot_code as u64) << 24
+        | ((read(FUSE_OPT_FAB_CODE) & 0x3F) as u64) << 50
+        | ((read(FUSE_OPT_VENDOR_CODE) & 0xF) as u64) << 56
+}
+
+impl Identity {
+    /// Decodes the identity from the fuse cache, where `read` reads the word at the
+    /// given offset from the base of the fuse controller.
+    pub fn read<R: Fn(usize) -> u32>(read: R) -> Self {
+        let mut key_hash = [0; KEY_HASH_SIZE];
+        for (index, chunk) in key_hash.chunks_mut(4).enumerate() {
+            chunk.copy_from_slice(&read(FUSE_PUBLIC_KEY0 + index * 4).to_le_bytes());
+        }
+
+        let mut odm = [0; ODM_WORDS];
+        for (index, word) in odm.iter_mut().enumerate() {
+            *word = read(FUSE_RESERVED_ODM0 + index * 4);
+        }
+
+        Identity {
+            sku: read(FUSE_SKU_INFO) as u8,
+            device_id: device_id(&read),
+            cpu_speedo: [
+                read(FUSE_CPU_SPEEDO_0),
+                read(FUSE_CPU_SPEEDO_1),
+                read(FUSE_CPU_SPEEDO_2),
+            ],
+            soc_speedo: [
+                read(FUSE_SOC_SPEEDO_0),
+                read(FUSE_SOC_SPEEDO_1),
+                read(FUSE_SOC_SPEEDO_2),
+            ],
+            cpu_iddq_ma: read(FUSE_CPU_IDDQ) * IDDQ_SCALE_MA,
+            soc_iddq_ma: read(FUSE_SOC_IDDQ) * IDDQ_SCALE_MA,
+            gpu_iddq_ma: read(FUSE_GPU_IDDQ) * IDDQ_SCALE_MA,
+            key_hash,
+            production_mode: read(FUSE_SECURITY_MODE) & 1 != 0,
+            odm,
+        }
+    }
+}
+
+/// Formats the identity as a report that fits on a single screen.
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Device ID: {:016X}", self.device_id)?;
+        writeln!(
+            f,
+            "SKU: {:#04X}, production mode: {}",
+            self.sku,
+            if self.production_mode { "yes" } else { "no" }
+        )?;
+        writeln!(
+            f,
+            "CPU speedo: {} {} {}, IDDQ: {} mA",
+            self.cpu_speedo[0], self.cpu_speedo[1], self.cpu_speedo[2], self.cpu_iddq_ma
+        )?;
+        writeln!(
+            f,
+            "SoC speedo: {} {} {}, IDDQ: {} mA",
+            self.soc_speedo[0], self.soc_speedo[1], self.soc_speedo[2], self.soc_iddq_ma
+        )?;
+        writeln!(f, "GPU IDDQ: {} mA", self.gpu_iddq_ma)?;
+
+        // Split the key hash and the ODM words in two lines each.
+        for (line, half) in self.key_hash.chunks(KEY_HASH_SIZE / 2).enumerate() {
+            f.write_str(if line == 0 {
+                "Key hash: "
+            } else {
+                "          "
+            })?;
+            for byte in half {
+                write!(f, "{:02X}", byte)?;
+            }
+            writeln!(f)?;
+        }
+        for (line, half) in self.odm.chunks(ODM_WORDS / 2).enumerate() {
+            f.write_str(if line == 0 { "ODM:" } else { "    " })?;
+            for word in half {
+                write!(f, " {:08X}", word)?;
+            }
+            if line == 0 {
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+    use std::string::ToString;
+
+    /// A synthetic fuse dump of a retail unit in production mode, as offsets from the
+    /// base of the fuse controller and cache words. Words that are not listed read as
+    /// zero.
+    ///
+    /// The chip was made by vendor 3 in fab 0x2A, on wafer 0x15 of lot 12345 at
+    /// position (0x34, 0x12). Unrelated bits are set above some of the fields.
+    #[rustfmt::skip]
+    const RETAIL: &[(usize, u32)] = &[
+        (FUSE_SKU_INFO, 0x0000_0083),
+        (FUSE_CPU_SPEEDO_0, 1950), (FUSE_CPU_SPEEDO_1, 1980), (FUSE_CPU_SPEEDO_2, 1975),
+        (FUSE_CPU_IDDQ, 90),
+        (FUSE_SOC_SPEEDO_0, 1840), (FUSE_SOC_SPEEDO_1, 1860), (FUSE_SOC_SPEEDO_2, 1850),
+        (FUSE_SOC_IDDQ, 30),
+        (0x164, 0x0302_0100), (0x168, 0x0706_0504), (0x16C, 0x0B0A_0908), (0x170, 0x0F0E_0D0C),
+        (0x174, 0x1312_1110), (0x178, 0x1716_1514), (0x17C, 0x1B1A_1918), (0x180, 0x1F1E_1D1C),
+        (FUSE_SECURITY_MODE, 0x0000_0001),
+        (0x1C8, 0x0000_0000), (0x1CC, 0x0000_0000), (0x1D0, 0x0000_0000), (0x1D4, 0x0000_0000),
+        (0x1D8, 0x0000_0001), (0x1DC, 0x0000_0000), (0x1E0, 0x0000_0004), (0x1E4, 0x0000_0000),
+        (FUSE_OPT_VENDOR_CODE, 0xFFFF_FFF3),
+        (FUSE_OPT_FAB_CODE, 0x0000_00EA),
+        // The base-36 digits 1, 2, 3, 4 and 5.
+        (FUSE_OPT_LOT_CODE_0, 0xC108_3105),
+        (FUSE_OPT_WAFER_ID, 0x0000_0055),
+        (FUSE_OPT_X_COORDINATE, 0x0000_0234),
+        (FUSE_OPT_Y_COORDINATE, 0x0000_FE12),
+        (FUSE_GPU_IDDQ, 100),
+    ];
+
+    fn reader(dump: &'static [(usize, u32)]) -> impl Fn(usize) -> u32 {
+        move |offset| {
+            dump.iter()
+                .find(|&&(word, _)| word == offset)
+                .map_or(0, |&(_, value)| value)
+        }
+    }
+
+    #[test]
+    fn decodes_retail_dump() {
+        let mut key_hash = [0; KEY_HASH_SIZE];
+        for (index, byte) in key_hash.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+
+        assert_eq!(
+            Identity::read(reader(RETAIL)),
+            Identity {
+                sku: 0x83,
+                device_id: 0x03A8_1B1D_4554_6812,
+                cpu_speedo: [1950, 1980, 1975],
+                soc_speedo: [1840, 1860, 1850],
+                cpu_iddq_ma: 360,
+                soc_iddq_ma: 120,
+                gpu_iddq_ma: 400,
+                key_hash,
+                production_mode: true,
+                odm: [0, 0, 0, 0, 1, 0, 4, 0],
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_blank_dump() {
+        let identity = Identity::read(reader(&[]));
+        assert_eq!(identity.device_id, 0);
+        assert_eq!(identity.key_hash, [0; KEY_HASH_SIZE]);
+        assert!(!identity.production_mode);
+    }
+
+    #[test]
+    fn derives_device_id_from_every_field() {
+        let fields = [
+            (FUSE_OPT_Y_COORDINATE, 0x1FF, 0x0000_0000_0000_01FF),
+            (FUSE_OPT_X_COORDINATE, 0x1FF, 0x0000_0000_0003_FE00),
+            (FUSE_OPT_WAFER_ID, 0x3F, 0x0000_0000_00FC_0000),
+            (FUSE_OPT_FAB_CODE, 0x3F, 0x00FC_0000_0000_0000),
+            (FUSE_OPT_VENDOR_CODE, 0xF, 0x0F00_0000_0000_0000),
+            // The lot code Z0000 in base 36, i.e. 35 * 36^4.
+            (FUSE_OPT_LOT_CODE_0, 35 << 24, 58_786_560 << 24),
+        ];
+
+        for &(field, value, expected) in fields.iter() {
+            let id = device_id(&|offset| if offset == field { value } else { 0 });
+            assert_eq!(id, expected, "{:#X}", field);
+        }
+
+        // Digits of 63 derive 63 * (36^4 + 36^3 + 36^2 + 36 + 1) = 108839115, which is
+        // truncated to 26 bits.
+        let id = device_id(&|offset| {
+            if offset == FUSE_OPT_LOT_CODE_0 {
+                0x3FFF_FFFF
+            } else {
+                0
+            }
+        });
+        assert_eq!(id, (108_839_115 & 0x03FF_FFFF) << 24);
+    }
+
+    #[test]
+    fn reports_identity() {
+        assert_eq!(
+            Identity::read(reader(RETAIL)).to_string(),
+            "Device ID: 03A81B1D45546812\n\
+             SKU: 0x83, production mode: yes\n\
+             CPU speedo: 1950 1980 1975, IDDQ: 360 mA\n\
+             SoC speedo: 1840 1860 1850, IDDQ: 120 mA\n\
+             GPU IDDQ: 400 mA\n\
+             Key hash: 000102030405060708090A0B0C0D0E0F\n          \
+             101112131415161718191A1B1C1D1E1F\n\
+             ODM: 00000000 00000000 00000000 00000000\n     \
+             00000001 00000000 00000004 00000000"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! Access to the fuse cache and the fuse programming interface.
+//!
+//! Reading goes through the fuse cache that is made visible by `libtegra::fuse::init`.
+//! The fuses that identify the unit are decoded by the [`identity`] module. Burning
+//! fuses is only compiled in with the `fuse_burn` feature and requires fuse
+//! programming to not have been disabled yet during this boot. Changes requested by
+//! the operator go through the safeguards of the [`burn`] module.
+//!
+//! [`identity`]: identity/index.html
+//! [`burn`]: burn/index.html
+
+#[cfg(feature = "fuse_burn")]
+pub mod burn;
+pub mod identity;
+#[cfg(test)]
+pub mod simulated;
+
+use core::ptr;
+
+use self::identity::Identity;
+
+/// The base address of the fuse controller.
+const FUSE_BASE: usize = 0x7000_F800;
+
+const FUSE_RESERVED_ODM0: usize = 0x1C8;
+
+/// The number of ODM reserved fuse words.
+pub const ODM_WORDS: usize = 8;
+
+/// Errors that may occur while accessing fuses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The requested fuse word does not exist.
+    InvalidWord,
+    /// Fuse programming was disabled until the next reboot.
+    #[cfg(feature = "fuse_burn")]
+    ProgrammingDisabled,
+    /// Reading back the fuses after burning did not yield the expected value.
+    #[cfg(feature = "fuse_burn")]
+    VerifyFailed,
+    /// The fuse controller did not become idle in time.
+    #[cfg(feature = "fuse_burn")]
+    Timeout,
+}
+
+/// Read access to the fuses of the SoC.
+pub trait FuseArray {
+    /// Reads the ODM reserved fuse word at `index`.
+    fn read_odm(&self, index: usize) -> Result<u32, Error>;
+}
+
+/// Write access to the fuses of the SoC.
+///
+/// Fuses are one-time programmable, so bits can only ever be changed from 0 to 1.
+#[cfg(feature = "fuse_burn")]
+pub trait FuseProgrammer: FuseArray {
+    /// Burns the bits set in `mask` into the ODM reserved fuse word at `index`.
+    fn burn_odm(&mut self, index: usize, mask: u32) -> Result<(), Error>;
+}
+
+/// The hardware fuses of the Tegra X1.
+pub struct Fuses;
+
+fn read_fuse_reg(offset: usize) -> u32 {
+    unsafe { ptr::read_volatile((FUSE_BASE + offset) as *const u32) }
+}
+
+impl Fuses {
+    /// Reads the fuse cache word at `offset` from the base of the fuse controller.
+    ///
+    /// This is meant for calibration values; ODM words go through [`FuseArray`].
+    ///
+    /// [`FuseArray`]: trait.FuseArray.html
+    pub fn read_cache(&self, offset: usize) -> u32 {
+        read_fuse_reg(offset)
+    }
+
+    /// Reads the fused identity of the unit.
+    pub fn identity(&self) -> Identity {
+        Identity::read(read_fuse_reg)
+    }
+}
+
+impl FuseArray for Fuses {
+    fn read_odm(&self, index: usize) -> Result<u32, Error> {
+        if index >= ODM_WORDS {
+            return Err(Error::InvalidWord);
+        }
+
+        Ok(read_fuse_reg(FUSE_RESERVED_ODM0 + index * 4))
+    }
+}
+
+#[cfg(feature = "fuse_burn")]
+pub use programming::init_for_programming;
+
+#[cfg(feature = "fuse_burn")]
+mod programming {
+    use core::ptr;
+
+    use super::{read_fuse_reg, Error, FuseProgrammer, Fuses, FUSE_BASE, ODM_WORDS};
+    use crate::bus::TimerDelay;
+    use crate::deadline;
+
+    /// The base address of the Clock and Reset Controller.
+    const CAR_BASE: usize = 0x6000_6000;
+
+    /// The base address of the Power Management Controller.
+    const PMC_BASE: usize = 0x7000_E400;
+
+    const CLK_RST_CONTROLLER_MISC_CLK_ENB: usize = 0x48;
+    const CFG_ALL_VISIBLE: u32 = 1 << 28;
+
+    const FUSE_FUSECTRL: usize = 0x00;
+    const FUSE_FUSEADDR: usize = 0x04;
+    const FUSE_FUSERDATA: usize = 0x08;
+    const FUSE_FUSEWDATA: usize = 0x0C;
+    const FUSE_FUSETIME_PGM2: usize = 0x1C;
+    const FUSE_PRIVATEKEYDISABLE: usize = 0x24;
+    const FUSE_DISABLEREGPROGRAM: usize = 0x2C;
+    const FUSE_WRITE_ACCESS_SW: usize = 0x30;
+    const FUSE_PWR_GOOD_SW: usize = 0x34;
+
+    const APBDEV_PMC_FUSE_CONTROL: usize = 0x450;
+    const PS18_LATCH_SET: u32 = 1 << 8;
+    const PS18_LATCH_CLEAR: u32 = 1 << 9;
+
+    const FUSECTRL_CMD_MASK: u32 = 0x3;
+    const FUSECTRL_CMD_READ: u32 = 0x1;
+    const FUSECTRL_CMD_WRITE: u32 = 0x2;
+    const FUSECTRL_CMD_SENSE: u32 = 0x3;
+    const FUSECTRL_STATE_SHIFT: u32 = 16;
+    const FUSECTRL_STATE_MASK: u32 = 0x1F;
+    const FUSECTRL_STATE_IDLE: u32 = 0x4;
+
+    /// The time a single fuse controller command may take, in microseconds.
+    const COMMAND_TIMEOUT_US: u32 = 10_000;
+
+    /// The fuse array address of the first ODM reserved word.
+    ///
+    /// Every word is followed by its redundant copy, so consecutive ODM words are two
+    /// rows apart in the array.
+    const ODM_FUSE_ADDR: u32 = 0x2E;
+
+    fn write_fuse_reg(offset: usize, value: u32) {
+        unsafe { ptr::write_volatile((FUSE_BASE + offset) as *mut u32, value) }
+    }
+
+    fn modify_reg(address: usize, clear: u32, set: u32) {
+        let reg = address as *mut u32;
+        unsafe { ptr::write_volatile(reg, (ptr::read_volatile(reg) & !clear) | set) }
+    }
+
+    /// Initializes the fuse driver like `libtegra::fuse::init`, but only revokes write
+    /// access instead of disabling programming.
+    ///
+    /// Disabling programming cannot be undone until the next reset, so write access is
+    /// only granted for the duration of a burn instead. The caller is responsible for
+    /// calling `libtegra::fuse::disable_programming` as soon as no more fuses may be
+    /// burnt during this boot.
+    pub fn init_for_programming() {
+        // Make the fuse registers visible.
+        modify_reg(
+            CAR_BASE + CLK_RST_CONTROLLER_MISC_CLK_ENB,
+            0,
+            CFG_ALL_VISIBLE,
+        );
+
+        // Disable the private key.
+        write_fuse_reg(FUSE_PRIVATEKEYDISABLE, 0x10);
+
+        // Revoke write access until a burn grants it.
+        write_fuse_reg(
+            FUSE_WRITE_ACCESS_SW,
+            read_fuse_reg(FUSE_WRITE_ACCESS_SW) | 1,
+        );
+    }
+
+    fn wait_idle() -> Result<(), Error> {
+        deadline::poll_until(
+            &TimerDelay,
+            || {
+                (read_fuse_reg(FUSE_FUSECTRL) >> FUSECTRL_STATE_SHIFT) & FUSECTRL_STATE_MASK
+                    == FUSECTRL_STATE_IDLE
+            },
+            COMMAND_TIMEOUT_US,
+        )
+        .map_err(|_| Error::Timeout)
+    }
+
+    fn command(command: u32) -> Result<(), Error> {
+        wait_idle()?;
+        write_fuse_reg(
+            FUSE_FUSECTRL,
+            (read_fuse_reg(FUSE_FUSECTRL) & !FUSECTRL_CMD_MASK) | command,
+        );
+        wait_idle()
+    }
+
+    fn read_array(address: u32) -> Result<u32, Error> {
+        write_fuse_reg(FUSE_FUSEADDR, address);
+        command(FUSECTRL_CMD_READ)?;
+        Ok(read_fuse_reg(FUSE_FUSERDATA))
+    }
+
+    fn write_array(address: u32, value: u32) -> Result<(), Error> {
+        write_fuse_reg(FUSE_FUSEADDR, address);
+        write_fuse_reg(FUSE_FUSEWDATA, value);
+        command(FUSECTRL_CMD_WRITE)
+    }
+
+    impl FuseProgrammer for Fuses {
+        fn burn_odm(&mut self, index: usize, mask: u32) -> Result<(), Error> {
+            if index >= ODM_WORDS {
+                return Err(Error::InvalidWord);
+            }
+            if read_fuse_reg(FUSE_DISABLEREGPROGRAM) & 1 != 0 {
+                return Err(Error::ProgrammingDisabled);
+            }
+
+            let address = ODM_FUSE_ADDR + index as u32 * 2;
+
+            // Set up a 5us programming pulse based on the 38.4MHz oscillator.
+            write_fuse_reg(
+                FUSE_FUSETIME_PGM2,
+                (read_fuse_reg(FUSE_FUSETIME_PGM2) & !0xFFFF) | 0xC0,
+            );
+
+            // Enable software write access and power up the programming circuitry.
+            write_fuse_reg(
+                FUSE_WRITE_ACCESS_SW,
+                read_fuse_reg(FUSE_WRITE_ACCESS_SW) & !1,
+            );
+            modify_reg(
+                PMC_BASE + APBDEV_PMC_FUSE_CONTROL,
+                PS18_LATCH_CLEAR,
+                PS18_LATCH_SET,
+            );
+            write_fuse_reg(FUSE_PWR_GOOD_SW, 1);
+
+            // Burn the primary row and its redundant copy. The programming circuitry
+            // has to be powered down again even if the controller hangs.
+            let burnt = write_array(address, mask).and_then(|_| write_array(address + 1, mask));
+
+            // Power down the programming circuitry and revoke write access again.
+            write_fuse_reg(FUSE_PWR_GOOD_SW, 0);
+            modify_reg(
+                PMC_BASE + APBDEV_PMC_FUSE_CONTROL,
+                PS18_LATCH_SET,
+                PS18_LATCH_CLEAR,
+            );
+            write_fuse_reg(
+                FUSE_WRITE_ACCESS_SW,
+                read_fuse_reg(FUSE_WRITE_ACCESS_SW) | 1,
+            );
+
+            burnt?;
+
+            // Reload the fuse cache and make sure the bits were actually burnt.
+            command(FUSECTRL_CMD_SENSE)?;
+            if read_array(address)? & mask != mask {
+                return Err(Error::VerifyFailed);
+            }
+
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! A fuse array in memory, for testing code that reads or burns fuses on the host.
+
+#[cfg(feature = "fuse_burn")]
+use super::FuseProgrammer;
+use super::{Error, FuseArray, ODM_WORDS};
+
+/// Simulated ODM reserved fuses.
+#[derive(Clone, Debug, Default)]
+pub struct SimulatedFuses {
+    /// The values of the ODM words.
+    pub odm: [u32; ODM_WORDS],
+    /// Bits that fail to burn, in every word.
+    pub stuck: u32,
+    /// Whether programming was disabled.
+    pub disabled: bool,
+    /// The number of burns performed.
+    pub burns: usize,
+}
+
+impl FuseArray for SimulatedFuses {
+    fn read_odm(&self, index: usize) -> Result<u32, Error> {
+        self.odm.get(index).copied().ok_or(Error::InvalidWord)
+    }
+}
+
+#[cfg(feature = "fuse_burn")]
+impl FuseProgrammer for SimulatedFuses {
+    fn burn_odm(&mut self, index: usize, mask: u32) -> Result<(), Error> {
+        if index >= ODM_WORDS {
+            return Err(Error::InvalidWord);
+        }
+        if self.disabled {
+            return Err(Error::ProgrammingDisabled);
+        }
+
+        self.burns += 1;
+        self.odm[index] |= mask & !self.stuck;
+        Ok(())
+    }
+}
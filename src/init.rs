@@ -1,180 +1,58 @@
 //! Hardware initialization for the NVIDIA Tegra X1.
 
-use libtegra::i2c::{Error, I2c};
-use libtegra::pinmux::{
-    PinFunction, PinGrP, PinIo, PinIoHv as PinEIoHv, PinLock, PinOd, PinPull, PinTristate,
-};
+#[cfg(not(feature = "fuse_burn"))]
+use libtegra::fuse;
+use libtegra::i2c::I2c;
 #[cfg(feature = "debug_uart_port")]
 use libtegra::uart::{Uart, BAUD_115200};
-use libtegra::{apb, car, fuse, gpio, mc, pmc, timer};
+use libtegra::{apb, car, mc, pmc, timer};
 
-const MAX77620_PWR: u32 = 0x3C;
+use crate::board::{GPIO_CONFIG, PIN_CONFIG};
+use crate::bus::TimerDelay;
+use crate::clock::pll::Pll;
+use crate::clock::{self, tree};
+use crate::error::{BootError, ErrorKind, Stage};
+use crate::sequencer::{self, Step};
+use crate::thermal::{self, Status};
 
-// TODO: Configure remaining GPIOs for the advanced stages of the system here?
-const GPIO_CONFIG: [(gpio::Gpio, gpio::Config); 6] = [
-    (tegra_gpio!(D, 1), gpio::Config::Input), // Pin mode for Joy-Con IsAttached and UART-C TX
-    (tegra_gpio!(E, 6), gpio::Config::Input), // Joy-Con IsAttached mode
-    (tegra_gpio!(G, 0), gpio::Config::Input), // Pin mode for Joy-Con IsAttached and UART-B TX
-    (tegra_gpio!(H, 6), gpio::Config::Input), // Joy-Con IsAttached mode
-    (tegra_gpio!(X, 6), gpio::Config::Input), // Volume Up
-    (tegra_gpio!(X, 7), gpio::Config::Input), // Volume Down
-];
+const MAX77620_PWR: u32 = 0x3C;
 
-const PIN_CONFIG: [(
-    PinGrP,
-    PinFunction,
-    PinPull,
-    PinTristate,
-    PinIo,
-    PinLock,
-    PinOd,
-    PinEIoHv,
-); 12] = [
-    // UART-A TX
-    (
-        PinGrP::Uart1TxPu0,
-        PinFunction::Uarta,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Output,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Default,
-    ),
-    // UART-A RX
-    (
-        PinGrP::Uart1RxPu1,
-        PinFunction::Uarta,
-        PinPull::Up,
-        PinTristate::Passthrough,
-        PinIo::Input,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Default,
-    ),
-    // UART-A RTS
-    (
-        PinGrP::Uart1RtsPu2,
-        PinFunction::Uarta,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Output,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Default,
-    ),
-    // UART-A CTS
-    (
-        PinGrP::Uart1CtsPu3,
-        PinFunction::Uarta,
-        PinPull::Down,
-        PinTristate::Passthrough,
-        PinIo::Input,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Default,
-    ),
-    // UART-B TX
-    (
-        PinGrP::Uart2TxPg0,
-        PinFunction::Uartb,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Output,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Default,
-    ),
-    // UART-C TX
-    (
-        PinGrP::Uart3TxPd1,
-        PinFunction::Uartc,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Output,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Default,
-    ),
-    // GPIO PE6
-    (
-        PinGrP::Pe6,
-        PinFunction::Default,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Input,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Default,
-    ),
-    // GPIO PH6
-    (
-        PinGrP::Ph6,
-        PinFunction::Default,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Input,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Default,
-    ),
-    // I2C-1 SCL
-    (
-        PinGrP::Gen1I2CSclPj1,
-        PinFunction::I2C1,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Input,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Normal,
-    ),
-    // I2C-1 SDA
-    (
-        PinGrP::Gen1I2CSdaPj0,
-        PinFunction::I2C1,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Input,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Normal,
-    ),
-    // I2C-5 SCL
-    (
-        PinGrP::PwrI2CSclPy3,
-        PinFunction::I2Cpmu,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Input,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Normal,
-    ),
-    // I2C-5 SDA
-    (
-        PinGrP::PwrI2CSdaPy4,
-        PinFunction::I2Cpmu,
-        PinPull::None,
-        PinTristate::Passthrough,
-        PinIo::Input,
-        PinLock::Default,
-        PinOd::Disable,
-        PinEIoHv::Normal,
-    ),
+/// The MAX77620 power-up sequence, applied as a whole or not at all.
+const PMIC_SEQUENCE: [Step; 13] = [
+    // Configure the backup battery charger.
+    Step::write(MAX77620_PWR, 0x04, 0x40),
+    // Configure the ONOFF behavior.
+    Step::write(MAX77620_PWR, 0x41, 0x60),
+    // Configure the Flexible Power Sequencer and the rails it controls.
+    Step::write(MAX77620_PWR, 0x43, 0x38),
+    Step::write(MAX77620_PWR, 0x44, 0x3A),
+    Step::write(MAX77620_PWR, 0x45, 0x38),
+    Step::write(MAX77620_PWR, 0x4A, 0x0F),
+    Step::write(MAX77620_PWR, 0x4E, 0xC7),
+    Step::write(MAX77620_PWR, 0x4F, 0x4F),
+    Step::write(MAX77620_PWR, 0x50, 0x29),
+    Step::write(MAX77620_PWR, 0x52, 0x1B),
+    Step::write(MAX77620_PWR, 0x56, 0x22),
+    // Configure SD0 voltage and let the rail settle. Only the rail voltage is worth
+    // the extra transfer of reading it back.
+    Step::write(MAX77620_PWR, 0x16, 0x2A).verified(),
+    Step::delay(1000),
 ];
 
-fn config_oscillators(car: &car::Registers, pmc: &pmc::Registers) {
+fn config_oscillators(car: &car::Registers, pmc: &pmc::Registers) -> Result<(), BootError> {
     let sysctr0 = unsafe { &*pmc::counter0::REGISTERS };
     let timer = unsafe { &*timer::timerus::REGISTERS };
+    let invalid = || BootError::new(ErrorKind::Config, Stage::Clock);
 
     // Set CLK_M_DIVISOR to 2.
     car.CLK_RST_CONTROLLER_SPARE_REG0_0
         .set((car.CLK_RST_CONTROLLER_SPARE_REG0_0.get() & 0xFFFF_FFF3) | 0x4);
     // Set counter frequency.
-    sysctr0.SYSCTR0_CNTFID0_0.set(19200000);
-    // Set 19.2MHz clk_m.
-    timer.TIMERUS_USEC_CFG_0.set(0x45F);
+    sysctr0.SYSCTR0_CNTFID0_0.set(tree::CLK_M_HZ);
+    // Derive the microsecond timer from the 19.2MHz clk_m.
+    timer
+        .TIMERUS_USEC_CFG_0
+        .set(tree::usec_config(tree::CLK_M_HZ).ok_or_else(invalid)?);
     // Set OSC to 38.4MHz and drive strength.
     car.CLK_RST_CONTROLLER_OSC_CTRL_0.set(0x5000_0071);
 
@@ -189,24 +67,23 @@ fn config_oscillators(car: &car::Registers, pmc: &pmc::Registers) {
     pmc.APBDEV_PMC_SCRATCH188_0
         .set((pmc.APBDEV_PMC_SCRATCH188_0.get() & 0xFCFF_FFFF) | 0x2000000);
 
-    // Set HCLK div to 2 and PCLK div to 1.
-    car.CLK_RST_CONTROLLER_CLK_SYSTEM_RATE_0.set(0x10);
     // PLLMB disable.
-    car.CLK_RST_CONTROLLER_PLLMB_BASE_0
-        .set(car.CLK_RST_CONTROLLER_PLLMB_BASE_0.get() & 0xBFFF_FFFF);
+    clock::disable_pll(Pll::MB);
 
-    // 0x249F = 19200000 * (16 / 32.768 KHz)
+    // Count the timestamp counter cycles in 16 cycles of the 32.768kHz clock.
+    let tsc_mult = tree::tsc_mult(tree::CLK_M_HZ).ok_or_else(invalid)?;
     pmc.APBDEV_PMC_TSC_MULT_0
-        .set((pmc.APBDEV_PMC_TSC_MULT_0.get() & 0xFFFF_0000) | 0x249F);
+        .set((pmc.APBDEV_PMC_TSC_MULT_0.get() & 0xFFFF_0000) | tsc_mult);
 
     // Set BPMP/SCLK div to 1.
     car.CLK_RST_CONTROLLER_CLK_SOURCE_SYS_0.set(0);
-    // Set BPMP/SCLK source to Run and PLLP_OUT2 (204MHz).
-    car.CLK_RST_CONTROLLER_SCLK_BURST_POLICY_0.set(0x2000_4444);
-    // Enable SUPER_SDIV to 1.
-    car.CLK_RST_CONTROLLER_SUPER_SCLK_DIVIDER_0.set(0x8000_0000);
-    // Set HCLK div to 1 and PCLK div to 3.
-    car.CLK_RST_CONTROLLER_CLK_SYSTEM_RATE_0.set(0x2);
+    // Bring up PLLP at 408MHz, unless the boot ROM already did. Run from clk_m meanwhile.
+    if clock::pll_rate_hz(Pll::P) != Some(tree::PLLP_HZ) {
+        clock::set_system_clocks(tree::SystemSource::ClkM, clock::CLK_M_RATES)?;
+        clock::enable_pll(Pll::P, tree::PLLP_HZ)?;
+    }
+    // Run BPMP/SCLK from PLLP_OUT2 (204MHz).
+    clock::set_system_clocks(tree::SystemSource::PllpOut2, clock::BOOT_RATES)
 }
 
 fn config_pinmux(apb_misc: &apb::misc::AmbaPeripheralBus) {
@@ -214,20 +91,20 @@ fn config_pinmux(apb_misc: &apb::misc::AmbaPeripheralBus) {
     apb_misc.pp.APB_MISC_PP_PINMUX_GLOBAL_0_0.set(0);
 
     // Configure the GPIOs.
-    for entry in GPIO_CONFIG.iter() {
-        entry.0.config(entry.1);
+    for &(gpio, config) in GPIO_CONFIG.iter() {
+        gpio.config(config);
     }
 
     // Configure the pin multiplexing.
-    for entry in PIN_CONFIG.iter() {
+    for config in PIN_CONFIG.iter() {
         unsafe {
-            entry.0.set_function(entry.1);
-            entry.0.set_pull(entry.2);
-            entry.0.set_tristate(entry.3);
-            entry.0.set_io(entry.4);
-            entry.0.set_lock(entry.5);
-            entry.0.set_od(entry.6);
-            entry.0.set_io_hv(entry.7);
+            config.pin.set_function(config.function);
+            config.pin.set_pull(config.pull);
+            config.pin.set_tristate(config.tristate);
+            config.pin.set_io(config.io);
+            config.pin.set_lock(config.lock);
+            config.pin.set_od(config.od);
+            config.pin.set_io_hv(config.hv);
         }
     }
 }
@@ -245,7 +122,13 @@ fn config_pmc_scratch(pmc: &pmc::Registers) {
 }
 
 /// Performs hardware initialization for the Tegra X1 SoC.
-pub fn init_hardware() -> Result<(), Error> {
+///
+/// If configuring the PMIC fails, its registers are restored to the state the boot
+/// ROM left them in and the device and register of the failing step of
+/// [`PMIC_SEQUENCE`] are reported.
+///
+/// [`PMIC_SEQUENCE`]: constant.PMIC_SEQUENCE.html
+pub fn init_hardware() -> Result<(), BootError> {
     let apb_misc = unsafe { &*apb::misc::REGISTERS };
     let car = unsafe { &*car::REGISTERS };
     let pmc = unsafe { &*pmc::REGISTERS };
@@ -267,13 +150,19 @@ pub fn init_hardware() -> Result<(), Error> {
 
     // Initialize the fuse driver by making the registers visible, disabling
     // the private key and disabling programming.
+    #[cfg(not(feature = "fuse_burn"))]
     fuse::init();
 
+    // Same as above, but only revoke write access, so that fuses may still be
+    // burnt until the second stage is loaded.
+    #[cfg(feature = "fuse_burn")]
+    crate::fuses::init_for_programming();
+
     // Enable clocks to Memory Controllers and disable AHB redirect.
     mc::enable_mc();
 
     // Initialize counters, CLKM, BPMP and other clocks based on 38.4MHz oscillator.
-    config_oscillators(car, pmc);
+    config_oscillators(car, pmc)?;
 
     // Initialize the SoC pin configurations.
     config_pinmux(apb_misc);
@@ -293,28 +182,20 @@ pub fn init_hardware() -> Result<(), Error> {
     I2c::C5.init();
 
     // Configure the PMIC.
-    I2c::C5.write_byte(MAX77620_PWR, 0x04, 0x40)?;
-
-    I2c::C5.write_byte(MAX77620_PWR, 0x41, 0x60)?;
-    I2c::C5.write_byte(MAX77620_PWR, 0x43, 0x38)?;
-    I2c::C5.write_byte(MAX77620_PWR, 0x44, 0x3A)?;
-    I2c::C5.write_byte(MAX77620_PWR, 0x45, 0x38)?;
-    I2c::C5.write_byte(MAX77620_PWR, 0x4A, 0x0F)?;
-    I2c::C5.write_byte(MAX77620_PWR, 0x4E, 0xC7)?;
-    I2c::C5.write_byte(MAX77620_PWR, 0x4F, 0x4F)?;
-    I2c::C5.write_byte(MAX77620_PWR, 0x50, 0x29)?;
-    I2c::C5.write_byte(MAX77620_PWR, 0x52, 0x1B)?;
-    I2c::C5.write_byte(MAX77620_PWR, 0x56, 0x22)?;
-
-    // Configure SD0 voltage.
-    I2c::C5.write_byte(MAX77620_PWR, 0x16, 0x2A)?;
+    sequencer::run(&mut I2c::C5, &TimerDelay, &PMIC_SEQUENCE)
+        .map_err(|failure| BootError::from_sequence(Stage::Pmic, &PMIC_SEQUENCE, failure))?;
 
     // Configure and lock PMC scratch registers.
     // XXX: Starting from 4.0.0+, this was removed.
     config_pmc_scratch(pmc);
 
-    // Set SCLK to PLLP_OUT (408MHz).
-    car.CLK_RST_CONTROLLER_SCLK_BURST_POLICY_0.set(0x2000_3333);
+    // Bring up the thermal sensors. A critical reading powers the system off.
+    thermal::init();
+
+    // Set SCLK to PLLP_OUT0 (408MHz), unless the system is already running hot.
+    if thermal::check() == Status::Normal {
+        clock::set_system_clocks(tree::SystemSource::PllpOut0, clock::FULL_RATES)?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,5 @@
+//! Public keys for the chain of trust, baked in at build time.
+//!
+//! See the build script for how to supply custom keys.
+
+include!(concat!(env!("OUT_DIR"), "/keys.rs"));
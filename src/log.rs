@@ -0,0 +1,116 @@
+//! A ring buffer that keeps the most recent log messages of the boot.
+//!
+//! Retail units lack a debug UART, so everything that is reported during boot is also
+//! recorded here and can be read back over USB in recovery mode.
+
+use core::fmt;
+
+/// The capacity of the log ring in bytes.
+pub const LOG_SIZE: usize = 0x400;
+
+/// A ring buffer of text that overwrites its oldest contents when full.
+pub struct LogRing {
+    buffer: [u8; LOG_SIZE],
+    /// The index at which the next byte is written.
+    head: usize,
+    /// The number of valid bytes in the buffer.
+    len: usize,
+}
+
+impl LogRing {
+    /// Creates an empty log ring.
+    pub const fn new() -> Self {
+        LogRing {
+            buffer: [0; LOG_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Gets the number of bytes in the log.
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    /// Appends `bytes` to the log.
+    pub fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.buffer[self.head] = byte;
+            self.head = (self.head + 1) % LOG_SIZE;
+        }
+
+        self.len = (self.len + bytes.len()).min(LOG_SIZE);
+    }
+
+    /// Copies the log, starting `offset` bytes after the oldest byte, into `out`.
+    ///
+    /// Returns the number of bytes copied.
+    pub fn read(&self, offset: usize, out: &mut [u8]) -> usize {
+        let start = (self.head + LOG_SIZE - self.len) % LOG_SIZE;
+        let count = self.len.saturating_sub(offset).min(out.len());
+
+        for (index, byte) in out[..count].iter_mut().enumerate() {
+            *byte = self.buffer[(start + offset + index) % LOG_SIZE];
+        }
+
+        count
+    }
+}
+
+impl fmt::Write for LogRing {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The log of the current boot.
+static mut LOG: LogRing = LogRing::new();
+
+/// Whether [`LOG`] is currently lent out by [`with_ring`].
+///
+/// [`LOG`]: static.LOG.html
+/// [`with_ring`]: fn.with_ring.html
+static mut LENT: bool = false;
+
+/// Runs `f` with exclusive access to the log of the current boot.
+///
+/// The bootloader runs on a single core without interrupts, so the only way to alias
+/// the log is to call this function again from within `f`, which panics.
+pub fn with_ring<R>(f: impl FnOnce(&mut LogRing) -> R) -> R {
+    // Nothing else accesses `LOG` and `LENT`, and the flag rules out nested
+    // borrows on the single thread of execution.
+    unsafe {
+        assert!(!LENT, "log ring borrowed twice");
+        LENT = true;
+        let result = f(&mut LOG);
+        LENT = false;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_most_recent_bytes() {
+        let mut ring = LogRing::new();
+        for byte in 0..LOG_SIZE + 3 {
+            ring.push(&[byte as u8]);
+        }
+
+        let mut out = [0; 4];
+        assert_eq!(ring.size(), LOG_SIZE);
+        assert_eq!(ring.read(0, &mut out), 4);
+        assert_eq!(out, [3, 4, 5, 6]);
+        assert_eq!(ring.read(LOG_SIZE - 2, &mut out), 2);
+        assert_eq!(out[..2], [(LOG_SIZE + 1) as u8, (LOG_SIZE + 2) as u8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "log ring borrowed twice")]
+    fn rejects_nested_borrows() {
+        with_ring(|_| with_ring(|_| ()));
+    }
+}
@@ -9,22 +9,62 @@ compile_error!("Please compile the first bootloader stage for ARM7TDMI!");
 #[macro_use]
 extern crate libtegra;
 
+mod backlight;
+mod battery;
+mod blink;
+mod board;
+mod boot;
+mod bus;
+mod clock;
+mod console;
+mod crypto;
+mod deadline;
+mod display;
+mod error;
+mod fastboot;
+mod fuses;
 mod init;
+mod keys;
+mod log;
 mod memory;
 mod panic;
+mod power;
+mod recovery;
 #[allow(dead_code)]
 #[macro_use]
 mod rt;
+mod rollback;
+mod sequencer;
+mod splash;
+mod storage;
+mod thermal;
+mod usb;
+mod verify;
+mod watchdog;
 
-#[cfg(feature = "debug_uart_port")]
-use core::fmt::Write;
+use core::fmt::{self, Write};
 
-use libtegra::gpio;
-use libtegra::pinmux::{PinGrP, PinTristate};
+#[cfg(feature = "fuse_burn")]
+use libtegra::fuse;
+use libtegra::i2c::I2c;
 use libtegra::se::SecurityEngine;
-use libtegra::timer::sleep;
+use libtegra::timer::usleep;
 #[cfg(feature = "debug_uart_port")]
 use libtegra::uart::Uart;
+use manifest::Manifest;
+use slot::Metadata;
+
+use crate::backlight::Backlight;
+use crate::battery::{charger, gauge, Decision};
+use crate::clock::pll::Pll;
+use crate::console::Console;
+use crate::display::{Display, Framebuffer};
+use crate::error::{BootError, ErrorKind, Stage};
+use crate::fuses::Fuses;
+use crate::recovery::BootTarget;
+use crate::storage::emmc::{Emmc, Partition};
+use crate::usb::UsbDevice;
+use crate::watchdog::Checkpoint;
 
 entrypoint!(main);
 
@@ -36,6 +76,7 @@ pub const SECURITY_ENGINE: SecurityEngine = SecurityEngine::SE1;
 /// The first-stage bootloader is responsible for loading the second bootloader to
 /// this address before passing execution to the TSEC firmware. The TSEC will then
 /// decrypt and verify the bootloader at this exact address and pass execution to it.
+/// `link.ld` refuses to link a first stage that would extend past it.
 const BOOTLOADER_START: *mut u32 = 0x4001_6FE0 as *mut _;
 
 /// The size of the second-stage bootloader blob.
@@ -43,18 +84,255 @@ const BOOTLOADER_START: *mut u32 = 0x4001_6FE0 as *mut _;
 /// This should be word-aligned to optimize memory copying and clearing operations.
 const BOOTLOADER_SIZE: usize = 0x28810;
 
-fn bring_up_backlight() {
-    unsafe {
-        PinGrP::LcdBlPwmPv0.set_tristate(PinTristate::Passthrough);
-        PinGrP::LcdBlEnPv1.set_tristate(PinTristate::Passthrough);
+/// The address of the signed boot manifest describing the second-stage bootloader.
+///
+/// The manifest directly follows the blob in memory, at `BOOTLOADER_START + BOOTLOADER_SIZE`.
+const BOOTLOADER_MANIFEST: *const u8 = 0x4003_F7F0 as *const _;
+
+/// The duration of the backlight fade-in in milliseconds.
+const BACKLIGHT_RAMP_MS: u32 = 250;
+
+/// The interval in which the battery is checked while it charges, in milliseconds.
+const CHARGE_POLL_MS: u32 = 1000;
+
+/// The duration of the blink that signals charging without a display, in
+/// milliseconds.
+const CHARGE_BLINK_MS: u32 = 100;
+
+/// How long the reason for powering off stays on the screen, in milliseconds.
+const POWER_OFF_DELAY_MS: u32 = 3000;
+
+/// Attaches to USB as the device described by `descriptors`.
+fn attach_usb(descriptors: &'static usb::control::Descriptors) -> Result<UsbDevice, BootError> {
+    // Bring-up only fails if the hardware does not respond.
+    UsbDevice::init(descriptors).map_err(|_| BootError::new(ErrorKind::Timeout, Stage::Usb))
+}
+
+fn bring_up_display() -> Result<Console<Framebuffer>, BootError> {
+    let mut display = Display::init()?;
+
+    // Put a banner on the splash and fade it in.
+    let mut console = Console::new(
+        display.framebuffer(),
+        display::HIGHLIGHT_COLOR,
+        display::SPLASH_BACKGROUND,
+    );
+    let _ = writeln!(&mut console, "Mirage v{}", env!("CARGO_PKG_VERSION"));
+    console.set_colors(display::TEXT_COLOR, display::SPLASH_BACKGROUND);
+
+    // The logo was validated at build time, so this only fails on a broken build.
+    splash::draw_logo(&mut display.framebuffer())
+        .map_err(|_| BootError::new(ErrorKind::Config, Stage::Display))?;
+
+    Backlight::init().ramp_to(100, BACKLIGHT_RAMP_MS);
+
+    Ok(console)
+}
+
+/// Reports boot progress on the debug UART and the console, where available, and
+/// records it in the log.
+fn report(console: &mut Option<Console<Framebuffer>>, progress: u8, message: fmt::Arguments<'_>) {
+    #[cfg(feature = "debug_uart_port")]
+    let _ = writeln!(&mut Uart::E, "[Mirage] {}", message);
+    let _ = log::with_ring(|ring| writeln!(ring, "{}", message));
+
+    if let Some(console) = console {
+        let _ = writeln!(console, "{}", message);
+        console.progress_bar(progress);
+    }
+}
+
+/// Makes sure that the battery holds enough charge to boot, waiting for it to charge
+/// if necessary. Powers off if it does not and is not charging.
+fn check_battery(console: &mut Option<Console<Framebuffer>>) {
+    let mut reported = None;
+
+    // A fault latched before the check, e.g. while plugging in the charger, must not
+    // power off a device that is charging. If this fails, so does the read below.
+    let _ = charger::clear_faults(&mut I2c::C1);
+
+    loop {
+        watchdog::checkpoint(Checkpoint::Battery);
+
+        let reading = gauge::read(&mut I2c::C1);
+        let charger = charger::read_status(&mut I2c::C1);
+        let (reading, charger) = match (reading, charger) {
+            (Ok(reading), Ok(charger)) => (reading, charger),
+            // Refusing to boot over a broken fuel gauge would brick the device.
+            _ => {
+                report(
+                    console,
+                    0,
+                    format_args!("Failed to read the battery state."),
+                );
+                return;
+            }
+        };
+
+        match battery::decide(&reading, &charger) {
+            Decision::Boot => return,
+            Decision::Charge => {
+                // Only report changes, so that the console does not overflow.
+                if reported != Some(reading.charge_percent) {
+                    report(
+                        console,
+                        reading.charge_percent,
+                        format_args!(
+                            "Battery low, charging: {}% ({} mV)",
+                            reading.charge_percent, reading.voltage_mv
+                        ),
+                    );
+                    reported = Some(reading.charge_percent);
+                }
+
+                // Without a display, blink the backlight instead.
+                if console.is_none() {
+                    backlight::take_over();
+                    usleep(CHARGE_BLINK_MS * 1000);
+                    backlight::set_on(false);
+                    usleep((CHARGE_POLL_MS - CHARGE_BLINK_MS) * 1000);
+                } else {
+                    usleep(CHARGE_POLL_MS * 1000);
+                }
+            }
+            Decision::PowerOff => {
+                report(
+                    console,
+                    0,
+                    format_args!(
+                        "Battery too low to boot: {}% ({} mV), {:?}",
+                        reading.charge_percent, reading.voltage_mv, charger.state
+                    ),
+                );
+                usleep(POWER_OFF_DELAY_MS * 1000);
+                power::power_off();
+            }
+        }
+    }
+}
+
+/// Waits for a second stage to be uploaded and verified over USB, after neither slot
+/// could be booted.
+///
+/// `error` is the reason the last slot was rejected for, if any slot could be loaded at
+/// all, and is reported to the host.
+fn recover(
+    console: &mut Option<Console<Framebuffer>>,
+    metadata: Metadata,
+    error: BootError,
+) -> Result<Manifest, BootError> {
+    report(console, 0, format_args!("{}", error));
+    report(console, 0, format_args!("Waiting for USB recovery..."));
+
+    // Waiting for the host may take arbitrarily long, so do not leave the last
+    // rejected image in memory in the meantime.
+    boot::clear_loaded();
+    watchdog::disarm();
+
+    let mut usb = attach_usb(&recovery::DESCRIPTORS)?;
+    let manifest = recovery::run(&mut usb, &mut BootTarget::new(metadata, error.code()));
+    usb.detach();
+
+    report(
+        console,
+        100,
+        format_args!("Received second stage over USB."),
+    );
+    Ok(manifest)
+}
+
+/// Serves fastboot over USB until the host boots a verified image.
+fn fastboot_mode(
+    console: &mut Option<Console<Framebuffer>>,
+    emmc: &mut Emmc,
+) -> Result<Manifest, BootError> {
+    report(console, 30, format_args!("Entering fastboot mode..."));
+
+    // Identify the unit to whoever provisions it.
+    if let Some(console) = console {
+        let _ = writeln!(console, "{}", Fuses.identity());
     }
 
-    tegra_gpio!(V, 0).config(gpio::Config::OutputHigh);
-    tegra_gpio!(V, 1).config(gpio::Config::OutputHigh);
+    // Waiting for the host may take arbitrarily long.
+    watchdog::disarm();
+
+    let mut usb = attach_usb(&fastboot::DESCRIPTORS)?;
+    let manifest = fastboot::run(&mut usb, emmc);
+    usb.detach();
+
+    report(
+        console,
+        100,
+        format_args!("Booting second stage from fastboot."),
+    );
+    Ok(manifest)
+}
+
+fn load_second_stage(console: &mut Option<Console<Framebuffer>>) -> Result<(), BootError> {
+    // Load the second-stage bootloader from the best slot that passes verification and
+    // rollback checks.
+    report(console, 0, format_args!("Initializing the eMMC..."));
+    watchdog::checkpoint(Checkpoint::Storage);
+    let mut emmc = Emmc::init()?;
+    emmc.select_partition(Partition::Boot1)?;
+
+    // Holding Volume Down skips the slots in favor of fastboot.
+    let _proven = if fastboot::requested() {
+        fastboot_mode(console, &mut emmc)?;
+        None
+    } else {
+        report(console, 30, format_args!("Loading the second stage..."));
+        watchdog::checkpoint(Checkpoint::Load);
+        let mut rejection = None;
+        let loaded = boot::load_second_stage(&mut emmc, &Fuses, |slot, error| {
+            report(
+                console,
+                30,
+                format_args!("Slot {:?} rejected: {}", slot, error),
+            );
+            rejection = Some(error);
+        });
+
+        match loaded {
+            Ok((slot, manifest)) => {
+                report(
+                    console,
+                    100,
+                    format_args!("Loaded second stage from slot {:?}.", slot),
+                );
 
-    sleep(5);
+                // Only an image that has booted successfully before has proven itself.
+                let metadata = boot::read_metadata(&mut emmc)?;
+                if metadata.slot(slot).successful {
+                    Some(manifest)
+                } else {
+                    None
+                }
+            }
+            Err(boot::Error::NoBootableSlot) => {
+                let metadata = boot::read_metadata(&mut emmc)?;
+                let error = rejection
+                    .unwrap_or_else(|| BootError::new(ErrorKind::NoBootableSlot, Stage::Boot));
+                recover(console, metadata, error)?;
+                None
+            }
+            Err(error) => return Err(error.into()),
+        }
+    };
 
-    tegra_gpio!(V, 0).write(gpio::Level::Low);
+    // Revoke older images once the new one has proven itself, so that a failed update
+    // can still fall back to the other slot. No fuse is burnt after this point.
+    #[cfg(feature = "fuse_burn")]
+    {
+        let advanced = match _proven {
+            Some(manifest) => rollback::advance(&mut Fuses, manifest.header.security_version),
+            None => Ok(()),
+        };
+        fuse::disable_programming();
+        advanced?;
+    }
+
+    Ok(())
 }
 
 fn main() {
@@ -62,6 +340,71 @@ fn main() {
     #[cfg(feature = "debug_uart_port")]
     let _ = writeln!(&mut Uart::E, "[Mirage] Hello!");
 
-    // Bring up backlight for debugging.
-    bring_up_backlight();
+    // Show the boot splash. Booting does not depend on it, so a failure is not fatal.
+    watchdog::checkpoint(Checkpoint::Display);
+    let mut console = match bring_up_display() {
+        Ok(console) => Some(console),
+        Err(_error) => {
+            #[cfg(feature = "debug_uart_port")]
+            let _ = writeln!(&mut Uart::E, "[Mirage] No display: {}", _error);
+            None
+        }
+    };
+
+    if let Some(checkpoint) = watchdog::previous_crash() {
+        report(
+            &mut console,
+            0,
+            format_args!("The previous boot hung at {:?}.", checkpoint),
+        );
+    }
+
+    if let Some(temperature) = thermal::soc_millicelsius() {
+        if temperature >= thermal::LIMIT_CELSIUS * 1000 {
+            report(
+                &mut console,
+                0,
+                format_args!(
+                    "Running hot at {} C, keeping clocks low.",
+                    temperature / 1000
+                ),
+            );
+        }
+    }
+
+    if let Some(rates) = clock::system_rates() {
+        report(
+            &mut console,
+            0,
+            format_args!(
+                "SCLK at {} MHz, HCLK at {} MHz, PCLK at {} MHz.",
+                rates.sclk_hz / 1_000_000,
+                rates.hclk_hz / 1_000_000,
+                rates.pclk_hz / 1_000_000
+            ),
+        );
+    }
+
+    // Record the state of the PLLs for diagnosis.
+    log::with_ring(|ring| {
+        for &pll in [Pll::P, Pll::C, Pll::M, Pll::MB].iter() {
+            let _ = match clock::pll_rate_hz(pll) {
+                Some(rate_hz) => writeln!(ring, "PLL{:?} at {} MHz.", pll, rate_hz / 1_000_000),
+                None => writeln!(ring, "PLL{:?} off.", pll),
+            };
+        }
+    });
+
+    // Compare the pinmux and GPIO state to the board description.
+    #[cfg(feature = "debug_uart_port")]
+    let _ = board::dump(&mut Uart::E);
+    board::diff(|mismatch| report(&mut console, 0, format_args!("{}", mismatch)));
+
+    // Avoid a brownout in the middle of an eMMC write.
+    check_battery(&mut console);
+
+    // A failure ends up in the panic handler, which wipes the blob.
+    if let Err(error) = load_second_stage(&mut console) {
+        error::fatal(error);
+    }
 }
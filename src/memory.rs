@@ -1,9 +1,70 @@
-//! Utility functions assisting with memory manipulation.
+//! Utility functions assisting with memory manipulation, and the layout of IRAM.
+//!
+//! Without DRAM, everything the first stage uses lives in IRAM:
+//!
+//! | Region                    | Contents                                         |
+//! |---------------------------|--------------------------------------------------|
+//! | `0x40000000..0x4000A000`  | The framebuffer of the boot splash               |
+//! | `0x4000C000..0x4000C180`  | The USB queue heads and transfer descriptors     |
+//! | `0x4000D000..0x4000D800`  | The buffer for packets from the USB host         |
+//! | `0x4000D800..0x4000E000`  | The buffer for responses to the USB host         |
+//! | `0x4000F000..0x40010000`  | The stack, as reserved by `link.ld`              |
+//! | `0x40010000..0x40016FE0`  | The first stage itself, bounded by `link.ld`     |
+//! | `0x40016FE0..`            | The second stage followed by its manifest        |
+//!
+//! The regions below the first stage are defined here and checked not to overlap at
+//! compile time. The dead code lint does not see uses in such checks, so the items only
+//! they use are exempt from it.
 //!
 //! Core Requirements for the Rust std are also defined here.
 
 use core::{mem, ops::Range, ptr};
 
+/// The framebuffer of the boot splash, see `display`.
+pub const FRAMEBUFFER: Range<usize> = 0x4000_0000..0x4000_A000;
+
+/// The USB queue heads and transfer descriptors, see `usb`.
+pub const USB_QUEUES: Range<usize> = 0x4000_C000..0x4000_C180;
+
+/// The buffer for packets from the host in USB recovery, see `recovery`.
+pub const PACKET_BUFFER: Range<usize> = 0x4000_D000..0x4000_D800;
+
+/// The buffer for responses to the host in USB recovery, see `recovery`.
+pub const RESPONSE_BUFFER: Range<usize> = 0x4000_D800..0x4000_E000;
+
+/// The stack, as reserved by `link.ld` right below the first stage.
+#[allow(dead_code)]
+pub const STACK: Range<usize> = 0x4000_F000..0x4001_0000;
+
+/// Whether the `regions` are in ascending order and do not overlap.
+#[allow(dead_code)]
+const fn disjoint(regions: &[Range<usize>]) -> bool {
+    let mut i = 0;
+    while i < regions.len() {
+        if regions[i].start > regions[i].end || (i > 0 && regions[i - 1].end > regions[i].start) {
+            return false;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+/// Whether `size` bytes fit into `region`.
+#[allow(dead_code)]
+pub const fn fits(size: usize, region: Range<usize>) -> bool {
+    size <= region.end - region.start
+}
+
+// Fails to compile if any of the regions overlap.
+const _: [(); 1] = [(); disjoint(&[
+    FRAMEBUFFER,
+    USB_QUEUES,
+    PACKET_BUFFER,
+    RESPONSE_BUFFER,
+    STACK,
+]) as usize];
+
 /// Fills the block of memory denoted by the given `range` with `value`.
 pub unsafe fn memset(range: Range<*mut u32>, val: u32) {
     let mut ptr = range.start;
@@ -5,15 +5,19 @@
 use core::fmt::Write;
 use core::panic::PanicInfo;
 
+use libtegra::fuse;
 use libtegra::memory_map::EXCEPTION_VECTORS;
+use libtegra::timer::usleep;
 #[cfg(feature = "debug_uart_port")]
 use libtegra::uart::Uart;
-use libtegra::{bpmp, fuse};
 
-use crate::memory;
 use crate::SECURITY_ENGINE;
+use crate::{backlight, blink, display, error, memory, power, watchdog};
 use crate::{BOOTLOADER_SIZE, BOOTLOADER_START};
 
+/// How often the blink pattern of the error code is shown before halting.
+const BLINK_REPEATS: usize = 5;
+
 extern "C" {
     static mut __stack_start__: u32;
     static mut __stack_end__: u32;
@@ -26,11 +30,34 @@ pub unsafe extern "C" fn panic_handler() -> ! {
     asm!("bl rust_panic_handler", options(noreturn))
 }
 
+/// Flashes the backlight in the blink pattern of the given error `code`.
+///
+/// See the [`blink`] module for details on the encoding.
+///
+/// [`blink`]: ../blink/index.html
+fn blink_error_code(code: u16) {
+    backlight::take_over();
+
+    for _ in 0..BLINK_REPEATS {
+        for pulse in blink::schedule(code) {
+            backlight::set_on(pulse.on);
+            usleep(pulse.duration_ms * 1000);
+        }
+    }
+}
+
 /// Implementation of the panic handler for the bootloader.
 ///
 /// The panic handler is either called when a Rust-side panic is hit through
 /// a more idiomatic wrapper or through the ARM exception vectors which will
 /// be poisoned with a pointer to this function.
+///
+/// After wiping all sensitive state, the code of the fatal [`BootError`] is
+/// flashed on the backlight. Panics and exceptions without one show up as `0`.
+/// Afterwards, the system is rebooted or powered off as the [`PANIC_POLICY`] decides.
+///
+/// [`BootError`]: ../error/struct.BootError.html
+/// [`PANIC_POLICY`]: ../power/constant.PANIC_POLICY.html
 #[no_mangle]
 pub unsafe extern "C" fn rust_panic_handler() -> ! {
     // Reset the stack pointer.
@@ -42,6 +69,9 @@ pub unsafe extern "C" fn rust_panic_handler() -> ! {
     let stack_top: *mut u32 = &mut __stack_start__;
     memory::clear_mem(stack_top..stack_bottom.offset(-1));
 
+    // Stop the watchdog from cutting the error signal short.
+    watchdog::disarm();
+
     // Disable the Security Engine.
     SECURITY_ENGINE.disable();
 
@@ -51,10 +81,12 @@ pub unsafe extern "C" fn rust_panic_handler() -> ! {
     // Clear the second-stage bootloader from memory.
     memory::clear_mem(BOOTLOADER_START..BOOTLOADER_START.add(BOOTLOADER_SIZE));
 
-    // Halt the Boot and Power Management processor.
-    loop {
-        bpmp::halt();
-    }
+    // Signal the error to the user on the display and the backlight.
+    display::show_error_screen();
+    blink_error_code(error::last_error_code().unwrap_or(0));
+
+    // Reboot, power off or halt, depending on the policy.
+    power::handle_failure()
 }
 
 /// The exception handling personality function used by the bootloader.
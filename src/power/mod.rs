@@ -0,0 +1,138 @@
+//! Resetting and powering off the system.
+//!
+//! Besides the plain reset through the PMC, the system can be reset into RCM, the USB
+//! recovery mode of the boot ROM, by setting a flag in a PMC scratch register that
+//! the boot ROM checks, or turned off entirely through the MAX77620 PMIC.
+//!
+//! The panic handler runs one of these as the [`Action`] that the [`policy`] picks
+//! after a fatal error. To keep a device from rebooting in circles, the number of
+//! boots that failed in a row is counted in another PMC scratch register, which
+//! survives resets but not a power-off.
+//!
+//! [`Action`]: policy/enum.Action.html
+//! [`policy`]: policy/index.html
+
+pub mod policy;
+
+use libtegra::i2c::I2c;
+use libtegra::pinmux::{PinGrP, PinTristate};
+use libtegra::{bpmp, gpio, pmc};
+
+use self::policy::{Action, Failure, Policy};
+use crate::error;
+
+#[cfg(all(feature = "panic_halt", feature = "panic_rcm"))]
+compile_error!("The panic_halt and panic_rcm features are mutually exclusive!");
+
+/// The policy that decides what to do after a fatal error.
+pub const PANIC_POLICY: Policy = if cfg!(feature = "panic_halt") {
+    Policy::Fixed(Action::Halt)
+} else if cfg!(feature = "panic_rcm") {
+    Policy::Fixed(Action::RebootToRcm)
+} else {
+    Policy::Auto
+};
+
+const PMC_CNTRL_MAIN_RST: u32 = 1 << 4;
+
+/// The flag in `APBDEV_PMC_SCRATCH0` that makes the boot ROM enter RCM.
+const PMC_SCRATCH0_MODE_RCM: u32 = 1 << 1;
+
+const MAX77620_PWR: u32 = 0x3C;
+const MAX77620_REG_ONOFFCNFG1: u8 = 0x41;
+const ONOFFCNFG1_PWR_OFF: u8 = 1 << 1;
+
+/// Marks the failure count in the scratch register, `PF` in ASCII.
+const FAILURES_MAGIC: u32 = 0x5046_0000;
+
+/// Halts the BPMP for good, leaving the rest of the system powered.
+pub fn halt() -> ! {
+    loop {
+        bpmp::halt();
+    }
+}
+
+/// Resets the whole SoC through the PMC.
+pub fn reboot() -> ! {
+    let pmc = unsafe { &*pmc::REGISTERS };
+    pmc.APBDEV_PMC_CNTRL_0
+        .set(pmc.APBDEV_PMC_CNTRL_0.get() | PMC_CNTRL_MAIN_RST);
+
+    halt()
+}
+
+/// Resets the SoC into RCM.
+pub fn reboot_to_rcm() -> ! {
+    let pmc = unsafe { &*pmc::REGISTERS };
+    pmc.APBDEV_PMC_SCRATCH0_0
+        .set(pmc.APBDEV_PMC_SCRATCH0_0.get() | PMC_SCRATCH0_MODE_RCM);
+
+    reboot()
+}
+
+/// Turns off the system through the PMIC.
+///
+/// Falls back to halting if the PMIC cannot be reached.
+pub fn power_off() -> ! {
+    if let Ok(value) = I2c::C5.read_byte(MAX77620_PWR, MAX77620_REG_ONOFFCNFG1) {
+        let _ = I2c::C5.write_byte(
+            MAX77620_PWR,
+            MAX77620_REG_ONOFFCNFG1,
+            value | ONOFFCNFG1_PWR_OFF,
+        );
+    }
+
+    // Either the power is gone by now or the PMIC did not listen.
+    halt()
+}
+
+/// Checks whether the user asks for RCM by holding Volume Up.
+fn rcm_requested() -> bool {
+    unsafe {
+        PinGrP::ButtonVolUpPx6.set_tristate(PinTristate::Passthrough);
+    }
+    tegra_gpio!(X, 6).config(gpio::Config::Input);
+
+    // The button pulls the line low while it is pressed.
+    tegra_gpio!(X, 6).read() == gpio::Level::Low
+}
+
+/// Counts a failed boot and gets the number of boots that failed in a row before.
+fn record_failure() -> u8 {
+    let pmc = unsafe { &*pmc::REGISTERS };
+
+    let record = pmc.APBDEV_PMC_SCRATCH46_0.get();
+    let failures = if record & 0xFFFF_0000 == FAILURES_MAGIC {
+        record as u8
+    } else {
+        0
+    };
+    pmc.APBDEV_PMC_SCRATCH46_0
+        .set(FAILURES_MAGIC | failures.saturating_add(1) as u32);
+
+    failures
+}
+
+/// Resets the count of boots that failed in a row, once a boot succeeded.
+pub fn clear_failures() {
+    let pmc = unsafe { &*pmc::REGISTERS };
+    pmc.APBDEV_PMC_SCRATCH46_0.set(0);
+}
+
+/// Decides on and carries out the [`Action`] after a fatal error.
+///
+/// [`Action`]: policy/enum.Action.html
+pub fn handle_failure() -> ! {
+    let failure = Failure {
+        error: error::last_error_code(),
+        previous_failures: record_failure(),
+        rcm_requested: rcm_requested(),
+    };
+
+    match policy::decide(PANIC_POLICY, &failure) {
+        Action::Halt => halt(),
+        Action::Reboot => reboot(),
+        Action::RebootToRcm => reboot_to_rcm(),
+        Action::PowerOff => power_off(),
+    }
+}
@@ -0,0 +1,185 @@
+//! The choice of what to do with the system after a fatal error.
+//!
+//! Halting the BPMP forever keeps the rest of the system powered and drains the
+//! battery. After the error was signalled, the panic handler instead asks [`decide`]
+//! for an [`Action`] based on the configured [`Policy`] and the circumstances of the
+//! failure. This module does not access any hardware.
+//!
+//! [`decide`]: fn.decide.html
+//! [`Action`]: enum.Action.html
+//! [`Policy`]: enum.Policy.html
+
+use crate::error::code;
+
+/// The number of consecutive failed boots after which [`Policy::Auto`] stops
+/// rebooting.
+///
+/// [`Policy::Auto`]: enum.Policy.html#variant.Auto
+pub const MAX_REBOOTS: u8 = 3;
+
+/// The categories of [`BootError`] codes that may go away on their own: I2C
+/// failures, timeouts and storage errors.
+///
+/// [`BootError`]: ../../error/struct.BootError.html
+const TRANSIENT_CATEGORIES: [u8; 3] = [code::I2C, code::TIMEOUT, code::STORAGE];
+
+/// What to do with the system after a fatal error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Halt the BPMP and leave the system powered, e.g. to attach a debugger.
+    Halt,
+    /// Reset the whole SoC and boot again.
+    Reboot,
+    /// Reset the SoC into the USB recovery mode of the boot ROM.
+    RebootToRcm,
+    /// Turn off the system through the PMIC.
+    PowerOff,
+}
+
+/// How to pick the [`Action`] after a fatal error.
+///
+/// [`Action`]: enum.Action.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Always take the given action.
+    Fixed(Action),
+    /// Reboot after errors that may be transient, up to [`MAX_REBOOTS`] times in a row,
+    /// and power off otherwise. The user may ask for RCM instead.
+    ///
+    /// [`MAX_REBOOTS`]: constant.MAX_REBOOTS.html
+    Auto,
+}
+
+/// The circumstances of a fatal error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// The code of the fatal [`BootError`], if the failure was caused by one.
+    ///
+    /// [`BootError`]: ../../error/struct.BootError.html
+    pub error: Option<u16>,
+    /// The number of boots that failed in a row before this one.
+    pub previous_failures: u8,
+    /// Whether the user asks for RCM.
+    pub rcm_requested: bool,
+}
+
+impl Failure {
+    /// Whether the failure may go away by booting again.
+    ///
+    /// Panics and exceptions without an error code are assumed to be bugs, which do
+    /// not.
+    fn is_transient(&self) -> bool {
+        match self.error {
+            Some(error) => TRANSIENT_CATEGORIES.contains(&code::category(error)),
+            None => false,
+        }
+    }
+}
+
+/// Decides what to do after `failure` under `policy`.
+pub fn decide(policy: Policy, failure: &Failure) -> Action {
+    match policy {
+        Policy::Fixed(action) => action,
+        Policy::Auto if failure.rcm_requested => Action::RebootToRcm,
+        Policy::Auto if failure.is_transient() && failure.previous_failures < MAX_REBOOTS => {
+            Action::Reboot
+        }
+        Policy::Auto => Action::PowerOff,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A failure with `error` after `previous_failures` failed boots.
+    fn failure(error: Option<u16>, previous_failures: u8) -> Failure {
+        Failure {
+            error,
+            previous_failures,
+            rcm_requested: false,
+        }
+    }
+
+    #[test]
+    fn fixed_policy_ignores_circumstances() {
+        let actions = [
+            Action::Halt,
+            Action::Reboot,
+            Action::RebootToRcm,
+            Action::PowerOff,
+        ];
+        let failures = [
+            failure(None, 0),
+            failure(Some(0x5300), 0),
+            failure(Some(0x6600), MAX_REBOOTS),
+            Failure {
+                rcm_requested: true,
+                ..failure(Some(0x1100), 0)
+            },
+        ];
+
+        for &action in actions.iter() {
+            for failure in failures.iter() {
+                assert_eq!(decide(Policy::Fixed(action), failure), action);
+            }
+        }
+    }
+
+    #[test]
+    fn reboots_after_transient_errors() {
+        // An I2C error, a timeout and a storage error during various stages.
+        for &code in [0x1101, 0x8300, 0x2412].iter() {
+            for previous_failures in 0..MAX_REBOOTS {
+                assert_eq!(
+                    decide(Policy::Auto, &failure(Some(code), previous_failures)),
+                    Action::Reboot,
+                    "{:04X} after {} failures",
+                    code,
+                    previous_failures
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stops_rebooting_after_repeated_failures() {
+        for &previous_failures in [MAX_REBOOTS, MAX_REBOOTS + 1, u8::MAX].iter() {
+            assert_eq!(
+                decide(Policy::Auto, &failure(Some(0x2412), previous_failures)),
+                Action::PowerOff
+            );
+        }
+    }
+
+    #[test]
+    fn reboots_only_for_transient_categories() {
+        for category in 0..0x10 {
+            let error = code::pack(0x5, category, 0x42);
+            let expected = match category {
+                code::I2C | code::TIMEOUT | code::STORAGE => Action::Reboot,
+                _ => Action::PowerOff,
+            };
+
+            assert_eq!(decide(Policy::Auto, &failure(Some(error), 0)), expected);
+        }
+    }
+
+    #[test]
+    fn powers_off_after_panics() {
+        assert_eq!(decide(Policy::Auto, &failure(None, 0)), Action::PowerOff);
+    }
+
+    #[test]
+    fn honors_rcm_requests() {
+        for &error in [None, Some(0x2412), Some(0x3605)].iter() {
+            for &previous_failures in [0, MAX_REBOOTS].iter() {
+                let failure = Failure {
+                    rcm_requested: true,
+                    ..failure(error, previous_failures)
+                };
+                assert_eq!(decide(Policy::Auto, &failure), Action::RebootToRcm);
+            }
+        }
+    }
+}
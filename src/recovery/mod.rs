@@ -0,0 +1,158 @@
+//! USB recovery mode for devices without a bootable slot.
+//!
+//! When both slots fail to load, the first stage enumerates as a vendor-specific USB
+//! device and waits for the host to upload a second stage along with its signed
+//! manifest into the payload area at `BOOTLOADER_START`. The upload has to pass the
+//! same verification and rollback checks as a slot loaded from storage before it may
+//! be booted. See the [`protocol`] module for the wire format.
+//!
+//! [`protocol`]: protocol/index.html
+
+pub mod protocol;
+
+use core::slice;
+
+use manifest::{Manifest, MAX_MANIFEST_SIZE};
+use slot::{Metadata, METADATA_SIZE};
+
+use self::protocol::{Outcome, Session, Target};
+use crate::fuses::Fuses;
+use crate::usb::control::{self, Descriptors};
+use crate::usb::{self, UsbDevice};
+use crate::{boot, log, memory};
+use crate::{BOOTLOADER_SIZE, BOOTLOADER_START};
+
+/// The address of the buffer for packets from the host, see [`memory::PACKET_BUFFER`].
+///
+/// [`memory::PACKET_BUFFER`]: ../memory/constant.PACKET_BUFFER.html
+const PACKET_BUFFER: usize = memory::PACKET_BUFFER.start;
+/// The address of the buffer for responses to the host, see
+/// [`memory::RESPONSE_BUFFER`].
+///
+/// [`memory::RESPONSE_BUFFER`]: ../memory/constant.RESPONSE_BUFFER.html
+const RESPONSE_BUFFER: usize = memory::RESPONSE_BUFFER.start;
+/// The size of the packet and response buffers in bytes.
+const BUFFER_SIZE: usize = 0x800;
+
+// Fails to compile if the buffers outgrow their regions of IRAM.
+const _: [(); 1] = [(); (memory::fits(BUFFER_SIZE, memory::PACKET_BUFFER)
+    && memory::fits(BUFFER_SIZE, memory::RESPONSE_BUFFER)) as usize];
+
+/// The size of the boot state reported by [`GET_STATE`].
+///
+/// [`GET_STATE`]: protocol/constant.GET_STATE.html
+const STATE_SIZE: usize = METADATA_SIZE + 8;
+
+static MANUFACTURER: [u8; 14] = control::string_descriptor(b"Mirage");
+static PRODUCT: [u8; 32] = control::string_descriptor(b"Mirage Recovery");
+static STRINGS: [&[u8]; 3] = [&control::LANGUAGES, &MANUFACTURER, &PRODUCT];
+
+/// The descriptors of the recovery device, which has a single vendor-specific
+/// interface.
+pub static DESCRIPTORS: Descriptors = Descriptors::bulk(0xFF, 0x00, 0x00, &STRINGS);
+
+/// The payload area of the second stage as a recovery target.
+///
+/// The second-stage blob is uploaded to offset 0 and its manifest to offset
+/// `BOOTLOADER_SIZE`, mirroring the layout of a slot.
+pub struct BootTarget {
+    metadata: Metadata,
+    error: u16,
+    manifest: Option<Manifest>,
+}
+
+impl BootTarget {
+    /// Creates a target that reports the slot `metadata` and the code of the `error`
+    /// that caused recovery mode to be entered.
+    pub fn new(metadata: Metadata, error: u16) -> Self {
+        BootTarget {
+            metadata,
+            error,
+            manifest: None,
+        }
+    }
+}
+
+impl Target for BootTarget {
+    fn capacity(&self) -> usize {
+        BOOTLOADER_SIZE + MAX_MANIFEST_SIZE
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        self.manifest = None;
+
+        let area =
+            unsafe { slice::from_raw_parts_mut(BOOTLOADER_START as *mut u8, self.capacity()) };
+        area[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    fn verify(&mut self) -> Result<(), u16> {
+        let manifest = boot::verify_loaded(&Fuses).map_err(|e| e.code())?;
+
+        self.manifest = Some(manifest);
+        Ok(())
+    }
+
+    /// The state consists of the encoded slot metadata, followed by the error code as
+    /// a `u16`, two reserved bytes and the size of the log as a `u32`.
+    fn state(&self, out: &mut [u8]) -> usize {
+        let mut state = [0; STATE_SIZE];
+        state[..METADATA_SIZE].copy_from_slice(&self.metadata.encode());
+        state[METADATA_SIZE..METADATA_SIZE + 2].copy_from_slice(&self.error.to_le_bytes());
+        state[METADATA_SIZE + 4..].copy_from_slice(&(self.log_size() as u32).to_le_bytes());
+
+        let length = STATE_SIZE.min(out.len());
+        out[..length].copy_from_slice(&state[..length]);
+        length
+    }
+
+    fn read_log(&self, offset: usize, out: &mut [u8]) -> usize {
+        log::with_ring(|ring| ring.read(offset, out))
+    }
+
+    fn log_size(&self) -> usize {
+        log::with_ring(|ring| ring.size())
+    }
+}
+
+/// Serves a single recovery session until the host boots a payload or a transfer fails.
+fn serve(usb: &mut UsbDevice, target: &mut BootTarget) -> Result<Manifest, usb::Error> {
+    let packet = unsafe { slice::from_raw_parts_mut(PACKET_BUFFER as *mut u8, BUFFER_SIZE) };
+    let response = unsafe { slice::from_raw_parts_mut(RESPONSE_BUFFER as *mut u8, BUFFER_SIZE) };
+    let mut session = Session::new();
+
+    loop {
+        let length = usb.read(packet)?;
+
+        match session.handle(target, &packet[..length], response) {
+            Outcome::Pending => {}
+            Outcome::Respond(length) => usb.write(&response[..length])?,
+            Outcome::Boot(length) => {
+                usb.write(&response[..length])?;
+
+                // The session only allows booting after a successful verification.
+                if let Some(manifest) = target.manifest {
+                    return Ok(manifest);
+                }
+            }
+        }
+    }
+}
+
+/// Runs recovery mode until the host boots a verified payload, whose manifest is
+/// returned.
+///
+/// A bus reset or a failed transfer ends the current session, so the host has to start
+/// over with a new upload.
+pub fn run(usb: &mut UsbDevice, target: &mut BootTarget) -> Manifest {
+    loop {
+        // Wait for the host to enumerate the device.
+        while !usb.is_configured() {
+            let _ = usb.poll();
+        }
+
+        if let Ok(manifest) = serve(usb, target) {
+            return manifest;
+        }
+    }
+}
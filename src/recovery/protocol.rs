@@ -0,0 +1,589 @@
+//! The framing and dispatch of the USB recovery protocol.
+//!
+//! The host drives the protocol by sending requests to the bulk OUT endpoint, each of
+//! which is answered with a response on the bulk IN endpoint. Both start with a
+//! 16-byte header of little-endian fields:
+//!
+//! | Offset | Request                  | Response                       |
+//! |--------|--------------------------|--------------------------------|
+//! | 0x0    | Magic, `MRCV`            | Magic, `MRCV`                  |
+//! | 0x4    | Command (`u16`)          | Status (`u16`)                 |
+//! | 0x6    | Reserved (`u16`)         | Command being answered (`u16`) |
+//! | 0x8    | Argument (`u32`)         | Value (`u32`)                  |
+//! | 0xC    | Data length (`u32`)      | Data length (`u32`)            |
+//!
+//! Response data directly follows the header in the same transfer. The data of an
+//! [`UPLOAD`] request is sent in transfers of its own after the header, and the
+//! response is only sent once all of it was received.
+//!
+//! This module does not access any hardware. The device side is abstracted behind the
+//! [`Target`] trait, so that a [`Session`] can be driven by anything that exchanges
+//! packets with it, including a simulated host.
+//!
+//! [`UPLOAD`]: constant.UPLOAD.html
+//! [`Target`]: trait.Target.html
+//! [`Session`]: struct.Session.html
+
+/// The magic value at the start of every request and response.
+pub const MAGIC: [u8; 4] = *b"MRCV";
+
+/// The size of a request or response header in bytes.
+pub const HEADER_SIZE: usize = 0x10;
+
+/// Queries the boot state. Responds with the data of [`Target::state`].
+///
+/// [`Target::state`]: trait.Target.html#tymethod.state
+pub const GET_STATE: u16 = 0x1;
+/// Uploads `length` bytes of data to the payload area at offset `argument`.
+pub const UPLOAD: u16 = 0x2;
+/// Verifies the uploaded payload.
+pub const VERIFY: u16 = 0x3;
+/// Boots the verified payload.
+pub const BOOT: u16 = 0x4;
+/// Reads up to `length` bytes of the log, starting at offset `argument`. The value of
+/// the response holds the total size of the log.
+pub const READ_LOG: u16 = 0x5;
+
+/// The request succeeded.
+pub const STATUS_OK: u16 = 0x0;
+/// The request header is malformed.
+pub const STATUS_BAD_REQUEST: u16 = 0x1;
+/// The command is not known.
+pub const STATUS_UNKNOWN_COMMAND: u16 = 0x2;
+/// The upload does not fit into the payload area.
+pub const STATUS_OUT_OF_BOUNDS: u16 = 0x3;
+/// The payload failed verification. The value of the response holds the error code.
+pub const STATUS_VERIFICATION_FAILED: u16 = 0x4;
+/// A payload has to be verified before it can be booted.
+pub const STATUS_NOT_VERIFIED: u16 = 0x5;
+
+/// A decoded request header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub command: u16,
+    pub argument: u32,
+    pub length: u32,
+}
+
+impl Request {
+    /// Decodes a request header from the start of `packet`.
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        if packet.len() < HEADER_SIZE || packet[..4] != MAGIC {
+            return None;
+        }
+
+        let word = |offset: usize| {
+            u32::from_le_bytes([
+                packet[offset],
+                packet[offset + 1],
+                packet[offset + 2],
+                packet[offset + 3],
+            ])
+        };
+
+        Some(Request {
+            command: u16::from_le_bytes([packet[4], packet[5]]),
+            argument: word(0x8),
+            length: word(0xC),
+        })
+    }
+
+    /// Encodes the request header.
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4..6].copy_from_slice(&self.command.to_le_bytes());
+        header[8..12].copy_from_slice(&self.argument.to_le_bytes());
+        header[12..16].copy_from_slice(&self.length.to_le_bytes());
+        header
+    }
+}
+
+/// A decoded response header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub command: u16,
+    pub value: u32,
+    pub length: u32,
+}
+
+impl Response {
+    /// Encodes the response header into the start of `out`.
+    fn encode(&self, out: &mut [u8]) {
+        out[..HEADER_SIZE].copy_from_slice(
+            &Request {
+                command: self.status,
+                argument: self.value,
+                length: self.length,
+            }
+            .encode(),
+        );
+        out[6..8].copy_from_slice(&self.command.to_le_bytes());
+    }
+}
+
+/// The device side of the protocol.
+pub trait Target {
+    /// Gets the size of the payload area in bytes.
+    fn capacity(&self) -> usize;
+
+    /// Writes `data` to the payload area at `offset`, which is always in bounds.
+    fn write(&mut self, offset: usize, data: &[u8]);
+
+    /// Verifies the payload area, returning an error code on failure.
+    fn verify(&mut self) -> Result<(), u16>;
+
+    /// Writes a description of the boot state to `out` and returns its size.
+    fn state(&self, out: &mut [u8]) -> usize;
+
+    /// Copies the log, starting at `offset`, to `out` and returns the number of bytes
+    /// copied.
+    fn read_log(&self, offset: usize, out: &mut [u8]) -> usize;
+
+    /// Gets the total size of the log in bytes.
+    fn log_size(&self) -> usize;
+}
+
+/// What to do after a packet was handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Wait for the next packet without responding.
+    Pending,
+    /// Send the first bytes of the response buffer.
+    Respond(usize),
+    /// Send the first bytes of the response buffer, then boot the verified payload.
+    Boot(usize),
+}
+
+/// An upload whose data is still being received.
+#[derive(Clone, Copy, Debug)]
+struct Upload {
+    offset: usize,
+    remaining: usize,
+    length: u32,
+}
+
+/// The state of a recovery session with the host.
+#[derive(Debug, Default)]
+pub struct Session {
+    upload: Option<Upload>,
+    verified: bool,
+}
+
+impl Session {
+    /// Starts a new session, in which nothing was uploaded yet.
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    /// Handles a `packet` received from the host and writes the response, if any, to
+    /// `response`.
+    ///
+    /// `response` has to be at least [`HEADER_SIZE`] bytes. Response data is truncated
+    /// to the space behind the header.
+    ///
+    /// [`HEADER_SIZE`]: constant.HEADER_SIZE.html
+    pub fn handle<T: Target>(
+        &mut self,
+        target: &mut T,
+        packet: &[u8],
+        response: &mut [u8],
+    ) -> Outcome {
+        if let Some(upload) = self.upload.take() {
+            return self.receive(upload, target, packet, response);
+        }
+
+        let request = match Request::decode(packet) {
+            Some(request) => request,
+            None => return respond(response, STATUS_BAD_REQUEST, 0, 0, 0),
+        };
+        let command = request.command;
+
+        match command {
+            GET_STATE => {
+                let length = target.state(&mut response[HEADER_SIZE..]);
+                respond(response, STATUS_OK, command, 0, length)
+            }
+            UPLOAD => {
+                let offset = request.argument as usize;
+                let length = request.length as usize;
+                if offset
+                    .checked_add(length)
+                    .map_or(true, |end| end > target.capacity())
+                {
+                    return respond(response, STATUS_OUT_OF_BOUNDS, command, 0, 0);
+                }
+
+                // Any change to the payload area invalidates an earlier verification.
+                self.verified = false;
+                if length == 0 {
+                    return respond(response, STATUS_OK, command, 0, 0);
+                }
+
+                self.upload = Some(Upload {
+                    offset,
+                    remaining: length,
+                    length: request.length,
+                });
+                Outcome::Pending
+            }
+            VERIFY => match target.verify() {
+                Ok(()) => {
+                    self.verified = true;
+                    respond(response, STATUS_OK, command, 0, 0)
+                }
+                Err(code) => respond(
+                    response,
+                    STATUS_VERIFICATION_FAILED,
+                    command,
+                    code as u32,
+                    0,
+                ),
+            },
+            BOOT if self.verified => {
+                Outcome::Boot(write_header(response, STATUS_OK, command, 0, 0))
+            }
+            BOOT => respond(response, STATUS_NOT_VERIFIED, command, 0, 0),
+            READ_LOG => {
+                let out = &mut response[HEADER_SIZE..];
+                let max = out.len().min(request.length as usize);
+                let length = target.read_log(request.argument as usize, &mut out[..max]);
+                respond(
+                    response,
+                    STATUS_OK,
+                    command,
+                    target.log_size() as u32,
+                    length,
+                )
+            }
+            _ => respond(response, STATUS_UNKNOWN_COMMAND, command, 0, 0),
+        }
+    }
+
+    /// Handles a `packet` of upload data.
+    fn receive<T: Target>(
+        &mut self,
+        mut upload: Upload,
+        target: &mut T,
+        packet: &[u8],
+        response: &mut [u8],
+    ) -> Outcome {
+        // A host that sends more than it announced has lost track of the protocol, so
+        // the upload is abandoned.
+        if packet.len() > upload.remaining {
+            return respond(response, STATUS_BAD_REQUEST, UPLOAD, 0, 0);
+        }
+
+        target.write(upload.offset, packet);
+        upload.offset += packet.len();
+        upload.remaining -= packet.len();
+
+        if upload.remaining > 0 {
+            self.upload = Some(upload);
+            return Outcome::Pending;
+        }
+
+        respond(response, STATUS_OK, UPLOAD, upload.length, 0)
+    }
+}
+
+/// Writes a response header for `length` bytes of data, which are already in place.
+///
+/// Returns the total size of the response.
+fn write_header(
+    response: &mut [u8],
+    status: u16,
+    command: u16,
+    value: u32,
+    length: usize,
+) -> usize {
+    Response {
+        status,
+        command,
+        value,
+        length: length as u32,
+    }
+    .encode(response);
+
+    HEADER_SIZE + length
+}
+
+fn respond(response: &mut [u8], status: u16, command: u16, value: u32, length: usize) -> Outcome {
+    Outcome::Respond(write_header(response, status, command, value, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The error code reported for payloads that fail verification.
+    const BAD_PAYLOAD: u16 = 0x3605;
+
+    /// A log that does not fit into a single response.
+    const LOG: &[u8] = b"Initializing the eMMC...\nLoading the second stage...\nSlot A rejected.\n";
+
+    /// A device whose payload verifies if it starts with `GOOD`.
+    struct SimulatedTarget {
+        payload: [u8; 64],
+        verifications: usize,
+    }
+
+    impl SimulatedTarget {
+        fn new() -> Self {
+            SimulatedTarget {
+                payload: [0; 64],
+                verifications: 0,
+            }
+        }
+    }
+
+    impl Target for SimulatedTarget {
+        fn capacity(&self) -> usize {
+            self.payload.len()
+        }
+
+        fn write(&mut self, offset: usize, data: &[u8]) {
+            self.payload[offset..offset + data.len()].copy_from_slice(data);
+        }
+
+        fn verify(&mut self) -> Result<(), u16> {
+            self.verifications += 1;
+            if self.payload.starts_with(b"GOOD") {
+                Ok(())
+            } else {
+                Err(BAD_PAYLOAD)
+            }
+        }
+
+        fn state(&self, out: &mut [u8]) -> usize {
+            let state = b"STATE";
+            let length = state.len().min(out.len());
+            out[..length].copy_from_slice(&state[..length]);
+            length
+        }
+
+        fn read_log(&self, offset: usize, out: &mut [u8]) -> usize {
+            let log = LOG.get(offset..).unwrap_or(&[]);
+            let length = log.len().min(out.len());
+            out[..length].copy_from_slice(&log[..length]);
+            length
+        }
+
+        fn log_size(&self) -> usize {
+            LOG.len()
+        }
+    }
+
+    /// The host side of a session with a simulated target.
+    struct Host {
+        session: Session,
+        target: SimulatedTarget,
+        response: [u8; 64],
+    }
+
+    impl Host {
+        fn new() -> Self {
+            Host {
+                session: Session::new(),
+                target: SimulatedTarget::new(),
+                response: [0; 64],
+            }
+        }
+
+        fn send(&mut self, packet: &[u8]) -> Outcome {
+            self.session
+                .handle(&mut self.target, packet, &mut self.response)
+        }
+
+        fn request(&mut self, command: u16, argument: u32, length: u32) -> Outcome {
+            let request = Request {
+                command,
+                argument,
+                length,
+            };
+            self.send(&request.encode())
+        }
+
+        /// Decodes the response header, checking it against `outcome`.
+        fn response(&self, outcome: Outcome) -> Response {
+            let size = match outcome {
+                Outcome::Respond(size) | Outcome::Boot(size) => size,
+                Outcome::Pending => panic!("no response"),
+            };
+            let header = Request::decode(&self.response).unwrap();
+            let response = Response {
+                status: header.command,
+                command: u16::from_le_bytes([self.response[6], self.response[7]]),
+                value: header.argument,
+                length: header.length,
+            };
+            assert_eq!(size, HEADER_SIZE + response.length as usize);
+            response
+        }
+
+        fn data(&self, response: &Response) -> &[u8] {
+            &self.response[HEADER_SIZE..HEADER_SIZE + response.length as usize]
+        }
+
+        /// Sends a request and expects a response with `status` and no data.
+        fn expect(&mut self, command: u16, argument: u32, length: u32, status: u16) -> u32 {
+            let outcome = self.request(command, argument, length);
+            let response = self.response(outcome);
+            assert_eq!(response.status, status);
+            assert_eq!(response.command, command);
+            response.value
+        }
+
+        fn upload(&mut self, offset: u32, data: &[u8], chunk: usize) {
+            assert_eq!(
+                self.request(UPLOAD, offset, data.len() as u32),
+                Outcome::Pending
+            );
+
+            let mut chunks = data.chunks(chunk).peekable();
+            while let Some(chunk) = chunks.next() {
+                let outcome = self.send(chunk);
+                if chunks.peek().is_some() {
+                    assert_eq!(outcome, Outcome::Pending);
+                } else {
+                    let response = self.response(outcome);
+                    assert_eq!(response.status, STATUS_OK);
+                    assert_eq!(response.command, UPLOAD);
+                    assert_eq!(response.value, data.len() as u32);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uploads_verifies_and_boots() {
+        let mut host = Host::new();
+        host.upload(0, b"GOOD second stage", 5);
+        host.upload(32, b"manifest", 64);
+        assert_eq!(&host.target.payload[..17], b"GOOD second stage");
+        assert_eq!(&host.target.payload[32..40], b"manifest");
+
+        host.expect(VERIFY, 0, 0, STATUS_OK);
+
+        let outcome = host.request(BOOT, 0, 0);
+        assert!(matches!(outcome, Outcome::Boot(_)));
+        assert_eq!(host.response(outcome).status, STATUS_OK);
+    }
+
+    #[test]
+    fn refuses_to_boot_unverified_payload() {
+        let mut host = Host::new();
+        host.expect(BOOT, 0, 0, STATUS_NOT_VERIFIED);
+
+        host.upload(0, b"BAD!", 4);
+        assert_eq!(
+            host.expect(VERIFY, 0, 0, STATUS_VERIFICATION_FAILED),
+            BAD_PAYLOAD as u32
+        );
+        host.expect(BOOT, 0, 0, STATUS_NOT_VERIFIED);
+    }
+
+    #[test]
+    fn upload_invalidates_verification() {
+        let mut host = Host::new();
+        host.upload(0, b"GOOD", 4);
+        host.expect(VERIFY, 0, 0, STATUS_OK);
+
+        // Even an empty upload counts as a change.
+        host.expect(UPLOAD, 0, 0, STATUS_OK);
+        host.expect(BOOT, 0, 0, STATUS_NOT_VERIFIED);
+
+        host.upload(4, b"more", 4);
+        host.expect(BOOT, 0, 0, STATUS_NOT_VERIFIED);
+        assert_eq!(host.target.verifications, 1);
+    }
+
+    #[test]
+    fn rejects_uploads_out_of_bounds() {
+        let mut host = Host::new();
+        host.expect(UPLOAD, 60, 5, STATUS_OUT_OF_BOUNDS);
+        host.expect(UPLOAD, 65, 0, STATUS_OUT_OF_BOUNDS);
+        host.expect(UPLOAD, u32::MAX, 2, STATUS_OUT_OF_BOUNDS);
+
+        // The full payload area is fine.
+        host.upload(0, &[0xAA; 64], 16);
+    }
+
+    #[test]
+    fn abandons_upload_on_excess_data() {
+        let mut host = Host::new();
+        assert_eq!(host.request(UPLOAD, 0, 4), Outcome::Pending);
+
+        let outcome = host.send(b"GOOD!");
+        let response = host.response(outcome);
+        assert_eq!(response.status, STATUS_BAD_REQUEST);
+        assert_eq!(response.command, UPLOAD);
+        assert_eq!(host.target.payload[0], 0);
+
+        // The session is back to handling requests.
+        host.expect(BOOT, 0, 0, STATUS_NOT_VERIFIED);
+    }
+
+    #[test]
+    fn upload_data_is_not_parsed_as_request() {
+        let mut host = Host::new();
+        let boot = Request {
+            command: BOOT,
+            argument: 0,
+            length: 0,
+        }
+        .encode();
+
+        host.upload(0, &boot, HEADER_SIZE);
+        assert_eq!(host.target.payload[..HEADER_SIZE], boot);
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let mut host = Host::new();
+
+        let mut request = Request {
+            command: GET_STATE,
+            argument: 0,
+            length: 0,
+        }
+        .encode();
+        let outcome = host.send(&request[..HEADER_SIZE - 1]);
+        assert_eq!(host.response(outcome).status, STATUS_BAD_REQUEST);
+
+        request[0] = b'X';
+        let outcome = host.send(&request);
+        assert_eq!(host.response(outcome).status, STATUS_BAD_REQUEST);
+
+        host.expect(0x7F, 0, 0, STATUS_UNKNOWN_COMMAND);
+    }
+
+    #[test]
+    fn reports_state() {
+        let mut host = Host::new();
+        let outcome = host.request(GET_STATE, 0, 0);
+        let response = host.response(outcome);
+
+        assert_eq!(response.status, STATUS_OK);
+        assert_eq!(host.data(&response), b"STATE");
+    }
+
+    #[test]
+    fn reads_log_in_chunks() {
+        let mut host = Host::new();
+        let outcome = host.request(READ_LOG, 0, 8);
+        let response = host.response(outcome);
+        assert_eq!(response.value, LOG.len() as u32);
+        assert_eq!(host.data(&response), &LOG[..8]);
+
+        let outcome = host.request(READ_LOG, 8, 0x1000);
+        let response = host.response(outcome);
+        assert_eq!(host.data(&response), &LOG[8..8 + 64 - HEADER_SIZE]);
+
+        let outcome = host.request(READ_LOG, LOG.len() as u32, 8);
+        let response = host.response(outcome);
+        assert_eq!(response.length, 0);
+        assert_eq!(response.value, LOG.len() as u32);
+    }
+}
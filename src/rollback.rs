@@ -0,0 +1,257 @@
+//! Rollback protection based on a version counter in the ODM reserved fuses.
+//!
+//! The counter is stored as a thermometer code: version `n` is represented by the
+//! lowest `n` bits of the counter words being burnt. Since fuses can only ever be
+//! burnt and never cleared, the counter can only move forwards.
+//!
+//! With the `fuse_burn` feature, the counter is advanced to the security version of a
+//! slot only once that slot has booted successfully. Until then, the other slot stays
+//! bootable, so that a failed update can still fall back to it.
+
+#[cfg(feature = "fuse_burn")]
+use crate::fuses::FuseProgrammer;
+use crate::fuses::{self, FuseArray};
+
+/// The first ODM reserved fuse word that holds the counter.
+pub const COUNTER_FIRST_WORD: usize = 6;
+
+/// The number of ODM reserved fuse words that hold the counter.
+pub const COUNTER_WORDS: usize = 2;
+
+/// The highest security version the counter can represent.
+#[cfg(feature = "fuse_burn")]
+pub const MAX_VERSION: u32 = COUNTER_WORDS as u32 * 32;
+
+/// Errors that may occur during the rollback check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The image is older than the version recorded in the fuses.
+    Rollback {
+        /// The security version of the image.
+        image: u32,
+        /// The security version recorded in the fuses.
+        fused: u32,
+    },
+    /// The security version exceeds what the counter can represent.
+    #[cfg(feature = "fuse_burn")]
+    VersionTooLarge,
+    /// Accessing the fuses failed.
+    Fuse(fuses::Error),
+}
+
+impl From<fuses::Error> for Error {
+    fn from(error: fuses::Error) -> Self {
+        Error::Fuse(error)
+    }
+}
+
+/// Decodes the security version from the raw counter words.
+///
+/// The version is derived from the highest burnt bit, so a single bit that failed to
+/// burn in the middle of the counter cannot be used to lower the version.
+pub fn decode(words: &[u32; COUNTER_WORDS]) -> u32 {
+    words
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, word)| **word != 0)
+        .map(|(index, word)| index as u32 * 32 + (32 - word.leading_zeros()))
+        .unwrap_or(0)
+}
+
+/// Encodes a security version into the raw counter words.
+#[cfg(feature = "fuse_burn")]
+pub fn encode(version: u32) -> Result<[u32; COUNTER_WORDS], Error> {
+    if version > MAX_VERSION {
+        return Err(Error::VersionTooLarge);
+    }
+
+    let mut words = [0; COUNTER_WORDS];
+    for (index, word) in words.iter_mut().enumerate() {
+        let bits = version.saturating_sub(index as u32 * 32).min(32);
+        *word = if bits == 32 { !0 } else { (1 << bits) - 1 };
+    }
+    Ok(words)
+}
+
+fn read_counter<F: FuseArray>(fuses: &F) -> Result<[u32; COUNTER_WORDS], Error> {
+    let mut words = [0; COUNTER_WORDS];
+    for (index, word) in words.iter_mut().enumerate() {
+        *word = fuses.read_odm(COUNTER_FIRST_WORD + index)?;
+    }
+    Ok(words)
+}
+
+/// Reads the security version recorded in the fuses.
+pub fn fused_version<F: FuseArray>(fuses: &F) -> Result<u32, Error> {
+    read_counter(fuses).map(|words| decode(&words))
+}
+
+/// Checks that an image with the given security version may be booted.
+pub fn check<F: FuseArray>(fuses: &F, version: u32) -> Result<(), Error> {
+    let fused = fused_version(fuses)?;
+    if version < fused {
+        return Err(Error::Rollback {
+            image: version,
+            fused,
+        });
+    }
+
+    Ok(())
+}
+
+/// Advances the fused security version to `version`, permanently preventing any
+/// older image from being booted on this device.
+///
+/// Only the bits that are not burnt yet are programmed, and the counter is read back
+/// afterwards to make sure the new version actually took effect. Versions at or
+/// below the current one leave the fuses untouched.
+#[cfg(feature = "fuse_burn")]
+pub fn advance<F: FuseProgrammer>(fuses: &mut F, version: u32) -> Result<(), Error> {
+    let target = encode(version)?;
+    let current = read_counter(fuses)?;
+    if version <= decode(&current) {
+        return Ok(());
+    }
+
+    for (index, (target, current)) in target.iter().zip(current.iter()).enumerate() {
+        let mask = target & !current;
+        if mask != 0 {
+            fuses.burn_odm(COUNTER_FIRST_WORD + index, mask)?;
+        }
+    }
+
+    if fused_version(fuses)? < version {
+        return Err(Error::Fuse(fuses::Error::VerifyFailed));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuses::simulated::SimulatedFuses;
+
+    fn fuses(counter: [u32; COUNTER_WORDS]) -> SimulatedFuses {
+        let mut fuses = SimulatedFuses::default();
+        fuses.odm[COUNTER_FIRST_WORD..COUNTER_FIRST_WORD + COUNTER_WORDS].copy_from_slice(&counter);
+        fuses
+    }
+
+    #[test]
+    fn decoding() {
+        assert_eq!(decode(&[0, 0]), 0);
+        assert_eq!(decode(&[0b1, 0]), 1);
+        assert_eq!(decode(&[0b111, 0]), 3);
+        assert_eq!(decode(&[!0, 0]), 32);
+        assert_eq!(decode(&[!0, 0b1]), 33);
+        assert_eq!(decode(&[!0, !0]), 64);
+    }
+
+    #[test]
+    fn decoding_uses_highest_bit() {
+        // A bit that failed to burn below the highest one does not lower the version.
+        assert_eq!(decode(&[0b1011, 0]), 4);
+        assert_eq!(decode(&[0, 0b1]), 33);
+        assert_eq!(decode(&[0b1, 1 << 31]), 64);
+    }
+
+    #[test]
+    fn check_versions() {
+        let fuses = fuses([0b111, 0]);
+
+        assert_eq!(fused_version(&fuses), Ok(3));
+        assert_eq!(check(&fuses, 3), Ok(()));
+        assert_eq!(check(&fuses, 4), Ok(()));
+        assert_eq!(
+            check(&fuses, 2),
+            Err(Error::Rollback { image: 2, fused: 3 })
+        );
+        assert_eq!(
+            check(&fuses, 0),
+            Err(Error::Rollback { image: 0, fused: 3 })
+        );
+    }
+
+    #[test]
+    fn unfused_counter() {
+        let fuses = SimulatedFuses::default();
+
+        assert_eq!(fused_version(&fuses), Ok(0));
+        assert_eq!(check(&fuses, 0), Ok(()));
+    }
+
+    #[cfg(feature = "fuse_burn")]
+    #[test]
+    fn encoding() {
+        assert_eq!(encode(0), Ok([0, 0]));
+        assert_eq!(encode(1), Ok([0b1, 0]));
+        assert_eq!(encode(31), Ok([0x7FFF_FFFF, 0]));
+        assert_eq!(encode(32), Ok([!0, 0]));
+        assert_eq!(encode(33), Ok([!0, 0b1]));
+        assert_eq!(encode(MAX_VERSION), Ok([!0, !0]));
+        assert_eq!(encode(MAX_VERSION + 1), Err(Error::VersionTooLarge));
+
+        for version in 0..=MAX_VERSION {
+            assert_eq!(decode(&encode(version).unwrap()), version);
+        }
+    }
+
+    #[cfg(feature = "fuse_burn")]
+    #[test]
+    fn advancing() {
+        let mut fuses = fuses([0b111, 0]);
+
+        assert_eq!(advance(&mut fuses, 34), Ok(()));
+        assert_eq!(fuses.odm[COUNTER_FIRST_WORD..][..COUNTER_WORDS], [!0, 0b11]);
+        assert_eq!(fused_version(&fuses), Ok(34));
+        assert_eq!(fuses.burns, 2);
+
+        // Only the word with new bits is burnt.
+        assert_eq!(advance(&mut fuses, 35), Ok(()));
+        assert_eq!(fuses.burns, 3);
+        assert_eq!(
+            check(&fuses, 34),
+            Err(Error::Rollback {
+                image: 34,
+                fused: 35
+            })
+        );
+    }
+
+    #[cfg(feature = "fuse_burn")]
+    #[test]
+    fn advancing_never_lowers() {
+        let mut fuses = fuses([0b1111, 0]);
+
+        assert_eq!(advance(&mut fuses, 4), Ok(()));
+        assert_eq!(advance(&mut fuses, 2), Ok(()));
+        assert_eq!(fused_version(&fuses), Ok(4));
+        assert_eq!(fuses.burns, 0);
+    }
+
+    #[cfg(feature = "fuse_burn")]
+    #[test]
+    fn advancing_fails() {
+        let mut fuses = SimulatedFuses::default();
+        assert_eq!(
+            advance(&mut fuses, MAX_VERSION + 1),
+            Err(Error::VersionTooLarge)
+        );
+        assert_eq!(fuses.burns, 0);
+
+        // The highest bit does not burn, so the counter falls short.
+        fuses.stuck = 1 << 4;
+        assert_eq!(
+            advance(&mut fuses, 5),
+            Err(Error::Fuse(fuses::Error::VerifyFailed))
+        );
+
+        fuses.disabled = true;
+        assert_eq!(
+            advance(&mut fuses, 8),
+            Err(Error::Fuse(fuses::Error::ProgrammingDisabled))
+        );
+    }
+}
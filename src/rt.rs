@@ -38,12 +38,23 @@ macro_rules! entrypoint {
             // Execute the .init_array methods of the binary.
             $crate::rt::call_init_array();
 
+            // Arm the watchdog, so that a hang from here on resets the system.
+            $crate::watchdog::arm();
+
             // Initialize the hardware.
-            $crate::init::init_hardware().expect("Failed to initialize the hardware!");
+            if let Err(error) = $crate::init::init_hardware() {
+                $crate::error::fatal(error);
+            }
 
             // Jump to the real Rust entrypoint.
             func();
 
+            // The boot succeeded, so forget about earlier failures.
+            $crate::power::clear_failures();
+
+            // Pass the watchdog on to the next stage.
+            $crate::watchdog::hand_over();
+
             // Execute the .fini_array methods of the binary.
             $crate::rt::call_fini_array();
         }
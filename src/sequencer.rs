@@ -0,0 +1,755 @@
+//! An engine for running verified register sequences on I2C devices.
+//!
+//! Power sequencing involves a series of register writes, polls and settle delays
+//! that must either complete as a whole or not at all, as a half-configured PMIC may
+//! leave the rails in an unsafe state. The [`run`] function executes a list of
+//! [`Step`]s with per-step retries and restores every register it touched in reverse
+//! order when a step ultimately fails. Writes are only read back for steps that opt
+//! into it with [`Step::verified`], e.g. for rail voltages.
+//!
+//! [`run`]: fn.run.html
+//! [`Step`]: struct.Step.html
+//! [`Step::verified`]: struct.Step.html#method.verified
+
+use crate::deadline::{Clock, Deadline};
+
+/// The maximum number of registers a single sequence may modify.
+const MAX_UNDO: usize = 32;
+
+/// The default number of retries for each step.
+const DEFAULT_RETRIES: u8 = 3;
+
+/// The delay between retries of a failed step, in microseconds.
+const RETRY_DELAY_US: u32 = 100;
+
+/// The interval in which wait steps poll their register, in microseconds.
+const POLL_INTERVAL_US: u32 = 10;
+
+/// Byte-wise register access to devices on an I2C bus.
+pub trait I2cBus {
+    /// The error type reported by the bus.
+    type Error;
+
+    /// Reads the register `register` of the device at `device`.
+    fn read_byte(&mut self, device: u32, register: u8) -> Result<u8, Self::Error>;
+
+    /// Writes `value` to the register `register` of the device at `device`.
+    fn write_byte(&mut self, device: u32, register: u8, value: u8) -> Result<(), Self::Error>;
+
+    /// Reads the 16-bit little-endian register `register` of the device at `device`.
+    fn read_word(&mut self, device: u32, register: u8) -> Result<u16, Self::Error>;
+}
+
+/// A source of busy-wait delays.
+pub trait Delay {
+    /// Blocks for at least `us` microseconds.
+    fn delay_us(&self, us: u32);
+}
+
+/// The operation carried out by a [`Step`].
+///
+/// [`Step`]: struct.Step.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Writes `value` to a register.
+    Write {
+        device: u32,
+        register: u8,
+        value: u8,
+    },
+    /// Replaces the bits in `mask` of a register with those of `value`.
+    Modify {
+        device: u32,
+        register: u8,
+        mask: u8,
+        value: u8,
+    },
+    /// Polls a register until the bits in `mask` equal those of `value`.
+    #[allow(dead_code)]
+    WaitFor {
+        device: u32,
+        register: u8,
+        mask: u8,
+        value: u8,
+        timeout_us: u32,
+    },
+    /// Waits for `us` microseconds, e.g. to let a rail settle.
+    Delay { us: u32 },
+}
+
+impl Action {
+    /// Gets the device and register the action operates on, if any.
+    pub fn target(&self) -> Option<(u32, u8)> {
+        match *self {
+            Action::Write {
+                device, register, ..
+            }
+            | Action::Modify {
+                device, register, ..
+            }
+            | Action::WaitFor {
+                device, register, ..
+            } => Some((device, register)),
+            Action::Delay { .. } => None,
+        }
+    }
+}
+
+/// A single step of a register sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// The operation to carry out.
+    pub action: Action,
+    /// How often the step is retried before the sequence is aborted.
+    pub retries: u8,
+    /// Whether a written register is read back to verify the write.
+    pub verify: bool,
+}
+
+impl Step {
+    /// Creates a write step.
+    pub const fn write(device: u32, register: u8, value: u8) -> Self {
+        Step {
+            action: Action::Write {
+                device,
+                register,
+                value,
+            },
+            retries: DEFAULT_RETRIES,
+            verify: false,
+        }
+    }
+
+    /// Creates a read-modify-write step.
+    pub const fn modify(device: u32, register: u8, mask: u8, value: u8) -> Self {
+        Step {
+            action: Action::Modify {
+                device,
+                register,
+                mask,
+                value,
+            },
+            retries: DEFAULT_RETRIES,
+            verify: false,
+        }
+    }
+
+    /// Creates a step that waits for register bits to reach a value.
+    #[allow(dead_code)]
+    pub const fn wait_for(device: u32, register: u8, mask: u8, value: u8, timeout_us: u32) -> Self {
+        Step {
+            action: Action::WaitFor {
+                device,
+                register,
+                mask,
+                value,
+                timeout_us,
+            },
+            retries: 0,
+            verify: false,
+        }
+    }
+
+    /// Creates a delay step.
+    pub const fn delay(us: u32) -> Self {
+        Step {
+            action: Action::Delay { us },
+            retries: 0,
+            verify: false,
+        }
+    }
+
+    /// Reads the register back after writing it and fails the step if it does not
+    /// hold the written value. Has no effect on wait and delay steps.
+    pub const fn verified(self) -> Self {
+        Step {
+            verify: true,
+            ..self
+        }
+    }
+}
+
+/// The reason a step failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepError<E> {
+    /// The bus reported an error.
+    Bus(E),
+    /// Reading back a written register yielded a different value.
+    Mismatch { expected: u8, actual: u8 },
+    /// A wait step did not observe the expected value in time.
+    Timeout,
+    /// The sequence modifies more registers than can be restored.
+    TooManyRegisters,
+}
+
+/// Describes which step of a sequence failed and why.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Failure<E> {
+    /// The index of the failing step in the sequence.
+    pub step: usize,
+    /// The error of the last attempt at the step.
+    pub error: StepError<E>,
+    /// Whether all modified registers were successfully restored.
+    pub rolled_back: bool,
+}
+
+/// The original value of a register modified by a sequence.
+#[derive(Clone, Copy)]
+struct Saved {
+    device: u32,
+    register: u8,
+    value: u8,
+}
+
+/// Tracks the original values of all registers touched by a sequence.
+struct UndoLog {
+    entries: [Saved; MAX_UNDO],
+    len: usize,
+}
+
+impl UndoLog {
+    fn contains(&self, device: u32, register: u8) -> bool {
+        self.entries[..self.len]
+            .iter()
+            .any(|saved| saved.device == device && saved.register == register)
+    }
+
+    fn push<E>(&mut self, device: u32, register: u8, value: u8) -> Result<(), StepError<E>> {
+        if self.contains(device, register) {
+            return Ok(());
+        }
+        if self.len == MAX_UNDO {
+            return Err(StepError::TooManyRegisters);
+        }
+
+        self.entries[self.len] = Saved {
+            device,
+            register,
+            value,
+        };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Restores all saved registers in reverse order, returning whether every
+    /// register was restored.
+    fn restore<B: I2cBus>(&self, bus: &mut B) -> bool {
+        self.entries[..self.len]
+            .iter()
+            .rev()
+            .fold(true, |ok, saved| {
+                bus.write_byte(saved.device, saved.register, saved.value)
+                    .is_ok()
+                    && ok
+            })
+    }
+}
+
+fn write_register<B: I2cBus>(
+    bus: &mut B,
+    undo: &mut UndoLog,
+    device: u32,
+    register: u8,
+    mask: u8,
+    value: u8,
+    verify: bool,
+) -> Result<(), StepError<B::Error>> {
+    let original = bus.read_byte(device, register).map_err(StepError::Bus)?;
+    undo.push(device, register, original)?;
+
+    let expected = (original & !mask) | (value & mask);
+    bus.write_byte(device, register, expected)
+        .map_err(StepError::Bus)?;
+    if !verify {
+        return Ok(());
+    }
+
+    let actual = bus.read_byte(device, register).map_err(StepError::Bus)?;
+    if actual != expected {
+        return Err(StepError::Mismatch { expected, actual });
+    }
+
+    Ok(())
+}
+
+fn execute<B: I2cBus, T: Delay + Clock>(
+    bus: &mut B,
+    timer: &T,
+    undo: &mut UndoLog,
+    step: &Step,
+) -> Result<(), StepError<B::Error>> {
+    match step.action {
+        Action::Write {
+            device,
+            register,
+            value,
+        } => write_register(bus, undo, device, register, 0xFF, value, step.verify),
+        Action::Modify {
+            device,
+            register,
+            mask,
+            value,
+        } => write_register(bus, undo, device, register, mask, value, step.verify),
+        Action::WaitFor {
+            device,
+            register,
+            mask,
+            value,
+            timeout_us,
+        } => {
+            let deadline = Deadline::new(timer, timeout_us);
+            loop {
+                let expired = deadline.expired();
+                let current = bus.read_byte(device, register).map_err(StepError::Bus)?;
+                if current & mask == value & mask {
+                    return Ok(());
+                }
+                if expired {
+                    return Err(StepError::Timeout);
+                }
+
+                timer.delay_us(POLL_INTERVAL_US);
+            }
+        }
+        Action::Delay { us } => {
+            timer.delay_us(us);
+            Ok(())
+        }
+    }
+}
+
+/// Runs the given sequence of `steps`.
+///
+/// Each step is retried as often as it specifies. If a step still fails after that,
+/// all registers modified by the sequence so far are restored to their original
+/// values in reverse order and the failing step is reported. Wait steps give up once
+/// their timeout has passed on the clock of `timer`.
+pub fn run<B: I2cBus, T: Delay + Clock>(
+    bus: &mut B,
+    timer: &T,
+    steps: &[Step],
+) -> Result<(), Failure<B::Error>> {
+    let mut undo = UndoLog {
+        entries: [Saved {
+            device: 0,
+            register: 0,
+            value: 0,
+        }; MAX_UNDO],
+        len: 0,
+    };
+
+    for (index, step) in steps.iter().enumerate() {
+        let mut attempt = 0;
+        while let Err(error) = execute(bus, timer, &mut undo, step) {
+            if attempt >= step.retries {
+                return Err(Failure {
+                    step: index,
+                    error,
+                    rolled_back: undo.restore(bus),
+                });
+            }
+
+            attempt += 1;
+            timer.delay_us(RETRY_DELAY_US);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::vec::Vec;
+
+    use super::*;
+
+    const DEVICE: u32 = 0x3C;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Nak;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Transfer {
+        Read(u8),
+        Write(u8, u8),
+    }
+
+    /// A single I2C device that NAKs on demand.
+    struct SimulatedBus {
+        registers: [u8; 256],
+        /// The successful transfers, in order.
+        transfers: Vec<Transfer>,
+        /// The register whose writes are NAKed, and how often.
+        nak_writes: Option<(u8, usize)>,
+        /// The number of transfers after which the device stops responding.
+        lost_after: Option<usize>,
+        /// The register bits that cannot be set.
+        stuck: Option<(u8, u8)>,
+        /// The register that takes a value after being polled a number of times.
+        settles: Option<(u8, usize, u8)>,
+    }
+
+    impl SimulatedBus {
+        fn new() -> Self {
+            let mut registers = [0; 256];
+            for (register, value) in registers.iter_mut().enumerate() {
+                *value = !(register as u8);
+            }
+
+            SimulatedBus {
+                registers,
+                transfers: Vec::new(),
+                nak_writes: None,
+                lost_after: None,
+                stuck: None,
+                settles: None,
+            }
+        }
+
+        fn writes(&self) -> Vec<(u8, u8)> {
+            self.transfers
+                .iter()
+                .filter_map(|transfer| match *transfer {
+                    Transfer::Write(register, value) => Some((register, value)),
+                    Transfer::Read(_) => None,
+                })
+                .collect()
+        }
+
+        fn respond(&mut self, device: u32) -> Result<(), Nak> {
+            match self.lost_after {
+                _ if device != DEVICE => Err(Nak),
+                Some(0) => Err(Nak),
+                Some(ref mut left) => {
+                    *left -= 1;
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        }
+    }
+
+    impl I2cBus for SimulatedBus {
+        type Error = Nak;
+
+        fn read_byte(&mut self, device: u32, register: u8) -> Result<u8, Nak> {
+            self.respond(device)?;
+            self.transfers.push(Transfer::Read(register));
+
+            if let Some((settling, ref mut polls, value)) = self.settles {
+                if settling == register {
+                    if *polls == 0 {
+                        self.registers[register as usize] = value;
+                    } else {
+                        *polls -= 1;
+                    }
+                }
+            }
+
+            Ok(self.registers[register as usize])
+        }
+
+        fn write_byte(&mut self, device: u32, register: u8, value: u8) -> Result<(), Nak> {
+            self.respond(device)?;
+            if let Some((nak, ref mut count)) = self.nak_writes {
+                if nak == register && *count > 0 {
+                    *count -= 1;
+                    return Err(Nak);
+                }
+            }
+            self.transfers.push(Transfer::Write(register, value));
+
+            let stuck = match self.stuck {
+                Some((stuck, bits)) if stuck == register => bits,
+                _ => 0,
+            };
+            self.registers[register as usize] = value & !stuck;
+            Ok(())
+        }
+
+        fn read_word(&mut self, device: u32, register: u8) -> Result<u16, Nak> {
+            let low = self.read_byte(device, register)?;
+            let high = self.read_byte(device, register.wrapping_add(1))?;
+            Ok(u16::from_le_bytes([low, high]))
+        }
+    }
+
+    /// Records the delays instead of waiting, and serves as a clock that advances by
+    /// the recorded delays.
+    #[derive(Default)]
+    struct RecordedDelay {
+        total_us: Cell<u32>,
+        /// The time that passes on every read of the clock, e.g. for slow transfers.
+        drift_us: u32,
+        now_us: Cell<u32>,
+    }
+
+    impl Delay for RecordedDelay {
+        fn delay_us(&self, us: u32) {
+            self.total_us.set(self.total_us.get() + us);
+            self.now_us.set(self.now_us.get() + us);
+        }
+    }
+
+    impl Clock for RecordedDelay {
+        fn now_us(&self) -> u32 {
+            self.now_us.set(self.now_us.get() + self.drift_us);
+            self.now_us.get()
+        }
+    }
+
+    const SEQUENCE: [Step; 5] = [
+        Step::write(DEVICE, 0x10, 0x11),
+        Step::modify(DEVICE, 0x20, 0x0F, 0x05),
+        Step::write(DEVICE, 0x30, 0x33),
+        Step::delay(1000),
+        Step::write(DEVICE, 0x40, 0x44),
+    ];
+
+    #[test]
+    fn applies_sequence() {
+        let mut bus = SimulatedBus::new();
+        let delay = RecordedDelay::default();
+
+        assert_eq!(run(&mut bus, &delay, &SEQUENCE), Ok(()));
+        assert_eq!(bus.registers[0x10], 0x11);
+        assert_eq!(bus.registers[0x20], 0xD5);
+        assert_eq!(bus.registers[0x30], 0x33);
+        assert_eq!(bus.registers[0x40], 0x44);
+        assert_eq!(delay.total_us.get(), 1000);
+    }
+
+    #[test]
+    fn reads_back_verified_steps_only() {
+        let mut bus = SimulatedBus::new();
+        let steps = [
+            Step::write(DEVICE, 0x10, 0x11),
+            Step::write(DEVICE, 0x20, 0x22).verified(),
+        ];
+
+        assert_eq!(run(&mut bus, &RecordedDelay::default(), &steps), Ok(()));
+        assert_eq!(
+            bus.transfers,
+            [
+                Transfer::Read(0x10),
+                Transfer::Write(0x10, 0x11),
+                Transfer::Read(0x20),
+                Transfer::Write(0x20, 0x22),
+                Transfer::Read(0x20),
+            ]
+        );
+    }
+
+    #[test]
+    fn nak_mid_sequence_restores_in_reverse_order() {
+        let mut bus = SimulatedBus::new();
+        bus.nak_writes = Some((0x30, usize::MAX));
+        let delay = RecordedDelay::default();
+
+        assert_eq!(
+            run(&mut bus, &delay, &SEQUENCE),
+            Err(Failure {
+                step: 2,
+                error: StepError::Bus(Nak),
+                rolled_back: false,
+            })
+        );
+        assert_eq!(
+            delay.total_us.get(),
+            DEFAULT_RETRIES as u32 * RETRY_DELAY_US
+        );
+
+        // The failing register is restored too, as the NAKed write may have landed.
+        assert_eq!(
+            bus.writes(),
+            [(0x10, 0x11), (0x20, 0xD5), (0x20, 0xDF), (0x10, 0xEF)]
+        );
+        assert_eq!(bus.registers[0x10], 0xEF);
+        assert_eq!(bus.registers[0x20], 0xDF);
+        assert_eq!(bus.registers[0x40], 0xBF);
+    }
+
+    #[test]
+    fn transient_nak_mid_sequence_is_rolled_back() {
+        let mut bus = SimulatedBus::new();
+        bus.nak_writes = Some((0x30, DEFAULT_RETRIES as usize + 1));
+
+        assert_eq!(
+            run(&mut bus, &RecordedDelay::default(), &SEQUENCE),
+            Err(Failure {
+                step: 2,
+                error: StepError::Bus(Nak),
+                rolled_back: true,
+            })
+        );
+        assert_eq!(
+            bus.writes(),
+            [
+                (0x10, 0x11),
+                (0x20, 0xD5),
+                (0x30, 0xCF),
+                (0x20, 0xDF),
+                (0x10, 0xEF)
+            ]
+        );
+    }
+
+    #[test]
+    fn retries_failed_steps() {
+        let mut bus = SimulatedBus::new();
+        bus.nak_writes = Some((0x30, DEFAULT_RETRIES as usize));
+        let delay = RecordedDelay::default();
+
+        assert_eq!(run(&mut bus, &delay, &SEQUENCE), Ok(()));
+        assert_eq!(bus.registers[0x30], 0x33);
+        assert_eq!(
+            delay.total_us.get(),
+            1000 + DEFAULT_RETRIES as u32 * RETRY_DELAY_US
+        );
+    }
+
+    #[test]
+    fn reports_lost_device_during_rollback() {
+        let mut bus = SimulatedBus::new();
+        // Enough transfers for the first two steps.
+        bus.lost_after = Some(4);
+
+        assert_eq!(
+            run(&mut bus, &RecordedDelay::default(), &SEQUENCE),
+            Err(Failure {
+                step: 2,
+                error: StepError::Bus(Nak),
+                rolled_back: false,
+            })
+        );
+        assert_eq!(bus.registers[0x10], 0x11);
+    }
+
+    #[test]
+    fn verified_write_detects_mismatch() {
+        let mut bus = SimulatedBus::new();
+        bus.stuck = Some((0x20, 0x02));
+        let steps = [
+            Step::write(DEVICE, 0x10, 0x11),
+            Step::write(DEVICE, 0x20, 0x22).verified(),
+        ];
+
+        assert_eq!(
+            run(&mut bus, &RecordedDelay::default(), &steps),
+            Err(Failure {
+                step: 1,
+                error: StepError::Mismatch {
+                    expected: 0x22,
+                    actual: 0x20,
+                },
+                rolled_back: true,
+            })
+        );
+        assert_eq!(bus.registers[0x10], 0xEF);
+    }
+
+    #[test]
+    fn unverified_write_ignores_mismatch() {
+        let mut bus = SimulatedBus::new();
+        bus.stuck = Some((0x20, 0x02));
+        let steps = [Step::write(DEVICE, 0x20, 0x22)];
+
+        assert_eq!(run(&mut bus, &RecordedDelay::default(), &steps), Ok(()));
+        assert_eq!(bus.registers[0x20], 0x20);
+    }
+
+    #[test]
+    fn restores_original_value_of_registers_written_twice() {
+        let mut bus = SimulatedBus::new();
+        bus.nak_writes = Some((0x30, usize::MAX));
+        let steps = [
+            Step::write(DEVICE, 0x10, 0x11),
+            Step::write(DEVICE, 0x10, 0x12),
+            Step::write(DEVICE, 0x30, 0x33),
+        ];
+
+        assert!(run(&mut bus, &RecordedDelay::default(), &steps).is_err());
+        assert_eq!(bus.writes(), [(0x10, 0x11), (0x10, 0x12), (0x10, 0xEF)]);
+    }
+
+    #[test]
+    fn waits_for_register() {
+        let mut bus = SimulatedBus::new();
+        bus.settles = Some((0x50, 3, 0x40));
+        let delay = RecordedDelay::default();
+        let steps = [Step::wait_for(DEVICE, 0x50, 0x40, 0x40, 100)];
+
+        assert_eq!(run(&mut bus, &delay, &steps), Ok(()));
+        assert_eq!(delay.total_us.get(), 3 * POLL_INTERVAL_US);
+    }
+
+    #[test]
+    fn wait_times_out_and_rolls_back() {
+        let mut bus = SimulatedBus::new();
+        let delay = RecordedDelay::default();
+        let steps = [
+            Step::write(DEVICE, 0x10, 0x11),
+            Step::wait_for(DEVICE, 0x50, 0x40, 0x40, 100),
+        ];
+
+        assert_eq!(
+            run(&mut bus, &delay, &steps),
+            Err(Failure {
+                step: 1,
+                error: StepError::Timeout,
+                rolled_back: true,
+            })
+        );
+        assert_eq!(delay.total_us.get(), 100);
+        assert_eq!(bus.registers[0x10], 0xEF);
+    }
+
+    #[test]
+    fn rejects_sequences_beyond_undo_capacity() {
+        let mut bus = SimulatedBus::new();
+        let mut steps = [Step::delay(0); MAX_UNDO + 1];
+        for (register, step) in steps.iter_mut().enumerate() {
+            *step = Step::write(DEVICE, register as u8, 0);
+        }
+
+        assert_eq!(
+            run(&mut bus, &RecordedDelay::default(), &steps),
+            Err(Failure {
+                step: MAX_UNDO,
+                error: StepError::TooManyRegisters,
+                rolled_back: true,
+            })
+        );
+        assert!(bus.registers[..MAX_UNDO + 1]
+            .iter()
+            .enumerate()
+            .all(|(register, value)| *value == !(register as u8)));
+    }
+
+    #[test]
+    fn wait_accounts_for_time_spent_polling() {
+        let mut bus = SimulatedBus::new();
+        // Every poll takes longer than the poll interval.
+        let delay = RecordedDelay {
+            drift_us: 4 * POLL_INTERVAL_US,
+            ..RecordedDelay::default()
+        };
+        let steps = [Step {
+            retries: 0,
+            ..Step::wait_for(DEVICE, 0x50, 0x40, 0x40, 100)
+        }];
+
+        assert_eq!(
+            run(&mut bus, &delay, &steps),
+            Err(Failure {
+                step: 0,
+                error: StepError::Timeout,
+                rolled_back: true,
+            })
+        );
+        assert_eq!(bus.transfers, [Transfer::Read(0x50); 3]);
+    }
+}
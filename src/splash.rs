@@ -0,0 +1,32 @@
+//! The boot splash shown while the bootloader is running.
+
+use logo::Logo;
+
+use crate::console::Canvas;
+
+/// The boot logo, converted by `mklogo` and validated by the build script.
+static LOGO: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/logo.mlg"));
+
+/// Draws the boot logo centered on `canvas`.
+///
+/// The logo is clipped to the canvas, should it not fit.
+pub fn draw_logo<C: Canvas>(canvas: &mut C) -> Result<(), logo::Error> {
+    let logo = Logo::decode(LOGO)?;
+
+    let (width, height) = (canvas.width(), canvas.height());
+    let left = width.saturating_sub(logo.header.width as usize) / 2;
+    let top = height.saturating_sub(logo.header.height as usize) / 2;
+
+    for run in logo.runs() {
+        let x = left + run.x as usize;
+        let y = top + run.y as usize;
+        if x >= width || y >= height {
+            continue;
+        }
+
+        let length = (run.length as usize).min(width - x);
+        canvas.fill_rect(x, y, length, 1, run.color);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,368 @@
+//! A minimal driver for the eMMC connected to the SDMMC4 controller.
+//!
+//! The driver operates the SDHCI-compatible controller in PIO mode with a 1-bit bus,
+//! which is slow but sufficient for loading the comparatively small payloads of the
+//! boot chain.
+
+use core::ptr;
+
+use libtegra::timer::usleep;
+
+use super::{BlockDevice, Error, BLOCK_SIZE};
+use crate::bus::TimerDelay;
+use crate::deadline::{self, Deadline};
+
+/// The base address of the SDMMC4 controller.
+const SDMMC4_BASE: usize = 0x700B_0600;
+
+/// The base address of the Clock and Reset Controller.
+const CAR_BASE: usize = 0x6000_6000;
+
+const CLK_RST_CONTROLLER_RST_DEVICES_L: usize = 0x4;
+const CLK_RST_CONTROLLER_CLK_OUT_ENB_L: usize = 0x10;
+const CLK_RST_CONTROLLER_CLK_SOURCE_SDMMC4: usize = 0x164;
+const SDMMC4_DEVICE_BIT: u32 = 1 << 15;
+
+const SDHCI_BLOCK: usize = 0x04;
+const SDHCI_ARGUMENT: usize = 0x08;
+const SDHCI_COMMAND: usize = 0x0C;
+const SDHCI_RESPONSE: usize = 0x10;
+const SDHCI_BUFFER: usize = 0x20;
+const SDHCI_PRESENT_STATE: usize = 0x24;
+const SDHCI_HOST_CONTROL: usize = 0x28;
+const SDHCI_CLOCK_CONTROL: usize = 0x2C;
+const SDHCI_INT_STATUS: usize = 0x30;
+const SDHCI_INT_ENABLE: usize = 0x34;
+
+const PRESENT_CMD_INHIBIT: u32 = 1 << 0;
+const PRESENT_DAT_INHIBIT: u32 = 1 << 1;
+
+const INT_CMD_COMPLETE: u32 = 1 << 0;
+const INT_XFER_COMPLETE: u32 = 1 << 1;
+const INT_BUF_WRITE_READY: u32 = 1 << 4;
+const INT_BUF_READ_READY: u32 = 1 << 5;
+const INT_ERROR: u32 = 1 << 15;
+
+const CLOCK_INTERNAL_ENABLE: u32 = 1 << 0;
+const CLOCK_INTERNAL_STABLE: u32 = 1 << 1;
+const CLOCK_CARD_ENABLE: u32 = 1 << 2;
+const RESET_ALL: u32 = 1 << 24;
+const DATA_TIMEOUT_MAX: u32 = 0xE << 16;
+
+/// SD bus power on at 1.8V.
+const POWER_ON_1V8: u32 = 0x0B << 8;
+
+/// The controller input clock of 48MHz divided down to 400kHz for identification.
+const SDCLK_DIV_IDENT: u32 = 60;
+/// The controller input clock of 48MHz divided down to 24MHz for data transfers.
+const SDCLK_DIV_TRANSFER: u32 = 1;
+
+/// The time the controller may take to reset, in microseconds.
+const RESET_TIMEOUT_US: u32 = 100_000;
+/// The time the internal clock may take to stabilize, in microseconds.
+const CLOCK_TIMEOUT_US: u32 = 150_000;
+/// The time the device may take to power up, in microseconds.
+const POWER_UP_TIMEOUT_US: u32 = 1_000_000;
+/// The time a command or a block transfer may take, including busy signalling, in
+/// microseconds.
+const BUSY_TIMEOUT_US: u32 = 1_000_000;
+
+/// The relative card address assigned to the eMMC.
+const RCA: u32 = 1;
+
+/// `SEND_OP_COND` argument for a sector-addressed device at 1.8V or 2.7-3.6V.
+const OCR_SECTOR_MODE: u32 = 0x40FF_8080;
+const OCR_READY: u32 = 1 << 31;
+
+/// The `PARTITION_CONFIG` byte in the EXT_CSD register.
+const EXT_CSD_PARTITION_CONFIG: u32 = 179;
+
+/// The card status bit signalling readiness for data.
+const STATUS_READY_FOR_DATA: u32 = 1 << 8;
+/// The card status field holding the current state.
+const STATUS_STATE_SHIFT: u32 = 9;
+const STATUS_STATE_TRAN: u32 = 4;
+
+/// The response type of an eMMC command.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Response {
+    None,
+    R1,
+    R1b,
+    R2,
+    R3,
+}
+
+impl Response {
+    fn flags(self) -> u32 {
+        // Response length select, CRC and index checks.
+        match self {
+            Response::None => 0,
+            Response::R1 => 0x2 | 1 << 3 | 1 << 4,
+            Response::R1b => 0x3 | 1 << 3 | 1 << 4,
+            Response::R2 => 0x1 | 1 << 3,
+            Response::R3 => 0x2,
+        }
+    }
+}
+
+/// The hardware partitions of the eMMC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partition {
+    /// The user data area.
+    User = 0,
+    /// The first boot partition.
+    #[allow(dead_code)]
+    Boot0 = 1,
+    /// The second boot partition.
+    Boot1 = 2,
+}
+
+/// The eMMC on the SDMMC4 controller.
+pub struct Emmc {
+    partition: Partition,
+}
+
+fn read_reg(offset: usize) -> u32 {
+    unsafe { ptr::read_volatile((SDMMC4_BASE + offset) as *const u32) }
+}
+
+fn write_reg(offset: usize, value: u32) {
+    unsafe { ptr::write_volatile((SDMMC4_BASE + offset) as *mut u32, value) }
+}
+
+fn modify_car_reg(offset: usize, clear: u32, set: u32) {
+    let reg = (CAR_BASE + offset) as *mut u32;
+    unsafe { ptr::write_volatile(reg, (ptr::read_volatile(reg) & !clear) | set) }
+}
+
+fn set_card_clock(divider: u32) -> Result<(), Error> {
+    let clock = read_reg(SDHCI_CLOCK_CONTROL);
+    write_reg(SDHCI_CLOCK_CONTROL, clock & !(CLOCK_CARD_ENABLE | 0xFFC0));
+
+    let divider = (divider & 0xFF) << 8 | ((divider >> 8) & 0x3) << 6;
+    write_reg(
+        SDHCI_CLOCK_CONTROL,
+        (clock & !0xFFC7) | divider | DATA_TIMEOUT_MAX | CLOCK_INTERNAL_ENABLE,
+    );
+    deadline::poll_until(
+        &TimerDelay,
+        || read_reg(SDHCI_CLOCK_CONTROL) & CLOCK_INTERNAL_STABLE != 0,
+        CLOCK_TIMEOUT_US,
+    )?;
+
+    write_reg(
+        SDHCI_CLOCK_CONTROL,
+        read_reg(SDHCI_CLOCK_CONTROL) | CLOCK_CARD_ENABLE,
+    );
+    Ok(())
+}
+
+/// Reports errors of a data transfer as [`DataError`], unless it timed out.
+///
+/// [`DataError`]: ../enum.Error.html#variant.DataError
+fn data_error(error: Error) -> Error {
+    match error {
+        Error::Timeout => Error::Timeout,
+        _ => Error::DataError,
+    }
+}
+
+fn check_length(length: usize) -> Result<u32, Error> {
+    if length % BLOCK_SIZE != 0 {
+        return Err(Error::UnalignedBuffer);
+    }
+
+    Ok((length / BLOCK_SIZE) as u32)
+}
+
+impl Emmc {
+    /// Powers up the SDMMC4 controller and brings the eMMC into transfer state.
+    pub fn init() -> Result<Self, Error> {
+        // Clock the controller from PLLP_OUT0 (408MHz) divided down to 48MHz.
+        modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_L, 0, SDMMC4_DEVICE_BIT);
+        modify_car_reg(CLK_RST_CONTROLLER_CLK_OUT_ENB_L, 0, SDMMC4_DEVICE_BIT);
+        modify_car_reg(CLK_RST_CONTROLLER_CLK_SOURCE_SDMMC4, !0, 15);
+        usleep(2);
+        modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_L, SDMMC4_DEVICE_BIT, 0);
+
+        // Reset the controller and power up the bus.
+        write_reg(SDHCI_CLOCK_CONTROL, RESET_ALL);
+        deadline::poll_until(
+            &TimerDelay,
+            || read_reg(SDHCI_CLOCK_CONTROL) & RESET_ALL == 0,
+            RESET_TIMEOUT_US,
+        )?;
+        write_reg(SDHCI_HOST_CONTROL, POWER_ON_1V8);
+        write_reg(SDHCI_INT_ENABLE, !0);
+        set_card_clock(SDCLK_DIV_IDENT)?;
+
+        // Give the device 74 clock cycles to power up.
+        usleep(1000);
+
+        let mut emmc = Emmc {
+            partition: Partition::User,
+        };
+
+        emmc.command(0, 0, Response::None)?;
+        let deadline = Deadline::new(&TimerDelay, POWER_UP_TIMEOUT_US);
+        while emmc.command(1, OCR_SECTOR_MODE, Response::R3)? & OCR_READY == 0 {
+            if deadline.expired() {
+                return Err(Error::Timeout);
+            }
+            usleep(1000);
+        }
+        emmc.command(2, 0, Response::R2)?;
+        emmc.command(3, RCA << 16, Response::R1)?;
+        emmc.command(7, RCA << 16, Response::R1b)?;
+        emmc.wait_ready()?;
+
+        set_card_clock(SDCLK_DIV_TRANSFER)?;
+
+        Ok(emmc)
+    }
+
+    /// Switches block accesses to the given hardware `partition`.
+    pub fn select_partition(&mut self, partition: Partition) -> Result<(), Error> {
+        if self.partition == partition {
+            return Ok(());
+        }
+
+        // SWITCH command writing the partition access bits of PARTITION_CONFIG.
+        let argument = 3 << 24 | EXT_CSD_PARTITION_CONFIG << 16 | (partition as u32) << 8;
+        self.command(6, argument, Response::R1b)?;
+        self.wait_ready()?;
+
+        self.partition = partition;
+        Ok(())
+    }
+
+    fn command(&mut self, index: u8, argument: u32, response: Response) -> Result<u32, Error> {
+        self.command_with_data(index, argument, response, None)
+    }
+
+    fn command_with_data(
+        &mut self,
+        index: u8,
+        argument: u32,
+        response: Response,
+        read: Option<bool>,
+    ) -> Result<u32, Error> {
+        let mut inhibit = PRESENT_CMD_INHIBIT;
+        if read.is_some() || response == Response::R1b {
+            inhibit |= PRESENT_DAT_INHIBIT;
+        }
+        deadline::poll_until(
+            &TimerDelay,
+            || read_reg(SDHCI_PRESENT_STATE) & inhibit == 0,
+            BUSY_TIMEOUT_US,
+        )?;
+
+        // Clear stale interrupt status bits.
+        write_reg(SDHCI_INT_STATUS, !0);
+
+        let mut command = (index as u32) << 24 | response.flags() << 16;
+        if let Some(read) = read {
+            write_reg(SDHCI_BLOCK, 1 << 16 | BLOCK_SIZE as u32);
+            command |= 1 << 21 | (read as u32) << 4;
+        }
+
+        write_reg(SDHCI_ARGUMENT, argument);
+        write_reg(SDHCI_COMMAND, command);
+
+        let status = self.wait_interrupt(INT_CMD_COMPLETE, index)?;
+        write_reg(SDHCI_INT_STATUS, status & INT_CMD_COMPLETE);
+
+        Ok(read_reg(SDHCI_RESPONSE))
+    }
+
+    fn wait_interrupt(&mut self, mask: u32, index: u8) -> Result<u32, Error> {
+        let deadline = Deadline::new(&TimerDelay, BUSY_TIMEOUT_US);
+
+        loop {
+            let status = read_reg(SDHCI_INT_STATUS);
+            if status & INT_ERROR != 0 {
+                write_reg(SDHCI_INT_STATUS, status);
+                return Err(Error::CommandFailed {
+                    command: index,
+                    status: (status >> 16) as u16,
+                });
+            }
+            if status & mask != 0 {
+                return Ok(status);
+            }
+            if deadline.expired() {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    /// Waits until the device is back in transfer state and ready for data.
+    fn wait_ready(&mut self) -> Result<(), Error> {
+        let deadline = Deadline::new(&TimerDelay, BUSY_TIMEOUT_US);
+
+        loop {
+            let status = self.command(13, RCA << 16, Response::R1)?;
+            if status & STATUS_READY_FOR_DATA != 0
+                && (status >> STATUS_STATE_SHIFT) & 0xF == STATUS_STATE_TRAN
+            {
+                return Ok(());
+            }
+            if deadline.expired() {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    fn finish_transfer(&mut self, index: u8) -> Result<(), Error> {
+        let status = self
+            .wait_interrupt(INT_XFER_COMPLETE, index)
+            .map_err(data_error)?;
+        write_reg(SDHCI_INT_STATUS, status);
+        Ok(())
+    }
+}
+
+impl BlockDevice for Emmc {
+    fn read_blocks(&mut self, lba: u32, buffer: &mut [u8]) -> Result<(), Error> {
+        check_length(buffer.len())?;
+
+        for (block, chunk) in buffer.chunks_mut(BLOCK_SIZE).enumerate() {
+            // READ_SINGLE_BLOCK
+            self.command_with_data(17, lba + block as u32, Response::R1, Some(true))?;
+            self.wait_interrupt(INT_BUF_READ_READY, 17)
+                .map_err(data_error)?;
+            write_reg(SDHCI_INT_STATUS, INT_BUF_READ_READY);
+
+            for word in chunk.chunks_mut(4) {
+                word.copy_from_slice(&read_reg(SDHCI_BUFFER).to_le_bytes());
+            }
+            self.finish_transfer(17)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u32, buffer: &[u8]) -> Result<(), Error> {
+        check_length(buffer.len())?;
+
+        for (block, chunk) in buffer.chunks(BLOCK_SIZE).enumerate() {
+            // WRITE_BLOCK
+            self.command_with_data(24, lba + block as u32, Response::R1, Some(false))?;
+            self.wait_interrupt(INT_BUF_WRITE_READY, 24)
+                .map_err(data_error)?;
+            write_reg(SDHCI_INT_STATUS, INT_BUF_WRITE_READY);
+
+            for word in chunk.chunks(4) {
+                write_reg(
+                    SDHCI_BUFFER,
+                    u32::from_le_bytes([word[0], word[1], word[2], word[3]]),
+                );
+            }
+            self.finish_transfer(24)?;
+            self.wait_ready()?;
+        }
+
+        Ok(())
+    }
+}
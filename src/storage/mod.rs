@@ -0,0 +1,43 @@
+//! Block storage access for loading payloads.
+
+pub mod emmc;
+
+use crate::deadline::Timeout;
+
+/// The size of a single storage block in bytes.
+pub const BLOCK_SIZE: usize = 512;
+
+/// Errors that may occur while accessing block storage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A command was rejected or reported an error.
+    CommandFailed {
+        /// The index of the failing command.
+        command: u8,
+        /// The error interrupt status of the controller.
+        status: u16,
+    },
+    /// A data transfer reported an error.
+    DataError,
+    /// The controller or the device did not respond in time.
+    Timeout,
+    /// The buffer length is not a multiple of [`BLOCK_SIZE`].
+    ///
+    /// [`BLOCK_SIZE`]: constant.BLOCK_SIZE.html
+    UnalignedBuffer,
+}
+
+impl From<Timeout> for Error {
+    fn from(_: Timeout) -> Self {
+        Error::Timeout
+    }
+}
+
+/// A block-addressed storage device.
+pub trait BlockDevice {
+    /// Reads `buffer.len() / BLOCK_SIZE` blocks starting at block `lba` into `buffer`.
+    fn read_blocks(&mut self, lba: u32, buffer: &mut [u8]) -> Result<(), Error>;
+
+    /// Writes `buffer.len() / BLOCK_SIZE` blocks from `buffer` starting at block `lba`.
+    fn write_blocks(&mut self, lba: u32, buffer: &[u8]) -> Result<(), Error>;
+}
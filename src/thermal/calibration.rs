@@ -0,0 +1,256 @@
+//! Calibration of the SOC_THERM temperature sensors and decoding of their readings.
+//!
+//! Every sensor counts oscillations of a temperature-dependent ring oscillator. The
+//! slope and offset that turn the count into a temperature are derived from
+//! calibration values that were measured at two known temperatures during production
+//! and burnt into the fuses: a set shared by all sensors and a pair per sensor. Each
+//! sensor is further corrected by a per-sensor linear fit. The hardware applies the
+//! resulting coefficients on its own and reports temperatures in half degrees.
+//!
+//! The derivation follows the one of the Linux SOC_THERM driver for the Tegra X1.
+//! This module does not access any hardware.
+
+/// The nominal temperature of the CP calibration, in degrees Celsius.
+const NOMINAL_CALIB_CP: i32 = 25;
+/// The nominal temperature of the FT calibration, in degrees Celsius.
+const NOMINAL_CALIB_FT: i32 = 105;
+
+/// The fixed-point shift of the slope coefficient.
+const THERMA_SHIFT: u32 = 13;
+
+/// The scale of the correction coefficients.
+const CORRECTION_SCALE: i64 = 1_000_000;
+
+const READBACK_VALUE_SHIFT: u16 = 8;
+const READBACK_ADD_HALF: u16 = 1 << 7;
+const READBACK_NEGATE: u16 = 1 << 0;
+
+/// Interprets bit `index` of `value` as the sign bit of a two's complement number.
+fn sign_extend(value: u32, index: u32) -> i32 {
+    let shift = 31 - index;
+    ((value << shift) as i32) >> shift
+}
+
+/// Divides `a` by `b`, rounding down.
+fn div_round(a: i64, b: i64) -> i64 {
+    // Scale up for a more precise division, like the reference implementation. The
+    // added half only compensates for its truncation towards zero, so this does not
+    // round to the nearest integer.
+    (((a << 16) * 2 + 1) / (2 * b)) >> 16
+}
+
+/// The calibration values shared by all sensors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SharedCalibration {
+    base_cp: i32,
+    base_ft: i32,
+    actual_temp_cp: i32,
+    actual_temp_ft: i32,
+}
+
+impl SharedCalibration {
+    /// Decodes the shared calibration from the `FUSE_TSENSOR_COMMON` fuse word.
+    pub fn decode(common: u32) -> Self {
+        let shifted_ft = sign_extend(common >> 6 & 0x1F, 4);
+        let shifted_cp = sign_extend(common & 0x3F, 5);
+
+        SharedCalibration {
+            base_cp: (common >> 11 & 0x3FF) as i32,
+            base_ft: (common >> 21 & 0x7FF) as i32,
+            actual_temp_cp: 2 * NOMINAL_CALIB_CP + shifted_cp,
+            actual_temp_ft: 2 * NOMINAL_CALIB_FT + shifted_ft,
+        }
+    }
+}
+
+/// The linear correction of a single sensor, scaled by 1,000,000.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Correction {
+    pub alpha: i32,
+    pub beta: i32,
+}
+
+/// Computes the value of the `CONFIG2` register of a sensor, which holds its slope and
+/// offset coefficients, from its calibration fuse word `fuse`.
+///
+/// Returns `None` if the fuses do not hold a usable calibration, e.g. because they were
+/// never burnt.
+pub fn sensor_config2(
+    shared: &SharedCalibration,
+    fuse: u32,
+    correction: Correction,
+) -> Option<u32> {
+    let actual_cp = shared.base_cp * 64 + sign_extend(fuse & 0x1FFF, 12);
+    let actual_ft = shared.base_ft * 32 + sign_extend(fuse >> 13 & 0x1FFF, 12);
+
+    let delta_sens = (actual_ft - actual_cp) as i64;
+    if delta_sens == 0 {
+        return None;
+    }
+    let delta_temp = (shared.actual_temp_ft - shared.actual_temp_cp) as i64;
+
+    let therma = div_round(delta_temp << THERMA_SHIFT, delta_sens);
+    let thermb = div_round(
+        shared.actual_temp_cp as i64 * actual_ft as i64
+            - shared.actual_temp_ft as i64 * actual_cp as i64,
+        delta_sens,
+    );
+
+    // Apply the per-sensor correction.
+    let therma = div_round(therma * correction.alpha as i64, CORRECTION_SCALE);
+    let thermb = div_round(
+        thermb * correction.alpha as i64 + correction.beta as i64,
+        CORRECTION_SCALE,
+    );
+
+    Some((therma as u16 as u32) << 16 | thermb as u16 as u32)
+}
+
+/// Converts a temperature reading as reported by the hardware to millidegrees
+/// Celsius.
+pub fn millicelsius(readback: u16) -> i32 {
+    let mut temperature = (readback >> READBACK_VALUE_SHIFT) as i32 * 1000;
+    if readback & READBACK_ADD_HALF != 0 {
+        temperature += 500;
+    }
+    if readback & READBACK_NEGATE != 0 {
+        temperature = -temperature;
+    }
+
+    temperature
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The identity correction.
+    const UNCORRECTED: Correction = Correction {
+        alpha: 1_000_000,
+        beta: 0,
+    };
+
+    /// The shared calibration with the base counts 704 and 1440 and the actual
+    /// temperatures of 23.5 and 107.5 degrees.
+    const COMMON: u32 = 1440 << 21 | 704 << 11 | 0x05 << 6 | 0x3D;
+
+    /// The sensor calibration that is off by -20 and 37 counts, i.e. that counted 45036
+    /// at the CP and 46117 at the FT temperature.
+    const SENSOR: u32 = 37 << 13 | 0x1FEC;
+
+    /// Splits a `CONFIG2` value into the slope and offset coefficients.
+    fn coefficients(config2: u32) -> (i64, i64) {
+        ((config2 >> 16) as i16 as i64, config2 as i16 as i64)
+    }
+
+    /// Evaluates the line given by `config2` at `count`, in half degrees.
+    fn half_degrees(config2: u32, count: i64) -> i64 {
+        let (therma, thermb) = coefficients(config2);
+        ((therma * count) >> THERMA_SHIFT) + thermb
+    }
+
+    #[test]
+    fn sign_extends() {
+        assert_eq!(sign_extend(0x0F, 4), 15);
+        assert_eq!(sign_extend(0x10, 4), -16);
+        assert_eq!(sign_extend(0x1F, 4), -1);
+        assert_eq!(sign_extend(0x3D, 5), -3);
+        assert_eq!(sign_extend(0x1FEC, 12), -20);
+        // Bits above the sign bit are ignored.
+        assert_eq!(sign_extend(0xFFFF_0025, 12), 37);
+    }
+
+    #[test]
+    fn divides_rounding_down() {
+        assert_eq!(div_round(6, 3), 2);
+        assert_eq!(div_round(7, 3), 2);
+        assert_eq!(div_round(8, 3), 2);
+        assert_eq!(div_round(7, 2), 3);
+        assert_eq!(div_round(1 << 40, 3), 366_503_875_925);
+        assert_eq!(div_round(-6, 3), -2);
+        assert_eq!(div_round(-7, 3), -3);
+    }
+
+    #[test]
+    fn decodes_shared_calibration() {
+        assert_eq!(
+            SharedCalibration::decode(COMMON),
+            SharedCalibration {
+                base_cp: 704,
+                base_ft: 1440,
+                actual_temp_cp: 47,
+                actual_temp_ft: 215,
+            }
+        );
+        assert_eq!(
+            SharedCalibration::decode(0),
+            SharedCalibration {
+                base_cp: 0,
+                base_ft: 0,
+                actual_temp_cp: 50,
+                actual_temp_ft: 210,
+            }
+        );
+    }
+
+    #[test]
+    fn computes_coefficients() {
+        let shared = SharedCalibration::decode(COMMON);
+        let config2 = sensor_config2(&shared, SENSOR, UNCORRECTED).unwrap();
+
+        // 168 half degrees over 1081 counts, through 47 half degrees at 45036 counts.
+        assert_eq!(coefficients(config2), (1273, -6953));
+        assert_eq!(config2, 0x04F9_E4D7);
+    }
+
+    #[test]
+    fn fits_both_calibration_points() {
+        let shared = SharedCalibration::decode(COMMON);
+        let config2 = sensor_config2(&shared, SENSOR, UNCORRECTED).unwrap();
+
+        // Within the precision of the fixed-point slope, which is off by 0.2 / 8192
+        // half degrees per count.
+        assert!((half_degrees(config2, 45036) - 47).abs() <= 2);
+        assert!((half_degrees(config2, 46117) - 215).abs() <= 2);
+    }
+
+    #[test]
+    fn applies_correction() {
+        let shared = SharedCalibration::decode(COMMON);
+        let doubled = Correction {
+            alpha: 2_000_000,
+            beta: 0,
+        };
+        let shifted = Correction {
+            alpha: 1_000_000,
+            beta: 3_000_000,
+        };
+
+        let config2 = sensor_config2(&shared, SENSOR, doubled).unwrap();
+        assert_eq!(coefficients(config2), (2546, -13906));
+        let config2 = sensor_config2(&shared, SENSOR, shifted).unwrap();
+        assert_eq!(coefficients(config2), (1273, -6950));
+    }
+
+    #[test]
+    fn rejects_unburnt_fuses() {
+        let shared = SharedCalibration::decode(0);
+        assert_eq!(sensor_config2(&shared, 0, UNCORRECTED), None);
+
+        // A sensor that counted the same at both temperatures.
+        let shared = SharedCalibration::decode(1408 << 21 | 704 << 11);
+        assert_eq!(sensor_config2(&shared, 0, UNCORRECTED), None);
+    }
+
+    #[test]
+    fn converts_readback() {
+        assert_eq!(millicelsius(0x0000), 0);
+        assert_eq!(millicelsius(0x1900), 25_000);
+        assert_eq!(millicelsius(0x1980), 25_500);
+        assert_eq!(millicelsius(0x1981), -25_500);
+        assert_eq!(millicelsius(0x6400), 100_000);
+        assert_eq!(millicelsius(0xFF80), 255_500);
+        // Bits between the flags are ignored.
+        assert_eq!(millicelsius(0x197E), 25_000);
+    }
+}
@@ -0,0 +1,264 @@
+//! Thermal monitoring through the SoC and PMIC temperature sensors.
+//!
+//! The SOC_THERM controller measures the temperature of the CPU, GPU, memory and PLLX
+//! areas of the die, once its sensors are calibrated from the fuses, see the
+//! [`calibration`] module. The MAX77620 PMIC additionally raises alarms when its own
+//! junction temperature exceeds 120°C and 140°C.
+//!
+//! Above [`LIMIT_CELSIUS`], which is set at build time through `MIRAGE_THERMAL_LIMIT`,
+//! the first stage keeps its clocks low and refuses to flash. Above
+//! [`CRITICAL_CELSIUS`], it powers off.
+//!
+//! [`calibration`]: calibration/index.html
+//! [`LIMIT_CELSIUS`]: constant.LIMIT_CELSIUS.html
+//! [`CRITICAL_CELSIUS`]: constant.CRITICAL_CELSIUS.html
+
+pub mod calibration;
+
+use core::ptr;
+
+use libtegra::i2c::I2c;
+
+use self::calibration::{Correction, SharedCalibration};
+use crate::bus::TimerDelay;
+use crate::deadline;
+use crate::fuses::Fuses;
+use crate::power;
+
+include!(concat!(env!("OUT_DIR"), "/thermal.rs"));
+
+/// The temperature above which the system is powered off, in degrees Celsius.
+pub const CRITICAL_CELSIUS: i32 = 100;
+
+/// The base address of the SOC_THERM controller.
+const SOC_THERM_BASE: usize = 0x700E_2000;
+
+/// The base address of the Clock and Reset Controller.
+const CAR_BASE: usize = 0x6000_6000;
+
+const CLK_RST_CONTROLLER_RST_DEVICES_U: usize = 0xC;
+const CLK_RST_CONTROLLER_CLK_OUT_ENB_U: usize = 0x18;
+const CLK_RST_CONTROLLER_CLK_SOURCE_TSENSOR: usize = 0x3B8;
+const CLK_RST_CONTROLLER_RST_DEVICES_V: usize = 0x358;
+const CLK_RST_CONTROLLER_CLK_OUT_ENB_V: usize = 0x360;
+const CLK_RST_CONTROLLER_CLK_SOURCE_SOC_THERM: usize = 0x644;
+const SOC_THERM_DEVICE_BIT: u32 = 1 << 14;
+const TSENSOR_DEVICE_BIT: u32 = 1 << 4;
+
+/// SOC_THERM fed by PLLP_OUT0 divided by 16.
+const SOC_THERM_CLOCK_SOURCE: u32 = 0x1E;
+/// TSENSOR fed by CLK_M divided by 12.
+const TSENSOR_CLOCK_SOURCE: u32 = 4 << 29 | 0x16;
+
+const SENSOR_CONFIG0: usize = 0x0;
+const SENSOR_CONFIG1: usize = 0x4;
+const SENSOR_CONFIG2: usize = 0x8;
+const SENSOR_STATUS1: usize = 0x10;
+const SENSOR_TEMP1: usize = 0x1C8;
+const SENSOR_TEMP2: usize = 0x1CC;
+
+/// The sensor timing for the Tegra X1.
+const SENSOR_CONFIG0_VALUE: u32 = 16300 << 8;
+const SENSOR_CONFIG1_VALUE: u32 = 1 << 31 | 1 << 24 | 1 << 15 | (120 - 1);
+const STATUS1_TEMP_VALID: u32 = 1 << 31;
+
+/// The fuse word holding the calibration shared by all sensors.
+const FUSE_TSENSOR_COMMON: usize = 0x280;
+
+/// The register offset, calibration fuse and correction of every sensor.
+const SENSORS: [(usize, usize, Correction); 8] = [
+    // CPU0 to CPU3.
+    (
+        0x0C0,
+        0x198,
+        Correction {
+            alpha: 1_085_000,
+            beta: 3_244_200,
+        },
+    ),
+    (
+        0x0E0,
+        0x184,
+        Correction {
+            alpha: 1_126_200,
+            beta: -67_500,
+        },
+    ),
+    (
+        0x100,
+        0x188,
+        Correction {
+            alpha: 1_098_400,
+            beta: 2_251_100,
+        },
+    ),
+    (
+        0x120,
+        0x22C,
+        Correction {
+            alpha: 1_108_000,
+            beta: 602_700,
+        },
+    ),
+    // MEM0 and MEM1.
+    (
+        0x140,
+        0x258,
+        Correction {
+            alpha: 1_069_200,
+            beta: 3_549_900,
+        },
+    ),
+    (
+        0x160,
+        0x25C,
+        Correction {
+            alpha: 1_173_700,
+            beta: -6_263_600,
+        },
+    ),
+    // GPU.
+    (
+        0x180,
+        0x254,
+        Correction {
+            alpha: 1_074_300,
+            beta: 2_734_900,
+        },
+    ),
+    // PLLX.
+    (
+        0x1A0,
+        0x260,
+        Correction {
+            alpha: 1_039_700,
+            beta: 6_829_100,
+        },
+    ),
+];
+
+/// The time the sensors may take to deliver the first reading, in microseconds.
+const SENSOR_TIMEOUT_US: u32 = 10_000;
+
+const MAX77620_PWR: u32 = 0x3C;
+const MAX77620_REG_STATLBT: u8 = 0x10;
+const STATLBT_TJALRM1: u8 = 1 << 2;
+const STATLBT_TJALRM2: u8 = 1 << 1;
+
+/// Whether SOC_THERM delivers calibrated readings.
+static mut READY: bool = false;
+
+/// The thermal state of the system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The system may run at full speed.
+    Normal,
+    /// The system is above the limit and should keep its clocks low.
+    Hot,
+}
+
+fn read_reg(offset: usize) -> u32 {
+    unsafe { ptr::read_volatile((SOC_THERM_BASE + offset) as *const u32) }
+}
+
+fn write_reg(offset: usize, value: u32) {
+    unsafe { ptr::write_volatile((SOC_THERM_BASE + offset) as *mut u32, value) }
+}
+
+fn modify_car_reg(offset: usize, clear: u32, set: u32) {
+    let reg = (CAR_BASE + offset) as *mut u32;
+    unsafe { ptr::write_volatile(reg, (ptr::read_volatile(reg) & !clear) | set) }
+}
+
+fn write_car_reg(offset: usize, value: u32) {
+    unsafe { ptr::write_volatile((CAR_BASE + offset) as *mut u32, value) }
+}
+
+/// Brings up SOC_THERM and programs the calibration of every sensor.
+///
+/// Sensors without a usable calibration are left disabled. Temperatures are only
+/// read from SOC_THERM if all sensors could be calibrated and deliver readings.
+pub fn init() {
+    // Assert reset, enable the clocks and release SOC_THERM and TSENSOR.
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_U, 0, SOC_THERM_DEVICE_BIT);
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_V, 0, TSENSOR_DEVICE_BIT);
+    write_car_reg(
+        CLK_RST_CONTROLLER_CLK_SOURCE_SOC_THERM,
+        SOC_THERM_CLOCK_SOURCE,
+    );
+    write_car_reg(CLK_RST_CONTROLLER_CLK_SOURCE_TSENSOR, TSENSOR_CLOCK_SOURCE);
+    modify_car_reg(CLK_RST_CONTROLLER_CLK_OUT_ENB_U, 0, SOC_THERM_DEVICE_BIT);
+    modify_car_reg(CLK_RST_CONTROLLER_CLK_OUT_ENB_V, 0, TSENSOR_DEVICE_BIT);
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_U, SOC_THERM_DEVICE_BIT, 0);
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_V, TSENSOR_DEVICE_BIT, 0);
+
+    let shared = SharedCalibration::decode(Fuses.read_cache(FUSE_TSENSOR_COMMON));
+    let mut calibrated = true;
+    for &(base, fuse, correction) in SENSORS.iter() {
+        match calibration::sensor_config2(&shared, Fuses.read_cache(fuse), correction) {
+            Some(config2) => {
+                write_reg(base + SENSOR_CONFIG0, SENSOR_CONFIG0_VALUE);
+                write_reg(base + SENSOR_CONFIG1, SENSOR_CONFIG1_VALUE);
+                write_reg(base + SENSOR_CONFIG2, config2);
+            }
+            None => calibrated = false,
+        }
+    }
+
+    // Wait for the first sensor to deliver a reading, after which all of them do.
+    let (first, _, _) = SENSORS[0];
+    let valid = deadline::poll_until(
+        &TimerDelay,
+        || read_reg(first + SENSOR_STATUS1) & STATUS1_TEMP_VALID != 0,
+        SENSOR_TIMEOUT_US,
+    );
+
+    unsafe {
+        READY = calibrated && valid.is_ok();
+    }
+}
+
+/// Reads the highest temperature of the die in millidegrees Celsius, if SOC_THERM
+/// delivers calibrated readings.
+pub fn soc_millicelsius() -> Option<i32> {
+    if unsafe { !READY } {
+        return None;
+    }
+
+    // Each register holds the readings of two sensor groups.
+    let temp1 = read_reg(SENSOR_TEMP1);
+    let temp2 = read_reg(SENSOR_TEMP2);
+    let readings = [
+        (temp1 >> 16) as u16,
+        temp1 as u16,
+        (temp2 >> 16) as u16,
+        temp2 as u16,
+    ];
+
+    readings
+        .iter()
+        .map(|&readback| calibration::millicelsius(readback))
+        .max()
+}
+
+/// Gets the thermal state of the system, powering it off if it is critical.
+///
+/// Without calibrated SOC_THERM readings, only the alarms of the PMIC are taken into
+/// account.
+pub fn check() -> Status {
+    let alarms = I2c::C5
+        .read_byte(MAX77620_PWR, MAX77620_REG_STATLBT)
+        .unwrap_or(0);
+    let temperature = soc_millicelsius();
+    let above = |celsius: i32| temperature.map_or(false, |t| t >= celsius * 1000);
+
+    if above(CRITICAL_CELSIUS) || alarms & STATLBT_TJALRM2 != 0 {
+        power::power_off();
+    }
+
+    if above(LIMIT_CELSIUS) || alarms & STATLBT_TJALRM1 != 0 {
+        Status::Hot
+    } else {
+        Status::Normal
+    }
+}
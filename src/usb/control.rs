@@ -0,0 +1,244 @@
+//! Standard requests on the default control endpoint.
+//!
+//! This module answers the chapter 9 requests a host issues during enumeration from a
+//! static set of descriptors. It does not access any hardware; the driver forwards each
+//! setup packet and carries out the returned [`Response`].
+//!
+//! [`Response`]: enum.Response.html
+
+/// The size of a setup packet in bytes.
+pub const SETUP_SIZE: usize = 8;
+
+/// The maximum packet size of the default control endpoint.
+pub const EP0_MAX_PACKET_SIZE: u16 = 64;
+
+/// The maximum packet size of a high-speed bulk endpoint.
+pub const BULK_MAX_PACKET_SIZE: u16 = 512;
+
+/// The vendor ID of pid.codes, the open source USB ID registry.
+const VENDOR_ID: u16 = 0x1209;
+
+/// The product ID reserved by pid.codes for testing.
+const PRODUCT_ID: u16 = 0x0001;
+
+const GET_STATUS: u8 = 0x00;
+const CLEAR_FEATURE: u8 = 0x01;
+const SET_FEATURE: u8 = 0x03;
+const SET_ADDRESS: u8 = 0x05;
+const GET_DESCRIPTOR: u8 = 0x06;
+const GET_CONFIGURATION: u8 = 0x08;
+const SET_CONFIGURATION: u8 = 0x09;
+const GET_INTERFACE: u8 = 0x0A;
+const SET_INTERFACE: u8 = 0x0B;
+
+const DESCRIPTOR_DEVICE: u8 = 0x01;
+const DESCRIPTOR_CONFIGURATION: u8 = 0x02;
+const DESCRIPTOR_STRING: u8 = 0x03;
+const DESCRIPTOR_INTERFACE: u8 = 0x04;
+const DESCRIPTOR_ENDPOINT: u8 = 0x05;
+const DESCRIPTOR_DEVICE_QUALIFIER: u8 = 0x06;
+
+/// The value of the only configuration of the device.
+pub const CONFIGURATION_VALUE: u8 = 1;
+
+/// The bulk OUT endpoint of the vendor interface.
+pub const BULK_OUT: u8 = 0x01;
+
+/// The bulk IN endpoint of the vendor interface.
+pub const BULK_IN: u8 = 0x81;
+
+/// The total size of a configuration descriptor with one interface and two endpoints.
+const CONFIGURATION_SIZE: usize = 9 + 9 + 2 * 7;
+
+/// The fields of a setup packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupPacket {
+    /// Decodes a setup packet as received on the wire.
+    pub fn parse(bytes: [u8; SETUP_SIZE]) -> Self {
+        SetupPacket {
+            request_type: bytes[0],
+            request: bytes[1],
+            value: u16::from_le_bytes([bytes[2], bytes[3]]),
+            index: u16::from_le_bytes([bytes[4], bytes[5]]),
+            length: u16::from_le_bytes([bytes[6], bytes[7]]),
+        }
+    }
+
+    /// Whether the request is a standard request, as opposed to a class or vendor one.
+    fn is_standard(&self) -> bool {
+        self.request_type & 0x60 == 0
+    }
+}
+
+/// The descriptors presented to the host.
+pub struct Descriptors {
+    /// The device descriptor.
+    pub device: [u8; 18],
+    /// The device qualifier descriptor.
+    pub qualifier: [u8; 10],
+    /// The configuration descriptor, followed by its interface and endpoints.
+    pub configuration: [u8; CONFIGURATION_SIZE],
+    /// The string descriptors, starting with the supported languages at index 0.
+    pub strings: &'static [&'static [u8]],
+}
+
+impl Descriptors {
+    /// Describes a device with a single interface of the given class, subclass and
+    /// protocol that has one bulk endpoint in either direction.
+    pub const fn bulk(
+        class: u8,
+        subclass: u8,
+        protocol: u8,
+        strings: &'static [&'static [u8]],
+    ) -> Self {
+        let [vendor_lo, vendor_hi] = VENDOR_ID.to_le_bytes();
+        let [product_lo, product_hi] = PRODUCT_ID.to_le_bytes();
+        let [bulk_lo, bulk_hi] = BULK_MAX_PACKET_SIZE.to_le_bytes();
+        let ep0 = EP0_MAX_PACKET_SIZE as u8;
+
+        Descriptors {
+            #[rustfmt::skip]
+            device: [
+                18, DESCRIPTOR_DEVICE,
+                0x00, 0x02, // USB 2.0
+                0, 0, 0, // Class defined by the interface
+                ep0,
+                vendor_lo, vendor_hi,
+                product_lo, product_hi,
+                0x00, 0x01, // Device release 1.0
+                1, 2, 0, // Manufacturer, product, no serial number
+                1,
+            ],
+            #[rustfmt::skip]
+            qualifier: [
+                10, DESCRIPTOR_DEVICE_QUALIFIER,
+                0x00, 0x02,
+                0, 0, 0,
+                ep0,
+                1,
+                0,
+            ],
+            #[rustfmt::skip]
+            configuration: [
+                9, DESCRIPTOR_CONFIGURATION,
+                CONFIGURATION_SIZE as u8, 0,
+                1, // One interface
+                CONFIGURATION_VALUE,
+                0,
+                0x80, // Bus-powered
+                50, // 100mA
+
+                9, DESCRIPTOR_INTERFACE,
+                0, 0, // Interface 0, alternate setting 0
+                2, // Two endpoints
+                class, subclass, protocol,
+                2,
+
+                7, DESCRIPTOR_ENDPOINT, BULK_OUT, 0x02, bulk_lo, bulk_hi, 0,
+                7, DESCRIPTOR_ENDPOINT, BULK_IN, 0x02, bulk_lo, bulk_hi, 0,
+            ],
+            strings,
+        }
+    }
+}
+
+/// The string descriptor listing US English as the only supported language.
+pub const LANGUAGES: [u8; 4] = [4, DESCRIPTOR_STRING, 0x09, 0x04];
+
+/// Encodes an ASCII string as a string descriptor of `N` bytes.
+///
+/// `N` has to be `2 + 2 * s.len()`, which is checked at compile time for constants.
+pub const fn string_descriptor<const N: usize>(s: &[u8]) -> [u8; N] {
+    let mut descriptor = [0; N];
+    descriptor[0] = N as u8;
+    descriptor[1] = DESCRIPTOR_STRING;
+
+    let mut index = 0;
+    while index < s.len() {
+        descriptor[2 + 2 * index] = s[index];
+        index += 1;
+    }
+
+    descriptor
+}
+
+/// How the driver should complete a control transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Response {
+    /// Send the data to the host, then receive the status stage.
+    Data(&'static [u8]),
+    /// Acknowledge the request with an empty status stage.
+    Ack,
+    /// Acknowledge the request, then switch to the given address.
+    SetAddress(u8),
+    /// Acknowledge the request, then switch to the given configuration.
+    SetConfiguration(u8),
+    /// Reject the request.
+    Stall,
+}
+
+static ZERO_STATUS: [u8; 2] = [0, 0];
+static CONFIGURATIONS: [u8; 2] = [0, CONFIGURATION_VALUE];
+
+/// Determines the response to `setup`, given the current `configuration` of the device.
+pub fn handle(
+    setup: &SetupPacket,
+    descriptors: &'static Descriptors,
+    configuration: u8,
+) -> Response {
+    if !setup.is_standard() {
+        return Response::Stall;
+    }
+
+    let response = match setup.request {
+        // Neither remote wakeup nor self-power is supported and no endpoint is ever halted.
+        GET_STATUS => Response::Data(&ZERO_STATUS),
+        CLEAR_FEATURE | SET_FEATURE => Response::Stall,
+        SET_ADDRESS if setup.value < 0x80 => Response::SetAddress(setup.value as u8),
+        GET_DESCRIPTOR => match descriptor(setup.value, descriptors) {
+            Some(data) => Response::Data(data),
+            None => Response::Stall,
+        },
+        GET_CONFIGURATION => {
+            let configured = (configuration == CONFIGURATION_VALUE) as usize;
+            Response::Data(&CONFIGURATIONS[configured..configured + 1])
+        }
+        SET_CONFIGURATION if setup.value <= CONFIGURATION_VALUE as u16 => {
+            Response::SetConfiguration(setup.value as u8)
+        }
+        GET_INTERFACE if configuration != 0 && setup.index == 0 => {
+            Response::Data(&ZERO_STATUS[..1])
+        }
+        SET_INTERFACE if configuration != 0 && setup.index == 0 && setup.value == 0 => {
+            Response::Ack
+        }
+        _ => Response::Stall,
+    };
+
+    // Never send more than the host asked for.
+    match response {
+        Response::Data(data) => Response::Data(&data[..data.len().min(setup.length as usize)]),
+        response => response,
+    }
+}
+
+/// Looks up the descriptor requested by the `value` of a `GET_DESCRIPTOR` request.
+fn descriptor(value: u16, descriptors: &'static Descriptors) -> Option<&'static [u8]> {
+    let index = (value & 0xFF) as usize;
+
+    match (value >> 8) as u8 {
+        DESCRIPTOR_DEVICE => Some(&descriptors.device),
+        DESCRIPTOR_DEVICE_QUALIFIER => Some(&descriptors.qualifier),
+        DESCRIPTOR_CONFIGURATION if index == 0 => Some(&descriptors.configuration),
+        DESCRIPTOR_STRING => descriptors.strings.get(index).copied(),
+        _ => None,
+    }
+}
@@ -0,0 +1,563 @@
+//! A minimal USB device driver for the USB2 OTG controller.
+//!
+//! The controller is ChipIdea-compatible: every endpoint has a queue head in a list in
+//! memory, and transfers are described by transfer descriptors that are linked into
+//! these queue heads. Since the first stage never has more than one transfer in flight
+//! per endpoint, each endpoint owns exactly one transfer descriptor and all transfers
+//! are polled to completion.
+//!
+//! The queue heads and transfer descriptors live in the unused IRAM between the
+//! framebuffer and the stack.
+//!
+//! Waits on the controller itself and control transfers are bounded, but bulk
+//! transfers wait for the host for as long as it takes.
+
+pub mod control;
+
+use core::{mem, ptr};
+
+use libtegra::timer::usleep;
+
+use self::control::{Descriptors, Response, SetupPacket, SETUP_SIZE};
+use crate::bus::TimerDelay;
+use crate::deadline::{self, Deadline, Timeout};
+use crate::memory;
+
+/// The base address of the USB2 OTG controller.
+const USB_BASE: usize = 0x7D00_0000;
+
+/// The base address of the Clock and Reset Controller.
+const CAR_BASE: usize = 0x6000_6000;
+
+const CLK_RST_CONTROLLER_RST_DEVICES_L: usize = 0x4;
+const CLK_RST_CONTROLLER_CLK_OUT_ENB_L: usize = 0x10;
+const CLK_RST_CONTROLLER_PLLU_BASE: usize = 0xC0;
+const CLK_RST_CONTROLLER_UTMIP_PLL_CFG0: usize = 0x480;
+const CLK_RST_CONTROLLER_UTMIP_PLL_CFG2: usize = 0x488;
+const USBD_DEVICE_BIT: u32 = 1 << 22;
+
+/// PLLU enabled at 38.4MHz / 2 * 25 = 480MHz.
+const PLLU_BASE: u32 = 1 << 30 | 25 << 8 | 2;
+const PLLU_LOCK: u32 = 1 << 27;
+
+/// UTMIPLL at 38.4MHz * 25 = 960MHz.
+const UTMIP_PLL_CFG0: u32 = 25 << 16 | 1 << 8;
+/// Force UTMIPLL and all of its outputs out of power-down.
+const UTMIP_PLL_CFG2_POWERDOWN: u32 = 0x3F | 1 << 30;
+
+const USB_SUSP_CTRL: usize = 0x400;
+const UTMIP_XCVR_CFG0: usize = 0x808;
+const UTMIP_BIAS_CFG0: usize = 0x80C;
+const UTMIP_XCVR_CFG1: usize = 0x838;
+
+const SUSP_CTRL_PHY_CLK_VALID: u32 = 1 << 7;
+const SUSP_CTRL_UTMIP_RESET: u32 = 1 << 11;
+const XCVR_CFG0_POWERDOWN: u32 = 1 << 14 | 1 << 16 | 1 << 18;
+const XCVR_CFG1_POWERDOWN: u32 = 1 << 0 | 1 << 2 | 1 << 4;
+const BIAS_CFG0_POWERDOWN: u32 = 1 << 10 | 1 << 11;
+
+const USBCMD: usize = 0x130;
+const USBSTS: usize = 0x134;
+const DEVICEADDR: usize = 0x144;
+const ENDPOINTLISTADDR: usize = 0x148;
+const USBMODE: usize = 0x1F8;
+const ENDPTSETUPSTAT: usize = 0x208;
+const ENDPTPRIME: usize = 0x20C;
+const ENDPTFLUSH: usize = 0x210;
+const ENDPTCOMPLETE: usize = 0x218;
+const ENDPTCTRL0: usize = 0x21C;
+
+const USBCMD_RUN: u32 = 1 << 0;
+const USBCMD_RESET: u32 = 1 << 1;
+const USBCMD_SETUP_TRIPWIRE: u32 = 1 << 13;
+const USBSTS_RESET: u32 = 1 << 6;
+const USBMODE_DEVICE: u32 = 0x2;
+const USBMODE_SETUP_LOCKOUT_OFF: u32 = 1 << 3;
+const DEVICEADDR_ADVANCE: u32 = 1 << 24;
+
+const ENDPTCTRL_RX_STALL: u32 = 1 << 0;
+const ENDPTCTRL_RX_BULK: u32 = 2 << 2;
+const ENDPTCTRL_RX_TOGGLE_RESET: u32 = 1 << 6;
+const ENDPTCTRL_RX_ENABLE: u32 = 1 << 7;
+const ENDPTCTRL_TX_STALL: u32 = 1 << 16;
+const ENDPTCTRL_TX_BULK: u32 = 2 << 18;
+const ENDPTCTRL_TX_TOGGLE_RESET: u32 = 1 << 22;
+const ENDPTCTRL_TX_ENABLE: u32 = 1 << 23;
+
+/// The address of the endpoint queue head list, which has to be 2K-aligned.
+///
+/// Only endpoints 0 and 1 are used, so the list holds four queue heads.
+const QUEUE_HEADS: usize = memory::USB_QUEUES.start;
+/// The number of queue heads in the list.
+const QUEUE_HEAD_COUNT: usize = 4;
+/// The address of the transfer descriptors, one per queue head.
+const TRANSFER_DESCRIPTORS: usize = QUEUE_HEADS + QUEUE_HEAD_COUNT * mem::size_of::<QueueHead>();
+
+// Fails to compile if the queue heads are misaligned or outgrow their region of IRAM
+// along with the descriptors.
+const _: [(); 1] = [(); (QUEUE_HEADS % 0x800 == 0
+    && memory::fits(
+        QUEUE_HEAD_COUNT * (mem::size_of::<QueueHead>() + mem::size_of::<TransferDescriptor>()),
+        memory::USB_QUEUES,
+    )) as usize];
+
+const QH_INTERRUPT_ON_SETUP: u32 = 1 << 15;
+const QH_ZERO_LENGTH_TERMINATION_OFF: u32 = 1 << 29;
+
+const DTD_TERMINATE: u32 = 1;
+const DTD_INTERRUPT_ON_COMPLETE: u32 = 1 << 15;
+const DTD_ACTIVE: u32 = 1 << 7;
+const DTD_HALTED: u32 = 1 << 6;
+const DTD_BUFFER_ERROR: u32 = 1 << 5;
+const DTD_TRANSACTION_ERROR: u32 = 1 << 3;
+
+/// The time PLLU may take to lock, in microseconds.
+const PLL_LOCK_TIMEOUT_US: u32 = 1000;
+/// The time the controller may take to reset, to flush its endpoints or for the PHY
+/// clock to come up, in microseconds.
+const CONTROLLER_TIMEOUT_US: u32 = 100_000;
+/// The time the host may take to complete a stage of a control transfer, in
+/// microseconds.
+const CONTROL_TIMEOUT_US: u32 = 5_000_000;
+
+/// The largest transfer a single descriptor can cover, regardless of buffer alignment.
+pub const MAX_TRANSFER_SIZE: usize = 0x4000;
+
+/// Errors that may occur during USB transfers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The host reset the bus while a transfer was pending.
+    Reset,
+    /// A transfer ended with the given error status.
+    TransferFailed(u8),
+    /// The controller or the host did not respond in time.
+    Timeout,
+}
+
+impl From<Timeout> for Error {
+    fn from(_: Timeout) -> Self {
+        Error::Timeout
+    }
+}
+
+/// An endpoint queue head as laid out in the list.
+#[repr(C, align(64))]
+struct QueueHead {
+    capabilities: u32,
+    current: u32,
+    next: u32,
+    token: u32,
+    buffers: [u32; 5],
+    _reserved: u32,
+    setup: [u8; SETUP_SIZE],
+    _padding: [u32; 4],
+}
+
+/// A transfer descriptor.
+#[repr(C, align(32))]
+struct TransferDescriptor {
+    next: u32,
+    token: u32,
+    buffers: [u32; 5],
+    _padding: u32,
+}
+
+/// The direction of an endpoint, from the point of view of the host.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Out = 0,
+    In = 1,
+}
+
+/// An endpoint and direction, which identifies a queue head.
+#[derive(Clone, Copy)]
+struct Endpoint {
+    number: usize,
+    direction: Direction,
+}
+
+impl Endpoint {
+    const CONTROL_OUT: Endpoint = Endpoint::new(0, Direction::Out);
+    const CONTROL_IN: Endpoint = Endpoint::new(0, Direction::In);
+    const BULK_OUT: Endpoint = Endpoint::new(1, Direction::Out);
+    const BULK_IN: Endpoint = Endpoint::new(1, Direction::In);
+
+    const fn new(number: usize, direction: Direction) -> Self {
+        Endpoint { number, direction }
+    }
+
+    fn index(self) -> usize {
+        self.number * 2 + self.direction as usize
+    }
+
+    /// Gets the bit of the endpoint in the prime, status and complete registers.
+    fn bit(self) -> u32 {
+        1 << (self.number + 16 * self.direction as usize)
+    }
+
+    fn queue_head(self) -> *mut QueueHead {
+        (QUEUE_HEADS as *mut QueueHead).wrapping_add(self.index())
+    }
+
+    fn transfer_descriptor(self) -> *mut TransferDescriptor {
+        (TRANSFER_DESCRIPTORS as *mut TransferDescriptor).wrapping_add(self.index())
+    }
+}
+
+fn read_reg(offset: usize) -> u32 {
+    unsafe { ptr::read_volatile((USB_BASE + offset) as *const u32) }
+}
+
+fn write_reg(offset: usize, value: u32) {
+    unsafe { ptr::write_volatile((USB_BASE + offset) as *mut u32, value) }
+}
+
+fn modify_reg(offset: usize, clear: u32, set: u32) {
+    write_reg(offset, (read_reg(offset) & !clear) | set);
+}
+
+fn modify_car_reg(offset: usize, clear: u32, set: u32) {
+    let reg = (CAR_BASE + offset) as *mut u32;
+    unsafe { ptr::write_volatile(reg, (ptr::read_volatile(reg) & !clear) | set) }
+}
+
+fn write_car_reg(offset: usize, value: u32) {
+    unsafe { ptr::write_volatile((CAR_BASE + offset) as *mut u32, value) }
+}
+
+fn read_car_reg(offset: usize) -> u32 {
+    unsafe { ptr::read_volatile((CAR_BASE + offset) as *const u32) }
+}
+
+fn enable_clocks() -> Result<(), Timeout> {
+    // Bring up PLLU and the UTMI PLL derived from it.
+    write_car_reg(CLK_RST_CONTROLLER_PLLU_BASE, PLLU_BASE);
+    deadline::poll_until(
+        &TimerDelay,
+        || read_car_reg(CLK_RST_CONTROLLER_PLLU_BASE) & PLLU_LOCK != 0,
+        PLL_LOCK_TIMEOUT_US,
+    )?;
+    modify_car_reg(CLK_RST_CONTROLLER_UTMIP_PLL_CFG0, 0xFF_FF00, UTMIP_PLL_CFG0);
+    modify_car_reg(
+        CLK_RST_CONTROLLER_UTMIP_PLL_CFG2,
+        UTMIP_PLL_CFG2_POWERDOWN,
+        0,
+    );
+    usleep(10);
+
+    // Assert reset, enable the clock and release USBD.
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_L, 0, USBD_DEVICE_BIT);
+    modify_car_reg(CLK_RST_CONTROLLER_CLK_OUT_ENB_L, 0, USBD_DEVICE_BIT);
+    usleep(2);
+    modify_car_reg(CLK_RST_CONTROLLER_RST_DEVICES_L, USBD_DEVICE_BIT, 0);
+
+    Ok(())
+}
+
+fn power_up_phy() -> Result<(), Timeout> {
+    // Power up the transceiver and bias circuitry while the PHY is held in reset.
+    modify_reg(USB_SUSP_CTRL, 0, SUSP_CTRL_UTMIP_RESET);
+    modify_reg(UTMIP_XCVR_CFG0, XCVR_CFG0_POWERDOWN, 0);
+    modify_reg(UTMIP_XCVR_CFG1, XCVR_CFG1_POWERDOWN, 0);
+    modify_reg(UTMIP_BIAS_CFG0, BIAS_CFG0_POWERDOWN, 0);
+    usleep(10);
+
+    modify_reg(USB_SUSP_CTRL, SUSP_CTRL_UTMIP_RESET, 0);
+    deadline::poll_until(
+        &TimerDelay,
+        || read_reg(USB_SUSP_CTRL) & SUSP_CTRL_PHY_CLK_VALID != 0,
+        CONTROLLER_TIMEOUT_US,
+    )
+}
+
+/// Sets up the queue head of `endpoint` for packets of up to `max_packet_size` bytes.
+fn init_queue_head(endpoint: Endpoint, max_packet_size: u16) {
+    let mut capabilities = (max_packet_size as u32) << 16 | QH_ZERO_LENGTH_TERMINATION_OFF;
+    if endpoint.number == 0 && endpoint.direction == Direction::Out {
+        capabilities |= QH_INTERRUPT_ON_SETUP;
+    }
+
+    unsafe {
+        let queue_head = endpoint.queue_head();
+        ptr::write_volatile(
+            queue_head,
+            QueueHead {
+                capabilities,
+                current: 0,
+                next: DTD_TERMINATE,
+                token: 0,
+                buffers: [0; 5],
+                _reserved: 0,
+                setup: [0; SETUP_SIZE],
+                _padding: [0; 4],
+            },
+        );
+    }
+}
+
+/// Cancels all pending transfers.
+fn flush_endpoints() -> Result<(), Timeout> {
+    write_reg(ENDPTFLUSH, 0xFFFF_FFFF);
+    deadline::poll_until(
+        &TimerDelay,
+        || read_reg(ENDPTFLUSH) == 0,
+        CONTROLLER_TIMEOUT_US,
+    )?;
+    write_reg(ENDPTCOMPLETE, read_reg(ENDPTCOMPLETE));
+    Ok(())
+}
+
+/// Reads the setup packet of the control endpoint.
+fn read_setup_packet() -> SetupPacket {
+    let queue_head = Endpoint::CONTROL_OUT.queue_head();
+
+    // The tripwire is cleared by the controller if another setup packet arrives while
+    // the buffer is being copied.
+    let bytes = loop {
+        modify_reg(USBCMD, 0, USBCMD_SETUP_TRIPWIRE);
+        let bytes = unsafe { ptr::read_volatile(&(*queue_head).setup) };
+        if read_reg(USBCMD) & USBCMD_SETUP_TRIPWIRE != 0 {
+            break bytes;
+        }
+    };
+    modify_reg(USBCMD, USBCMD_SETUP_TRIPWIRE, 0);
+    write_reg(ENDPTSETUPSTAT, Endpoint::CONTROL_OUT.bit());
+
+    SetupPacket::parse(bytes)
+}
+
+/// The USB controller, operating as a device with a single configuration.
+pub struct UsbDevice {
+    descriptors: &'static Descriptors,
+    configuration: u8,
+}
+
+impl UsbDevice {
+    /// Powers up the controller and the PHY and attaches to the bus, presenting the
+    /// given `descriptors` to the host.
+    ///
+    /// Fails with [`Error::Timeout`] if the clocks, the PHY or the controller do not
+    /// come up.
+    ///
+    /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+    pub fn init(descriptors: &'static Descriptors) -> Result<Self, Error> {
+        enable_clocks()?;
+        power_up_phy()?;
+
+        // Reset the controller and switch it to device mode.
+        write_reg(USBCMD, USBCMD_RESET);
+        deadline::poll_until(
+            &TimerDelay,
+            || read_reg(USBCMD) & USBCMD_RESET == 0,
+            CONTROLLER_TIMEOUT_US,
+        )?;
+        write_reg(USBMODE, USBMODE_DEVICE | USBMODE_SETUP_LOCKOUT_OFF);
+
+        init_queue_head(Endpoint::CONTROL_OUT, control::EP0_MAX_PACKET_SIZE);
+        init_queue_head(Endpoint::CONTROL_IN, control::EP0_MAX_PACKET_SIZE);
+        write_reg(ENDPOINTLISTADDR, QUEUE_HEADS as u32);
+
+        // Setting the run bit enables the pull-up, which makes the host notice us.
+        modify_reg(USBCMD, 0, USBCMD_RUN);
+
+        Ok(UsbDevice {
+            descriptors,
+            configuration: 0,
+        })
+    }
+
+    /// Detaches from the bus by stopping the controller, which drops the pull-up.
+    pub fn detach(self) {
+        // The controller is stopped either way.
+        let _ = flush_endpoints();
+        modify_reg(USBCMD, USBCMD_RUN, 0);
+    }
+
+    /// Whether the host has selected the configuration with the bulk endpoints.
+    pub fn is_configured(&self) -> bool {
+        self.configuration == control::CONFIGURATION_VALUE
+    }
+
+    /// Handles pending bus resets and control requests.
+    ///
+    /// Returns [`Error::Reset`] if the bus was reset, which cancels all transfers and
+    /// deconfigures the device.
+    ///
+    /// [`Error::Reset`]: enum.Error.html#variant.Reset
+    pub fn poll(&mut self) -> Result<(), Error> {
+        if read_reg(USBSTS) & USBSTS_RESET != 0 {
+            write_reg(USBSTS, USBSTS_RESET);
+            write_reg(ENDPTSETUPSTAT, read_reg(ENDPTSETUPSTAT));
+            write_reg(DEVICEADDR, 0);
+            self.configuration = 0;
+            flush_endpoints()?;
+
+            return Err(Error::Reset);
+        }
+
+        if read_reg(ENDPTSETUPSTAT) & Endpoint::CONTROL_OUT.bit() != 0 {
+            let setup = read_setup_packet();
+            self.handle_setup(&setup)?;
+        }
+
+        Ok(())
+    }
+
+    /// Receives a single transfer of up to `buffer.len()` bytes from the bulk OUT
+    /// endpoint, while servicing control requests.
+    ///
+    /// Returns the number of bytes received.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let length = buffer.len().min(MAX_TRANSFER_SIZE);
+        self.transfer(Endpoint::BULK_OUT, buffer.as_mut_ptr() as u32, length)
+    }
+
+    /// Sends `data` to the bulk IN endpoint, while servicing control requests.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        for chunk in data.chunks(MAX_TRANSFER_SIZE) {
+            self.transfer(Endpoint::BULK_IN, chunk.as_ptr() as u32, chunk.len())?;
+        }
+        Ok(())
+    }
+
+    fn handle_setup(&mut self, setup: &SetupPacket) -> Result<(), Error> {
+        match control::handle(setup, self.descriptors, self.configuration) {
+            Response::Data(data) => {
+                self.control_transfer(Endpoint::CONTROL_IN, data.as_ptr() as u32, data.len())?;
+                self.control_transfer(Endpoint::CONTROL_OUT, 0, 0)?;
+            }
+            Response::Ack => self.control_transfer(Endpoint::CONTROL_IN, 0, 0)?,
+            Response::SetAddress(address) => {
+                // The controller switches to the new address after the status stage.
+                write_reg(DEVICEADDR, (address as u32) << 25 | DEVICEADDR_ADVANCE);
+                self.control_transfer(Endpoint::CONTROL_IN, 0, 0)?;
+            }
+            Response::SetConfiguration(configuration) => {
+                self.configure(configuration);
+                self.control_transfer(Endpoint::CONTROL_IN, 0, 0)?;
+            }
+            Response::Stall => write_reg(
+                ENDPTCTRL0,
+                read_reg(ENDPTCTRL0) | ENDPTCTRL_RX_STALL | ENDPTCTRL_TX_STALL,
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Enables or disables the bulk endpoints.
+    fn configure(&mut self, configuration: u8) {
+        let ctrl = ENDPTCTRL0 + 4 * Endpoint::BULK_OUT.number;
+        if configuration == control::CONFIGURATION_VALUE {
+            init_queue_head(Endpoint::BULK_OUT, control::BULK_MAX_PACKET_SIZE);
+            init_queue_head(Endpoint::BULK_IN, control::BULK_MAX_PACKET_SIZE);
+            write_reg(
+                ctrl,
+                ENDPTCTRL_RX_ENABLE
+                    | ENDPTCTRL_RX_TOGGLE_RESET
+                    | ENDPTCTRL_RX_BULK
+                    | ENDPTCTRL_TX_ENABLE
+                    | ENDPTCTRL_TX_TOGGLE_RESET
+                    | ENDPTCTRL_TX_BULK,
+            );
+        } else {
+            write_reg(ctrl, 0);
+        }
+
+        self.configuration = configuration;
+    }
+
+    /// Runs a transfer on the control endpoint to completion.
+    ///
+    /// This does not service control requests, as a new setup packet aborts the
+    /// request currently being handled anyway. A transfer that the host does not
+    /// complete in time is cancelled.
+    fn control_transfer(
+        &mut self,
+        endpoint: Endpoint,
+        address: u32,
+        length: usize,
+    ) -> Result<(), Error> {
+        prime(endpoint, address, length);
+
+        let deadline = Deadline::new(&TimerDelay, CONTROL_TIMEOUT_US);
+        loop {
+            if read_reg(USBSTS) & USBSTS_RESET != 0 {
+                return Err(Error::Reset);
+            }
+            if let Some(result) = complete(endpoint, length) {
+                return result.map(|_| ());
+            }
+            if deadline.expired() {
+                flush_endpoints()?;
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    /// Runs a transfer on a bulk endpoint to completion, while servicing control
+    /// requests.
+    fn transfer(
+        &mut self,
+        endpoint: Endpoint,
+        address: u32,
+        length: usize,
+    ) -> Result<usize, Error> {
+        prime(endpoint, address, length);
+        loop {
+            self.poll()?;
+            if let Some(result) = complete(endpoint, length) {
+                return result;
+            }
+        }
+    }
+}
+
+/// Queues a transfer of `length` bytes at `address` on `endpoint`.
+fn prime(endpoint: Endpoint, address: u32, length: usize) {
+    let page = address & !0xFFF;
+    let descriptor = endpoint.transfer_descriptor();
+
+    unsafe {
+        ptr::write_volatile(
+            descriptor,
+            TransferDescriptor {
+                next: DTD_TERMINATE,
+                token: (length as u32) << 16 | DTD_INTERRUPT_ON_COMPLETE | DTD_ACTIVE,
+                buffers: [
+                    address,
+                    page + 0x1000,
+                    page + 0x2000,
+                    page + 0x3000,
+                    page + 0x4000,
+                ],
+                _padding: 0,
+            },
+        );
+
+        let queue_head = endpoint.queue_head();
+        ptr::write_volatile(&mut (*queue_head).next, descriptor as u32);
+        ptr::write_volatile(&mut (*queue_head).token, 0);
+    }
+
+    write_reg(ENDPTPRIME, endpoint.bit());
+}
+
+/// Checks whether the transfer on `endpoint` has completed.
+///
+/// Returns the number of bytes transferred, out of `length` requested bytes, once it has.
+fn complete(endpoint: Endpoint, length: usize) -> Option<Result<usize, Error>> {
+    if read_reg(ENDPTCOMPLETE) & endpoint.bit() == 0 {
+        return None;
+    }
+    write_reg(ENDPTCOMPLETE, endpoint.bit());
+
+    let token = unsafe { ptr::read_volatile(&(*endpoint.transfer_descriptor()).token) };
+    let status = token & (DTD_HALTED | DTD_BUFFER_ERROR | DTD_TRANSACTION_ERROR);
+    if status != 0 {
+        return Some(Err(Error::TransferFailed(status as u8)));
+    }
+
+    let remaining = (token >> 16 & 0x7FFF) as usize;
+    Some(Ok(length - remaining))
+}
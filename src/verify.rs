@@ -0,0 +1,108 @@
+//! Verification of the boot manifest and the payloads it describes.
+
+use core::slice;
+
+use manifest::{Entry, Manifest, MAX_MANIFEST_SIZE};
+
+#[cfg(feature = "ed25519")]
+use crate::crypto::ed25519::{PublicKey, SIGNATURE_SIZE};
+#[cfg(not(feature = "ed25519"))]
+use crate::crypto::rsa::{PublicKey, SIGNATURE_SIZE};
+use crate::crypto::{self, ct_eq, sha256::sha256, Verifier};
+#[cfg(feature = "ed25519")]
+use crate::keys::ED25519_PUBLIC_KEY as PUBLIC_KEY;
+#[cfg(not(feature = "ed25519"))]
+use crate::keys::RSA_MODULUS as PUBLIC_KEY;
+use crate::{BOOTLOADER_MANIFEST, BOOTLOADER_SIZE, BOOTLOADER_START};
+
+/// Errors that may occur while verifying the manifest or a payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The manifest is malformed.
+    Manifest(manifest::Error),
+    /// The signature of the manifest is invalid.
+    Crypto(crypto::Error),
+    /// The manifest does not describe the requested payload.
+    MissingPayload,
+    /// The payload size does not match the manifest.
+    SizeMismatch,
+    /// The payload hash does not match the manifest.
+    HashMismatch,
+    /// The payload requires a newer version of the bootloader.
+    VersionTooOld,
+}
+
+impl From<manifest::Error> for Error {
+    fn from(error: manifest::Error) -> Self {
+        Error::Manifest(error)
+    }
+}
+
+impl From<crypto::Error> for Error {
+    fn from(error: crypto::Error) -> Self {
+        Error::Crypto(error)
+    }
+}
+
+/// Gets the version of this bootloader in the packed representation of manifests.
+fn bootloader_version() -> u32 {
+    let part = |s: &str| s.parse().unwrap_or(0);
+
+    manifest::version(
+        part(env!("CARGO_PKG_VERSION_MAJOR")),
+        part(env!("CARGO_PKG_VERSION_MINOR")),
+        part(env!("CARGO_PKG_VERSION_PATCH")),
+    )
+}
+
+/// Decodes the manifest in `bytes` and verifies its signature.
+///
+/// The signature scheme is RSA-2048 PSS by default and Ed25519 when the `ed25519`
+/// feature is enabled.
+pub fn verify_manifest(bytes: &[u8]) -> Result<Manifest, Error> {
+    let (manifest, signed, signature) = Manifest::decode(bytes)?;
+    if signature.len() != SIGNATURE_SIZE {
+        return Err(Error::Crypto(crypto::Error::InvalidSignature));
+    }
+
+    let key = PublicKey::new(&PUBLIC_KEY)?;
+    key.verify(signed, signature)?;
+
+    Ok(manifest)
+}
+
+/// Verifies a loaded `payload` against its manifest `entry`.
+pub fn verify_payload(entry: &Entry, payload: &[u8]) -> Result<(), Error> {
+    if entry.min_version > bootloader_version() {
+        return Err(Error::VersionTooOld);
+    }
+    if payload.len() != entry.size as usize {
+        return Err(Error::SizeMismatch);
+    }
+    if !ct_eq(&sha256(payload), &entry.hash) {
+        return Err(Error::HashMismatch);
+    }
+
+    Ok(())
+}
+
+/// Verifies the second-stage bootloader in memory against the boot manifest.
+pub fn verify_second_stage() -> Result<Manifest, Error> {
+    let manifest =
+        verify_manifest(unsafe { slice::from_raw_parts(BOOTLOADER_MANIFEST, MAX_MANIFEST_SIZE) })?;
+
+    let entry = manifest
+        .entries()
+        .iter()
+        .find(|entry| entry.load_address == BOOTLOADER_START as u32)
+        .ok_or(Error::MissingPayload)?;
+    if entry.size as usize > BOOTLOADER_SIZE {
+        return Err(Error::SizeMismatch);
+    }
+
+    verify_payload(entry, unsafe {
+        slice::from_raw_parts(BOOTLOADER_START as *const u8, entry.size as usize)
+    })?;
+
+    Ok(manifest)
+}
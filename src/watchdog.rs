@@ -0,0 +1,181 @@
+//! A watchdog that resets the system if the boot hangs.
+//!
+//! The watchdog is armed at the very start of the entrypoint and has to be petted at
+//! every [`Checkpoint`] of the boot flow, at most [`TIMEOUT_MS`] after the previous one.
+//! Each checkpoint is recorded in a PMC scratch register before the watchdog is petted.
+//! Scratch registers survive the watchdog reset, so the next boot can tell in which
+//! stage the previous one hung.
+//!
+//! [`Checkpoint`]: enum.Checkpoint.html
+//! [`TIMEOUT_MS`]: constant.TIMEOUT_MS.html
+
+use core::ptr;
+
+use libtegra::pmc;
+
+/// The base address of the timers and watchdogs.
+const TIMER_BASE: usize = 0x6000_5000;
+
+/// The registers of the timer that clocks the watchdog.
+const TIMER_TMR9_PTV: usize = 0x80;
+const TIMER_TMR9_PCR: usize = 0x84;
+
+/// The registers of the watchdog, which is not used by the boot ROM.
+const TIMER_WDT4_CONFIG: usize = 0x180;
+const TIMER_WDT4_COMMAND: usize = 0x188;
+const TIMER_WDT4_UNLOCK_PATTERN: usize = 0x18C;
+
+const TIMER_ENABLE: u32 = 1 << 31;
+const TIMER_PERIODIC: u32 = 1 << 30;
+const TIMER_INTR_CLR: u32 = 1 << 30;
+
+const WDT_SOURCE_TMR9: u32 = 9;
+const WDT_PERIOD_1: u32 = 1 << 4;
+const WDT_PMC2CAR_RESET: u32 = 1 << 15;
+const WDT_START_COUNTER: u32 = 1 << 0;
+const WDT_DISABLE_COUNTER: u32 = 1 << 1;
+const WDT_UNLOCK_PATTERN: u32 = 0xC45A;
+
+/// The watchdog resets the system on the fourth expiration of its timer. The earlier
+/// ones only raise interrupts, which are not enabled.
+const EXPIRATIONS_TO_RESET: u32 = 4;
+
+/// The maximum time between two checkpoints in milliseconds.
+pub const TIMEOUT_MS: u32 = 5000;
+
+/// The reset source reported by the PMC after a watchdog reset.
+const RST_SOURCE_WATCHDOG: u32 = 0x1;
+const RST_SOURCE_MASK: u32 = 0x7;
+
+/// Marks the checkpoint records in the scratch register, `WD` in ASCII.
+const RECORD_MAGIC: u32 = 0x5744_0000;
+
+/// The checkpoints of the boot flow at which the watchdog is petted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Checkpoint {
+    /// Hardware initialization, right after the watchdog was armed.
+    Init = 0x1,
+    /// Display bring-up.
+    Display = 0x2,
+    /// eMMC initialization.
+    Storage = 0x3,
+    /// Loading and verifying the second stage.
+    Load = 0x4,
+    /// Handing over to the next stage.
+    Handoff = 0x5,
+    /// Checking the battery, or waiting for it to charge.
+    Battery = 0x6,
+}
+
+impl Checkpoint {
+    /// Decodes a checkpoint from the value of the scratch register.
+    fn from_record(record: u32) -> Option<Self> {
+        if record & 0xFFFF_0000 != RECORD_MAGIC {
+            return None;
+        }
+
+        match record & 0xFF {
+            0x1 => Some(Checkpoint::Init),
+            0x2 => Some(Checkpoint::Display),
+            0x3 => Some(Checkpoint::Storage),
+            0x4 => Some(Checkpoint::Load),
+            0x5 => Some(Checkpoint::Handoff),
+            0x6 => Some(Checkpoint::Battery),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the watchdog is currently armed.
+static mut ARMED: bool = false;
+
+/// The checkpoint at which the previous boot was reset by the watchdog, if it was.
+static mut PREVIOUS_CRASH: Option<Checkpoint> = None;
+
+fn write_reg(offset: usize, value: u32) {
+    unsafe { ptr::write_volatile((TIMER_BASE + offset) as *mut u32, value) }
+}
+
+fn record(checkpoint: Checkpoint) {
+    let pmc = unsafe { &*pmc::REGISTERS };
+    pmc.APBDEV_PMC_SCRATCH45_0
+        .set(RECORD_MAGIC | checkpoint as u32);
+}
+
+/// Arms the watchdog at the [`Init`] checkpoint.
+///
+/// This has to be called before the reset status in the PMC is cleared, so that a
+/// previous watchdog reset can be detected.
+///
+/// [`Init`]: enum.Checkpoint.html#variant.Init
+pub fn arm() {
+    let pmc = unsafe { &*pmc::REGISTERS };
+
+    // Find out whether the previous boot hung.
+    if pmc.APBDEV_PMC_RST_STATUS_0.get() & RST_SOURCE_MASK == RST_SOURCE_WATCHDOG {
+        unsafe {
+            PREVIOUS_CRASH = Checkpoint::from_record(pmc.APBDEV_PMC_SCRATCH45_0.get());
+        }
+    }
+    record(Checkpoint::Init);
+
+    // Tick the microsecond-based timer so that the fourth expiration resets the system
+    // after the timeout.
+    let period_us = TIMEOUT_MS * 1000 / EXPIRATIONS_TO_RESET;
+    write_reg(
+        TIMER_TMR9_PTV,
+        TIMER_ENABLE | TIMER_PERIODIC | (period_us - 1),
+    );
+    write_reg(TIMER_TMR9_PCR, TIMER_INTR_CLR);
+
+    write_reg(
+        TIMER_WDT4_CONFIG,
+        WDT_SOURCE_TMR9 | WDT_PERIOD_1 | WDT_PMC2CAR_RESET,
+    );
+    write_reg(TIMER_WDT4_COMMAND, WDT_START_COUNTER);
+
+    unsafe {
+        ARMED = true;
+    }
+}
+
+/// Records that the boot reached `checkpoint` and pets the watchdog, if it is armed.
+pub fn checkpoint(checkpoint: Checkpoint) {
+    if unsafe { ARMED } {
+        record(checkpoint);
+
+        // Restarting the counter resets the expiration count.
+        write_reg(TIMER_WDT4_COMMAND, WDT_START_COUNTER);
+    }
+}
+
+/// Disarms the watchdog, e.g. before waiting on the user for an unbounded time.
+pub fn disarm() {
+    write_reg(TIMER_WDT4_UNLOCK_PATTERN, WDT_UNLOCK_PATTERN);
+    write_reg(TIMER_WDT4_COMMAND, WDT_DISABLE_COUNTER);
+    write_reg(TIMER_TMR9_PTV, 0);
+
+    unsafe {
+        ARMED = false;
+    }
+}
+
+/// Hands the watchdog over to the next stage, if it is armed.
+///
+/// With the `watchdog_handover` feature, the watchdog is petted one last time and keeps
+/// running, so the next stage has [`TIMEOUT_MS`] to take it over. Otherwise, it is
+/// disarmed.
+///
+/// [`TIMEOUT_MS`]: constant.TIMEOUT_MS.html
+pub fn hand_over() {
+    checkpoint(Checkpoint::Handoff);
+
+    #[cfg(not(feature = "watchdog_handover"))]
+    disarm();
+}
+
+/// Gets the checkpoint at which the watchdog reset the previous boot, if it did.
+pub fn previous_crash() -> Option<Checkpoint> {
+    unsafe { PREVIOUS_CRASH }
+}
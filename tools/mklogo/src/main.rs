@@ -0,0 +1,105 @@
+//! Host-side tool for converting PNG images into the boot logo format.
+//!
+//! Every pixel is mapped to the closest color of the logo palette, while pixels that
+//! are mostly transparent take the background color. Example:
+//!
+//! ```text
+//! mklogo assets/logo.png -o assets/logo.mlg
+//! ```
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use logo::PALETTE;
+use png::{ColorType, Decoder, Transformations};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Converts PNG images into Mirage boot logos")]
+struct Options {
+    /// The PNG image to convert.
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+
+    /// The palette index that transparent pixels are mapped to.
+    #[structopt(long, default_value = "0")]
+    background: u8,
+
+    /// Where to write the encoded logo to.
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+}
+
+/// Finds the palette index of the color closest to `rgb`.
+fn closest_color(rgb: [u8; 3]) -> u8 {
+    let distance = |color: u32| {
+        let channels = [(color >> 16) as u8, (color >> 8) as u8, color as u8];
+        channels
+            .iter()
+            .zip(rgb.iter())
+            .map(|(&a, &b)| (a as i32 - b as i32).pow(2))
+            .sum::<i32>()
+    };
+
+    (0..PALETTE.len())
+        .min_by_key(|&index| distance(PALETTE[index]))
+        .unwrap() as u8
+}
+
+/// Decodes the PNG at `path` into its dimensions and palette indices.
+fn load_png(path: &Path, background: u8) -> Result<(u32, u32, Vec<u8>), Box<dyn Error>> {
+    let mut decoder = Decoder::new(File::open(path)?);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer)?;
+
+    let (channels, has_alpha) = match info.color_type {
+        ColorType::Grayscale => (1, false),
+        ColorType::GrayscaleAlpha => (2, true),
+        ColorType::RGB => (3, false),
+        ColorType::RGBA => (4, true),
+        ColorType::Indexed => return Err("indexed images were not expanded".into()),
+    };
+
+    let pixels = buffer
+        .chunks(channels)
+        .map(|pixel| {
+            if has_alpha && pixel[channels - 1] < 0x80 {
+                return background;
+            }
+
+            match channels {
+                1 | 2 => closest_color([pixel[0]; 3]),
+                _ => closest_color([pixel[0], pixel[1], pixel[2]]),
+            }
+        })
+        .collect();
+
+    Ok((info.width, info.height, pixels))
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = Options::from_args();
+
+    if options.background as usize >= PALETTE.len() {
+        return Err(format!("the background must be below {}", PALETTE.len()).into());
+    }
+
+    let (width, height, pixels) = load_png(&options.input, options.background)?;
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err("the image is too large".into());
+    }
+
+    // Runs take up to two bytes each, and each run covers at least one pixel.
+    let mut buffer = vec![0; logo::HEADER_SIZE + 2 * pixels.len()];
+    let size = logo::encode(width as u16, height as u16, &pixels, &mut buffer)
+        .map_err(|e| format!("failed to encode the logo: {:?}", e))?;
+
+    fs::write(&options.output, &buffer[..size])?;
+    println!("{}x{} logo, {} bytes", width, height, size);
+
+    Ok(())
+}
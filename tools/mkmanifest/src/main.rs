@@ -0,0 +1,242 @@
+//! Host-side tool for building and signing boot manifests.
+//!
+//! Payloads are hashed and described in a manifest which is then signed by invoking
+//! `openssl`, so that the same keys and tooling as for the bootloader build can be
+//! used. `openssl` has to be on the `PATH`, in version 1.1.1 or newer for Ed25519.
+//! Example:
+//!
+//! ```text
+//! mkmanifest --key keys/dev_rsa.pem --security-version 1 \
+//!     --payload second_stage.bin@0x40016FE0:boot,tsec -o manifest.bin
+//! ```
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::str::FromStr;
+
+use manifest::{Entry, Header, Manifest, FLAG_BOOT, FLAG_TSEC, MAX_ENTRIES, MAX_MANIFEST_SIZE};
+use sha2::{Digest, Sha256};
+use structopt::StructOpt;
+
+/// The signature schemes the bootloader can be built to verify.
+#[derive(Clone, Copy, Debug)]
+enum Scheme {
+    Rsa,
+    Ed25519,
+}
+
+impl Scheme {
+    fn signature_size(self) -> u16 {
+        match self {
+            Scheme::Rsa => 0x100,
+            Scheme::Ed25519 => 0x40,
+        }
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rsa" => Ok(Scheme::Rsa),
+            "ed25519" => Ok(Scheme::Ed25519),
+            _ => Err(format!("unknown signature scheme: {}", s)),
+        }
+    }
+}
+
+/// A payload given on the command line as `PATH@ADDRESS[:FLAG,...]`.
+#[derive(Debug)]
+struct PayloadSpec {
+    path: PathBuf,
+    load_address: u32,
+    flags: u32,
+}
+
+impl FromStr for PayloadSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, rest) = split_once(s, '@').ok_or("expected PATH@ADDRESS[:FLAGS]")?;
+        let (address, flags) = split_once(rest, ':').unwrap_or((rest, ""));
+
+        Ok(PayloadSpec {
+            path: PathBuf::from(path),
+            load_address: parse_u32(address)?,
+            flags: flags
+                .split(',')
+                .filter(|flag| !flag.is_empty())
+                .map(|flag| match flag {
+                    "boot" => Ok(FLAG_BOOT),
+                    "tsec" => Ok(FLAG_TSEC),
+                    _ => Err(format!("unknown payload flag: {}", flag)),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .fold(0, |acc, flag| acc | flag),
+        })
+    }
+}
+
+fn split_once(s: &str, delimiter: char) -> Option<(&str, &str)> {
+    let index = s.find(delimiter)?;
+    Some((&s[..index], &s[index + 1..]))
+}
+
+fn parse_u32(s: &str) -> Result<u32, String> {
+    let result = if let Some(hex) = s.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    };
+
+    result.map_err(|e| format!("invalid number {}: {}", s, e))
+}
+
+fn parse_version(s: &str) -> Result<u32, String> {
+    let parts = s
+        .split('.')
+        .map(|part| part.parse::<u8>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid version {}: {}", s, e))?;
+
+    match parts[..] {
+        [major, minor, patch] => Ok(manifest::version(major, minor, patch)),
+        _ => Err(format!("expected MAJOR.MINOR.PATCH, got {}", s)),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Builds and signs Mirage boot manifests")]
+struct Options {
+    /// The PEM-encoded private key to sign the manifest with.
+    #[structopt(long, parse(from_os_str))]
+    key: PathBuf,
+
+    /// The signature scheme the bootloader was built for: `rsa` or `ed25519`.
+    #[structopt(long, default_value = "rsa")]
+    scheme: Scheme,
+
+    /// The security version of the manifest, used for rollback protection.
+    #[structopt(long, default_value = "0")]
+    security_version: u32,
+
+    /// The minimum bootloader version required to load the payloads.
+    #[structopt(long, default_value = "0.1.0", parse(try_from_str = parse_version))]
+    min_version: u32,
+
+    /// A payload to include, given as `PATH@ADDRESS[:FLAG,...]` with the flags `boot`
+    /// and `tsec`.
+    #[structopt(long = "payload", required = true)]
+    payloads: Vec<PayloadSpec>,
+
+    /// Where to write the signed manifest to.
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+}
+
+/// A file in the temporary directory that is removed again when dropped, including
+/// when signing fails.
+struct StagingFile(PathBuf);
+
+impl StagingFile {
+    fn create(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let path = env::temp_dir().join(format!("mkmanifest-{}.tbs", process::id()));
+        let file = StagingFile(path);
+        fs::write(&file.0, data)?;
+        Ok(file)
+    }
+}
+
+impl Drop for StagingFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Signs `data` with the private key at `key` using `openssl`.
+///
+/// The data is staged in a temporary file since one-shot Ed25519 signing in `openssl`
+/// cannot operate on standard input.
+fn sign(scheme: Scheme, key: &Path, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let staging = StagingFile::create(data)?;
+
+    let mut command = Command::new("openssl");
+    match scheme {
+        Scheme::Rsa => command.args(&[
+            "dgst",
+            "-sha256",
+            "-sigopt",
+            "rsa_padding_mode:pss",
+            "-sigopt",
+            "rsa_pss_saltlen:32",
+            "-sign",
+        ]),
+        Scheme::Ed25519 => command.args(&["pkeyutl", "-sign", "-rawin", "-inkey"]),
+    };
+    match scheme {
+        Scheme::Rsa => command.arg(key).arg(&staging.0),
+        Scheme::Ed25519 => command.arg(key).arg("-in").arg(&staging.0),
+    };
+
+    let result = command
+        .output()
+        .map_err(|e| format!("failed to run openssl: {}", e))?;
+    if !result.status.success() {
+        return Err("openssl failed to sign the manifest".into());
+    }
+
+    Ok(result.stdout)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = Options::from_args();
+
+    if options.payloads.len() > MAX_ENTRIES {
+        return Err(format!("at most {} payloads are supported", MAX_ENTRIES).into());
+    }
+
+    let mut manifest = Manifest {
+        header: Header {
+            entry_count: options.payloads.len() as u16,
+            security_version: options.security_version,
+            signature_size: options.scheme.signature_size(),
+        },
+        ..Default::default()
+    };
+
+    for (entry, payload) in manifest.entries.iter_mut().zip(options.payloads.iter()) {
+        let data = fs::read(&payload.path)?;
+
+        let mut hash = [0; manifest::HASH_SIZE];
+        hash.copy_from_slice(&Sha256::digest(&data));
+
+        *entry = Entry {
+            load_address: payload.load_address,
+            size: data.len() as u32,
+            flags: payload.flags,
+            min_version: options.min_version,
+            hash,
+        };
+    }
+
+    let mut buffer = [0; MAX_MANIFEST_SIZE];
+    let signed_size = manifest
+        .encode(&mut buffer)
+        .map_err(|e| format!("failed to encode the manifest: {:?}", e))?;
+
+    let signature = sign(options.scheme, &options.key, &buffer[..signed_size])?;
+    if signature.len() != manifest.header.signature_size as usize {
+        return Err("the key does not match the selected signature scheme".into());
+    }
+
+    let mut output = buffer[..signed_size].to_vec();
+    output.extend_from_slice(&signature);
+    fs::write(&options.output, output)?;
+
+    Ok(())
+}